@@ -0,0 +1,114 @@
+use std::f32::consts::FRAC_PI_2;
+
+use anyhow::Result;
+
+use vulkan_engine::{
+    application::{Application, ApplicationInfo, FrameInfo, Runable},
+    camera::{Camera3D, Camera3DController, DebugCamera3DController},
+    engine::{
+        ecs::components::MeshComponent, input_handler::InputHandler,
+        material::simple_material::SimpleMaterial, mesh::primitives, transform::Transform,
+        Engine,
+    },
+    glam::Vec3,
+};
+
+/// Two decals lying on a ground plane: the left one z-fights with the ground, the right one is
+/// drawn with a depth bias and stays on top
+struct CoplanarPlanes {
+    camera_controller: DebugCamera3DController,
+}
+
+impl Runable for CoplanarPlanes {
+    fn new(engine: &mut Engine) -> Self {
+        let ground = primitives::make_plane_xz(engine, 2, 2, false).expect("Failed to create mesh");
+        let decal = primitives::make_plane_xz(engine, 2, 2, false).expect("Failed to create mesh");
+        let biased_decal =
+            primitives::make_plane_xz(engine, 2, 2, false).expect("Failed to create mesh");
+
+        let scene = engine.scene_mut();
+        let grey = scene.new_material(SimpleMaterial::new(0.6, 0.6, 0.6));
+        let orange = scene.new_material(SimpleMaterial::new(0.9, 0.5, 0.1));
+        let biased_orange =
+            scene.new_material(SimpleMaterial::new(0.9, 0.5, 0.1).with_depth_bias(-1.0, -1.0));
+
+        let mut ground_model = Transform::new();
+        ground_model.scale(Vec3::new(6.0, 1.0, 4.0));
+        let ground_entity = scene.spawn_entity();
+        scene.entity_add_component(
+            ground_entity,
+            MeshComponent {
+                mesh: ground,
+                model: ground_model,
+                material: grey,
+                tint: Vec3::ONE,
+                submeshes: Vec::new(),
+            },
+        );
+
+        let mut decal_model = Transform::new();
+        decal_model
+            .translate(Vec3::new(-1.5, 0.0, 0.0))
+            .scale(Vec3::new(2.0, 1.0, 2.0));
+        let decal_entity = scene.spawn_entity();
+        scene.entity_add_component(
+            decal_entity,
+            MeshComponent {
+                mesh: decal,
+                model: decal_model,
+                material: orange,
+                tint: Vec3::ONE,
+                submeshes: Vec::new(),
+            },
+        );
+
+        let mut biased_decal_model = Transform::new();
+        biased_decal_model
+            .translate(Vec3::new(1.5, 0.0, 0.0))
+            .scale(Vec3::new(2.0, 1.0, 2.0));
+        let biased_decal_entity = scene.spawn_entity();
+        scene.entity_add_component(
+            biased_decal_entity,
+            MeshComponent {
+                mesh: biased_decal,
+                model: biased_decal_model,
+                material: biased_orange,
+                tint: Vec3::ONE,
+                submeshes: Vec::new(),
+            },
+        );
+
+        scene.set_camera(Camera3D::new(
+            Vec3::new(0.0, 3.0, 6.0),
+            -FRAC_PI_2,
+            -0.5,
+            Vec3::Y,
+        ));
+
+        Self {
+            camera_controller: DebugCamera3DController::new(),
+        }
+    }
+
+    fn on_update(
+        &mut self,
+        engine: &mut Engine,
+        input: &InputHandler,
+        frame_info: &FrameInfo,
+    ) -> bool {
+        if let Some(camera) = engine.scene_mut().camera_mut() {
+            self.camera_controller
+                .update_camera(input, camera, frame_info.delta_time);
+        }
+
+        true
+    }
+}
+
+fn main() -> Result<()> {
+    Application::<CoplanarPlanes>::run_application(ApplicationInfo {
+        window_title: String::from("Coplanar planes"),
+        exit_on_escape: true,
+        ..Default::default()
+    })
+}
@@ -0,0 +1,89 @@
+use std::f32::consts::FRAC_PI_2;
+
+use anyhow::Result;
+
+use vulkan_engine::{
+    application::{Application, ApplicationInfo, FrameInfo, Runable},
+    camera::Camera3D,
+    engine::{
+        ecs::components::{InstancedMeshComponent, MeshInstance},
+        input_handler::InputHandler,
+        material::simple_material::SimpleMaterial,
+        mesh::primitives,
+        transform::Transform,
+        Engine,
+    },
+    glam::Vec3,
+};
+
+const GRID_SIZE: usize = 20;
+
+/// A grid of cubes drawn with a single instanced draw call, each instance with its own tint
+struct InstancedCubes;
+
+impl Runable for InstancedCubes {
+    fn new(engine: &mut Engine) -> Self {
+        let cube = primitives::make_sharp_cube(engine).expect("Failed to create mesh");
+
+        let scene = engine.scene_mut();
+        let white = scene.new_material(SimpleMaterial::from_srgb(1.0, 1.0, 1.0));
+
+        let instances = (0..GRID_SIZE * GRID_SIZE)
+            .map(|i| {
+                let (x, z) = ((i % GRID_SIZE) as f32, (i / GRID_SIZE) as f32);
+                let mut model = Transform::new();
+                model
+                    .translate(Vec3::new(x - GRID_SIZE as f32 / 2.0, 0.0, -z) * 1.5)
+                    .scale(Vec3::splat(0.5));
+
+                MeshInstance {
+                    model,
+                    tint: Vec3::new(x / GRID_SIZE as f32, 0.5, z / GRID_SIZE as f32),
+                }
+            })
+            .collect();
+
+        let entity = scene.spawn_entity();
+        scene.entity_add_component(
+            entity,
+            InstancedMeshComponent {
+                mesh: cube,
+                material: white,
+                instances,
+            },
+        );
+
+        scene.set_camera(Camera3D::new(
+            Vec3::new(0.0, 8.0, 10.0),
+            -FRAC_PI_2,
+            -0.5,
+            Vec3::Y,
+        ));
+
+        Self
+    }
+
+    fn on_update(
+        &mut self,
+        engine: &mut Engine,
+        _input: &InputHandler,
+        frame_info: &FrameInfo,
+    ) -> bool {
+        let angle = frame_info.delta_time;
+        for (_, instanced_mesh) in engine.scene_mut().instanced_meshes_mut() {
+            for instance in instanced_mesh.instances.iter_mut() {
+                instance.model.rotate(Vec3::Y, angle);
+            }
+        }
+
+        true
+    }
+}
+
+fn main() -> Result<()> {
+    Application::<InstancedCubes>::run_application(ApplicationInfo {
+        window_title: String::from("Instanced cubes"),
+        exit_on_escape: true,
+        ..Default::default()
+    })
+}
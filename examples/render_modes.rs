@@ -0,0 +1,143 @@
+use std::f32::consts::FRAC_PI_2;
+
+use anyhow::Result;
+
+use vulkan_engine::{
+    application::{Application, ApplicationInfo, FrameInfo, Runable},
+    camera::{Camera3D, Camera3DController, DebugCamera3DController},
+    engine::{
+        ecs::components::MeshComponent, input_handler::InputHandler,
+        material::simple_material::SimpleMaterial, mesh::primitives, renderer::RenderMode,
+        transform::Transform, Engine,
+    },
+    glam::Vec3,
+    vulkano::pipeline::graphics::rasterization::CullMode,
+    winit::keyboard::KeyCode,
+};
+
+struct RenderModes {
+    camera_controller: DebugCamera3DController,
+}
+
+impl Runable for RenderModes {
+    fn new(engine: &mut Engine) -> Self {
+        let cube = primitives::make_sharp_cube(engine).expect("Failed to create mesh");
+        let sphere = primitives::make_sphere_uv(engine, 32, 16).expect("Failed to create mesh");
+
+        let scene = engine.scene_mut();
+        let red = scene.new_material(SimpleMaterial::new(0.8, 0.2, 0.2));
+        let blue = scene.new_material(SimpleMaterial::new(0.2, 0.3, 0.8));
+
+        let mut cube_model = Transform::new();
+        cube_model.translate(Vec3::new(-1.0, 0.0, 0.0));
+        let cube_entity = scene.spawn_entity();
+        scene.entity_add_component(
+            cube_entity,
+            MeshComponent {
+                mesh: cube,
+                model: cube_model,
+                material: red,
+                tint: Vec3::ONE,
+                submeshes: Vec::new(),
+            },
+        );
+
+        let mut sphere_model = Transform::new();
+        sphere_model.translate(Vec3::new(1.0, 0.0, 0.0));
+        let sphere_entity = scene.spawn_entity();
+        scene.entity_add_component(
+            sphere_entity,
+            MeshComponent {
+                mesh: sphere,
+                model: sphere_model,
+                material: blue,
+                tint: Vec3::ONE,
+                submeshes: Vec::new(),
+            },
+        );
+
+        scene.set_camera(Camera3D::new(
+            Vec3::new(0.0, 1.0, 5.0),
+            -FRAC_PI_2,
+            -0.2,
+            Vec3::Y,
+        ));
+
+        println!("Press Tab to cycle through the render modes");
+        println!("Press I to toggle indirect drawing");
+        println!("Press C to toggle backface culling");
+        println!("Press O to toggle the wireframe overlay");
+
+        Self {
+            camera_controller: DebugCamera3DController::new(),
+        }
+    }
+
+    fn on_update(
+        &mut self,
+        engine: &mut Engine,
+        input: &InputHandler,
+        frame_info: &FrameInfo,
+    ) -> bool {
+        if input.key_pressed(KeyCode::Tab) {
+            let render_mode = match engine.render_mode() {
+                RenderMode::Default => RenderMode::NormalView,
+                RenderMode::NormalView => RenderMode::DepthView,
+                RenderMode::DepthView => RenderMode::Wireframe,
+                RenderMode::Wireframe => RenderMode::VertexColor,
+                RenderMode::VertexColor => RenderMode::Default,
+            };
+
+            // Skips the wireframe render mode on devices without fill_mode_non_solid
+            if let Err(e) = engine.set_render_mode(render_mode) {
+                println!("{e}");
+                engine
+                    .set_render_mode(RenderMode::VertexColor)
+                    .expect("Only the wireframe render mode can be unsupported");
+            }
+            println!("Render mode: {:?}", engine.render_mode());
+        }
+
+        if input.key_pressed(KeyCode::KeyI) {
+            let indirect_drawing = !engine.indirect_drawing();
+            match engine.set_indirect_drawing(indirect_drawing) {
+                Ok(()) => println!("Indirect drawing: {}", indirect_drawing),
+                Err(e) => println!("{e}"),
+            }
+        }
+
+        if input.key_pressed(KeyCode::KeyC) {
+            let cull_mode = match engine.cull_mode() {
+                CullMode::None => CullMode::Back,
+                _ => CullMode::None,
+            };
+
+            engine
+                .set_cull_mode(cull_mode)
+                .expect("Failed to rebuild the pipelines");
+            println!("Cull mode: {:?}", engine.cull_mode());
+        }
+
+        if input.key_pressed(KeyCode::KeyO) {
+            match engine.set_wireframe_overlay(!engine.wireframe_overlay()) {
+                Ok(()) => println!("Wireframe overlay: {}", engine.wireframe_overlay()),
+                Err(e) => println!("{e}"),
+            }
+        }
+
+        if let Some(camera) = engine.scene_mut().camera_mut() {
+            self.camera_controller
+                .update_camera(input, camera, frame_info.delta_time);
+        }
+
+        true
+    }
+}
+
+fn main() -> Result<()> {
+    Application::<RenderModes>::run_application(ApplicationInfo {
+        window_title: String::from("Render modes"),
+        exit_on_escape: true,
+        ..Default::default()
+    })
+}
@@ -0,0 +1,115 @@
+use std::f32::consts::FRAC_PI_2;
+
+use anyhow::Result;
+
+use vulkan_engine::{
+    application::{Application, ApplicationInfo, FrameInfo, Runable},
+    camera::{Camera3D, Camera3DController, DebugCamera3DController},
+    engine::{
+        ecs::components::MeshComponent, input_handler::InputHandler,
+        material::simple_material::SimpleMaterial, mesh::primitives, transform::Transform,
+        Engine,
+    },
+    glam::Vec3,
+};
+
+/// A row of spheres sharing materials, smooth on the top row and flat shaded on the bottom one,
+/// standing on a double sided floor
+struct SphereMaterials {
+    camera_controller: DebugCamera3DController,
+}
+
+impl Runable for SphereMaterials {
+    fn new(engine: &mut Engine) -> Self {
+        let colors = [
+            SimpleMaterial::from_srgb(0.9, 0.2, 0.2),
+            SimpleMaterial::from_srgb(0.2, 0.8, 0.3),
+            SimpleMaterial::from_srgb(0.2, 0.4, 0.9),
+            SimpleMaterial::from_srgb(0.9, 0.8, 0.2),
+        ];
+
+        let spheres = (0..colors.len())
+            .map(|_| {
+                let smooth = primitives::make_sphere_uv(engine, 32, 16)?;
+                let flat = primitives::make_sphere_uv_flat(engine, 12, 6)?;
+                Ok((smooth, flat))
+            })
+            .collect::<Result<Vec<_>>>()
+            .expect("Failed to create mesh");
+        let floor = primitives::make_plane_xz(engine, 2, 2, true).expect("Failed to create mesh");
+
+        let scene = engine.scene_mut();
+        let grey = scene.new_material(SimpleMaterial::new(0.5, 0.5, 0.5));
+
+        for (i, ((smooth, flat), color)) in spheres.into_iter().zip(colors).enumerate() {
+            let material = scene.new_material(color);
+            let x = i as f32 * 1.5 - 2.25;
+
+            for (mesh, y) in [(smooth, 1.5), (flat, 0.25)] {
+                let mut model = Transform::new();
+                model.translate(Vec3::new(x, y, 0.0));
+
+                let entity = scene.spawn_entity();
+                scene.entity_add_component(
+                    entity,
+                    MeshComponent {
+                        mesh,
+                        model,
+                        material,
+                        tint: Vec3::ONE,
+                        submeshes: Vec::new(),
+                    },
+                );
+            }
+        }
+
+        let mut floor_model = Transform::new();
+        floor_model
+            .translate(Vec3::new(0.0, -0.25, 0.0))
+            .scale(Vec3::new(8.0, 1.0, 4.0));
+        let floor_entity = scene.spawn_entity();
+        scene.entity_add_component(
+            floor_entity,
+            MeshComponent {
+                mesh: floor,
+                model: floor_model,
+                material: grey,
+                tint: Vec3::ONE,
+                submeshes: Vec::new(),
+            },
+        );
+
+        scene.set_camera(Camera3D::new(
+            Vec3::new(0.0, 1.0, 6.0),
+            -FRAC_PI_2,
+            -0.1,
+            Vec3::Y,
+        ));
+
+        Self {
+            camera_controller: DebugCamera3DController::new(),
+        }
+    }
+
+    fn on_update(
+        &mut self,
+        engine: &mut Engine,
+        input: &InputHandler,
+        frame_info: &FrameInfo,
+    ) -> bool {
+        if let Some(camera) = engine.scene_mut().camera_mut() {
+            self.camera_controller
+                .update_camera(input, camera, frame_info.delta_time);
+        }
+
+        true
+    }
+}
+
+fn main() -> Result<()> {
+    Application::<SphereMaterials>::run_application(ApplicationInfo {
+        window_title: String::from("Sphere materials"),
+        exit_on_escape: true,
+        ..Default::default()
+    })
+}
@@ -0,0 +1,71 @@
+use std::f32::consts::FRAC_PI_2;
+
+use anyhow::Result;
+
+use vulkan_engine::{
+    application::{Application, ApplicationInfo, FrameInfo, Runable},
+    camera::Camera3D,
+    engine::{
+        ecs::components::MeshComponent, input_handler::InputHandler,
+        material::simple_material::SimpleMaterial, mesh::primitives, transform::Transform,
+        Engine,
+    },
+    glam::Vec3,
+};
+
+/// Smallest application: a cube turning in front of a fixed camera
+struct SpinningCube {
+    speed: f32,
+}
+
+impl Runable for SpinningCube {
+    fn new(engine: &mut Engine) -> Self {
+        let cube = primitives::make_sharp_cube(engine).expect("Failed to create mesh");
+
+        let scene = engine.scene_mut();
+        let orange = scene.new_material(SimpleMaterial::from_srgb(0.9, 0.5, 0.1));
+
+        let cube_entity = scene.spawn_entity();
+        scene.entity_add_component(
+            cube_entity,
+            MeshComponent {
+                mesh: cube,
+                model: Transform::new(),
+                material: orange,
+                tint: Vec3::ONE,
+                submeshes: Vec::new(),
+            },
+        );
+
+        scene.set_camera(Camera3D::new(
+            Vec3::new(0.0, 1.0, 3.0),
+            -FRAC_PI_2,
+            -0.3,
+            Vec3::Y,
+        ));
+
+        Self { speed: 1.0 }
+    }
+
+    fn on_update(
+        &mut self,
+        engine: &mut Engine,
+        _input: &InputHandler,
+        frame_info: &FrameInfo,
+    ) -> bool {
+        let angle = self.speed * frame_info.delta_time;
+        for (_, mesh_component) in engine.scene_mut().meshes_mut() {
+            mesh_component.model.rotate(Vec3::new(1.0, 1.0, 0.0), angle);
+        }
+
+        true
+    }
+}
+
+fn main() -> Result<()> {
+    Application::<SpinningCube>::run_application(ApplicationInfo {
+        window_title: String::from("Spinning cube"),
+        exit_on_escape: true,
+        ..Default::default()
+    })
+}
@@ -0,0 +1,19 @@
+#version 450
+
+layout(location = 0) out vec2 uv;
+layout(location = 1) out vec3 color;
+
+// Colors of the top and bottom edges of the window for the gradient, unused by the image
+layout(push_constant) uniform Gradient
+{
+    vec4 top;
+    vec4 bottom;
+} gradient;
+
+// A single triangle covering the whole screen, generated from the vertex index. The gradient is
+// linear so interpolating it from the corners of the triangle gives the same colors on screen
+void main() {
+    uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    color = mix(gradient.top.rgb, gradient.bottom.rgb, uv.y);
+    gl_Position = vec4(uv * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -2,11 +2,16 @@
 
 layout(location = 0) out vec4 outColor;
 
+// Set when the near plane is at a depth of 1 and the far plane at 0
+layout(constant_id = 0) const bool reversed_z = false;
+
 void main() {
+    // Default clip planes of Camera3D, cameras with other planes show a scaled depth
     const float near = 0.1;
     const float far = 100.0;
 
-    float z = gl_FragCoord.z * 2.0 - 1.0;
+    float frag_depth = reversed_z ? 1.0 - gl_FragCoord.z : gl_FragCoord.z;
+    float z = frag_depth * 2.0 - 1.0;
     float depth = (2.0 * near * far) / (far + near - z * (far - near));
     depth /= far;
     outColor = vec4(vec3(depth), 1.0);
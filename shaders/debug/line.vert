@@ -0,0 +1,18 @@
+#version 450
+
+layout(location = 0) in vec3 in_position;
+layout(location = 1) in vec3 in_color;
+
+layout(location = 0) out vec3 color;
+
+layout(push_constant) uniform MVP
+{ 
+    mat4 model; 
+    mat4 view;
+    mat4 proj;
+} mvp;
+
+void main() {
+    gl_Position = mvp.proj * mvp.view * vec4(in_position, 1.0);
+    color = in_color;
+}
@@ -0,0 +1,5 @@
+#version 450
+
+// Only the depth of the fragments is written, see the depth prepass
+void main() {
+}
@@ -0,0 +1,27 @@
+#version 450
+
+layout(location = 0) in vec3 in_position;
+layout(location = 1) in vec3 in_normal;
+layout(location = 2) in vec2 in_texture_coord;
+
+// Instance stream, bound at binding 1
+layout(location = 3) in mat4 in_instance_model;
+layout(location = 7) in vec3 in_instance_tint;
+
+layout(location = 0) out vec3 normal;
+layout(location = 1) out vec2 tex_coords;
+layout(location = 2) out vec3 tint;
+
+layout(push_constant) uniform MVP 
+{ 
+    mat4 model; 
+    mat4 view;
+    mat4 proj;
+} mvp;
+
+void main() {
+    gl_Position = mvp.proj * mvp.view * in_instance_model * vec4(in_position, 1.0);
+    normal = mat3(transpose(inverse(in_instance_model))) * in_normal;
+    tex_coords = in_texture_coord;
+    tint = in_instance_tint;
+}
@@ -2,6 +2,8 @@
 
 layout(location = 0) in vec3 normal;
 layout(location = 1) in vec2 tex_coords;
+// Per object color multiplied into the material's one
+layout(location = 2) in vec3 tint;
 
 layout(location = 0) out vec4 out_color;
 
@@ -10,8 +12,23 @@ layout(binding = 0) uniform Material
     vec3 color;
 } material;
 
+// Uniforms of the frame, available to every material shader
+layout(set = 1, binding = 0) uniform Frame
+{
+    vec2 resolution;
+    float time;
+    uint frame_index;
+} frame;
+
+// Fragment push constants of the material, zero unless it sets them
+layout(push_constant) uniform Lighting
+{
+    // Light received by the faces turned away from the sun
+    layout(offset = 208) float ambient;
+} lighting;
+
 void main() {
     vec3 ligh_dir = normalize(vec3(0.2, -1.0, -0.3));
-    float attenuation = max(dot(-ligh_dir, normal), 0.0);
-    out_color = vec4(material.color * attenuation, 1.0);
+    float attenuation = max(dot(-ligh_dir, normal), lighting.ambient);
+    out_color = vec4(material.color * tint * attenuation, 1.0);
 }
@@ -0,0 +1,38 @@
+#version 450
+
+layout(location = 0) in vec3 in_position;
+layout(location = 1) in vec3 in_normal;
+layout(location = 2) in vec2 in_texture_coord;
+
+layout(location = 0) out vec3 normal;
+layout(location = 1) out vec2 tex_coords;
+layout(location = 2) out vec3 tint;
+
+layout(push_constant) uniform MVP 
+{ 
+    mat4 model; 
+    mat4 view;
+    mat4 proj;
+} mvp;
+
+struct Object
+{
+    mat4 model;
+    vec4 tint;
+};
+
+// One object per indirect draw, selected by the draw's first instance
+layout(set = 2, binding = 0) readonly buffer Objects
+{
+    Object objects[];
+} objects;
+
+void main() {
+    Object object = objects.objects[gl_InstanceIndex];
+    mat4 model = object.model;
+
+    gl_Position = mvp.proj * mvp.view * model * vec4(in_position, 1.0);
+    normal = mat3(transpose(inverse(model))) * in_normal;
+    tex_coords = in_texture_coord;
+    tint = object.tint.rgb;
+}
@@ -0,0 +1,41 @@
+#version 450
+
+layout(location = 0) in vec3 in_position;
+layout(location = 1) in vec3 in_normal;
+layout(location = 2) in vec2 in_texture_coord;
+layout(location = 3) in uvec4 in_bone_ids;
+layout(location = 4) in vec4 in_bone_weights;
+
+layout(location = 0) out vec3 normal;
+layout(location = 1) out vec2 tex_coords;
+layout(location = 2) out vec3 tint;
+
+layout(push_constant) uniform MVP 
+{ 
+    mat4 model; 
+    mat4 view;
+    mat4 proj;
+    vec3 tint;
+    // First matrix of the mesh's pose in the bones of the frame
+    uint bone_offset;
+} mvp;
+
+// Poses of every skinned mesh of the frame, one matrix per bone in model space
+layout(set = 2, binding = 0) readonly buffer Bones
+{
+    mat4 bones[];
+} bones;
+
+void main() {
+    uvec4 bone_ids = mvp.bone_offset + in_bone_ids;
+    mat4 skin = in_bone_weights.x * bones.bones[bone_ids.x]
+              + in_bone_weights.y * bones.bones[bone_ids.y]
+              + in_bone_weights.z * bones.bones[bone_ids.z]
+              + in_bone_weights.w * bones.bones[bone_ids.w];
+    mat4 model = mvp.model * skin;
+
+    gl_Position = mvp.proj * mvp.view * model * vec4(in_position, 1.0);
+    normal = mat3(transpose(inverse(model))) * in_normal;
+    tex_coords = in_texture_coord;
+    tint = mvp.tint;
+}
@@ -0,0 +1,12 @@
+#version 450
+
+layout(location = 0) in vec2 tex_coords;
+layout(location = 1) in vec3 color;
+
+layout(location = 0) out vec4 out_color;
+
+layout(set = 0, binding = 0) uniform sampler2D glyph_atlas;
+
+void main() {
+    out_color = vec4(color, texture(glyph_atlas, tex_coords).r);
+}
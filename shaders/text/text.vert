@@ -0,0 +1,21 @@
+#version 450
+
+layout(location = 0) in vec2 in_position;
+layout(location = 1) in vec2 in_texture_coord;
+layout(location = 2) in vec3 in_color;
+
+layout(location = 0) out vec2 tex_coords;
+layout(location = 1) out vec3 color;
+
+layout(push_constant) uniform MVP
+{ 
+    mat4 model; 
+    mat4 view;
+    mat4 proj;
+} mvp;
+
+void main() {
+    gl_Position = mvp.proj * vec4(in_position, 0.0, 1.0);
+    tex_coords = in_texture_coord;
+    color = in_color;
+}
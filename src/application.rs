@@ -1,11 +1,14 @@
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use winit::dpi::{LogicalSize, Size};
+use winit::dpi::{LogicalSize, PhysicalPosition, PhysicalSize, Position, Size};
 use winit::event::{ElementState, Event, KeyEvent, WindowEvent};
 use winit::event_loop::EventLoopWindowTarget;
 use winit::keyboard::{KeyCode, PhysicalKey};
-use winit::window::WindowBuilder;
+use winit::window::{Icon, WindowBuilder};
+
+use vulkano::{device::Features, Version};
 use winit::{
     event_loop::{ControlFlow, EventLoop},
     window::Window,
@@ -13,9 +16,13 @@ use winit::{
 
 use anyhow::{Ok, Result};
 
+#[cfg(feature = "egui")]
+use egui_winit_vulkano::egui;
+
 use crate::engine::input_handler::InputHandler;
+use crate::engine::renderer::RendererInfo;
 use crate::engine::Engine;
-use crate::vulkan_context::VulkanContext;
+use crate::vulkan_context::{self, AllocatorInfo, VulkanContext, VulkanContextInfo};
 
 pub trait Runable {
     fn new(engine: &mut Engine) -> Self;
@@ -25,26 +32,88 @@ pub trait Runable {
         input: &InputHandler,
         frame_info: &FrameInfo,
     ) -> bool;
+
+    /// Called after the renderer recreated its swapchain at a new size, in physical pixels. The
+    /// resize events received between two frames give a single call with the size applied
+    fn on_resize(&mut self, _engine: &mut Engine, _new_size: PhysicalSize<u32>) {}
+
+    /// Builds the debug UI of the frame, called right after `on_update`
+    #[cfg(feature = "egui")]
+    fn on_gui(&mut self, _ctx: &egui::Context) {}
 }
 
 pub struct FrameInfo {
+    /// Time elapsed since the previous frame in seconds, clamped to
+    /// `ApplicationInfo::max_delta_time`
     pub delta_time: f32,
+    /// Unclamped time elapsed since the previous frame in seconds
+    pub raw_delta_time: f32,
+    /// Number of frames updated before this one, 0 for the first frame
+    pub frame_index: u64,
+    /// Sum of the `delta_time` of every frame up to this one in seconds. Grows steadily, stalls
+    /// and paused time are not counted
+    pub total_time: f32,
+    /// Physical pixels per logical pixel of the monitor showing the window, to scale UI and text.
+    /// Changes when the window moves to a monitor of another DPI
+    pub scale_factor: f64,
 }
 
 pub struct ApplicationInfo {
+    /// Name reported to Vulkan, visible in tools like RenderDoc
+    pub application_name: String,
+    pub application_version: Version,
+    pub engine_name: Option<String>,
     pub window_title: String,
     pub window_size: [u32; 2],
     pub resizeable: bool,
     pub exit_on_escape: bool,
+    /// Image used as the window icon, the window keeps the default icon if it fails to load
+    pub icon: Option<PathBuf>,
+    /// Initial position of the window in physical pixels, chosen by the platform if `None`
+    pub position: Option<[i32; 2]>,
+    /// Upper bound of `FrameInfo::delta_time` in seconds, so that a stall (breakpoint, window
+    /// drag...) does not make the next frame jump forward
+    pub max_delta_time: f32,
+    /// Stops updating and rendering while the window is unfocused to save power
+    pub pause_on_unfocus: bool,
+    /// Caps the frame rate by waiting for the rest of the frame budget, uncapped if `None`
+    pub max_fps: Option<u32>,
+    pub renderer_info: RendererInfo,
+    /// Sizing of the Vulkan command buffer and descriptor set allocators
+    pub allocator_info: AllocatorInfo,
+    /// Optional device features to enable when supported, see `VulkanContextInfo::features`
+    pub features: Features,
+    /// See `VulkanContextInfo::pipeline_cache_path`
+    pub pipeline_cache_path: Option<PathBuf>,
+    /// Entities the scene has room for before it reallocates, each component type also gets room
+    /// for as many components. Use `Scene::reserve` to grow it later
+    pub scene_capacity: usize,
 }
 
 impl Default for ApplicationInfo {
     fn default() -> Self {
         Self {
+            application_name: String::from("Vulkan engine"),
+            application_version: Version {
+                major: 0,
+                minor: 1,
+                patch: 0,
+            },
+            engine_name: None,
             window_title: String::from("Vulkan application"),
             window_size: [800, 600],
             resizeable: false,
             exit_on_escape: false,
+            icon: None,
+            position: None,
+            max_delta_time: 0.1,
+            pause_on_unfocus: false,
+            max_fps: None,
+            renderer_info: RendererInfo::default(),
+            allocator_info: AllocatorInfo::default(),
+            features: vulkan_context::default_features(),
+            pipeline_cache_path: None,
+            scene_capacity: 0,
         }
     }
 }
@@ -63,6 +132,11 @@ where
 
     input_handler: InputHandler,
     exit_on_escape: bool,
+    max_delta_time: f32,
+    frame_budget: Option<Duration>,
+
+    pause_on_unfocus: bool,
+    focused: bool,
 }
 
 impl<T> Application<T>
@@ -71,20 +145,60 @@ where
 {
     pub fn run_application(application_info: ApplicationInfo) -> Result<()> {
         let event_loop = EventLoop::new().expect("Failed to create event loop");
+
+        let mut window_builder = WindowBuilder::new()
+            .with_title(application_info.window_title)
+            .with_inner_size(Size::Logical(LogicalSize::from(
+                application_info.window_size,
+            )))
+            .with_resizable(application_info.resizeable);
+
+        if let Some(position) = application_info.position {
+            window_builder = window_builder
+                .with_position(Position::Physical(PhysicalPosition::from(position)));
+        }
+
+        if let Some(icon_path) = &application_info.icon {
+            let icon = load_icon(icon_path)
+                .map_err(|error| {
+                    eprintln!(
+                        "Failed to load window icon {}: {}",
+                        icon_path.display(),
+                        error
+                    )
+                })
+                .ok();
+
+            window_builder = window_builder.with_window_icon(icon);
+        }
+
         let window = Arc::new(
-            WindowBuilder::new()
-                .with_title(application_info.window_title)
-                .with_inner_size(Size::Logical(LogicalSize::from(
-                    application_info.window_size,
-                )))
-                .with_resizable(application_info.resizeable)
+            window_builder
                 .build(&event_loop)
                 .expect("Failed to build window"),
         );
 
-        let vulkan_context = Arc::new(VulkanContext::new(&window)?);
-        let mut engine = Engine::new(Arc::clone(&vulkan_context), Arc::clone(&window))?;
+        let vulkan_context = Arc::new(VulkanContext::new(
+            &window,
+            VulkanContextInfo {
+                application_name: application_info.application_name,
+                application_version: application_info.application_version,
+                engine_name: application_info.engine_name,
+                allocator_info: application_info.allocator_info,
+                features: application_info.features,
+                pipeline_cache_path: application_info.pipeline_cache_path,
+            },
+        )?);
+        let mut engine = Engine::new(
+            Arc::clone(&vulkan_context),
+            Arc::clone(&window),
+            &application_info.renderer_info,
+            application_info.scene_capacity,
+        )?;
+        #[cfg(feature = "egui")]
+        engine.init_gui(&event_loop);
         let runable = T::new(&mut engine);
+        let scale_factor = window.scale_factor();
 
         let mut app = Self {
             runable,
@@ -92,11 +206,24 @@ where
             engine,
             window,
 
-            frame_info: FrameInfo { delta_time: 0.0 },
+            frame_info: FrameInfo {
+                delta_time: 0.0,
+                raw_delta_time: 0.0,
+                frame_index: 0,
+                total_time: 0.0,
+                scale_factor,
+            },
             previous_frame_time: Instant::now(),
 
             input_handler: InputHandler::new(),
             exit_on_escape: application_info.exit_on_escape,
+            max_delta_time: application_info.max_delta_time,
+            frame_budget: application_info
+                .max_fps
+                .map(|max_fps| Duration::from_secs_f64(1.0 / max_fps.max(1) as f64)),
+
+            pause_on_unfocus: application_info.pause_on_unfocus,
+            focused: true,
         };
 
         app.start(event_loop)?;
@@ -125,15 +252,6 @@ where
         window_target: &EventLoopWindowTarget<()>,
     ) -> Result<()> {
         match &event {
-            Event::NewEvents(_) => {
-                self.frame_info.delta_time =
-                    Instant::elapsed(&self.previous_frame_time).as_secs_f32();
-
-                self.previous_frame_time = Instant::now();
-
-                self.input_handler.step();
-            }
-
             Event::WindowEvent { event, .. } => {
                 self.handle_window_event(event, window_target)?;
             }
@@ -141,16 +259,26 @@ where
             Event::Suspended => self.engine.suspend(),
             Event::Resumed => self.engine.resume(Arc::clone(&self.window)),
 
-            Event::AboutToWait => {
-                if !self
-                    .runable
-                    .on_update(&mut self.engine, &self.input_handler, &self.frame_info)
-                {
-                    window_target.exit();
+            // The engine is only dropped after the event loop, the frames still in flight have to
+            // finish before the resources they use are released
+            Event::LoopExiting => self.engine.shutdown()?,
+
+            Event::AboutToWait if self.paused() => (),
+
+            Event::AboutToWait => match self.frame_budget {
+                Some(frame_budget) => {
+                    let next_frame_time = self.previous_frame_time + frame_budget;
+                    if Instant::now() >= next_frame_time {
+                        self.run_frame(window_target);
+                    }
+
+                    window_target.set_control_flow(ControlFlow::WaitUntil(
+                        self.previous_frame_time + frame_budget,
+                    ));
                 }
 
-                self.window.request_redraw();
-            }
+                None => self.run_frame(window_target),
+            },
 
             _ => (),
         }
@@ -160,11 +288,45 @@ where
         Ok(())
     }
 
+    fn run_frame(&mut self, window_target: &EventLoopWindowTarget<()>) {
+        self.frame_info.raw_delta_time = Instant::elapsed(&self.previous_frame_time).as_secs_f32();
+        self.frame_info.delta_time = f32::min(self.frame_info.raw_delta_time, self.max_delta_time);
+        self.frame_info.total_time += self.frame_info.delta_time;
+        self.engine
+            .set_frame_time(self.frame_info.total_time, self.frame_info.frame_index);
+
+        self.previous_frame_time = Instant::now();
+
+        // Inputs received between two frames are all seen by `on_update`, even when the event
+        // loop wakes up several times per frame
+        self.input_handler.step();
+
+        if !self
+            .runable
+            .on_update(&mut self.engine, &self.input_handler, &self.frame_info)
+        {
+            window_target.exit();
+        }
+
+        #[cfg(feature = "egui")]
+        {
+            let runable = &mut self.runable;
+            self.engine.gui_frame(|ctx| runable.on_gui(ctx));
+        }
+
+        self.frame_info.frame_index += 1;
+
+        self.window.request_redraw();
+    }
+
     fn handle_window_event(
         &mut self,
         window_event: &WindowEvent,
         window_target: &EventLoopWindowTarget<()>,
     ) -> Result<()> {
+        #[cfg(feature = "egui")]
+        self.engine.gui_update(window_event);
+
         match window_event {
             WindowEvent::CloseRequested => {
                 window_target.exit();
@@ -184,15 +346,54 @@ where
                 }
             }
 
-            WindowEvent::Resized(new_size) => {
-                self.engine.handle_window_resized(*new_size)?;
+            WindowEvent::Resized(new_size) => self.engine.handle_resize(*new_size)?,
+
+            // The physical size of the window follows the DPI of its monitor. winit only applies
+            // the new size once this event is handled, the window still has its old size here,
+            // then sends the `Resized` event which recreates the swapchain
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                self.frame_info.scale_factor = *scale_factor;
+            }
+
+            WindowEvent::Focused(focused) => {
+                self.focused = *focused;
+
+                if self.pause_on_unfocus {
+                    if *focused {
+                        // The time spent paused should not show up as a frame delta
+                        self.previous_frame_time = Instant::now();
+                        window_target.set_control_flow(ControlFlow::Poll);
+                    } else {
+                        window_target.set_control_flow(ControlFlow::Wait);
+                    }
+                }
             }
 
-            WindowEvent::RedrawRequested => self.engine.render_frame(),
+            WindowEvent::RedrawRequested if self.paused() => (),
+
+            WindowEvent::RedrawRequested => {
+                self.engine.render()?;
+
+                // The swapchain is only recreated when a frame is rendered, with the latest size
+                if let Some(new_size) = self.engine.take_applied_size() {
+                    self.runable.on_resize(&mut self.engine, new_size);
+                }
+            }
 
             _ => (),
         }
 
         Ok(())
     }
+
+    fn paused(&self) -> bool {
+        self.pause_on_unfocus && !self.focused
+    }
+}
+
+fn load_icon(path: &Path) -> Result<Icon> {
+    let image = image::open(path)?.into_rgba8();
+    let (width, height) = image.dimensions();
+
+    Ok(Icon::from_rgba(image.into_raw(), width, height)?)
 }
@@ -1,6 +1,6 @@
-use std::f32::consts::FRAC_PI_2;
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
 
-use glam::{Mat4, Vec3};
+use glam::{Mat3, Mat4, Quat, Vec3};
 use winit::{event::MouseButton, keyboard::KeyCode};
 
 use crate::engine::input_handler::InputHandler;
@@ -19,9 +19,25 @@ pub struct Camera3D {
 
     yaw: f32,
     pitch: f32,
+
+    /// Full orientation of the camera in free orientation mode, the yaw and pitch are then
+    /// ignored and the camera can roll and look straight up
+    orientation: Option<Quat>,
+
+    /// Vertical field of view in radians
+    fov: f32,
+    near: f32,
+    far: f32,
 }
 
 impl Camera3D {
+    pub const DEFAULT_FOV: f32 = 45.0 * PI / 180.0;
+    pub const DEFAULT_NEAR: f32 = 0.1;
+    pub const DEFAULT_FAR: f32 = 100.0;
+    /// Bound of the pitch in both directions, looking straight up or down would flip the camera
+    /// around the world up
+    pub const MAX_PITCH: f32 = FRAC_PI_2 - 0.1;
+
     pub fn new(position: Vec3, yaw: f32, pitch: f32, world_up: Vec3) -> Self {
         let world_up = world_up.normalize();
 
@@ -33,8 +49,14 @@ impl Camera3D {
 
             world_up,
 
-            yaw,
-            pitch,
+            yaw: wrap_angle(yaw),
+            pitch: pitch.clamp(-Self::MAX_PITCH, Self::MAX_PITCH),
+
+            orientation: None,
+
+            fov: Self::DEFAULT_FOV,
+            near: Self::DEFAULT_NEAR,
+            far: Self::DEFAULT_FAR,
         };
 
         camera.update_camera_vectors();
@@ -42,6 +64,11 @@ impl Camera3D {
         camera
     }
 
+    /// Builder naming each setting of the camera, see `Camera3DBuilder`
+    pub fn builder() -> Camera3DBuilder {
+        Camera3DBuilder::new()
+    }
+
     pub fn position(&self) -> Vec3 {
         self.position
     }
@@ -66,6 +93,25 @@ impl Camera3D {
         self.pitch
     }
 
+    /// Vertical field of view in radians
+    pub fn fov(&self) -> f32 {
+        self.fov
+    }
+
+    pub fn set_fov(&mut self, fov: f32) {
+        self.fov = fov;
+    }
+
+    /// Distances of the near and far clip planes
+    pub fn clip_planes(&self) -> (f32, f32) {
+        (self.near, self.far)
+    }
+
+    pub fn set_clip_planes(&mut self, near: f32, far: f32) {
+        self.near = near;
+        self.far = far;
+    }
+
     pub fn move_up(&mut self, amount: f32) {
         self.position += self.up * amount;
     }
@@ -106,56 +152,285 @@ impl Camera3D {
         self.position -= Vec3::new(self.front.x, 0.0, self.front.z).normalize() * amount;
     }
 
+    /// The yaw is kept within [-PI, PI] so that it does not lose precision over long sessions
     pub fn update_yaw(&mut self, amount: f32) {
-        self.yaw += amount;
+        self.yaw = wrap_angle(self.yaw + amount);
     }
 
+    /// The pitch is clamped to `MAX_PITCH`
     pub fn update_pitch(&mut self, amount: f32) {
-        self.pitch += amount;
+        self.pitch = (self.pitch + amount).clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
     }
 
+    /// Goes back to the yaw and pitch mode if the camera was in free orientation mode. The yaw is
+    /// wrapped into [-PI, PI]
     pub fn set_yaw(&mut self, yaw: f32) {
-        self.yaw = yaw;
+        self.yaw = wrap_angle(yaw);
+        self.orientation = None;
         self.update_camera_vectors();
     }
 
+    /// Goes back to the yaw and pitch mode if the camera was in free orientation mode. The pitch
+    /// is clamped to `MAX_PITCH`
     pub fn set_pitch(&mut self, pitch: f32) {
-        self.pitch = pitch;
+        self.pitch = pitch.clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+        self.orientation = None;
         self.update_camera_vectors();
     }
 
+    /// Goes back to the yaw and pitch mode if the camera was in free orientation mode, see
+    /// `set_yaw` and `set_pitch`
     pub fn set_pitch_and_yaw(&mut self, yaw: f32, pitch: f32) {
-        self.yaw = yaw;
-        self.pitch = pitch;
+        self.yaw = wrap_angle(yaw);
+        self.pitch = pitch.clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+        self.orientation = None;
         self.update_camera_vectors();
     }
 
-    pub(crate) fn get_view(&self) -> Mat4 {
+    pub fn is_free_orientation(&self) -> bool {
+        self.orientation.is_some()
+    }
+
+    /// Switches between the default yaw and pitch mode, where the camera stays level with the
+    /// world up, and the free orientation mode used by 6DOF cameras. The camera keeps looking in
+    /// the same direction, the roll is lost when leaving the free orientation mode
+    pub fn set_free_orientation(&mut self, free_orientation: bool) {
+        if free_orientation == self.is_free_orientation() {
+            return;
+        }
+
+        if free_orientation {
+            // The camera looks down its local -Z axis
+            let basis = Mat3::from_cols(self.right, self.up, -self.front);
+            self.orientation = Some(Quat::from_mat3(&basis).normalize());
+        } else {
+            self.pitch = self
+                .front
+                .y
+                .clamp(-1.0, 1.0)
+                .asin()
+                .clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+            self.yaw = self.front.z.atan2(self.front.x);
+            self.orientation = None;
+        }
+
+        self.update_camera_vectors();
+    }
+
+    /// Rotates the camera around `axis` given in its own space, X being right, Y up and -Z the
+    /// looking direction. Enables the free orientation mode, a zero axis leaves the camera
+    /// unchanged
+    pub fn rotate_local(&mut self, axis: Vec3, angle: f32) {
+        let Some(axis) = axis.try_normalize() else {
+            return;
+        };
+
+        self.set_free_orientation(true);
+
+        let orientation = self.orientation.unwrap();
+        let rotation = Quat::from_axis_angle(axis, angle);
+        self.orientation = Some((orientation * rotation).normalize());
+
+        self.update_camera_vectors();
+    }
+
+    /// Orientation of the camera, whichever mode it is in
+    pub fn orientation(&self) -> Quat {
+        self.orientation.unwrap_or_else(|| {
+            Quat::from_mat3(&Mat3::from_cols(self.right, self.up, -self.front)).normalize()
+        })
+    }
+
+    /// World to camera space matrix
+    pub fn view(&self) -> Mat4 {
         Mat4::look_at_rh(self.position, self.position + self.front, self.up)
     }
 
+    /// Perspective projection for a viewport of `aspect` width over height, to the clip space of
+    /// Vulkan: Y pointing down and a depth from 0 at the near plane to 1 at the far plane
+    pub fn projection(&self, aspect: f32) -> Mat4 {
+        self.projection_with_depth_range(aspect, false)
+    }
+
+    /// `projection` then `view`, takes world space positions to clip space
+    pub fn view_projection(&self, aspect: f32) -> Mat4 {
+        self.projection(aspect) * self.view()
+    }
+
+    /// Projection used by the renderer, the depth goes from 1 at the near plane to 0 at the far
+    /// plane when `reversed_z` is set
+    pub(crate) fn projection_with_depth_range(&self, aspect: f32, reversed_z: bool) -> Mat4 {
+        // Swapping the planes gives 1 minus the standard depth
+        let (near, far) = if reversed_z {
+            (self.far, self.near)
+        } else {
+            (self.near, self.far)
+        };
+        let mut projection = Mat4::perspective_rh(self.fov, aspect, near, far);
+        projection.y_axis.y *= -1.0;
+
+        projection
+    }
+
     fn update_camera_vectors(&mut self) {
+        if let Some(orientation) = self.orientation {
+            self.front = orientation * Vec3::NEG_Z;
+            self.right = orientation * Vec3::X;
+            self.up = orientation * Vec3::Y;
+            return;
+        }
+
         let front_y = self.pitch.sin();
 
         let pitch_cos = self.pitch.cos();
         let front_x = self.yaw.cos() * pitch_cos;
         let front_z = self.yaw.sin() * pitch_cos;
         self.front = Vec3::new(front_x, front_y, front_z).normalize();
-        self.right = self.front.cross(self.world_up).normalize();
-        self.up = self.right.cross(self.front);
+        (self.right, self.up) = orthonormal_basis(self.front, self.world_up);
+    }
+}
+
+/// Right and up unit vectors completing `front` into a right handed basis, with up on the side
+/// of `world_up`. When `front` is almost parallel to `world_up` the right vector can't be derived
+/// from it, the axis of `front` closest to perpendicular is used as reference instead
+pub fn orthonormal_basis(front: Vec3, world_up: Vec3) -> (Vec3, Vec3) {
+    let front = front.normalize();
+
+    let right = front.cross(world_up);
+    let right = if right.length_squared() > 1e-6 {
+        right.normalize()
+    } else {
+        front.cross(front.any_orthonormal_vector()).normalize()
+    };
+    let up = right.cross(front).normalize();
+
+    (right, up)
+}
+
+/// Same angle within [-PI, PI]
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(TAU) - PI
+}
+
+/// Chainable construction of a `Camera3D`, the settings left out keep their defaults: at the
+/// origin looking down -Z with a Y world up, and the projection of `Camera3D::new`
+#[derive(Debug, Clone, Copy)]
+pub struct Camera3DBuilder {
+    position: Vec3,
+    /// Overrides the yaw and pitch when set
+    target: Option<Vec3>,
+    yaw: f32,
+    pitch: f32,
+    fov: f32,
+    near: f32,
+    far: f32,
+    world_up: Vec3,
+}
+
+impl Camera3DBuilder {
+    pub fn new() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            target: None,
+            yaw: -FRAC_PI_2,
+            pitch: 0.0,
+            fov: Camera3D::DEFAULT_FOV,
+            near: Camera3D::DEFAULT_NEAR,
+            far: Camera3D::DEFAULT_FAR,
+            world_up: Vec3::Y,
+        }
+    }
+
+    pub fn position(self, position: Vec3) -> Self {
+        Self { position, ..self }
+    }
+
+    /// Points the camera at `target` from its position, replacing the yaw and pitch
+    pub fn look_at(self, target: Vec3) -> Self {
+        Self {
+            target: Some(target),
+            ..self
+        }
+    }
+
+    pub fn yaw(self, yaw: f32) -> Self {
+        Self {
+            yaw,
+            target: None,
+            ..self
+        }
+    }
+
+    pub fn pitch(self, pitch: f32) -> Self {
+        Self {
+            pitch,
+            target: None,
+            ..self
+        }
+    }
+
+    /// Vertical field of view in radians
+    pub fn fov(self, fov: f32) -> Self {
+        Self { fov, ..self }
+    }
+
+    pub fn clip_planes(self, near: f32, far: f32) -> Self {
+        Self { near, far, ..self }
+    }
+
+    pub fn world_up(self, world_up: Vec3) -> Self {
+        Self { world_up, ..self }
+    }
+
+    pub fn build(self) -> Camera3D {
+        let (yaw, pitch) = match self.target {
+            // Same angles as `Camera3D::set_free_orientation` reads back from a direction
+            Some(target) => {
+                let front = (target - self.position).normalize();
+                (front.z.atan2(front.x), front.y.clamp(-1.0, 1.0).asin())
+            }
+            None => (self.yaw, self.pitch),
+        };
+
+        let mut camera = Camera3D::new(self.position, yaw, pitch, self.world_up);
+        camera.set_fov(self.fov);
+        camera.set_clip_planes(self.near, self.far);
+
+        camera
+    }
+}
+
+impl Default for Camera3DBuilder {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 pub struct DebugCamera3DController {
     camera_speed: f32,
     mouse_sensitivity: f32,
+    /// Time in seconds for the motion to catch up with the input, 0 applies it instantly
+    smoothing: f32,
+    /// Current velocity along the right, up and forwards axes of the camera, up and forwards are
+    /// levelled with the world up outside of the free orientation mode
+    velocity: Vec3,
+    /// Yaw and pitch the camera is turning towards when smoothing
+    target_rotation: Option<(f32, f32)>,
 }
 
 impl DebugCamera3DController {
+    /// Factor applied to the speed per scroll wheel line
+    const SCROLL_SPEED_FACTOR: f32 = 1.2;
+    const MIN_SCROLL_SPEED: f32 = 0.1;
+    const MAX_SCROLL_SPEED: f32 = 1000.0;
+
     pub fn new() -> Self {
         Self {
             camera_speed: 10.0,
             mouse_sensitivity: 0.3,
+            smoothing: 0.0,
+            velocity: Vec3::ZERO,
+            target_rotation: None,
         }
     }
 
@@ -166,40 +441,221 @@ impl DebugCamera3DController {
     pub fn set_mouse_sensitivity(&mut self, mouse_sensitivity: f32) {
         self.mouse_sensitivity = mouse_sensitivity;
     }
+
+    /// Damps the movement and rotation of the camera, `smoothing` is roughly the time in seconds
+    /// the camera takes to reach the speed or orientation asked by the input. 0 disables it
+    pub fn set_smoothing(&mut self, smoothing: f32) {
+        self.smoothing = smoothing.max(0.0);
+    }
+
+    /// Fraction of the remaining distance to the target covered this frame, independent of the
+    /// frame rate
+    fn smoothing_step(&self, delta_time: f32) -> f32 {
+        if self.smoothing == 0.0 {
+            return 1.0;
+        }
+
+        1.0 - (-delta_time / self.smoothing).exp()
+    }
 }
 
 impl Camera3DController for DebugCamera3DController {
     fn update_camera(&mut self, input: &InputHandler, camera: &mut Camera3D, delta_time: f32) {
+        // Scrolling up speeds the camera up, scrolling down slows it down for detail work
+        let scroll = input.scroll_delta();
+        if scroll != 0.0 {
+            self.camera_speed = (self.camera_speed * Self::SCROLL_SPEED_FACTOR.powf(scroll))
+                .clamp(Self::MIN_SCROLL_SPEED, Self::MAX_SCROLL_SPEED);
+        }
+
+        let mut direction = Vec3::ZERO;
         if input.key_held(KeyCode::KeyW) {
-            camera.move_xz_forwards(self.camera_speed * delta_time);
+            direction.z += 1.0;
         }
         if input.key_held(KeyCode::KeyS) {
-            camera.move_xz_backwards(self.camera_speed * delta_time);
+            direction.z -= 1.0;
         }
         if input.key_held(KeyCode::KeyA) {
-            camera.move_left(self.camera_speed * delta_time);
+            direction.x -= 1.0;
         }
         if input.key_held(KeyCode::KeyD) {
-            camera.move_right(self.camera_speed * delta_time);
+            direction.x += 1.0;
         }
         if input.key_held(KeyCode::Space) {
-            camera.move_world_up(self.camera_speed * delta_time);
+            direction.y += 1.0;
         }
         if input.key_held(KeyCode::ControlLeft) {
-            camera.move_world_down(self.camera_speed * delta_time);
+            direction.y -= 1.0;
         }
 
+        let step = self.smoothing_step(delta_time);
+
+        let target_velocity = direction * self.camera_speed;
+        self.velocity = self.velocity.lerp(target_velocity, step);
+
+        camera.move_right(self.velocity.x * delta_time);
+        if camera.is_free_orientation() {
+            camera.move_up(self.velocity.y * delta_time);
+            camera.move_forwards(self.velocity.z * delta_time);
+        } else {
+            camera.move_world_up(self.velocity.y * delta_time);
+            camera.move_xz_forwards(self.velocity.z * delta_time);
+        }
+
+        // Free orientation cameras turn around their own axes and are not smoothed
+        if camera.is_free_orientation() {
+            self.target_rotation = None;
+            if input.mouse_held(MouseButton::Left) {
+                let (mouse_diff_x, mouse_diff_y) = input.mouse_diff();
+
+                camera.rotate_local(Vec3::Y, -mouse_diff_x * self.mouse_sensitivity * delta_time);
+                camera.rotate_local(Vec3::X, -mouse_diff_y * self.mouse_sensitivity * delta_time);
+            }
+            return;
+        }
+
+        let (mut yaw, mut pitch) = self
+            .target_rotation
+            .unwrap_or((camera.yaw(), camera.pitch()));
+
         if input.mouse_held(MouseButton::Left) {
             let (mouse_diff_x, mouse_diff_y) = input.mouse_diff();
-            let mut yaw = camera.yaw();
-            let mut pitch = camera.pitch();
 
             yaw += mouse_diff_x * self.mouse_sensitivity * delta_time;
             pitch -= mouse_diff_y * self.mouse_sensitivity * delta_time;
 
-            pitch = pitch.clamp(-FRAC_PI_2 + 0.1, FRAC_PI_2 - 0.1);
+            yaw = wrap_angle(yaw);
+            pitch = pitch.clamp(-Camera3D::MAX_PITCH, Camera3D::MAX_PITCH);
+        }
 
+        if self.smoothing == 0.0 {
+            self.target_rotation = None;
             camera.set_pitch_and_yaw(yaw, pitch);
+            return;
+        }
+
+        self.target_rotation = Some((yaw, pitch));
+        // The shortest way around, the target may be on the other side of the wrapping point
+        camera.set_pitch_and_yaw(
+            camera.yaw() + wrap_angle(yaw - camera.yaw()) * step,
+            camera.pitch() + (pitch - camera.pitch()) * step,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_look_at_faces_the_target() {
+        let position = Vec3::new(1.0, 2.0, 3.0);
+        let target = Vec3::new(-2.0, 0.0, 1.0);
+        let camera = Camera3D::builder()
+            .position(position)
+            .look_at(target)
+            .build();
+
+        let expected = (target - position).normalize();
+        assert!(camera.front().abs_diff_eq(expected, 1e-5));
+        assert_eq!(camera.position(), position);
+    }
+
+    #[test]
+    fn view_projection_maps_the_clip_planes() {
+        let camera = Camera3D::builder()
+            .position(Vec3::new(0.0, 0.0, 5.0))
+            .look_at(Vec3::ZERO)
+            .clip_planes(1.0, 10.0)
+            .build();
+        let view_projection = camera.view_projection(1.0);
+
+        let near = view_projection.project_point3(Vec3::new(0.0, 0.0, 4.0));
+        let far = view_projection.project_point3(Vec3::new(0.0, 0.0, -5.0));
+        assert!(near.abs_diff_eq(Vec3::ZERO, 1e-5));
+        assert!(far.abs_diff_eq(Vec3::Z, 1e-5));
+
+        // Vulkan's clip space points Y down
+        let above = view_projection.project_point3(Vec3::new(0.0, 1.0, 0.0));
+        assert!(above.y < 0.0);
+    }
+
+    #[test]
+    fn yaw_wraps_and_pitch_clamps() {
+        let mut camera = Camera3D::new(Vec3::ZERO, 0.0, 0.0, Vec3::Y);
+        for _ in 0..1000 {
+            camera.update_yaw(1.0);
         }
+        assert!((-PI..=PI).contains(&camera.yaw()));
+        assert!((wrap_angle(1000.0) - camera.yaw()).abs() < 1e-3);
+
+        camera.set_yaw(3.0 * PI / 2.0);
+        assert!((camera.yaw() + PI / 2.0).abs() < 1e-5);
+
+        camera.set_pitch(PI);
+        assert_eq!(camera.pitch(), Camera3D::MAX_PITCH);
+    }
+
+    #[test]
+    fn orthonormal_basis_handles_front_along_world_up() {
+        for front in [Vec3::new(1.0, 2.0, -3.0), Vec3::Y, Vec3::NEG_Y] {
+            let (right, up) = orthonormal_basis(front, Vec3::Y);
+            let front = front.normalize();
+
+            assert!(right.is_finite() && up.is_finite());
+            assert!((right.length() - 1.0).abs() < 1e-5);
+            assert!((up.length() - 1.0).abs() < 1e-5);
+            assert!(right.dot(front).abs() < 1e-5);
+            assert!(up.dot(front).abs() < 1e-5);
+            assert!(right.dot(up).abs() < 1e-5);
+        }
+
+        // Looking down +X with X as world up
+        let camera = Camera3D::new(Vec3::ZERO, 0.0, 0.0, Vec3::X);
+        assert!(camera.view().is_finite());
+    }
+
+    #[test]
+    fn free_orientation_keeps_the_look_direction() {
+        let mut camera = Camera3D::new(Vec3::ZERO, 0.3, 0.4, Vec3::Y);
+        let (front, right, up) = (camera.front(), camera.right(), camera.up());
+
+        camera.set_free_orientation(true);
+        assert!(camera.is_free_orientation());
+        assert!(camera.front().abs_diff_eq(front, 1e-5));
+        assert!(camera.right().abs_diff_eq(right, 1e-5));
+        assert!(camera.up().abs_diff_eq(up, 1e-5));
+
+        camera.set_free_orientation(false);
+        assert!(!camera.is_free_orientation());
+        assert!(camera.front().abs_diff_eq(front, 1e-5));
+        assert!((camera.yaw() - 0.3).abs() < 1e-5);
+        assert!((camera.pitch() - 0.4).abs() < 1e-5);
+    }
+
+    #[test]
+    fn rotate_local_rolls_around_the_view_axis() {
+        let mut camera = Camera3D::new(Vec3::ZERO, 0.3, 0.4, Vec3::Y);
+        let (front, right, up) = (camera.front(), camera.right(), camera.up());
+
+        // A quarter turn around the looking direction, clockwise as seen by the camera
+        camera.rotate_local(Vec3::NEG_Z, FRAC_PI_2);
+
+        assert!(camera.is_free_orientation());
+        assert!(camera.front().abs_diff_eq(front, 1e-5));
+        assert!(camera.right().abs_diff_eq(-up, 1e-5));
+        assert!(camera.up().abs_diff_eq(right, 1e-5));
+    }
+
+    #[test]
+    fn rotate_local_ignores_a_zero_axis() {
+        let mut camera = Camera3D::new(Vec3::ZERO, 0.3, 0.4, Vec3::Y);
+        camera.rotate_local(Vec3::X, 0.5);
+        let orientation = camera.orientation();
+
+        camera.rotate_local(Vec3::ZERO, 1.0);
+
+        assert!(camera.orientation().is_finite());
+        assert!(camera.orientation().abs_diff_eq(orientation, 1e-6));
     }
 }
@@ -1,7 +1,12 @@
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
-use self::{ecs::Scene, renderer::Renderer};
+use self::{
+    ecs::{Entity, Scene},
+    mesh::loader::MeshHandle,
+    renderer::{frame_graph::RenderPassNode, Background, RenderMode, Renderer, RendererInfo},
+};
 
+pub mod color;
 pub mod ecs;
 pub mod input_handler;
 pub mod material;
@@ -9,35 +14,73 @@ pub mod mesh;
 pub mod renderer;
 pub mod transform;
 
+mod compute;
+mod debug_draw;
 mod pipeline_manager;
+mod sprite_renderer;
+mod text_renderer;
+mod texture;
 
-use crate::vulkan_context::VulkanContext;
+use crate::{
+    camera::Camera3D,
+    vulkan_context::{MemoryReport, VulkanContext},
+};
 
 use anyhow::{Ok, Result};
+use glam::{Vec2, Vec3};
+use vulkano::{
+    descriptor_set::PersistentDescriptorSet,
+    pipeline::{
+        graphics::{
+            rasterization::{CullMode, FrontFace},
+            viewport::{Scissor, Viewport},
+        },
+        ComputePipeline,
+    },
+};
 use winit::{dpi::PhysicalSize, window::Window};
 
+#[cfg(feature = "egui")]
+use egui_winit_vulkano::egui;
+#[cfg(feature = "egui")]
+use winit::{event::WindowEvent, event_loop::EventLoopWindowTarget};
+
 pub struct Engine {
     vulkan_context: Arc<VulkanContext>,
     renderer: Renderer,
     scene: Scene,
+    views: Vec<(Camera3D, Viewport, Scissor)>,
 }
 
 impl Engine {
-    pub(crate) fn new(vulkan_context: Arc<VulkanContext>, window: Arc<Window>) -> Result<Self> {
-        let scene = Scene::new(Arc::clone(&vulkan_context));
+    pub(crate) fn new(
+        vulkan_context: Arc<VulkanContext>,
+        window: Arc<Window>,
+        renderer_info: &RendererInfo,
+        scene_capacity: usize,
+    ) -> Result<Self> {
+        let scene = Scene::with_capacity(Arc::clone(&vulkan_context), scene_capacity);
         let renderer = Renderer::new(
             Arc::clone(&vulkan_context),
             window,
             scene.material_manager(),
+            renderer_info,
         )?;
 
         Ok(Self {
             vulkan_context,
             renderer,
             scene,
+            views: Vec::new(),
         })
     }
 
+    /// Creates an engine rendering into a window owned by the caller, for use inside an existing
+    /// winit event loop instead of the `Application` harness
+    pub fn new_for_window(vulkan_context: Arc<VulkanContext>, window: Arc<Window>) -> Result<Self> {
+        Self::new(vulkan_context, window, &RendererInfo::default(), 0)
+    }
+
     pub fn scene(&self) -> &Scene {
         &self.scene
     }
@@ -46,21 +89,477 @@ impl Engine {
         &mut self.scene
     }
 
-    pub(crate) fn vulkan_context(&self) -> &VulkanContext {
+    pub fn renderer(&self) -> &Renderer {
+        &self.renderer
+    }
+
+    /// Fails for `RenderMode::Wireframe` if the device does not support the
+    /// `fill_mode_non_solid` feature, see `wireframe_supported`
+    pub fn set_render_mode(&mut self, render_mode: RenderMode) -> Result<()> {
+        self.renderer.set_render_mode(render_mode)
+    }
+
+    pub fn render_mode(&self) -> RenderMode {
+        self.renderer.render_mode()
+    }
+
+    /// Time and frame index read by the material shaders from the frame uniform block. Updated by
+    /// the `Application` every frame, to be called before `render` when driving the engine from
+    /// a custom event loop
+    pub fn set_frame_time(&mut self, total_time: f32, frame_index: u64) {
+        self.renderer.set_frame_time(total_time, frame_index);
+    }
+
+    /// Sets what the scene is drawn over, a solid color by default
+    pub fn set_background(&mut self, background: Background) {
+        self.renderer.set_background(background);
+    }
+
+    pub fn background(&self) -> Background {
+        self.renderer.background()
+    }
+
+    /// Draws the edges of the meshes over the current render mode, for a shaded plus wireframe
+    /// look. The lines use the width set with `set_line_width`. Fails if the device does not
+    /// support the `fill_mode_non_solid` feature
+    pub fn set_wireframe_overlay(&mut self, wireframe_overlay: bool) -> Result<()> {
+        self.renderer.set_wireframe_overlay(wireframe_overlay)
+    }
+
+    pub fn wireframe_overlay(&self) -> bool {
+        self.renderer.wireframe_overlay()
+    }
+
+    /// Whether the wireframe render mode and overlay can be used, they need the
+    /// `fill_mode_non_solid` device feature
+    pub fn wireframe_supported(&self) -> bool {
+        self.renderer.wireframe_supported()
+    }
+
+    /// Clears the color and depth of the next frame to the background before drawing it. Only
+    /// needed when `RendererInfo` loads the previous content of the attachments instead of
+    /// clearing them, gradient and image backgrounds still cover the whole frame
+    pub fn clear_next_frame(&mut self) {
+        self.renderer.clear_next_frame();
+    }
+
+    /// Presents a frame filled with the solid background color without drawing the scene, e.g.
+    /// while a loading screen waits for its assets. Replaces `render` for this frame
+    pub fn clear_screen(&mut self) -> Result<()> {
+        self.renderer.clear_screen()
+    }
+
+    /// Draws the whole scene with one indirect multi draw per material instead of one draw call
+    /// per mesh, only affects the default render mode. Fails if the device does not support the
+    /// `multi_draw_indirect` and `draw_indirect_first_instance` features
+    pub fn set_indirect_drawing(&mut self, indirect_drawing: bool) -> Result<()> {
+        self.renderer.set_indirect_drawing(indirect_drawing)
+    }
+
+    pub fn indirect_drawing(&self) -> bool {
+        self.renderer.indirect_drawing()
+    }
+
+    /// Sets which triangle winding is considered front facing, triangles facing away are culled.
+    /// The engine's primitives are clockwise, models from other tools are often counter-clockwise
+    pub fn set_front_face(&mut self, front_face: FrontFace) -> Result<()> {
+        self.renderer.set_front_face(front_face)
+    }
+
+    pub fn front_face(&self) -> FrontFace {
+        self.renderer.front_face()
+    }
+
+    /// Swaps the front facing winding, handy to check if missing geometry is being culled
+    pub fn flip_front_face(&mut self) -> Result<()> {
+        let front_face = match self.front_face() {
+            FrontFace::Clockwise => FrontFace::CounterClockwise,
+            FrontFace::CounterClockwise => FrontFace::Clockwise,
+        };
+
+        self.set_front_face(front_face)
+    }
+
+    /// Sets which faces are culled, `CullMode::None` shows both sides of the triangles which helps
+    /// debugging inside-out or thin geometry
+    pub fn set_cull_mode(&mut self, cull_mode: CullMode) -> Result<()> {
+        self.renderer.set_cull_mode(cull_mode)
+    }
+
+    pub fn cull_mode(&self) -> CullMode {
+        self.renderer.cull_mode()
+    }
+
+    /// Draws the depth of the meshes before shading them so that hidden fragments are never
+    /// shaded, which helps scenes where many meshes overlap on screen. Works with multisampling,
+    /// only affects the default render mode without indirect drawing
+    pub fn set_depth_prepass(&mut self, depth_prepass: bool) -> Result<()> {
+        self.renderer.set_depth_prepass(depth_prepass)
+    }
+
+    pub fn depth_prepass(&self) -> bool {
+        self.renderer.depth_prepass()
+    }
+
+    /// Names of the nodes recorded every frame, in order. A new engine runs the "depth prepass",
+    /// "scene", "occlusion queries" and "post process" nodes
+    pub fn render_node_names(&self) -> Vec<&'static str> {
+        self.renderer.render_node_names()
+    }
+
+    /// Records `node` at `index` in the nodes of every frame. Fails when a node has the same name
+    /// or when the nodes recording within the scene render pass would no longer be consecutive
+    pub fn insert_render_node(
+        &mut self,
+        index: usize,
+        node: Box<dyn RenderPassNode>,
+    ) -> Result<()> {
+        self.renderer.insert_render_node(index, node)
+    }
+
+    /// Stops recording the node named `name`, returned so that it can be inserted again. Fails
+    /// when there is no such node or when it is the last node drawing in the scene render pass
+    pub fn remove_render_node(&mut self, name: &str) -> Result<Box<dyn RenderPassNode>> {
+        self.renderer.remove_render_node(name)
+    }
+
+    /// Moves the node named `name` to `index`, failing like `insert_render_node`
+    pub fn move_render_node(&mut self, name: &str, index: usize) -> Result<()> {
+        self.renderer.move_render_node(name, index)
+    }
+
+    /// Clears the depth to 0 and draws the near plane at a depth of 1, which keeps much more
+    /// depth precision far from the camera and avoids z-fighting in large scenes. Depth biases of
+    /// the materials keep working the same way
+    pub fn set_reversed_z(&mut self, reversed_z: bool) -> Result<()> {
+        self.renderer.set_reversed_z(reversed_z)
+    }
+
+    pub fn reversed_z(&self) -> bool {
+        self.renderer.reversed_z()
+    }
+
+    /// Renders the scene from several cameras, each into its own viewport of the window. An empty
+    /// list goes back to rendering the scene camera over the whole window
+    pub fn set_views(&mut self, views: Vec<(Camera3D, Viewport, Scissor)>) {
+        self.views = views;
+    }
+
+    pub fn views(&self) -> &[(Camera3D, Viewport, Scissor)] {
+        &self.views
+    }
+
+    /// Width in pixels of the debug lines and of the wireframe render mode, falls back to 1.0 on
+    /// devices without wide lines support
+    pub fn set_line_width(&mut self, line_width: f32) {
+        self.renderer.set_line_width(line_width);
+    }
+
+    pub fn line_width(&self) -> f32 {
+        self.renderer.line_width()
+    }
+
+    /// Records the meshes of the scene on `threads` worker threads into secondary command buffers,
+    /// for scenes with many meshes. The threads are started for each frame, 1 records
+    /// everything on the main thread, like 0. A frame fails if its threads can't be started
+    pub fn set_parallel_recording(&mut self, threads: usize) {
+        self.renderer.set_parallel_recording(threads);
+    }
+
+    pub fn parallel_recording(&self) -> usize {
+        self.renderer.parallel_recording()
+    }
+
+    /// Counts the samples of a box drawn around the mesh of every entity after the scene to
+    /// estimate which entities are visible, see `entity_visible`. Disabling the queries disables
+    /// the occlusion culling
+    pub fn set_occlusion_queries(&mut self, occlusion_queries: bool) {
+        self.renderer.set_occlusion_queries(occlusion_queries);
+    }
+
+    pub fn occlusion_queries(&self) -> bool {
+        self.renderer.occlusion_queries()
+    }
+
+    /// Skips the meshes of the entities found occluded by the occlusion queries, which it enables.
+    /// Their boxes are still tested every frame so that they are drawn again once they come into
+    /// view, a few frames late as the estimate lags behind the scene
+    pub fn set_occlusion_culling(&mut self, occlusion_culling: bool) {
+        self.renderer.set_occlusion_culling(occlusion_culling);
+    }
+
+    pub fn occlusion_culling(&self) -> bool {
+        self.renderer.occlusion_culling()
+    }
+
+    /// Whether any sample of the box around the entity's mesh passed the depth test in any view,
+    /// `None` when the entity was not tested. The estimate lags a few frames behind the scene, and
+    /// a mesh crossing the near plane of every view is not tested
+    pub fn entity_visible(&self, entity: Entity) -> Option<bool> {
+        self.renderer.entity_visible(entity)
+    }
+
+    /// Starts loading a Wavefront OBJ file on a worker thread, poll the handle every frame until
+    /// the mesh is ready
+    pub fn load_mesh_async(&self, path: impl AsRef<Path>) -> MeshHandle {
+        MeshHandle::load_obj(path.as_ref().to_path_buf())
+    }
+
+    /// Draws a line for the current frame only
+    pub fn debug_line(&mut self, a: Vec3, b: Vec3, color: Vec3) {
+        self.renderer.debug_draw_mut().line(a, b, color);
+    }
+
+    /// Draws the edges of an axis aligned bounding box for the current frame only
+    pub fn debug_aabb(&mut self, min: Vec3, max: Vec3, color: Vec3) {
+        self.renderer.debug_draw_mut().aabb(min, max, color);
+    }
+
+    /// Loads the font used by `draw_text`, rasterized at `pixel_size`. Fails if the glyphs are too
+    /// large for the glyph atlas
+    pub fn load_font(&mut self, path: impl AsRef<Path>, pixel_size: f32) -> Result<()> {
+        self.renderer
+            .text_renderer_mut()
+            .load_font(&self.vulkan_context, path.as_ref(), pixel_size)
+    }
+
+    /// Loads an image to be drawn by `Sprite` components and returns the id they reference it
+    /// with. The image is treated as sRGB with straight alpha
+    pub fn load_sprite_texture(&mut self, path: impl AsRef<Path>) -> Result<u64> {
+        self.renderer
+            .sprite_renderer_mut()
+            .load_texture(&self.vulkan_context, path.as_ref())
+    }
+
+    /// Draws text for the current frame only, `position` is the top left corner of the text in
+    /// pixels. Does nothing until a font has been loaded with `load_font`
+    pub fn draw_text(&mut self, text: &str, position: Vec2, scale: f32, color: Vec3) {
+        self.renderer
+            .text_renderer_mut()
+            .queue_text(text, position, scale, color);
+    }
+
+    /// Memory heaps of the device and how much of them the application uses, for displaying
+    /// memory usage
+    pub fn memory_report(&self) -> MemoryReport {
+        self.vulkan_context.memory_report()
+    }
+
+    /// Creates a compute pipeline from SPIR-V, the descriptor sets of its layout are reflected
+    /// from the shader
+    pub fn load_compute_pipeline(&self, spirv: &[u32]) -> Result<Arc<ComputePipeline>> {
+        pipeline_manager::load_compute(
+            self.vulkan_context.device(),
+            self.vulkan_context.pipeline_cache(),
+            spirv,
+        )
+    }
+
+    /// Creates a compute pipeline from SPIR-V reading the descriptor set of a material at set 0,
+    /// to run with `dispatch_material_compute`. The shader may read and write the storage buffers
+    /// of `Material::storage_buffers` from binding 1, it fails to load if it reads anything else
+    pub fn load_material_compute_pipeline(&self, spirv: &[u32]) -> Result<Arc<ComputePipeline>> {
+        let device = self.vulkan_context.device();
+        let compute_shader = pipeline_manager::load_user_shader(device, spirv)?;
+
+        pipeline_manager::load_material_compute(
+            device,
+            self.vulkan_context.pipeline_cache(),
+            Arc::clone(self.scene.material_manager().material_set_layout()),
+            compute_shader,
+        )
+    }
+
+    /// Runs a pipeline of `load_material_compute_pipeline` with the descriptor set of `material`
+    /// and waits for it to complete, the next frames draw with the storage buffers it wrote
+    pub fn dispatch_material_compute(
+        &self,
+        pipeline: &Arc<ComputePipeline>,
+        material: u64,
+        group_counts: [u32; 3],
+    ) -> Result<()> {
+        let descriptor_set = self.scene.material_manager().descriptor_set(material);
+
+        compute::dispatch(
+            &self.vulkan_context,
+            pipeline,
+            Arc::clone(descriptor_set),
+            group_counts,
+        )
+    }
+
+    /// Runs `pipeline` with `descriptor_set` bound at set 0 and waits for it to complete
+    pub fn dispatch_compute(
+        &self,
+        pipeline: &Arc<ComputePipeline>,
+        descriptor_set: Arc<PersistentDescriptorSet>,
+        group_counts: [u32; 3],
+    ) -> Result<()> {
+        compute::dispatch(&self.vulkan_context, pipeline, descriptor_set, group_counts)
+    }
+
+    /// Gives access to the device and allocators, needed to create the buffers and descriptor
+    /// sets used by compute shaders
+    pub fn vulkan_context(&self) -> &VulkanContext {
         &self.vulkan_context
     }
 
-    pub(crate) fn handle_window_resized(&mut self, new_size: PhysicalSize<u32>) -> Result<()> {
-        self.renderer.resize(new_size)?;
+    /// Recreates the swapchain for the new window size, to be called on `WindowEvent::Resized`
+    /// when driving the engine from a custom event loop. A scale factor change is followed by a
+    /// `Resized` event once the window has its new size. The swapchain is recreated once before
+    /// the next frame with the latest size, however many resize events were received
+    pub fn handle_resize(&mut self, new_size: PhysicalSize<u32>) -> Result<()> {
+        self.renderer.request_resize(new_size);
         Ok(())
     }
 
+    /// Waits for the GPU to finish the submitted frames, to be called when the event loop exits
+    /// when driving the engine from a custom event loop. Dropping the engine also waits, but can't
+    /// report errors
+    pub fn shutdown(&mut self) -> Result<()> {
+        self.renderer.wait_idle()
+    }
+
+    #[cfg(feature = "egui")]
+    pub(crate) fn init_gui<T>(&mut self, event_loop: &EventLoopWindowTarget<T>) {
+        self.renderer.init_gui(event_loop);
+    }
+
+    #[cfg(feature = "egui")]
+    pub(crate) fn gui_update(&mut self, window_event: &WindowEvent) {
+        self.renderer.gui_update(window_event);
+    }
+
+    #[cfg(feature = "egui")]
+    pub(crate) fn gui_frame(&mut self, build_ui: impl FnOnce(&egui::Context)) {
+        self.renderer.gui_frame(build_ui);
+    }
+
     pub(crate) fn suspend(&self) {}
 
     pub(crate) fn resume(&self, _window: Arc<Window>) {}
 
-    pub(crate) fn render_frame(&mut self) {
-        debug_assert!(self.scene.camera().is_some());
-        let _ = self.renderer.render_scene(&self.scene);
+    /// Renders the scene and presents it to the window, to be called on
+    /// `WindowEvent::RedrawRequested` when driving the engine from a custom event loop. Until the
+    /// scene has a camera only the background and the overlays are drawn. The scene events not
+    /// drained before the call are dropped
+    pub fn render(&mut self) -> Result<()> {
+        let result = if !self.views.is_empty() {
+            self.renderer.render_scene_multi(&self.scene, &self.views)
+        } else {
+            self.renderer.render_scene(&self.scene)
+        };
+        self.scene.clear_events();
+
+        result
+    }
+
+    /// Size the last frames recreated the swapchain at, if it changed since the last call
+    pub(crate) fn take_applied_size(&mut self) -> Option<PhysicalSize<u32>> {
+        self.renderer.take_applied_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use winit::{event_loop::EventLoop, window::WindowBuilder};
+
+    use super::*;
+    use crate::{
+        engine::{
+            ecs::components::MeshComponent, material::simple_material::SimpleMaterial,
+            mesh::primitives, transform::Transform,
+        },
+        vulkan_context::VulkanContextInfo,
+    };
+
+    fn create_engine() -> Engine {
+        let window = Arc::new(
+            WindowBuilder::new()
+                .build(&EventLoop::new().unwrap())
+                .unwrap(),
+        );
+        let vulkan_context = VulkanContext::new(&window, VulkanContextInfo::default()).unwrap();
+
+        Engine::new_for_window(Arc::new(vulkan_context), window).unwrap()
+    }
+
+    fn look_at_origin(engine: &mut Engine) {
+        engine.scene_mut().set_camera(Camera3D::new(
+            Vec3::new(0.0, 1.0, 5.0),
+            -FRAC_PI_2,
+            -0.2,
+            Vec3::Y,
+        ));
+    }
+
+    #[test]
+    fn every_render_mode_records_a_frame() {
+        let mut engine = create_engine();
+        let cubes = [
+            primitives::make_sharp_cube(&engine).unwrap(),
+            primitives::make_sharp_cube(&engine).unwrap(),
+        ];
+
+        let scene = engine.scene_mut();
+        let material = scene.new_material(SimpleMaterial::new(0.8, 0.2, 0.2).with_ambient(0.1));
+        // One of the cubes binds the default entity set, the other its own uniform rewritten
+        // every frame
+        for (cube, (x, uniform)) in cubes.into_iter().zip([(-1.0, None), (1.0, Some(0.5f32))]) {
+            let entity = scene.spawn_entity();
+            scene.entity_add_component(
+                entity,
+                MeshComponent {
+                    mesh: cube,
+                    model: *Transform::new().translate(Vec3::new(x, 0.0, 0.0)),
+                    material,
+                    tint: Vec3::ONE,
+                    submeshes: Vec::new(),
+                },
+            );
+            if let Some(uniform) = uniform {
+                scene.entity_set_uniform(entity, uniform);
+            }
+        }
+        look_at_origin(&mut engine);
+
+        let mut render_modes = vec![
+            RenderMode::Default,
+            RenderMode::NormalView,
+            RenderMode::DepthView,
+            RenderMode::VertexColor,
+        ];
+        if engine.wireframe_supported() {
+            render_modes.push(RenderMode::Wireframe);
+        }
+
+        for render_mode in render_modes {
+            engine.set_render_mode(render_mode).unwrap();
+            engine.debug_line(Vec3::ZERO, Vec3::X, Vec3::ONE);
+            engine.render().unwrap();
+        }
+
+        engine.set_render_mode(RenderMode::Default).unwrap();
+        engine.set_depth_prepass(true).unwrap();
+        engine.render().unwrap();
+    }
+
+    /// Pipelines without material push constants once left the fragment range of the shared
+    /// layout unset
+    #[test]
+    fn frame_without_meshes_is_recorded() {
+        let mut engine = create_engine();
+        look_at_origin(&mut engine);
+        engine.set_background(Background::Gradient {
+            top: Vec3::ONE,
+            bottom: Vec3::ZERO,
+        });
+
+        engine.debug_line(Vec3::ZERO, Vec3::X, Vec3::ONE);
+        engine.debug_aabb(Vec3::NEG_ONE, Vec3::ONE, Vec3::Y);
+
+        engine.render().unwrap();
     }
 }
@@ -0,0 +1,65 @@
+//! Colors given to the engine (materials, clear color, debug lines, text) are in linear space.
+//! The swapchain uses an sRGB format so the hardware encodes the shaded colors when writing them,
+//! colors picked in an image editor or a color picker are sRGB and must be converted first
+
+use glam::Vec3;
+
+/// Converts an sRGB encoded channel in `[0, 1]` to linear space
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts a linear channel in `[0, 1]` to sRGB encoding
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Linear color from sRGB encoded channels
+pub fn srgb(r: f32, g: f32, b: f32) -> Vec3 {
+    Vec3::new(srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mid_gray_is_darker_in_linear_space() {
+        assert!((srgb_to_linear(0.5) - 0.21404).abs() < 1e-4);
+    }
+
+    #[test]
+    fn black_and_white_are_unchanged() {
+        for value in [0.0, 1.0] {
+            assert!((srgb_to_linear(value) - value).abs() < 1e-6);
+            assert!((linear_to_srgb(value) - value).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn linear_segment_threshold_round_trips() {
+        const SRGB_THRESHOLD: f32 = 0.04045;
+        const LINEAR_THRESHOLD: f32 = 0.0031308;
+
+        assert!((srgb_to_linear(SRGB_THRESHOLD) - LINEAR_THRESHOLD).abs() < 1e-6);
+        assert!((linear_to_srgb(LINEAR_THRESHOLD) - SRGB_THRESHOLD).abs() < 1e-5);
+        assert!((linear_to_srgb(srgb_to_linear(SRGB_THRESHOLD)) - SRGB_THRESHOLD).abs() < 1e-5);
+        assert!((srgb_to_linear(linear_to_srgb(LINEAR_THRESHOLD)) - LINEAR_THRESHOLD).abs() < 1e-6);
+    }
+
+    #[test]
+    fn conversions_round_trip() {
+        for i in 0..=10 {
+            let value = i as f32 / 10.0;
+            assert!((linear_to_srgb(srgb_to_linear(value)) - value).abs() < 1e-5);
+        }
+    }
+}
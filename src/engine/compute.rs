@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage},
+    descriptor_set::PersistentDescriptorSet,
+    pipeline::{ComputePipeline, Pipeline, PipelineBindPoint},
+    sync::{self, GpuFuture},
+};
+
+use crate::vulkan_context::VulkanContext;
+
+/// Records a single dispatch and submits it to the compute queue. Waits for the GPU to finish so
+/// the results can be used right away by the next frame or read back by the caller
+pub(crate) fn dispatch(
+    vulkan_context: &VulkanContext,
+    pipeline: &Arc<ComputePipeline>,
+    descriptor_set: Arc<PersistentDescriptorSet>,
+    group_counts: [u32; 3],
+) -> Result<()> {
+    let queue = vulkan_context.compute_queue();
+
+    let mut builder = AutoCommandBufferBuilder::primary(
+        vulkan_context.standard_command_buffer_allocator().as_ref(),
+        queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )?;
+
+    builder
+        .bind_pipeline_compute(Arc::clone(pipeline))?
+        .bind_descriptor_sets(
+            PipelineBindPoint::Compute,
+            Arc::clone(pipeline.layout()),
+            0,
+            descriptor_set,
+        )?
+        .dispatch(group_counts)?;
+
+    let command_buffer = builder.build()?;
+
+    sync::now(Arc::clone(vulkan_context.device()))
+        .then_execute(Arc::clone(queue), command_buffer)?
+        .then_signal_fence_and_flush()?
+        .wait(None)?;
+
+    Ok(())
+}
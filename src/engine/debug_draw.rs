@@ -0,0 +1,66 @@
+use glam::Vec3;
+use vulkano::{buffer::BufferContents, pipeline::graphics::vertex_input};
+
+#[derive(BufferContents, vertex_input::Vertex, Clone, Copy)]
+#[repr(C)]
+pub(crate) struct LineVertex {
+    #[format(R32G32B32_SFLOAT)]
+    pub in_position: Vec3,
+
+    #[format(R32G32B32_SFLOAT)]
+    pub in_color: Vec3,
+}
+
+/// Immediate mode debug primitives, accumulated during a frame and cleared once it is rendered
+pub(crate) struct DebugDraw {
+    line_vertices: Vec<LineVertex>,
+}
+
+impl DebugDraw {
+    pub(crate) fn new() -> Self {
+        Self {
+            line_vertices: Vec::new(),
+        }
+    }
+
+    pub(crate) fn line(&mut self, a: Vec3, b: Vec3, color: Vec3) {
+        self.line_vertices.extend([
+            LineVertex {
+                in_position: a,
+                in_color: color,
+            },
+            LineVertex {
+                in_position: b,
+                in_color: color,
+            },
+        ]);
+    }
+
+    pub(crate) fn aabb(&mut self, min: Vec3, max: Vec3, color: Vec3) {
+        let corner = |x: bool, y: bool, z: bool| {
+            Vec3::new(
+                if x { max.x } else { min.x },
+                if y { max.y } else { min.y },
+                if z { max.z } else { min.z },
+            )
+        };
+
+        for (y, z) in [(false, false), (true, false), (false, true), (true, true)] {
+            self.line(corner(false, y, z), corner(true, y, z), color);
+        }
+        for (x, z) in [(false, false), (true, false), (false, true), (true, true)] {
+            self.line(corner(x, false, z), corner(x, true, z), color);
+        }
+        for (x, y) in [(false, false), (true, false), (false, true), (true, true)] {
+            self.line(corner(x, y, false), corner(x, y, true), color);
+        }
+    }
+
+    pub(crate) fn line_vertices(&self) -> &[LineVertex] {
+        &self.line_vertices
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.line_vertices.clear();
+    }
+}
@@ -5,9 +5,18 @@ use std::{
     sync::Arc,
 };
 
+use anyhow::Result;
+use vulkano::buffer::BufferContents;
+
 use crate::{camera::Camera3D, vulkan_context::VulkanContext};
 
-use super::material::{material_manager::MaterialManager, Material};
+use self::components::{
+    DrawOrder, InstancedMeshComponent, MeshComponent, Name, SkinnedMeshComponent, UniformComponent,
+};
+use super::{
+    material::{material_manager::MaterialManager, Material},
+    renderer::entity_uniform::{self, UniformUpload},
+};
 
 pub mod components;
 
@@ -21,6 +30,7 @@ trait ComponentVec {
     fn swap_remove(&mut self, index: usize);
     fn inner_type_id(&self) -> TypeId;
     fn inner_type_name(&self) -> &str;
+    fn inner_type_size(&self) -> usize;
 }
 
 impl<T: 'static> ComponentVec for Vec<(Entity, T)> {
@@ -55,29 +65,68 @@ impl<T: 'static> ComponentVec for Vec<(Entity, T)> {
     fn inner_type_name(&self) -> &str {
         std::any::type_name::<T>()
     }
+
+    fn inner_type_size(&self) -> usize {
+        std::mem::size_of::<T>()
+    }
+}
+
+/// Change of the components of an entity, recorded by the scene until drained with
+/// `Scene::drain_events`. Replacing a component does not record anything
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SceneEvent {
+    ComponentAdded { entity: Entity, type_id: TypeId },
+    ComponentRemoved { entity: Entity, type_id: TypeId },
 }
 
 pub struct Scene {
     entities: HashMap<Entity, Vec<(TypeId, usize)>>,
     component_vecs: HashMap<TypeId, Box<dyn ComponentVec>>,
+    events: Vec<SceneEvent>,
+    /// Uploads of the `UniformComponent` types added with `entity_set_uniform`
+    uniform_uploads: HashMap<TypeId, UniformUpload>,
     material_manager: MaterialManager,
-    camera: Option<Camera3D>,
+    /// Cameras added to the scene by id, the scene is rendered from the active one
+    cameras: HashMap<u64, Camera3D>,
+    active_camera: Option<u64>,
+    next_camera_id: u64,
+    /// Number of components of each type the scene is sized for, a component vec grows to it when
+    /// a component of its type is added
+    component_capacity: usize,
 
     vulkan_context: Arc<VulkanContext>,
 }
 
 impl Scene {
-    pub(crate) fn new(vulkan_context: Arc<VulkanContext>) -> Self {
+    /// Scene sized for `capacity` entities, each component type gets room for `capacity`
+    /// components once the first one is added, see `ApplicationInfo::scene_capacity`
+    pub fn with_capacity(vulkan_context: Arc<VulkanContext>, capacity: usize) -> Self {
         Self {
-            entities: HashMap::new(),
+            entities: HashMap::with_capacity(capacity),
             component_vecs: HashMap::new(),
+            events: Vec::new(),
+            uniform_uploads: HashMap::new(),
             material_manager: MaterialManager::new(Arc::clone(vulkan_context.device())),
-            camera: None,
+            cameras: HashMap::new(),
+            active_camera: None,
+            next_camera_id: 0,
+            component_capacity: capacity,
 
             vulkan_context,
         }
     }
 
+    /// Reserves room for `additional` entities more than the scene has, like `Vec::reserve`, and
+    /// as many components of every type. The component vecs only grow when a component of their
+    /// type is added, types the new entities do not use keep their size. Avoids repeated
+    /// reallocations when generating large scenes
+    pub fn reserve(&mut self, additional: usize) {
+        self.entities.reserve(additional);
+        self.component_capacity = self
+            .component_capacity
+            .max(self.entities.len() + additional);
+    }
+
     pub fn entity_count(&self) -> usize {
         self.entities.len()
     }
@@ -103,8 +152,29 @@ impl Scene {
         self.entities.remove(&entity);
     }
 
+    /// Removes every entity and component, the materials and the cameras are kept
+    pub fn clear(&mut self) {
+        // The events follow the order of the entities, with the components of each in the order
+        // `remove_entity` would remove them, rather than the arbitrary order of the map
+        let mut entities = self.entities.keys().copied().collect::<Vec<_>>();
+        entities.sort_unstable();
+        for entity in entities {
+            let removed = self.entities[&entity]
+                .iter()
+                .rev()
+                .map(|&(type_id, _)| SceneEvent::ComponentRemoved { entity, type_id });
+            self.events.extend(removed);
+        }
+
+        self.entities.clear();
+        self.component_vecs.clear();
+    }
+
     fn entity_remove_last_component(&mut self, entity: Entity) {
         if let Some((type_id, index)) = self.entities.get_mut(&entity).unwrap().pop() {
+            self.events
+                .push(SceneEvent::ComponentRemoved { entity, type_id });
+
             let component_vec = self.component_vecs.get_mut(&type_id).unwrap();
 
             component_vec.swap_remove(index);
@@ -142,6 +212,11 @@ impl Scene {
             "Entity {entity} does not exist in the scene"
         );
 
+        self.events.push(SceneEvent::ComponentAdded {
+            entity,
+            type_id: TypeId::of::<T>(),
+        });
+
         if let Some(component_vec) = self.component_vecs.get_mut(&TypeId::of::<T>()) {
             let component_vec = component_vec
                 .as_any_mut()
@@ -151,6 +226,7 @@ impl Scene {
                 .get_mut(&entity)
                 .unwrap()
                 .push((TypeId::of::<T>(), component_vec.len()));
+            component_vec.reserve(self.component_capacity.saturating_sub(component_vec.len()));
             component_vec.push((entity, component));
             return;
         }
@@ -159,8 +235,56 @@ impl Scene {
             .get_mut(&entity)
             .unwrap()
             .push((TypeId::of::<T>(), 0));
+
+        let mut component_vec = Vec::with_capacity(self.component_capacity.max(1));
+        component_vec.push((entity, component));
         self.component_vecs
-            .insert(TypeId::of::<T>(), Box::new(vec![(entity, component)]));
+            .insert(TypeId::of::<T>(), Box::new(component_vec));
+    }
+
+    /// Adds the component to the entity, or overwrites the first component of the same type the
+    /// entity already has. Use this when an entity should own at most one component per type
+    pub fn entity_add_or_replace_component<T: 'static>(&mut self, entity: Entity, component: T) {
+        assert!(
+            self.entities.contains_key(&entity),
+            "Entity {entity} does not exist in the scene"
+        );
+
+        let existing_index = self.entities[&entity]
+            .iter()
+            .find(|(type_id, _)| *type_id == TypeId::of::<T>())
+            .map(|(_, index)| *index);
+
+        if let Some(index) = existing_index {
+            self.components_mut::<T>().unwrap()[index].1 = component;
+            return;
+        }
+
+        self.entity_add_component(entity, component);
+    }
+
+    /// Gives the entity data read by the material shader of its mesh, replacing the data of the
+    /// same type it already has. See `UniformComponent`
+    pub fn entity_set_uniform<T: BufferContents + Copy>(&mut self, entity: Entity, data: T) {
+        self.uniform_uploads.insert(
+            TypeId::of::<UniformComponent<T>>(),
+            entity_uniform::upload_uniform::<T>,
+        );
+        self.entity_add_or_replace_component(entity, UniformComponent { data });
+    }
+
+    /// Upload of the first uniform component of the entity with the index of the component in its
+    /// component vec, `None` when it has none
+    pub(crate) fn entity_uniform_upload(&self, entity: Entity) -> Option<(UniformUpload, usize)> {
+        self.entity_components(entity)
+            .iter()
+            .find_map(|(type_id, index)| Some((*self.uniform_uploads.get(type_id)?, *index)))
+    }
+
+    pub fn entity_has_component<T: 'static>(&self, entity: Entity) -> bool {
+        self.entity_components(entity)
+            .iter()
+            .any(|(type_id, _)| *type_id == TypeId::of::<T>())
     }
 
     pub fn entity_components(&self, entity: Entity) -> &Vec<(TypeId, usize)> {
@@ -181,6 +305,28 @@ impl Scene {
         self.entities.get_mut(&entity).unwrap()
     }
 
+    /// Number of components of type `T` in the scene, over every entity
+    pub fn component_count<T: 'static>(&self) -> usize {
+        self.component_vecs
+            .get(&TypeId::of::<T>())
+            .map_or(0, |component_vec| component_vec.len())
+    }
+
+    /// Full type name of every component type the scene stores, sorted. A type stays listed once
+    /// its last component is removed, with a count of 0, until the scene is cleared
+    pub fn component_type_names(&self) -> Vec<&str> {
+        let mut type_names = self
+            .component_vecs
+            .values()
+            .map(|component_vec| component_vec.inner_type_name())
+            .collect::<Vec<_>>();
+        type_names.sort_unstable();
+
+        type_names
+    }
+
+    /// Raw access to the storage of a component type, prefer `for_each` which does not depend on
+    /// how components are stored
     pub fn components<T: 'static>(&self) -> Option<&Vec<(Entity, T)>> {
         if let Some(component_vec) = self.component_vecs.get(&TypeId::of::<T>()) {
             component_vec.as_any().downcast_ref::<Vec<(Entity, T)>>()
@@ -189,6 +335,8 @@ impl Scene {
         }
     }
 
+    /// Raw mutable access to the storage of a component type, prefer `for_each_mut` which does
+    /// not depend on how components are stored
     pub fn components_mut<T: 'static>(&mut self) -> Option<&mut Vec<(Entity, T)>> {
         if let Some(component_vec) = self.component_vecs.get_mut(&TypeId::of::<T>()) {
             component_vec
@@ -199,6 +347,128 @@ impl Scene {
         }
     }
 
+    /// Every component of type `T` with its entity, sorted by entity. Unlike `components` the
+    /// order does not depend on the order in which components were added and removed
+    pub fn components_by_entity<T: 'static>(&self) -> Vec<(Entity, &T)> {
+        let mut components = self
+            .components::<T>()
+            .into_iter()
+            .flatten()
+            .map(|(entity, component)| (*entity, component))
+            .collect::<Vec<_>>();
+        components.sort_by_key(|(entity, _)| *entity);
+
+        components
+    }
+
+    /// Calls `f` with every component of type `T` and the entity owning it
+    pub fn for_each<T: 'static>(&self, mut f: impl FnMut(Entity, &T)) {
+        if let Some(components) = self.components::<T>() {
+            for (entity, component) in components.iter() {
+                f(*entity, component);
+            }
+        }
+    }
+
+    /// Calls `f` with every component of type `T` and the entity owning it
+    pub fn for_each_mut<T: 'static>(&mut self, mut f: impl FnMut(Entity, &mut T)) {
+        if let Some(components) = self.components_mut::<T>() {
+            for (entity, component) in components.iter_mut() {
+                f(*entity, component);
+            }
+        }
+    }
+
+    /// Every mesh component of the scene with the entity owning it
+    pub fn meshes(&self) -> impl Iterator<Item = (Entity, &MeshComponent)> {
+        self.components::<MeshComponent>()
+            .into_iter()
+            .flatten()
+            .map(|(entity, mesh_component)| (*entity, mesh_component))
+    }
+
+    /// Every mesh component of the scene with the entity owning it, typically used to move
+    /// objects around by changing their `model`
+    pub fn meshes_mut(&mut self) -> impl Iterator<Item = (Entity, &mut MeshComponent)> {
+        self.components_mut::<MeshComponent>()
+            .into_iter()
+            .flatten()
+            .map(|(entity, mesh_component)| (*entity, mesh_component))
+    }
+
+    /// First entity named `name`, entities are named by giving them a `Name` component
+    pub fn find_by_name(&self, name: &str) -> Option<Entity> {
+        self.components::<Name>()?
+            .iter()
+            .find(|(_, entity_name)| entity_name.0 == name)
+            .map(|(entity, _)| *entity)
+    }
+
+    fn entity_name(&self, entity: Entity) -> Option<&str> {
+        let names = self.components::<Name>()?;
+
+        self.entity_components(entity)
+            .iter()
+            .find(|(type_id, _)| *type_id == TypeId::of::<Name>())
+            .map(|(_, index)| names[*index].1 .0.as_str())
+    }
+
+    /// Draw order of the entity's mesh, 0 without a `DrawOrder` component
+    pub(crate) fn draw_order(&self, entity: Entity) -> i32 {
+        let Some(draw_orders) = self.components::<DrawOrder>() else {
+            return 0;
+        };
+
+        self.entity_components(entity)
+            .iter()
+            .find(|(type_id, _)| *type_id == TypeId::of::<DrawOrder>())
+            .map_or(0, |(_, index)| draw_orders[*index].1 .0)
+    }
+
+    pub fn skinned_meshes(&self) -> impl Iterator<Item = (Entity, &SkinnedMeshComponent)> {
+        self.components::<SkinnedMeshComponent>()
+            .into_iter()
+            .flatten()
+            .map(|(entity, skinned_mesh)| (*entity, skinned_mesh))
+    }
+
+    /// Every skinned mesh component of the scene, typically used to update the poses
+    pub fn skinned_meshes_mut(
+        &mut self,
+    ) -> impl Iterator<Item = (Entity, &mut SkinnedMeshComponent)> {
+        self.components_mut::<SkinnedMeshComponent>()
+            .into_iter()
+            .flatten()
+            .map(|(entity, skinned_mesh)| (*entity, skinned_mesh))
+    }
+
+    pub fn instanced_meshes(&self) -> impl Iterator<Item = (Entity, &InstancedMeshComponent)> {
+        self.components::<InstancedMeshComponent>()
+            .into_iter()
+            .flatten()
+            .map(|(entity, instanced_mesh)| (*entity, instanced_mesh))
+    }
+
+    pub fn instanced_meshes_mut(
+        &mut self,
+    ) -> impl Iterator<Item = (Entity, &mut InstancedMeshComponent)> {
+        self.components_mut::<InstancedMeshComponent>()
+            .into_iter()
+            .flatten()
+            .map(|(entity, instanced_mesh)| (*entity, instanced_mesh))
+    }
+
+    /// Takes the components added and removed since the last call, in the order they happened.
+    /// `Engine::render` drops the events left after each rendered frame, they have to be drained
+    /// before it, from `Runable::on_update` or the custom event loop
+    pub fn drain_events(&mut self) -> Vec<SceneEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub(crate) fn clear_events(&mut self) {
+        self.events.clear();
+    }
+
     pub(crate) fn material_manager(&self) -> &MaterialManager {
         &self.material_manager
     }
@@ -208,16 +478,105 @@ impl Scene {
             .new_material(material, Arc::clone(&self.vulkan_context))
     }
 
+    /// Material shaded by its own fragment shader, given as SPIR-V with a `main` entry point, for
+    /// the meshes drawn one by one. Indirect, skinned and instanced draws keep the engine's
+    /// shader. The shader receives the `normal`, `tex_coords` and `tint` of the engine's vertex
+    /// shader at locations 0, 1 and 2 and writes the color at location 0. It may read
+    ///
+    /// - the uniform block of `Material::shader_data` at set 0 binding 0 and the storage buffers
+    ///   of `Material::storage_buffers` from binding 1
+    /// - the uniforms of the frame at set `PipelineManager::FRAME_SET`
+    /// - the uniform component of the entity at set `PipelineManager::ENTITY_SET`, see
+    ///   `entity_set_uniform`
+    /// - `Material::fragment_push_constants` from the push constant offset
+    ///   `PipelineManager::FRAGMENT_PUSH_CONSTANT_OFFSET`
+    ///
+    /// Fails if the shader reads anything else
+    pub fn new_material_with_shader<T: Material + 'static>(
+        &mut self,
+        material: T,
+        fragment_spirv: &[u32],
+    ) -> Result<u64> {
+        self.material_manager.new_material_with_shader(
+            material,
+            fragment_spirv,
+            Arc::clone(&self.vulkan_context),
+        )
+    }
+
+    pub fn remove_material(&mut self, material: u64) {
+        self.material_manager.remove_material(material);
+    }
+
+    /// Removes every material, components still referencing them must not be rendered afterwards
+    pub fn clear_materials(&mut self) {
+        self.material_manager.clear();
+    }
+
+    /// Replaces the active camera, or adds `camera` and makes it active when there is none
     pub fn set_camera(&mut self, camera: Camera3D) {
-        self.camera = Some(camera);
+        match self.active_camera {
+            Some(id) => {
+                self.cameras.insert(id, camera);
+            }
+            None => {
+                let id = self.add_camera(camera);
+                self.active_camera = Some(id);
+            }
+        }
+    }
+
+    /// Adds a camera without rendering from it, see `set_active_camera`
+    pub fn add_camera(&mut self, camera: Camera3D) -> u64 {
+        let id = self.next_camera_id;
+        self.next_camera_id += 1;
+        self.cameras.insert(id, camera);
+
+        id
+    }
+
+    /// Removes the camera, the scene is no longer rendered from a camera if it was the active one
+    pub fn remove_camera(&mut self, id: u64) -> Camera3D {
+        let Some(camera) = self.cameras.remove(&id) else {
+            panic!("Camera {id} does not exist in the scene");
+        };
+
+        if self.active_camera == Some(id) {
+            self.active_camera = None;
+        }
+
+        camera
+    }
+
+    /// Renders the scene from the camera `id`
+    pub fn set_active_camera(&mut self, id: u64) {
+        assert!(
+            self.cameras.contains_key(&id),
+            "Camera {id} does not exist in the scene"
+        );
+
+        self.active_camera = Some(id);
+    }
+
+    pub fn active_camera_id(&self) -> Option<u64> {
+        self.active_camera
+    }
+
+    pub fn camera_by_id(&self, id: u64) -> Option<&Camera3D> {
+        self.cameras.get(&id)
     }
 
-    pub fn camera(&self) -> &Option<Camera3D> {
-        &self.camera
+    pub fn camera_by_id_mut(&mut self, id: u64) -> Option<&mut Camera3D> {
+        self.cameras.get_mut(&id)
     }
 
-    pub fn camera_mut(&mut self) -> &mut Option<Camera3D> {
-        &mut self.camera
+    /// The active camera, the scene is rendered from it
+    pub fn camera(&self) -> Option<&Camera3D> {
+        self.cameras.get(&self.active_camera?)
+    }
+
+    pub fn camera_mut(&mut self) -> Option<&mut Camera3D> {
+        self.cameras.get_mut(&self.active_camera?)
     }
 }
 
@@ -242,20 +601,45 @@ impl Display for Scene {
                 })
                 .collect::<Vec<_>>();
 
-            s.push_str(format!("\t{}: {:?}\n", entity, components).as_str());
+            match self.entity_name(entity) {
+                Some(name) => {
+                    s.push_str(format!("\t{} ({}): {:?}\n", entity, name, components).as_str())
+                }
+                None => s.push_str(format!("\t{}: {:?}\n", entity, components).as_str()),
+            }
         }
         s.push_str("}\n");
 
-        for (_, component_vec) in self.component_vecs.iter() {
+        // Sorted by name so that the output does not depend on the hash map order
+        let mut component_vecs = self.component_vecs.values().collect::<Vec<_>>();
+        component_vecs.sort_by_key(|component_vec| component_vec.inner_type_name());
+
+        let mut component_count = 0;
+        for component_vec in component_vecs.iter() {
             let mut entities = Vec::new();
             for i in 0..component_vec.len() {
                 entities.push(component_vec.get_entity(i).unwrap());
             }
             let component_name = component_vec.inner_type_name().split(":").last().unwrap();
-
-            s.push_str(format!("{}: {:?}\n", component_name, entities).as_str());
+            let memory = component_vec.len() * component_vec.inner_type_size();
+
+            s.push_str(
+                format!(
+                    "{}: {:?} ({} components, {} bytes)\n",
+                    component_name,
+                    entities,
+                    component_vec.len(),
+                    memory
+                )
+                .as_str(),
+            );
+            component_count += component_vec.len();
         }
 
+        s.push_str(format!("Total entities: {}\n", self.entity_count()).as_str());
+        s.push_str(format!("Total components: {}\n", component_count).as_str());
+        s.push_str(format!("Component types: {}\n", component_vecs.len()).as_str());
+
         write!(f, "{s}")
     }
 }
@@ -264,16 +648,29 @@ impl Display for Scene {
 mod tests {
     use winit::{event_loop::EventLoop, window::WindowBuilder};
 
-    use crate::vulkan_context::VulkanContext;
+    use crate::vulkan_context::{VulkanContext, VulkanContextInfo};
 
     use super::*;
 
     fn create_empty_scene() -> Scene {
+        create_scene_with_capacity(0)
+    }
+
+    fn create_scene_with_capacity(capacity: usize) -> Scene {
         let dummy_window = WindowBuilder::new()
             .build(&EventLoop::new().unwrap())
             .unwrap();
-        let vulkan_contex = VulkanContext::new(&Arc::new(dummy_window)).unwrap();
-        Scene::new(Arc::new(vulkan_contex))
+        let vulkan_contex =
+            VulkanContext::new(&Arc::new(dummy_window), VulkanContextInfo::default()).unwrap();
+        Scene::with_capacity(Arc::new(vulkan_contex), capacity)
+    }
+
+    fn component_vec_capacity<T: 'static>(scene: &Scene) -> usize {
+        scene.component_vecs[&TypeId::of::<T>()]
+            .as_any()
+            .downcast_ref::<Vec<(Entity, T)>>()
+            .unwrap()
+            .capacity()
     }
 
     fn consistency_check(scene: &Scene) {
@@ -381,6 +778,21 @@ mod tests {
         assert!(!scene.entities().contains(&&e2));
     }
 
+    #[test]
+    fn clear_scene() {
+        let mut scene = create_empty_scene();
+        scene.spawn_entity();
+        scene.spawn_entity();
+        scene.spawn_entity();
+
+        scene.clear();
+
+        assert_eq!(scene.entity_count(), 0, "The scene should be empty");
+
+        let e = scene.spawn_entity();
+        assert_eq!(e, 0, "Entity ids should start over after a clear");
+    }
+
     #[test]
     #[should_panic(expected = "Scene does not contain entity 666")]
     fn remove_non_existant_entity() {
@@ -426,6 +838,154 @@ mod tests {
         );
     }
 
+    #[test]
+    fn has_component() {
+        let mut scene = create_empty_scene();
+        let e1 = scene.spawn_entity();
+        let e2 = scene.spawn_entity();
+        scene.entity_add_component(e1, Dummy1(1));
+
+        assert!(scene.entity_has_component::<Dummy1>(e1));
+        assert!(!scene.entity_has_component::<Dummy2>(e1));
+        assert!(!scene.entity_has_component::<Dummy1>(e2));
+    }
+
+    #[test]
+    fn components_by_entity_are_sorted() {
+        let mut scene = create_empty_scene();
+        let entities = (0..4).map(|_| scene.spawn_entity()).collect::<Vec<_>>();
+        for &entity in entities.iter().rev() {
+            scene.entity_add_component(entity, Dummy1(entity as i32));
+        }
+        scene.remove_entity(entities[3]);
+
+        let components = scene.components_by_entity::<Dummy1>();
+
+        assert_eq!(
+            components,
+            vec![
+                (entities[0], &Dummy1(entities[0] as i32)),
+                (entities[1], &Dummy1(entities[1] as i32)),
+                (entities[2], &Dummy1(entities[2] as i32)),
+            ]
+        );
+    }
+
+    #[test]
+    fn count_components_by_type() {
+        let mut scene = construct_big_scene();
+
+        assert_eq!(scene.component_count::<Dummy1>(), 10);
+        assert_eq!(scene.component_count::<Dummy2>(), 10);
+        assert_eq!(scene.component_count::<i32>(), 1);
+        assert_eq!(scene.component_count::<Name>(), 0);
+        assert_eq!(
+            scene.component_type_names(),
+            vec![
+                "i32",
+                std::any::type_name::<Dummy1>(),
+                std::any::type_name::<Dummy2>(),
+            ]
+        );
+
+        scene.clear();
+        assert_eq!(scene.component_count::<Dummy1>(), 0);
+        assert!(scene.component_type_names().is_empty());
+    }
+
+    #[test]
+    fn find_entity_by_name() {
+        let mut scene = create_empty_scene();
+        let e1 = scene.spawn_entity();
+        let e2 = scene.spawn_entity();
+        scene.entity_add_component(e1, Name(String::from("player")));
+        scene.entity_add_component(e2, Name(String::from("enemy")));
+
+        assert_eq!(scene.find_by_name("enemy"), Some(e2));
+        assert_eq!(scene.find_by_name("player"), Some(e1));
+        assert_eq!(scene.find_by_name("camera"), None);
+    }
+
+    #[test]
+    fn add_or_replace_component() {
+        let mut scene = create_empty_scene();
+        let e1 = scene.spawn_entity();
+        let e2 = scene.spawn_entity();
+        scene.entity_add_component(e2, Dummy1(7));
+
+        scene.entity_add_or_replace_component(e1, Dummy1(1));
+        scene.entity_add_or_replace_component(e1, Dummy1(2));
+
+        assert_eq!(
+            scene.entity_components(e1).len(),
+            1,
+            "Replacing a component should not add a new one"
+        );
+
+        let components = scene.components::<Dummy1>().unwrap();
+        assert_eq!(components.len(), 2);
+        assert!(components.contains(&(e1, Dummy1(2))));
+        assert!(components.contains(&(e2, Dummy1(7))));
+
+        consistency_check(&scene);
+    }
+
+    #[test]
+    fn set_uniform_replaces_data() {
+        let mut scene = create_empty_scene();
+        let e = scene.spawn_entity();
+
+        scene.entity_set_uniform(e, 1.0f32);
+        scene.entity_set_uniform(e, 2.0f32);
+
+        assert_eq!(scene.entity_components(e).len(), 1);
+        let components = scene.components::<UniformComponent<f32>>().unwrap();
+        assert_eq!(components[0].1.data, 2.0);
+    }
+
+    #[test]
+    fn for_each_component() {
+        let mut scene = create_empty_scene();
+        let e1 = scene.spawn_entity();
+        let e2 = scene.spawn_entity();
+        scene.entity_add_component(e1, Dummy1(1));
+        scene.entity_add_component(e2, Dummy1(2));
+        scene.entity_add_component(e2, Dummy2(3));
+
+        let mut visited = Vec::new();
+        scene.for_each::<Dummy1>(|entity, component| visited.push((entity, component.0)));
+        visited.sort();
+
+        assert_eq!(visited, vec![(e1, 1), (e2, 2)]);
+    }
+
+    #[test]
+    fn for_each_mut_component() {
+        let mut scene = create_empty_scene();
+        let e1 = scene.spawn_entity();
+        let e2 = scene.spawn_entity();
+        scene.entity_add_component(e1, Dummy1(1));
+        scene.entity_add_component(e2, Dummy1(2));
+
+        scene.for_each_mut::<Dummy1>(|entity, component| component.0 += entity as i32 * 10);
+
+        let components = scene.components::<Dummy1>().unwrap();
+        assert!(components.contains(&(e1, Dummy1(1))));
+        assert!(components.contains(&(e2, Dummy1(12))));
+    }
+
+    #[test]
+    fn for_each_non_existant_component() {
+        let mut scene = create_empty_scene();
+        scene.spawn_entity();
+
+        let mut count = 0;
+        scene.for_each::<Dummy1>(|_, _| count += 1);
+        scene.for_each_mut::<Dummy1>(|_, _| count += 1);
+
+        assert_eq!(count, 0);
+    }
+
     #[test]
     fn consistency_adding_entities_and_components() {
         let mut scene = create_empty_scene();
@@ -599,6 +1159,162 @@ mod tests {
         consistency_check(&scene);
     }
 
+    #[test]
+    fn clear_removes_components() {
+        let mut scene = construct_big_scene();
+
+        scene.clear();
+
+        assert_eq!(scene.components::<Dummy1>(), None);
+        assert_eq!(scene.components::<Dummy2>(), None);
+        consistency_check(&scene);
+    }
+
+    #[test]
+    fn events_record_added_and_removed_components() {
+        let mut scene = create_empty_scene();
+        let entity = scene.spawn_entity();
+        scene.entity_add_component(entity, Dummy1(1));
+        scene.entity_add_or_replace_component(entity, Dummy1(2));
+        scene.entity_add_component(entity, Dummy2(3));
+        scene.remove_entity(entity);
+
+        let added = |type_id| SceneEvent::ComponentAdded { entity, type_id };
+        let removed = |type_id| SceneEvent::ComponentRemoved { entity, type_id };
+        let (dummy_1, dummy_2) = (TypeId::of::<Dummy1>(), TypeId::of::<Dummy2>());
+        assert_eq!(
+            scene.drain_events(),
+            vec![
+                added(dummy_1),
+                added(dummy_2),
+                removed(dummy_2),
+                removed(dummy_1),
+            ]
+        );
+        assert!(scene.drain_events().is_empty());
+    }
+
+    #[test]
+    fn clear_records_removals_in_entity_order() {
+        let mut scene = create_empty_scene();
+        let entities = (0..20).map(|_| scene.spawn_entity()).collect::<Vec<_>>();
+        for &entity in entities.iter() {
+            scene.entity_add_component(entity, Dummy1(entity as i32));
+            scene.entity_add_component(entity, Dummy2(entity as u64));
+        }
+        scene.drain_events();
+
+        scene.clear();
+
+        let (dummy_1, dummy_2) = (TypeId::of::<Dummy1>(), TypeId::of::<Dummy2>());
+        let expected = entities
+            .iter()
+            .flat_map(|&entity| {
+                [
+                    SceneEvent::ComponentRemoved {
+                        entity,
+                        type_id: dummy_2,
+                    },
+                    SceneEvent::ComponentRemoved {
+                        entity,
+                        type_id: dummy_1,
+                    },
+                ]
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(scene.drain_events(), expected);
+    }
+
+    #[test]
+    fn with_capacity_preallocates_components() {
+        let mut scene = create_scene_with_capacity(100);
+        assert!(scene.entities.capacity() >= 100);
+
+        let entity = scene.spawn_entity();
+        scene.entity_add_component(entity, Dummy1(1));
+
+        assert!(component_vec_capacity::<Dummy1>(&scene) >= 100);
+    }
+
+    #[test]
+    fn reserve_grows_capacity() {
+        let mut scene = create_empty_scene();
+        let entity = scene.spawn_entity();
+        scene.entity_add_component(entity, Dummy1(1));
+        assert!(component_vec_capacity::<Dummy1>(&scene) < 500);
+
+        scene.reserve(500);
+        assert!(scene.entities.capacity() >= 501);
+        // The component vecs only grow once a component of their type is added
+        assert!(component_vec_capacity::<Dummy1>(&scene) < 500);
+        scene.entity_add_component(entity, Dummy1(2));
+        assert!(component_vec_capacity::<Dummy1>(&scene) >= 501);
+
+        // Component types added after the reservation get the same room
+        scene.entity_add_component(entity, Dummy2(2));
+        assert!(component_vec_capacity::<Dummy2>(&scene) >= 501);
+        consistency_check(&scene);
+    }
+
+    #[test]
+    fn reserve_is_relative_to_the_entities() {
+        let mut scene = create_scene_with_capacity(100);
+        for _ in 0..100 {
+            scene.spawn_entity();
+        }
+
+        scene.reserve(50);
+        assert!(scene.entities.capacity() >= 150);
+
+        scene.entity_add_component(0, Dummy1(1));
+        assert!(component_vec_capacity::<Dummy1>(&scene) >= 150);
+    }
+
+    fn create_camera(x: f32) -> Camera3D {
+        Camera3D::new(glam::Vec3::new(x, 0.0, 0.0), 0.0, 0.0, glam::Vec3::Y)
+    }
+
+    #[test]
+    fn set_camera_replaces_active_camera() {
+        let mut scene = create_empty_scene();
+        assert!(scene.camera().is_none());
+
+        scene.set_camera(create_camera(1.0));
+        let id = scene.active_camera_id().unwrap();
+        scene.set_camera(create_camera(2.0));
+
+        assert_eq!(scene.active_camera_id(), Some(id));
+        assert_eq!(scene.camera().unwrap().position().x, 2.0);
+    }
+
+    #[test]
+    fn switch_active_camera() {
+        let mut scene = create_empty_scene();
+        let main = scene.add_camera(create_camera(1.0));
+        let minimap = scene.add_camera(create_camera(2.0));
+        assert!(
+            scene.camera().is_none(),
+            "Adding a camera should not make it active"
+        );
+
+        scene.set_active_camera(minimap);
+        assert_eq!(scene.camera().unwrap().position().x, 2.0);
+
+        scene.set_active_camera(main);
+        assert_eq!(scene.camera().unwrap().position().x, 1.0);
+
+        scene.remove_camera(main);
+        assert!(scene.camera().is_none());
+        assert!(scene.camera_by_id(minimap).is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "Camera 666 does not exist in the scene")]
+    fn activate_non_existant_camera() {
+        let mut scene = create_empty_scene();
+        scene.set_active_camera(666);
+    }
+
     #[test]
     #[should_panic(expected = "Entity 666 does not exist in the scene")]
     fn add_component_to_non_existant_entity() {
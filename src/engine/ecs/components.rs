@@ -1,7 +1,136 @@
-use crate::engine::{mesh::Mesh, transform::Transform};
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use vulkano::buffer::BufferContents;
+
+use crate::engine::{
+    mesh::{Mesh, Submesh},
+    transform::Transform,
+};
+
+/// Human readable name of an entity, used by `Scene::find_by_name` and shown by the scene's
+/// `Display` output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Name(pub String);
+
+/// Layer of the entity's mesh, meshes are drawn by increasing draw order and entities without
+/// the component are at 0. Within a draw order the meshes are still grouped by material
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DrawOrder(pub i32);
 
 pub struct MeshComponent {
     pub mesh: Mesh,
     pub model: Transform,
     pub material: u64,
+    /// Multiplied into the color of the material, lets meshes sharing a material look different.
+    /// `Vec3::ONE` keeps the material's color
+    pub tint: Vec3,
+    /// Index ranges drawn each with their own material instead of the whole mesh with `material`
+    /// when not empty, see `Mesh::material_groups` for the ones of a loaded file
+    pub submeshes: Vec<Submesh>,
+}
+
+impl MeshComponent {
+    /// Index ranges of the mesh drawn with their material
+    pub(crate) fn draw_ranges(&self) -> Vec<Submesh> {
+        if !self.submeshes.is_empty() {
+            return self.submeshes.clone();
+        }
+
+        vec![Submesh {
+            index_offset: 0,
+            index_count: self.mesh.index_count(),
+            material: self.material,
+        }]
+    }
+}
+
+/// Mesh deformed on the GPU by a skeleton, created with `Mesh::skinned` so that its vertices
+/// reference bones through a `BoneVertex` each. Skinned meshes are only drawn by the default render
+/// mode
+pub struct SkinnedMeshComponent {
+    pub mesh: Mesh,
+    pub model: Transform,
+    pub material: u64,
+    /// Multiplied into the color of the material, see `MeshComponent::tint`
+    pub tint: Vec3,
+    /// Current pose, one matrix per bone transforming from the bind pose to the animated pose in
+    /// model space. Uploaded every frame, at most `MAX_BONES`
+    pub bone_matrices: Vec<Mat4>,
+}
+
+/// Data of an entity written every frame into a uniform buffer, read by the material shader of
+/// the entity's mesh like the material's own uniform block, see `Scene::new_material_with_shader`.
+/// Added with `Scene::entity_set_uniform`, `T` must follow the std140 layout. Only meshes drawn one
+/// by one bind it, not those of indirect draws. The meshes of entities without one bind a zeroed
+/// uniform
+#[derive(Debug, Clone, Copy)]
+pub struct UniformComponent<T: BufferContents + Copy> {
+    pub data: T,
+}
+
+/// Copy of an `InstancedMeshComponent`'s mesh
+#[derive(Debug, Clone, Copy)]
+pub struct MeshInstance {
+    pub model: Transform,
+    /// Multiplied into the color of the material, see `MeshComponent::tint`
+    pub tint: Vec3,
+}
+
+impl MeshInstance {
+    /// Untinted instance placed by `model`
+    pub fn new(model: Transform) -> Self {
+        Self {
+            model,
+            tint: Vec3::ONE,
+        }
+    }
+}
+
+/// Mesh drawn many times with a single draw call, once per instance. The instances are uploaded
+/// every frame into a vertex buffer read per instance, nothing is drawn without instances.
+/// Instanced meshes are only drawn by the default render mode, as triangle lists
+pub struct InstancedMeshComponent {
+    pub mesh: Mesh,
+    pub material: u64,
+    pub instances: Vec<MeshInstance>,
+}
+
+/// Space in which a sprite is placed by its `model` transform
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpriteSpace {
+    /// The sprite is a unit quad of the XY plane seen through the cameras, hidden by the meshes
+    /// in front of it
+    World,
+    /// The sprite is a one pixel quad centered on the origin, `model` being given in pixels from
+    /// the top left corner of the window. Drawn over the scene, under the text
+    Screen,
+}
+
+/// Textured quad drawn with alpha blending. Sprites of the same space are drawn in the order of
+/// their entities, the later ones on top, and consecutive sprites sharing a texture are batched
+/// into a single draw
+pub struct Sprite {
+    /// Texture returned by `Engine::load_sprite_texture`
+    pub texture: u64,
+    /// Region of the texture drawn, (0, 0) being its top left corner and (1, 1) its bottom right
+    /// one. Lets many sprites share a texture atlas
+    pub uv_min: Vec2,
+    pub uv_max: Vec2,
+    pub model: Transform,
+    pub space: SpriteSpace,
+    /// Multiplies the texture color, alpha included
+    pub color: Vec4,
+}
+
+impl Sprite {
+    /// Sprite showing the whole texture, untinted
+    pub fn new(texture: u64, space: SpriteSpace) -> Self {
+        Self {
+            texture,
+            uv_min: Vec2::ZERO,
+            uv_max: Vec2::ONE,
+            model: Transform::new(),
+            space,
+            color: Vec4::ONE,
+        }
+    }
 }
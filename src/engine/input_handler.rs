@@ -1,15 +1,34 @@
 use std::collections::HashMap;
+use std::hash::Hash;
 use winit::{
     dpi::PhysicalPosition,
-    event::{DeviceEvent, ElementState, Event, KeyEvent, MouseButton, WindowEvent},
-    keyboard::{KeyCode, PhysicalKey},
+    event::{
+        DeviceEvent, ElementState, Event, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent,
+    },
+    keyboard::{KeyCode, ModifiersState, PhysicalKey},
 };
 
-#[derive(Debug, PartialEq, Eq)]
-enum InputState {
-    Pressed,
-    Released,
-    Held,
+/// State of a key or button during one frame
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct InputState {
+    /// Down after the last event of the frame
+    down: bool,
+    /// Went down at least once during the frame
+    pressed: bool,
+    /// Went up at least once during the frame
+    released: bool,
+}
+
+/// Input event received from the window, kept until the next `step`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RawInput {
+    Key(KeyCode, ElementState),
+    Modifiers(ModifiersState),
+    MouseButton(MouseButton, ElementState),
+    CursorMoved(f32, f32),
+    /// Scroll in lines, positive when scrolling up
+    Scroll(f32),
+    FocusLost,
 }
 
 #[derive(Debug)]
@@ -17,19 +36,28 @@ struct MouseState {
     button_state: HashMap<MouseButton, InputState>,
     current_position: (f32, f32),
     previous_position: (f32, f32),
+    /// Scroll of the current frame in lines, positive when scrolling up
+    scroll: f32,
 }
 
 #[derive(Debug)]
 pub struct InputHandler {
     keyboard_state: HashMap<KeyCode, InputState>,
+    /// Modifiers currently held on either side of the keyboard, as reported by the window
+    modifiers: ModifiersState,
     mouse_state: MouseState,
+    /// Events received since the last `step`, applied in order so that a key pressed and released
+    /// between two frames is still seen as both pressed and released
+    raw_inputs: Vec<RawInput>,
 }
 
 impl InputHandler {
     pub(crate) fn new() -> Self {
         Self {
             keyboard_state: HashMap::new(),
+            modifiers: ModifiersState::empty(),
             mouse_state: MouseState::new(),
+            raw_inputs: Vec::new(),
         }
     }
 
@@ -48,7 +76,7 @@ impl InputHandler {
     }
 
     fn update_window_event(&mut self, window_event: &WindowEvent) {
-        match window_event {
+        let raw_input = match window_event {
             WindowEvent::KeyboardInput {
                 event:
                     KeyEvent {
@@ -58,69 +86,101 @@ impl InputHandler {
                         ..
                     },
                 ..
-            } => match state {
-                ElementState::Pressed => self.update_key_press(*key_code),
-                ElementState::Released => self.update_key_release(*key_code),
-            },
+            } => RawInput::Key(*key_code, *state),
 
-            WindowEvent::MouseInput { state, button, .. } => {
-                self.mouse_state.update_input(state, button);
-            }
+            WindowEvent::ModifiersChanged(modifiers) => RawInput::Modifiers(modifiers.state()),
+
+            WindowEvent::MouseInput { state, button, .. } => RawInput::MouseButton(*button, *state),
 
             WindowEvent::CursorMoved { position, .. } => {
-                self.mouse_state.update_position(position);
+                let PhysicalPosition { x, y } = *position;
+                RawInput::CursorMoved(x as f32, y as f32)
             }
 
-            _ => (),
-        }
+            WindowEvent::MouseWheel { delta, .. } => RawInput::Scroll(scroll_lines(delta)),
+
+            WindowEvent::Focused(false) => RawInput::FocusLost,
+
+            _ => return,
+        };
+
+        self.raw_inputs.push(raw_input);
     }
 
     fn update_device_event(&mut self, _device_event: &DeviceEvent) {}
 
+    /// Starts a new frame, forgetting what was pressed and released during the previous one and
+    /// applying the events received since. Called before the application's update
     pub(crate) fn step(&mut self) {
-        self.keyboard_state = self
-            .keyboard_state
-            .iter()
-            .filter_map(|(key_code, key_state)| match key_state {
-                InputState::Pressed => Some((*key_code, InputState::Held)),
-                InputState::Held => Some((*key_code, InputState::Held)),
-                _ => None,
-            })
-            .collect();
-
+        step_states(&mut self.keyboard_state);
         self.mouse_state.step();
-    }
 
-    fn update_key_press(&mut self, key_code: KeyCode) {
-        self.keyboard_state.insert(key_code, InputState::Pressed);
+        for raw_input in std::mem::take(&mut self.raw_inputs) {
+            self.apply(raw_input);
+        }
     }
 
-    fn update_key_release(&mut self, key_code: KeyCode) {
-        self.keyboard_state.insert(key_code, InputState::Released);
-    }
+    fn apply(&mut self, raw_input: RawInput) {
+        match raw_input {
+            RawInput::Key(key_code, state) => {
+                apply_state(&mut self.keyboard_state, key_code, state)
+            }
 
-    pub fn key_pressed(&self, key_code: KeyCode) -> bool {
-        if let Some(key_state) = self.keyboard_state.get(&key_code) {
-            return *key_state == InputState::Pressed;
+            RawInput::Modifiers(modifiers) => self.modifiers = modifiers,
+
+            RawInput::MouseButton(button, state) => {
+                apply_state(&mut self.mouse_state.button_state, button, state)
+            }
+
+            RawInput::CursorMoved(x, y) => self.mouse_state.current_position = (x, y),
+
+            RawInput::Scroll(lines) => self.mouse_state.scroll += lines,
+
+            // Release events are not received while unfocused, keys held when the focus was lost
+            // would otherwise stay held forever
+            RawInput::FocusLost => {
+                self.keyboard_state.clear();
+                self.modifiers = ModifiersState::empty();
+                self.mouse_state.button_state.clear();
+            }
         }
+    }
 
-        false
+    /// Whether the key went down during the frame, even if it was released right after
+    pub fn key_pressed(&self, key_code: KeyCode) -> bool {
+        self.keyboard_state
+            .get(&key_code)
+            .is_some_and(|key_state| key_state.pressed)
     }
 
+    /// Whether the key went up during the frame, even if it was pressed right before
     pub fn key_released(&self, key_code: KeyCode) -> bool {
-        if let Some(key_state) = self.keyboard_state.get(&key_code) {
-            return *key_state == InputState::Released;
-        }
-
-        false
+        self.keyboard_state
+            .get(&key_code)
+            .is_some_and(|key_state| key_state.released)
     }
 
     pub fn key_held(&self, key_code: KeyCode) -> bool {
-        if let Some(key_state) = self.keyboard_state.get(&key_code) {
-            return *key_state == InputState::Held || *key_state == InputState::Pressed;
-        }
+        self.keyboard_state
+            .get(&key_code)
+            .is_some_and(|key_state| key_state.down)
+    }
+
+    /// Modifier keys currently held, for shortcuts such as `ctrl_held() && key_pressed(KeyS)`
+    pub fn modifiers(&self) -> ModifiersState {
+        self.modifiers
+    }
+
+    pub fn shift_held(&self) -> bool {
+        self.modifiers.shift_key()
+    }
 
-        false
+    pub fn ctrl_held(&self) -> bool {
+        self.modifiers.control_key()
+    }
+
+    pub fn alt_held(&self) -> bool {
+        self.modifiers.alt_key()
     }
 
     pub fn mouse_pressed(&self, button: MouseButton) -> bool {
@@ -138,6 +198,46 @@ impl InputHandler {
     pub fn mouse_diff(&self) -> (f32, f32) {
         self.mouse_state.mouse_diff()
     }
+
+    /// Lines scrolled with the mouse wheel during the last frame, positive when scrolling up
+    pub fn scroll_delta(&self) -> f32 {
+        self.mouse_state.scroll
+    }
+}
+
+/// Keeps only the inputs still down, which are no longer pressed or released in the new frame
+fn step_states<T: Eq + Hash>(states: &mut HashMap<T, InputState>) {
+    states.retain(|_, state| state.down);
+    for state in states.values_mut() {
+        *state = InputState {
+            down: true,
+            ..Default::default()
+        };
+    }
+}
+
+fn apply_state<T: Eq + Hash>(states: &mut HashMap<T, InputState>, input: T, state: ElementState) {
+    let input_state = states.entry(input).or_default();
+    match state {
+        ElementState::Pressed => {
+            input_state.down = true;
+            input_state.pressed = true;
+        }
+        ElementState::Released => {
+            input_state.down = false;
+            input_state.released = true;
+        }
+    }
+}
+
+fn scroll_lines(delta: &MouseScrollDelta) -> f32 {
+    // Touchpads report pixels, roughly converted to wheel lines
+    const PIXELS_PER_LINE: f32 = 20.0;
+
+    match delta {
+        MouseScrollDelta::LineDelta(_, y) => *y,
+        MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+    }
 }
 
 impl MouseState {
@@ -146,56 +246,33 @@ impl MouseState {
             button_state: HashMap::new(),
             current_position: (0.0, 0.0),
             previous_position: (0.0, 0.0),
+            scroll: 0.0,
         }
     }
 
-    fn update_input(&mut self, state: &ElementState, button: &MouseButton) {
-        match state {
-            ElementState::Pressed => self.button_state.insert(*button, InputState::Pressed),
-            ElementState::Released => self.button_state.insert(*button, InputState::Released),
-        };
-    }
-
-    fn update_position(&mut self, position: &PhysicalPosition<f64>) {
-        self.current_position = (position.x as f32, position.y as f32);
-    }
-
     fn step(&mut self) {
-        self.button_state = self
-            .button_state
-            .iter()
-            .filter_map(|(button, button_state)| match button_state {
-                InputState::Pressed => Some((*button, InputState::Held)),
-                InputState::Held => Some((*button, InputState::Held)),
-                _ => None,
-            })
-            .collect();
+        step_states(&mut self.button_state);
 
         self.previous_position = self.current_position;
+        self.scroll = 0.0;
     }
 
     fn button_pressed(&self, button: MouseButton) -> bool {
-        if let Some(button_state) = self.button_state.get(&button) {
-            return *button_state == InputState::Pressed;
-        }
-
-        false
+        self.button_state
+            .get(&button)
+            .is_some_and(|button_state| button_state.pressed)
     }
 
     fn button_released(&self, button: MouseButton) -> bool {
-        if let Some(button_state) = self.button_state.get(&button) {
-            return *button_state == InputState::Released;
-        }
-
-        false
+        self.button_state
+            .get(&button)
+            .is_some_and(|button_state| button_state.released)
     }
 
     fn button_held(&self, button: MouseButton) -> bool {
-        if let Some(button_state) = self.button_state.get(&button) {
-            return *button_state == InputState::Pressed || *button_state == InputState::Held;
-        }
-
-        false
+        self.button_state
+            .get(&button)
+            .is_some_and(|button_state| button_state.down)
     }
 
     fn mouse_diff(&self) -> (f32, f32) {
@@ -205,3 +282,101 @@ impl MouseState {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(input_handler: &mut InputHandler, raw_inputs: &[RawInput]) {
+        input_handler.raw_inputs.extend_from_slice(raw_inputs);
+        input_handler.step();
+    }
+
+    #[test]
+    fn tap_within_a_frame_is_pressed_and_released() {
+        let mut input_handler = InputHandler::new();
+
+        frame(
+            &mut input_handler,
+            &[
+                RawInput::Key(KeyCode::Space, ElementState::Pressed),
+                RawInput::Key(KeyCode::Space, ElementState::Released),
+            ],
+        );
+        assert!(input_handler.key_pressed(KeyCode::Space));
+        assert!(input_handler.key_released(KeyCode::Space));
+        assert!(!input_handler.key_held(KeyCode::Space));
+
+        frame(&mut input_handler, &[]);
+        assert!(!input_handler.key_pressed(KeyCode::Space));
+        assert!(!input_handler.key_released(KeyCode::Space));
+    }
+
+    #[test]
+    fn held_key_is_pressed_only_once() {
+        let mut input_handler = InputHandler::new();
+
+        frame(
+            &mut input_handler,
+            &[
+                RawInput::Key(KeyCode::KeyW, ElementState::Pressed),
+                RawInput::Key(KeyCode::KeyW, ElementState::Pressed),
+            ],
+        );
+        assert!(input_handler.key_pressed(KeyCode::KeyW));
+        assert!(input_handler.key_held(KeyCode::KeyW));
+
+        frame(&mut input_handler, &[]);
+        assert!(!input_handler.key_pressed(KeyCode::KeyW));
+        assert!(input_handler.key_held(KeyCode::KeyW));
+
+        frame(
+            &mut input_handler,
+            &[RawInput::Key(KeyCode::KeyW, ElementState::Released)],
+        );
+        assert!(input_handler.key_released(KeyCode::KeyW));
+        assert!(!input_handler.key_held(KeyCode::KeyW));
+    }
+
+    #[test]
+    fn release_and_press_again_within_a_frame() {
+        let mut input_handler = InputHandler::new();
+        frame(
+            &mut input_handler,
+            &[RawInput::MouseButton(MouseButton::Left, ElementState::Pressed)],
+        );
+
+        frame(
+            &mut input_handler,
+            &[
+                RawInput::MouseButton(MouseButton::Left, ElementState::Released),
+                RawInput::MouseButton(MouseButton::Left, ElementState::Pressed),
+            ],
+        );
+        assert!(input_handler.mouse_released(MouseButton::Left));
+        assert!(input_handler.mouse_pressed(MouseButton::Left));
+        assert!(input_handler.mouse_held(MouseButton::Left));
+    }
+
+    #[test]
+    fn mouse_motion_and_scroll_are_summed_over_the_frame() {
+        let mut input_handler = InputHandler::new();
+        frame(&mut input_handler, &[RawInput::CursorMoved(10.0, 10.0)]);
+
+        frame(
+            &mut input_handler,
+            &[
+                RawInput::CursorMoved(12.0, 15.0),
+                RawInput::Scroll(1.0),
+                RawInput::CursorMoved(14.0, 20.0),
+                RawInput::Scroll(0.5),
+            ],
+        );
+        assert_eq!(input_handler.mouse_diff(), (4.0, 10.0));
+        assert_eq!(input_handler.scroll_delta(), 1.5);
+
+        frame(&mut input_handler, &[]);
+        assert_eq!(input_handler.mouse_diff(), (0.0, 0.0));
+        assert_eq!(input_handler.scroll_delta(), 0.0);
+    }
+}
@@ -1,3 +1,5 @@
+use vulkano::buffer::Subbuffer;
+
 pub(crate) mod material_manager;
 pub mod simple_material;
 
@@ -8,7 +10,57 @@ pub enum MaterialType {
     GLTF2,
 }
 
+/// Offset applied to the depth of a material's fragments, a negative bias draws decals or
+/// coplanar geometry on top of the surface they lie on without z-fighting
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DepthBias {
+    pub constant_factor: f32,
+    pub slope_factor: f32,
+}
+
+impl DepthBias {
+    /// Bias actually applied to the depth, closer fragments having a greater depth with
+    /// reversed-Z the bias is negated so that it keeps moving the fragments in the same direction
+    pub(crate) fn for_depth_range(self, reversed_z: bool) -> Self {
+        if reversed_z {
+            Self {
+                constant_factor: -self.constant_factor,
+                slope_factor: -self.slope_factor,
+            }
+        } else {
+            self
+        }
+    }
+}
+
 pub trait Material {
     fn material_type(&self) -> MaterialType;
+
+    /// Size in bytes of the uniform block the material's shader expects, following the std140
+    /// layout rules
+    fn data_size(&self) -> usize;
+
+    /// Bytes uploaded to the material uniform buffer, must be exactly `data_size` long
     fn shader_data(&self) -> Vec<u8>;
+
+    fn depth_bias(&self) -> DepthBias {
+        DepthBias::default()
+    }
+
+    /// Storage buffers bound at consecutive bindings starting at binding 1 of the material set,
+    /// for materials reading large arrays such as instance data. At most 4 are supported, the
+    /// buffers must have the `STORAGE_BUFFER` usage and are bound once when the material is
+    /// created. They are read by the fragment shader given to `Scene::new_material_with_shader`
+    /// and can be filled on the GPU with `Engine::dispatch_material_compute`
+    fn storage_buffers(&self) -> Vec<Subbuffer<[u8]>> {
+        Vec::new()
+    }
+
+    /// Bytes pushed to the fragment shader when the material is bound, for small per draw
+    /// parameters like lighting without going through the uniform buffer. The shader reads them
+    /// from a push constant block starting at `PipelineManager::FRAGMENT_PUSH_CONSTANT_OFFSET`,
+    /// at most `PipelineManager::MAX_FRAGMENT_PUSH_CONSTANT_SIZE` bytes with a length multiple of 4
+    fn fragment_push_constants(&self) -> Vec<u8> {
+        Vec::new()
+    }
 }
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use anyhow::Result;
 use vulkano::{
     buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
     descriptor_set::{
@@ -11,29 +12,140 @@ use vulkano::{
     },
     device::Device,
     memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
-    shader::ShaderStages,
+    shader::{EntryPoint, ShaderStages},
     sync::Sharing,
 };
 
-use crate::{engine::pipeline_manager::PipelineManager, vulkan_context::VulkanContext};
+use crate::{
+    engine::{
+        pipeline_manager::{self, PipelineManager},
+        renderer::{entity_uniform, frame_uniform},
+    },
+    vulkan_context::VulkanContext,
+};
+
+use super::{DepthBias, Material, MaterialType};
 
-use super::{Material, MaterialType};
+/// Fragment push constants of a material padded to the size of the range
+pub(crate) type FragmentPushConstants =
+    [u8; PipelineManager::MAX_FRAGMENT_PUSH_CONSTANT_SIZE as usize];
 
 struct MaterialBuffer {
-    _material: Box<dyn Material>,
+    material: Box<dyn Material>,
     descriptor_set: Arc<PersistentDescriptorSet>,
+    fragment_push_constants: FragmentPushConstants,
+    /// Replaces the engine's fragment shader in the material pipeline drawing meshes one by one
+    fragment_shader: Option<EntryPoint>,
     _buffer: Subbuffer<[u8]>,
 }
 
+/// Values stored by id. An id packs the index of its slot in the low 32 bits and the generation
+/// of the slot in the high 32 bits. Freed slots are reused with the next generation, so the id
+/// of a removed value never designates the value reusing its slot
+struct Slots<T> {
+    slots: Vec<(u32, Option<T>)>,
+    free_indices: Vec<u32>,
+}
+
+impl<T> Slots<T> {
+    fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free_indices: Vec::new(),
+        }
+    }
+
+    fn split_id(id: u64) -> (usize, u32) {
+        (id as u32 as usize, (id >> 32) as u32)
+    }
+
+    fn id(index: u32, generation: u32) -> u64 {
+        ((generation as u64) << 32) | index as u64
+    }
+
+    fn insert(&mut self, value: T) -> u64 {
+        if let Some(index) = self.free_indices.pop() {
+            let slot = &mut self.slots[index as usize];
+            slot.1 = Some(value);
+            return Self::id(index, slot.0);
+        }
+
+        self.slots.push((0, Some(value)));
+        Self::id(self.slots.len() as u32 - 1, 0)
+    }
+
+    fn remove(&mut self, id: u64) -> Option<T> {
+        let (index, generation) = Self::split_id(id);
+        let slot = self.slots.get_mut(index)?;
+        if slot.0 != generation {
+            return None;
+        }
+
+        let value = slot.1.take()?;
+        slot.0 += 1;
+        self.free_indices.push(index as u32);
+
+        Some(value)
+    }
+
+    fn get(&self, id: u64) -> Option<&T> {
+        let (index, generation) = Self::split_id(id);
+        match self.slots.get(index) {
+            Some((slot_generation, value)) if *slot_generation == generation => value.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Whether `id` was given by `insert`, even if its value has been removed since
+    fn was_inserted(&self, id: u64) -> bool {
+        let (index, generation) = Self::split_id(id);
+        match self.slots.get(index) {
+            Some((slot_generation, value)) => {
+                generation < *slot_generation || (generation == *slot_generation && value.is_some())
+            }
+            None => false,
+        }
+    }
+
+    /// Removes every value, the slots are kept so that their ids stay invalid
+    fn clear(&mut self) {
+        for index in 0..self.slots.len() as u32 {
+            let id = Self::id(index, self.slots[index as usize].0);
+            self.remove(id);
+        }
+    }
+}
+
 pub struct MaterialManager {
-    next_id: u64,
-    materials: Vec<MaterialBuffer>,
+    materials: Slots<MaterialBuffer>,
     material_set_layout: Arc<DescriptorSetLayout>,
+    /// Layouts of the other sets of the material pipelines, which the shaders of the materials
+    /// are checked against
+    frame_set_layout: Arc<DescriptorSetLayout>,
+    entity_set_layout: Arc<DescriptorSetLayout>,
+    /// Bound to the storage bindings a material leaves empty, every binding of the set must be
+    /// written
+    placeholder_storage_buffer: Option<Subbuffer<[u8]>>,
 }
 
 impl MaterialManager {
     pub fn new(device: Arc<Device>) -> Self {
         let material_set_layout = {
+            let storage_bindings = (0..PipelineManager::MAX_MATERIAL_STORAGE_BUFFERS).map(|i| {
+                (
+                    PipelineManager::MATERIAL_STORAGE_BINDING + i,
+                    DescriptorSetLayoutBinding {
+                        descriptor_count: 1,
+                        // Compute passes fill the storage buffers, see
+                        // `Engine::load_material_compute_pipeline`
+                        stages: ShaderStages::VERTEX
+                            | ShaderStages::FRAGMENT
+                            | ShaderStages::COMPUTE,
+                        ..DescriptorSetLayoutBinding::descriptor_type(DescriptorType::StorageBuffer)
+                    },
+                )
+            });
+
             let set_info = DescriptorSetLayoutCreateInfo {
                 bindings: [(
                     PipelineManager::MATERIAL_BINDING,
@@ -44,6 +156,7 @@ impl MaterialManager {
                     },
                 )]
                 .into_iter()
+                .chain(storage_bindings)
                 .collect(),
                 ..Default::default()
             };
@@ -51,11 +164,17 @@ impl MaterialManager {
             DescriptorSetLayout::new(Arc::clone(&device), set_info)
                 .expect("Failed to create descriptor set layout")
         };
+        let frame_set_layout = frame_uniform::create_frame_set_layout(&device)
+            .expect("Failed to create descriptor set layout");
+        let entity_set_layout = entity_uniform::create_entity_set_layout(&device)
+            .expect("Failed to create descriptor set layout");
 
         Self {
-            next_id: 0,
-            materials: Vec::new(),
+            materials: Slots::new(),
             material_set_layout,
+            frame_set_layout,
+            entity_set_layout,
+            placeholder_storage_buffer: None,
         }
     }
 
@@ -64,8 +183,76 @@ impl MaterialManager {
         material: T,
         vulkan_context: Arc<VulkanContext>,
     ) -> u64 {
-        let id = self.next_id;
-        self.next_id += 1;
+        self.insert_material(material, None, vulkan_context)
+    }
+
+    /// Fails if the shader does not match the layout of the material pipelines, see
+    /// `Scene::new_material_with_shader`
+    pub fn new_material_with_shader<T: Material + 'static>(
+        &mut self,
+        material: T,
+        fragment_spirv: &[u32],
+        vulkan_context: Arc<VulkanContext>,
+    ) -> Result<u64> {
+        let fragment_shader =
+            pipeline_manager::load_user_shader(vulkan_context.device(), fragment_spirv)?;
+
+        self.new_material_with_entry_point(material, fragment_shader, vulkan_context)
+    }
+
+    /// Checks that the descriptors and push constants `fragment_shader` reads are those of the
+    /// material pipelines before adding the material
+    pub(crate) fn new_material_with_entry_point<T: Material + 'static>(
+        &mut self,
+        material: T,
+        fragment_shader: EntryPoint,
+        vulkan_context: Arc<VulkanContext>,
+    ) -> Result<u64> {
+        pipeline_manager::validate_material_fragment_shader(
+            &fragment_shader,
+            &[
+                Arc::clone(&self.material_set_layout),
+                Arc::clone(&self.frame_set_layout),
+                Arc::clone(&self.entity_set_layout),
+            ],
+        )?;
+
+        Ok(self.insert_material(material, Some(fragment_shader), vulkan_context))
+    }
+
+    fn insert_material<T: Material + 'static>(
+        &mut self,
+        material: T,
+        fragment_shader: Option<EntryPoint>,
+        vulkan_context: Arc<VulkanContext>,
+    ) -> u64 {
+        let shader_data = material.shader_data();
+        assert_eq!(
+            shader_data.len(),
+            material.data_size(),
+            "Material shader data does not match the size expected by the shader"
+        );
+
+        let storage_buffers = material.storage_buffers();
+        assert!(
+            storage_buffers.len() <= PipelineManager::MAX_MATERIAL_STORAGE_BUFFERS as usize,
+            "Materials can't bind more than {} storage buffers",
+            PipelineManager::MAX_MATERIAL_STORAGE_BUFFERS
+        );
+
+        let push_constant_data = material.fragment_push_constants();
+        assert!(
+            push_constant_data.len() <= PipelineManager::MAX_FRAGMENT_PUSH_CONSTANT_SIZE as usize,
+            "Materials can't push more than {} bytes to the fragment shader",
+            PipelineManager::MAX_FRAGMENT_PUSH_CONSTANT_SIZE
+        );
+        assert!(
+            push_constant_data.len() % 4 == 0,
+            "Fragment push constants must be a multiple of 4 bytes long"
+        );
+        let mut fragment_push_constants =
+            [0; PipelineManager::MAX_FRAGMENT_PUSH_CONSTANT_SIZE as usize];
+        fragment_push_constants[..push_constant_data.len()].copy_from_slice(&push_constant_data);
 
         let descriptor_allocator = vulkan_context.standard_descripor_set_allocator();
         let buffer_allocator = Arc::clone(vulkan_context.standard_memory_allocator());
@@ -81,34 +268,75 @@ impl MaterialManager {
                 memory_type_filter: MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
                 ..Default::default()
             },
-            material.shader_data(),
+            shader_data,
         )
         .expect("Failed to allocate buffer");
 
+        let placeholder = self
+            .placeholder_storage_buffer
+            .get_or_insert_with(|| create_placeholder_storage_buffer(&vulkan_context))
+            .clone();
+
+        let mut writes = vec![WriteDescriptorSet::buffer(
+            PipelineManager::MATERIAL_BINDING,
+            buffer.clone(),
+        )];
+        for i in 0..PipelineManager::MAX_MATERIAL_STORAGE_BUFFERS {
+            let storage_buffer = storage_buffers
+                .get(i as usize)
+                .cloned()
+                .unwrap_or_else(|| placeholder.clone());
+
+            writes.push(WriteDescriptorSet::buffer(
+                PipelineManager::MATERIAL_STORAGE_BINDING + i,
+                storage_buffer,
+            ));
+        }
+
         let descriptor_set = PersistentDescriptorSet::new(
             descriptor_allocator.as_ref(),
             Arc::clone(&self.material_set_layout),
-            vec![WriteDescriptorSet::buffer(
-                PipelineManager::MATERIAL_BINDING,
-                buffer.clone(),
-            )],
+            writes,
             Vec::new(),
         )
         .expect("Failed to create persistant descriptor set");
 
-        self.materials.push(MaterialBuffer {
-            _material: Box::new(material),
+        let material_buffer = MaterialBuffer {
+            material: Box::new(material),
             descriptor_set,
+            fragment_push_constants,
+            fragment_shader,
             _buffer: buffer,
-        });
+        };
+
+        // Slots freed by `remove_material` are reused before growing the list, with another id
+        self.materials.insert(material_buffer)
+    }
+
+    pub fn remove_material(&mut self, id: u64) {
+        assert!(
+            self.materials.remove(id).is_some(),
+            "Material {id} does not exist in the material manager"
+        );
+    }
 
-        id
+    /// Ids of the removed materials stay invalid, they are never given to new materials
+    pub fn clear(&mut self) {
+        self.materials.clear();
+    }
+
+    pub fn contains_material(&self, id: u64) -> bool {
+        self.materials.get(id).is_some()
     }
 
     pub fn _material_type(&self, id: u64) -> Option<MaterialType> {
         self.materials
-            .get(id as usize)
-            .map(|material| material._material.material_type())
+            .get(id)
+            .map(|material| material.material.material_type())
+    }
+
+    pub fn depth_bias(&self, material_id: u64) -> DepthBias {
+        self.material_buffer(material_id).material.depth_bias()
     }
 
     pub fn _material<SimpleMaterial>(_id: u64) -> Option<SimpleMaterial> {
@@ -116,10 +344,385 @@ impl MaterialManager {
     }
 
     pub fn descriptor_set(&self, material_id: u64) -> &Arc<PersistentDescriptorSet> {
-        &self.materials[material_id as usize].descriptor_set
+        &self.material_buffer(material_id).descriptor_set
+    }
+
+    pub(crate) fn fragment_push_constants(&self, material_id: u64) -> &FragmentPushConstants {
+        &self.material_buffer(material_id).fragment_push_constants
+    }
+
+    /// Fragment shader given to `new_material_with_shader`, `None` for the materials shaded by the
+    /// engine
+    pub(crate) fn fragment_shader(&self, material_id: u64) -> Option<&EntryPoint> {
+        self.material_buffer(material_id).fragment_shader.as_ref()
+    }
+
+    fn material_buffer(&self, material_id: u64) -> &MaterialBuffer {
+        match self.materials.get(material_id) {
+            Some(material) => material,
+            None if self.materials.was_inserted(material_id) => {
+                panic!("Material {material_id} has been removed")
+            }
+            None => panic!("Material {material_id} does not exist in the material manager"),
+        }
     }
 
     pub fn material_set_layout(&self) -> &Arc<DescriptorSetLayout> {
         &self.material_set_layout
     }
+
+    pub fn frame_set_layout(&self) -> &Arc<DescriptorSetLayout> {
+        &self.frame_set_layout
+    }
+
+    pub fn entity_set_layout(&self) -> &Arc<DescriptorSetLayout> {
+        &self.entity_set_layout
+    }
+}
+
+fn create_placeholder_storage_buffer(vulkan_context: &VulkanContext) -> Subbuffer<[u8]> {
+    Buffer::new_slice(
+        Arc::clone(vulkan_context.standard_memory_allocator()),
+        BufferCreateInfo {
+            sharing: Sharing::Exclusive,
+            usage: BufferUsage::STORAGE_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+            ..Default::default()
+        },
+        16,
+    )
+    .expect("Failed to allocate buffer")
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::{event_loop::EventLoop, window::WindowBuilder};
+
+    use super::*;
+    use crate::{
+        engine::{compute, material::simple_material::SimpleMaterial},
+        vulkan_context::VulkanContextInfo,
+    };
+
+    fn create_material_manager() -> (MaterialManager, Arc<VulkanContext>) {
+        let dummy_window = WindowBuilder::new()
+            .build(&EventLoop::new().unwrap())
+            .unwrap();
+        let vulkan_context =
+            VulkanContext::new(&Arc::new(dummy_window), VulkanContextInfo::default()).unwrap();
+
+        (
+            MaterialManager::new(Arc::clone(vulkan_context.device())),
+            Arc::new(vulkan_context),
+        )
+    }
+
+    vulkano_shaders::shader! {
+        shaders: {
+            matching: {
+                ty: "fragment",
+                src: r"
+                    #version 450
+
+                    layout(location = 0) in vec3 normal;
+                    layout(location = 0) out vec4 out_color;
+
+                    layout(set = 0, binding = 0) uniform MatchingMaterial { vec4 color; } material;
+                    layout(set = 0, binding = 1) readonly buffer Palette { vec4 colors[]; } palette;
+                    layout(set = 1, binding = 0) uniform MatchingFrame { vec2 resolution; float time; } frame;
+                    layout(set = 2, binding = 0) uniform MatchingEntity { float phase; } entity;
+                    layout(push_constant) uniform MatchingConstants {
+                        layout(offset = 208) float strength;
+                    } constants;
+
+                    void main() {
+                        float wave = sin(frame.time + entity.phase) * constants.strength;
+                        out_color = material.color * palette.colors[0] + vec4(normal * wave, 0.0);
+                    }
+                ",
+            },
+            storage_as_uniform: {
+                ty: "fragment",
+                src: r"
+                    #version 450
+
+                    layout(location = 0) out vec4 out_color;
+
+                    layout(set = 0, binding = 0) readonly buffer StorageMaterial { vec4 color; } material;
+
+                    void main() {
+                        out_color = material.color;
+                    }
+                ",
+            },
+            missing_set: {
+                ty: "fragment",
+                src: r"
+                    #version 450
+
+                    layout(location = 0) out vec4 out_color;
+
+                    layout(set = 3, binding = 0) uniform MissingSet { vec4 color; } extra;
+
+                    void main() {
+                        out_color = extra.color;
+                    }
+                ",
+            },
+            vertex_push_constants: {
+                ty: "fragment",
+                src: r"
+                    #version 450
+
+                    layout(location = 0) out vec4 out_color;
+
+                    layout(push_constant) uniform VertexConstants { vec4 color; } constants;
+
+                    void main() {
+                        out_color = constants.color;
+                    }
+                ",
+            },
+            doubling: {
+                ty: "compute",
+                src: r"
+                    #version 450
+
+                    layout(local_size_x = 4) in;
+
+                    layout(set = 0, binding = 1) readonly buffer Source { uint values[]; } source;
+                    layout(set = 0, binding = 2) buffer Doubled { uint values[]; } doubled;
+
+                    void main() {
+                        uint i = gl_GlobalInvocationID.x;
+                        doubled.values[i] = source.values[i] * 2;
+                    }
+                ",
+            },
+            uniform_as_storage: {
+                ty: "compute",
+                src: r"
+                    #version 450
+
+                    layout(local_size_x = 1) in;
+
+                    layout(set = 0, binding = 0) buffer UniformAsStorage { uint values[]; } material;
+
+                    void main() {
+                        material.values[0] = 0;
+                    }
+                ",
+            },
+        }
+    }
+
+    /// Material exposing storage buffers to the compute shaders
+    struct StorageMaterial {
+        storage_buffers: Vec<Subbuffer<[u8]>>,
+    }
+
+    impl Material for StorageMaterial {
+        fn material_type(&self) -> MaterialType {
+            MaterialType::Simple
+        }
+
+        fn data_size(&self) -> usize {
+            16
+        }
+
+        fn shader_data(&self) -> Vec<u8> {
+            vec![0; 16]
+        }
+
+        fn storage_buffers(&self) -> Vec<Subbuffer<[u8]>> {
+            self.storage_buffers.clone()
+        }
+    }
+
+    fn create_storage_buffer(vulkan_context: &VulkanContext, values: [u32; 4]) -> Subbuffer<[u32]> {
+        Buffer::from_iter(
+            Arc::clone(vulkan_context.standard_memory_allocator()),
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                ..Default::default()
+            },
+            values,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn material_shader_matching_the_layout_is_accepted() {
+        let (mut material_manager, vulkan_context) = create_material_manager();
+        let shader = load_matching(Arc::clone(vulkan_context.device()))
+            .unwrap()
+            .entry_point("main")
+            .unwrap();
+
+        let material = material_manager
+            .new_material_with_entry_point(
+                SimpleMaterial::new(1.0, 1.0, 1.0),
+                shader,
+                vulkan_context,
+            )
+            .unwrap();
+        assert!(material_manager.fragment_shader(material).is_some());
+    }
+
+    #[test]
+    fn mismatched_material_shaders_are_rejected() {
+        let (mut material_manager, vulkan_context) = create_material_manager();
+        let device = vulkan_context.device();
+
+        let shaders = [
+            (
+                load_storage_as_uniform(Arc::clone(device)).unwrap(),
+                "expects set 0 binding 0",
+            ),
+            (
+                load_missing_set(Arc::clone(device)).unwrap(),
+                "reads set 3 binding 0",
+            ),
+            (
+                load_vertex_push_constants(Arc::clone(device)).unwrap(),
+                "push constants from byte 0",
+            ),
+        ];
+        for (module, expected_error) in shaders {
+            let error = material_manager
+                .new_material_with_entry_point(
+                    SimpleMaterial::new(1.0, 1.0, 1.0),
+                    module.entry_point("main").unwrap(),
+                    Arc::clone(&vulkan_context),
+                )
+                .unwrap_err();
+            assert!(
+                error.to_string().contains(expected_error),
+                "{error} does not mention {expected_error}"
+            );
+        }
+
+        // The rejected materials were not added
+        assert_eq!(material_manager.materials.slots.len(), 0);
+    }
+
+    #[test]
+    fn compute_shader_reads_and_writes_material_storage_buffers() {
+        let (mut material_manager, vulkan_context) = create_material_manager();
+        let source = create_storage_buffer(&vulkan_context, [1, 2, 3, 4]);
+        let doubled = create_storage_buffer(&vulkan_context, [0; 4]);
+
+        let material = material_manager.new_material(
+            StorageMaterial {
+                storage_buffers: vec![source.into_bytes(), doubled.clone().into_bytes()],
+            },
+            Arc::clone(&vulkan_context),
+        );
+
+        let pipeline = pipeline_manager::load_material_compute(
+            vulkan_context.device(),
+            vulkan_context.pipeline_cache(),
+            Arc::clone(material_manager.material_set_layout()),
+            load_doubling(Arc::clone(vulkan_context.device()))
+                .unwrap()
+                .entry_point("main")
+                .unwrap(),
+        )
+        .unwrap();
+        compute::dispatch(
+            &vulkan_context,
+            &pipeline,
+            Arc::clone(material_manager.descriptor_set(material)),
+            [1, 1, 1],
+        )
+        .unwrap();
+
+        assert_eq!(&*doubled.read().unwrap(), &[2, 4, 6, 8]);
+    }
+
+    #[test]
+    fn mismatched_material_compute_shader_is_rejected() {
+        let (material_manager, vulkan_context) = create_material_manager();
+        let device = vulkan_context.device();
+
+        let error = pipeline_manager::load_material_compute(
+            device,
+            vulkan_context.pipeline_cache(),
+            Arc::clone(material_manager.material_set_layout()),
+            load_uniform_as_storage(Arc::clone(device))
+                .unwrap()
+                .entry_point("main")
+                .unwrap(),
+        )
+        .unwrap_err();
+        assert!(
+            error.to_string().contains("expects set 0 binding 0"),
+            "{error}"
+        );
+
+        // Fragment shaders are not compute shaders even if their descriptors match
+        let error = pipeline_manager::load_material_compute(
+            device,
+            vulkan_context.pipeline_cache(),
+            Arc::clone(material_manager.material_set_layout()),
+            load_matching(Arc::clone(device))
+                .unwrap()
+                .entry_point("main")
+                .unwrap(),
+        )
+        .unwrap_err();
+        assert!(
+            error.to_string().contains("instead of a compute shader"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn removed_id_is_not_reused() {
+        let mut slots = Slots::new();
+        let first = slots.insert("first");
+        assert_eq!(slots.remove(first), Some("first"));
+
+        let second = slots.insert("second");
+        assert_ne!(first, second);
+        assert_eq!(slots.get(first), None);
+        assert_eq!(slots.get(second), Some(&"second"));
+        assert!(slots.was_inserted(first));
+
+        // The removed id can't remove the value reusing its slot
+        assert_eq!(slots.remove(first), None);
+        assert_eq!(slots.get(second), Some(&"second"));
+    }
+
+    #[test]
+    fn cleared_ids_stay_invalid() {
+        let mut slots = Slots::new();
+        let ids = [slots.insert(1), slots.insert(2)];
+        slots.clear();
+
+        let new_ids = [slots.insert(3), slots.insert(4)];
+        for id in ids {
+            assert_eq!(slots.get(id), None);
+            assert!(!new_ids.contains(&id));
+        }
+        assert_eq!(slots.get(new_ids[0]), Some(&3));
+        assert_eq!(slots.get(new_ids[1]), Some(&4));
+    }
+
+    #[test]
+    fn unknown_id_was_not_inserted() {
+        let mut slots = Slots::new();
+        let id = slots.insert(());
+
+        assert!(!slots.was_inserted(id + 1));
+        assert!(!slots.was_inserted(id + (1 << 32)));
+    }
 }
@@ -1,17 +1,47 @@
 use glam::Vec3;
 
-use super::{Material, MaterialType};
+use crate::engine::color;
 
+use super::{DepthBias, Material, MaterialType};
+
+/// Flat colored material, `color` is in linear space
 pub struct SimpleMaterial {
     pub color: Vec3,
+    /// Fraction of the color kept by the faces turned away from the light
+    pub ambient: f32,
+    pub depth_bias: DepthBias,
 }
 
 impl SimpleMaterial {
     pub fn new(r: f32, g: f32, b: f32) -> Self {
         Self {
             color: Vec3::new(r, g, b),
+            ambient: 0.0,
+            depth_bias: DepthBias::default(),
+        }
+    }
+
+    /// Material from sRGB encoded channels, as given by color pickers
+    pub fn from_srgb(r: f32, g: f32, b: f32) -> Self {
+        Self {
+            color: color::srgb(r, g, b),
+            ambient: 0.0,
+            depth_bias: DepthBias::default(),
         }
     }
+
+    pub fn with_depth_bias(mut self, constant_factor: f32, slope_factor: f32) -> Self {
+        self.depth_bias = DepthBias {
+            constant_factor,
+            slope_factor,
+        };
+        self
+    }
+
+    pub fn with_ambient(mut self, ambient: f32) -> Self {
+        self.ambient = ambient;
+        self
+    }
 }
 
 impl Material for SimpleMaterial {
@@ -19,12 +49,70 @@ impl Material for SimpleMaterial {
         MaterialType::Simple
     }
 
+    fn data_size(&self) -> usize {
+        // A vec3 is aligned to 16 bytes in std140
+        4 * std::mem::size_of::<f32>()
+    }
+
     fn shader_data(&self) -> Vec<u8> {
         self.color
+            .extend(0.0)
             .to_array()
             .into_iter()
             .map(|x| x.to_bits().to_ne_bytes())
             .flatten()
             .collect()
     }
+
+    fn depth_bias(&self) -> DepthBias {
+        self.depth_bias
+    }
+
+    fn fragment_push_constants(&self) -> Vec<u8> {
+        self.ambient.to_ne_bytes().to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shader_data_is_std140_padded() {
+        let material = SimpleMaterial::new(0.25, 0.5, 1.0);
+        let data = material.shader_data();
+
+        assert_eq!(data.len(), material.data_size());
+        assert_eq!(&data[0..4], &0.25f32.to_ne_bytes());
+        assert_eq!(&data[4..8], &0.5f32.to_ne_bytes());
+        assert_eq!(&data[8..12], &1.0f32.to_ne_bytes());
+    }
+
+    #[test]
+    fn ambient_is_pushed_to_the_fragment_shader() {
+        let material = SimpleMaterial::new(1.0, 1.0, 1.0).with_ambient(0.25);
+
+        assert_eq!(material.fragment_push_constants(), 0.25f32.to_ne_bytes());
+        assert_eq!(
+            SimpleMaterial::new(1.0, 1.0, 1.0).fragment_push_constants(),
+            0.0f32.to_ne_bytes()
+        );
+    }
+
+    /// sRGB 0.5 in linear space
+    const LINEAR_MID_GRAY: f32 = 0.21404;
+
+    #[test]
+    fn mid_gray_material_matches_mid_gray_clear() {
+        let material = SimpleMaterial::from_srgb(0.5, 0.5, 0.5);
+        let clear_color = crate::engine::renderer::clear_color();
+
+        for channel in material.color.to_array() {
+            assert!((channel - LINEAR_MID_GRAY).abs() < 1e-4);
+        }
+        for channel in &clear_color[..3] {
+            assert!((channel - LINEAR_MID_GRAY).abs() < 1e-4);
+        }
+        assert_eq!(clear_color[3], 1.0);
+    }
 }
@@ -1,16 +1,24 @@
-use glam::{Vec2, Vec3};
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{anyhow, Result};
+use glam::{Mat3, UVec4, Vec2, Vec3, Vec4};
 use vulkano::{
     buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::AutoCommandBufferBuilder,
     memory::allocator::{AllocationCreateInfo, MemoryAllocatePreference, MemoryTypeFilter},
-    pipeline::graphics::vertex_input,
+    pipeline::graphics::{input_assembly::PrimitiveTopology, vertex_input},
     sync::Sharing,
 };
 
-use super::Engine;
+use self::obj::MeshData;
+
+use super::{transform::Transform, Engine};
 
+pub mod loader;
+pub mod obj;
 pub mod primitives;
 
-#[derive(BufferContents, vertex_input::Vertex)]
+#[derive(BufferContents, vertex_input::Vertex, Clone, Copy)]
 #[repr(C)]
 pub struct Vertex {
     #[format(R32G32B32_SFLOAT)]
@@ -24,6 +32,50 @@ pub struct Vertex {
 
     #[format(R32G32B32_SFLOAT)]
     pub in_color: Vec3,
+
+    /// Tangent in xyz and handedness of the bitangent in w
+    #[format(R32G32B32A32_SFLOAT)]
+    pub in_tangent: Vec4,
+}
+
+/// Most bones a skinned mesh can reference, and most bone matrices of its pose
+pub const MAX_BONES: u32 = 256;
+
+/// Vertex buffer binding of the bone stream of skinned meshes, the vertices being at binding 0
+pub(crate) const BONE_VERTEX_BINDING: u32 = 1;
+
+/// Bones influencing a vertex of a skinned mesh, kept in a stream of their own so that static
+/// meshes don't carry them. See `Mesh::skinned`
+#[derive(BufferContents, vertex_input::Vertex, Clone, Copy, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct BoneVertex {
+    #[format(R32G32B32A32_UINT)]
+    pub in_bone_ids: UVec4,
+
+    /// Influence of each bone of `in_bone_ids`, normalized to sum to 1 when the mesh is created
+    #[format(R32G32B32A32_SFLOAT)]
+    pub in_bone_weights: Vec4,
+}
+
+/// Position of a vertex alone, streamed from its own buffer to the pipelines reading nothing else
+/// so that they fetch a fraction of the vertex data
+#[derive(BufferContents, vertex_input::Vertex, Clone, Copy)]
+#[repr(C)]
+pub(crate) struct PositionVertex {
+    #[format(R32G32B32_SFLOAT)]
+    pub in_position: Vec3,
+}
+
+/// Vertex attributes read by a pipeline, selecting the vertex stream of the meshes bound for it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VertexAttributes {
+    /// Every attribute of `Vertex`
+    All,
+    /// Only `in_position`, read from a stream of `PositionVertex`
+    Position,
+    /// Every attribute of `Vertex` and those of `BoneVertex`, read from the bone stream at
+    /// `BONE_VERTEX_BINDING`
+    Skinned,
 }
 
 impl Default for Vertex {
@@ -33,22 +85,319 @@ impl Default for Vertex {
             in_normal: Vec3::ZERO,
             in_texture_coord: Vec2::ZERO,
             in_color: Vec3::ZERO,
+            in_tangent: Vec4::ZERO,
+        }
+    }
+}
+
+/// Computes per vertex tangents from the positions and texture coordinates of the triangles
+/// (Lengyel's method), orthonormalized against the vertex normals
+pub fn compute_tangents(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut tangents = vec![Vec3::ZERO; vertices.len()];
+    let mut bitangents = vec![Vec3::ZERO; vertices.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let [i0, i1, i2] = [0, 1, 2].map(|i| triangle[i] as usize);
+
+        let edge1 = vertices[i1].in_position - vertices[i0].in_position;
+        let edge2 = vertices[i2].in_position - vertices[i0].in_position;
+        let delta_uv1 = vertices[i1].in_texture_coord - vertices[i0].in_texture_coord;
+        let delta_uv2 = vertices[i2].in_texture_coord - vertices[i0].in_texture_coord;
+
+        let determinant = delta_uv1.x * delta_uv2.y - delta_uv2.x * delta_uv1.y;
+        if determinant.abs() < f32::EPSILON {
+            continue;
+        }
+
+        let r = 1.0 / determinant;
+        let tangent = (edge1 * delta_uv2.y - edge2 * delta_uv1.y) * r;
+        let bitangent = (edge2 * delta_uv1.x - edge1 * delta_uv2.x) * r;
+
+        for i in [i0, i1, i2] {
+            tangents[i] += tangent;
+            bitangents[i] += bitangent;
+        }
+    }
+
+    for (i, vertex) in vertices.iter_mut().enumerate() {
+        let normal = vertex.in_normal;
+        let tangent = (tangents[i] - normal * normal.dot(tangents[i])).normalize_or_zero();
+
+        // Vertices without usable texture coordinates still get a valid tangent frame
+        let tangent = if tangent == Vec3::ZERO {
+            normal.any_orthonormal_vector()
+        } else {
+            tangent
+        };
+
+        let handedness = if normal.cross(tangent).dot(bitangents[i]) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+
+        vertex.in_tangent = tangent.extend(handedness);
+    }
+}
+
+/// Unwelds a triangle list so that every triangle gets its own vertices, with the normal of its
+/// face for a faceted look. Front faces are clockwise, like everywhere in the engine
+pub fn compute_flat_normals(vertices: &[Vertex], indices: &[u32]) -> (Vec<Vertex>, Vec<u32>) {
+    let mut flat_vertices = Vec::with_capacity(indices.len());
+
+    for triangle in indices.chunks_exact(3) {
+        let [v0, v1, v2] = [0, 1, 2].map(|i| vertices[triangle[i] as usize]);
+
+        let edge1 = v1.in_position - v0.in_position;
+        let edge2 = v2.in_position - v0.in_position;
+        let normal = edge2.cross(edge1).normalize_or_zero();
+
+        for vertex in [v0, v1, v2] {
+            flat_vertices.push(Vertex {
+                in_normal: normal,
+                ..vertex
+            });
+        }
+    }
+
+    let flat_indices = (0..flat_vertices.len() as u32).collect();
+
+    (flat_vertices, flat_indices)
+}
+
+/// Splits every triangle of a triangle list into four by inserting a vertex at the middle of each
+/// edge, the triangles sharing an edge share its middle vertex. Every call quadruples the
+/// triangle count. The attributes of the new vertices are interpolated. The winding of the
+/// triangles is kept
+pub fn subdivide(vertices: &[Vertex], indices: &[u32]) -> (Vec<Vertex>, Vec<u32>) {
+    let mut new_vertices = vertices.to_vec();
+    let mut new_indices = Vec::with_capacity(indices.len() * 4);
+    let mut midpoints = HashMap::new();
+
+    let mut midpoint = |a: u32, b: u32| {
+        *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+            let (v0, v1) = (vertices[a as usize], vertices[b as usize]);
+            let tangent = v0.in_tangent.truncate().lerp(v1.in_tangent.truncate(), 0.5);
+
+            new_vertices.push(Vertex {
+                in_position: v0.in_position.lerp(v1.in_position, 0.5),
+                in_normal: v0.in_normal.lerp(v1.in_normal, 0.5).normalize_or_zero(),
+                in_texture_coord: v0.in_texture_coord.lerp(v1.in_texture_coord, 0.5),
+                in_color: v0.in_color.lerp(v1.in_color, 0.5),
+                in_tangent: tangent.normalize_or_zero().extend(v0.in_tangent.w),
+            });
+
+            new_vertices.len() as u32 - 1
+        })
+    };
+
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]];
+        let ab = midpoint(a, b);
+        let bc = midpoint(b, c);
+        let ca = midpoint(c, a);
+
+        new_indices.extend_from_slice(&[a, ab, ca, ab, b, bc, ca, bc, c, ab, bc, ca]);
+    }
+
+    (new_vertices, new_indices)
+}
+
+/// Scales the weights of every vertex to sum to 1, negative weights counting as 0. Vertices
+/// without any weight follow their first bone
+pub fn normalize_bone_weights(bones: &mut [BoneVertex]) {
+    for bone in bones.iter_mut() {
+        let weights = bone.in_bone_weights.max(Vec4::ZERO);
+        let total = weights.dot(Vec4::ONE);
+
+        bone.in_bone_weights = if total > f32::EPSILON {
+            weights / total
+        } else {
+            Vec4::X
+        };
+    }
+}
+
+/// Fails when the bones don't match the vertices one to one or reference a bone past
+/// `MAX_BONES`. Returns the number of bones the pose of the mesh needs
+fn check_bones(vertex_count: usize, bones: &[BoneVertex]) -> Result<u32> {
+    anyhow::ensure!(
+        bones.len() == vertex_count,
+        "Skinned meshes need one bone vertex per vertex, got {} for {vertex_count} vertices",
+        bones.len()
+    );
+
+    let max_bone_id = bones
+        .iter()
+        .map(|bone| bone.in_bone_ids.max_element())
+        .max()
+        .unwrap_or(0);
+    anyhow::ensure!(
+        max_bone_id < MAX_BONES,
+        "Bone {max_bone_id} is past the {MAX_BONES} bones of a skinned mesh"
+    );
+
+    Ok(max_bone_id + 1)
+}
+
+/// Minimum and maximum corners of the box bounding `positions`, both at the origin without any
+fn bounds(positions: impl Iterator<Item = Vec3>) -> (Vec3, Vec3) {
+    positions
+        .fold(None, |bounds, position| match bounds {
+            Some((min, max)) => Some((Vec3::min(min, position), Vec3::max(max, position))),
+            None => Some((position, position)),
+        })
+        .unwrap_or((Vec3::ZERO, Vec3::ZERO))
+}
+
+/// Bakes the transform of each mesh into its vertices and concatenates them into a single
+/// triangle list, the indices and material groups being offset to their new position. Triangles
+/// of meshes mirrored by their transform are flipped to keep facing outwards
+pub fn merge_data(meshes: &[(&MeshData, Transform)]) -> MeshData {
+    let mut merged = MeshData {
+        vertices: Vec::new(),
+        indices: Vec::new(),
+        material_groups: Vec::new(),
+    };
+
+    for (mesh_data, model) in meshes.iter() {
+        let matrix = model.transform();
+        let normal_matrix = Mat3::from_mat4(matrix).inverse().transpose();
+        let tangent_matrix = Mat3::from_mat4(matrix);
+        let mirrored = tangent_matrix.determinant() < 0.0;
+
+        let vertex_offset = merged.vertices.len() as u32;
+        let index_offset = merged.indices.len() as u32;
+
+        merged
+            .vertices
+            .extend(mesh_data.vertices.iter().map(|vertex| {
+                let tangent = tangent_matrix * vertex.in_tangent.truncate();
+
+                Vertex {
+                    in_position: matrix.transform_point3(vertex.in_position),
+                    in_normal: (normal_matrix * vertex.in_normal).normalize_or_zero(),
+                    in_tangent: tangent.normalize_or_zero().extend(vertex.in_tangent.w),
+                    ..*vertex
+                }
+            }));
+
+        for triangle in mesh_data.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| triangle[i] + vertex_offset);
+            if mirrored {
+                merged.indices.extend_from_slice(&[a, c, b]);
+            } else {
+                merged.indices.extend_from_slice(&[a, b, c]);
+            }
+        }
+
+        merged
+            .material_groups
+            .extend(mesh_data.material_groups.iter().map(|group| MaterialGroup {
+                index_offset: group.index_offset + index_offset,
+                ..group.clone()
+            }));
+    }
+
+    merged
+}
+
+/// How the indices of a mesh are assembled into primitives, the renderer keeps a set of pipelines
+/// per topology in use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeshTopology {
+    pub topology: PrimitiveTopology,
+    /// Makes the index `u32::MAX` start a new strip, only allowed with strip topologies
+    pub primitive_restart: bool,
+}
+
+impl MeshTopology {
+    pub const TRIANGLE_LIST: Self = Self {
+        topology: PrimitiveTopology::TriangleList,
+        primitive_restart: false,
+    };
+
+    pub const TRIANGLE_STRIP: Self = Self {
+        topology: PrimitiveTopology::TriangleStrip,
+        primitive_restart: true,
+    };
+
+    pub const LINE_STRIP: Self = Self {
+        topology: PrimitiveTopology::LineStrip,
+        primitive_restart: true,
+    };
+
+    /// Index ending the current strip when primitive restart is enabled
+    pub const RESTART_INDEX: u32 = u32::MAX;
+}
+
+impl Default for MeshTopology {
+    fn default() -> Self {
+        Self::TRIANGLE_LIST
+    }
+}
+
+/// Range of the indices of a mesh drawn with its own material, see `MeshComponent::submeshes`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Submesh {
+    pub index_offset: u32,
+    pub index_count: u32,
+    pub material: u64,
+}
+
+/// Range of the indices of an imported mesh using one material of the source file, mapped to a
+/// material of the scene with `submesh`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaterialGroup {
+    /// Name of the material in the source file, empty for the faces declared before any material
+    pub material_name: String,
+    pub index_offset: u32,
+    pub index_count: u32,
+}
+
+impl MaterialGroup {
+    pub fn submesh(&self, material: u64) -> Submesh {
+        Submesh {
+            index_offset: self.index_offset,
+            index_count: self.index_count,
+            material,
         }
     }
 }
 
 pub struct Mesh {
     vertex_buffer: Subbuffer<[Vertex]>,
+    /// Positions of `vertex_buffer`, see `VertexAttributes::Position`
+    position_buffer: Subbuffer<[PositionVertex]>,
+    /// Only created for skinned meshes, see `VertexAttributes::Skinned`
+    bone_buffer: Option<Subbuffer<[BoneVertex]>>,
+    /// Bones referenced by `bone_buffer`, the pose of the mesh needs at least as many
+    bone_count: u32,
     index_buffer: Subbuffer<[u32]>,
+    /// Minimum and maximum corners of the box bounding the vertices
+    bounds: (Vec3, Vec3),
+    topology: MeshTopology,
+    material_groups: Vec<MaterialGroup>,
 }
 
 impl Mesh {
-    pub fn new(engine: &Engine, vertices: Vec<Vertex>, indices: Vec<u32>) -> Self {
+    /// Fails when the buffers can't be allocated, for example when running out of memory
+    pub fn new(engine: &Engine, vertices: Vec<Vertex>, indices: Vec<u32>) -> Result<Self> {
+        Self::with_topology(engine, vertices, indices, MeshTopology::TRIANGLE_LIST)
+    }
+
+    /// Mesh whose indices describe strips or lines instead of a list of triangles
+    pub fn with_topology(
+        engine: &Engine,
+        vertices: Vec<Vertex>,
+        indices: Vec<u32>,
+        topology: MeshTopology,
+    ) -> Result<Self> {
         let allocator = engine.vulkan_context().standard_memory_allocator();
 
         let vertex_buffer_info = BufferCreateInfo {
             sharing: Sharing::Exclusive, // TODO: handle sharing across different queues
-            usage: BufferUsage::VERTEX_BUFFER,
+            usage: BufferUsage::VERTEX_BUFFER | BufferUsage::TRANSFER_SRC,
             ..Default::default()
         };
 
@@ -59,17 +408,30 @@ impl Mesh {
             ..Default::default()
         };
 
+        let bounds = bounds(vertices.iter().map(|vertex| vertex.in_position));
+        let positions = vertices
+            .iter()
+            .map(|vertex| PositionVertex {
+                in_position: vertex.in_position,
+            })
+            .collect::<Vec<_>>();
+        let position_buffer = Buffer::from_iter(
+            allocator.clone(),
+            vertex_buffer_info.clone(),
+            vertex_allocation_info.clone(),
+            positions,
+        )?;
+
         let vertex_buffer = Buffer::from_iter(
             allocator.clone(),
             vertex_buffer_info,
             vertex_allocation_info,
             vertices,
-        )
-        .expect("Failed to create vertex buffer");
+        )?;
 
         let index_buffer_info = BufferCreateInfo {
             sharing: Sharing::Exclusive, // TODO: handle sharing across different queues
-            usage: BufferUsage::INDEX_BUFFER,
+            usage: BufferUsage::INDEX_BUFFER | BufferUsage::TRANSFER_SRC,
             ..Default::default()
         };
 
@@ -85,20 +447,326 @@ impl Mesh {
             index_buffer_info,
             index_allocation_info,
             indices,
-        )
-        .expect("Failed to create index buffer");
+        )?;
 
-        Self {
+        Ok(Self {
             vertex_buffer,
+            position_buffer,
+            bone_buffer: None,
+            bone_count: 0,
             index_buffer,
-        }
+            bounds,
+            topology,
+            material_groups: Vec::new(),
+        })
+    }
+
+    /// Mesh deformed by a skeleton, drawn with a `SkinnedMeshComponent`. `bones` holds the bones
+    /// of each vertex, their weights are normalized. Fails when the bones don't match the
+    /// vertices or reference a bone past `MAX_BONES`
+    pub fn skinned(
+        engine: &Engine,
+        vertices: Vec<Vertex>,
+        mut bones: Vec<BoneVertex>,
+        indices: Vec<u32>,
+    ) -> Result<Self> {
+        let bone_count = check_bones(vertices.len(), &bones)?;
+        normalize_bone_weights(&mut bones);
+
+        let allocator = engine.vulkan_context().standard_memory_allocator();
+
+        let bone_buffer = Buffer::from_iter(
+            allocator.clone(),
+            BufferCreateInfo {
+                sharing: Sharing::Exclusive,
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                allocate_preference: MemoryAllocatePreference::Unknown,
+                ..Default::default()
+            },
+            bones,
+        )?;
+
+        Ok(Self {
+            bone_buffer: Some(bone_buffer),
+            bone_count,
+            ..Self::new(engine, vertices, indices)?
+        })
+    }
+
+    /// Mesh keeping the material groups of the loaded file
+    pub fn from_data(engine: &Engine, mesh_data: MeshData) -> Result<Self> {
+        Ok(Self {
+            material_groups: mesh_data.material_groups,
+            ..Self::new(engine, mesh_data.vertices, mesh_data.indices)?
+        })
+    }
+
+    /// Single mesh made of many static meshes placed by their transform, drawn with one draw call
+    /// instead of one per mesh. See `merge_data`
+    pub fn merge(engine: &Engine, meshes: &[(&MeshData, Transform)]) -> Result<Self> {
+        Self::from_data(engine, merge_data(meshes))
+    }
+
+    /// Loads a Wavefront OBJ file on the calling thread, see `Engine::load_mesh_async` to avoid
+    /// stalling the render loop with large files
+    pub fn load_obj(engine: &Engine, path: impl AsRef<Path>) -> Result<Self> {
+        let source = std::fs::read_to_string(path)?;
+        let mesh_data = obj::parse_obj(&source)?;
+
+        Self::from_data(engine, mesh_data)
+    }
+
+    pub fn topology(&self) -> MeshTopology {
+        self.topology
+    }
+
+    /// Groups of faces sharing a material in the file the mesh was loaded from, empty for meshes
+    /// built in code or files without materials
+    pub fn material_groups(&self) -> &[MaterialGroup] {
+        &self.material_groups
+    }
+
+    /// Minimum and maximum corners of the box bounding the vertices, in the space of the mesh. The
+    /// bones of skinned meshes are ignored
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        self.bounds
+    }
+
+    pub fn index_count(&self) -> u32 {
+        self.index_buffer.len() as u32
     }
 
     pub(crate) fn vectex_buffer(&self) -> &Subbuffer<[Vertex]> {
         &self.vertex_buffer
     }
 
+    /// Bones the pose of a skinned mesh needs, 0 for the other meshes
+    pub(crate) fn bone_count(&self) -> u32 {
+        self.bone_count
+    }
+
+    /// Binds at binding 0 the vertex stream holding `attributes`, and the bone stream of skinned
+    /// meshes at `BONE_VERTEX_BINDING`. Fails when binding the bones of a mesh without any
+    pub(crate) fn bind_vertex_stream<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        attributes: VertexAttributes,
+    ) -> Result<()> {
+        match attributes {
+            VertexAttributes::All => builder.bind_vertex_buffers(0, self.vertex_buffer.clone())?,
+            VertexAttributes::Position => {
+                builder.bind_vertex_buffers(0, self.position_buffer.clone())?
+            }
+            VertexAttributes::Skinned => {
+                let bone_buffer = self.bone_buffer.as_ref().ok_or_else(|| {
+                    anyhow!("Skinned meshes must be created with `Mesh::skinned`")
+                })?;
+
+                builder
+                    .bind_vertex_buffers(0, self.vertex_buffer.clone())?
+                    .bind_vertex_buffers(BONE_VERTEX_BINDING, bone_buffer.clone())?
+            }
+        };
+
+        Ok(())
+    }
+
     pub(crate) fn index_buffer(&self) -> &Subbuffer<[u32]> {
         &self.index_buffer
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tangents_follow_texture_u_axis() {
+        let vertex = |x: f32, y: f32| Vertex {
+            in_position: Vec3::new(x, y, 0.0),
+            in_normal: Vec3::Z,
+            in_texture_coord: Vec2::new(x, y),
+            ..Default::default()
+        };
+        let mut vertices = vec![
+            vertex(0.0, 0.0),
+            vertex(0.0, 1.0),
+            vertex(1.0, 1.0),
+            vertex(1.0, 0.0),
+        ];
+        let indices = vec![0, 1, 3, 1, 2, 3];
+
+        compute_tangents(&mut vertices, &indices);
+
+        for vertex in vertices.iter() {
+            assert!(vertex.in_tangent.abs_diff_eq(Vec4::new(1.0, 0.0, 0.0, 1.0), 1e-5));
+        }
+    }
+
+    #[test]
+    fn tangents_handedness_for_mirrored_uvs() {
+        let vertex = |x: f32, y: f32| Vertex {
+            in_position: Vec3::new(x, y, 0.0),
+            in_normal: Vec3::Z,
+            in_texture_coord: Vec2::new(x, 1.0 - y),
+            ..Default::default()
+        };
+        let mut vertices = vec![vertex(0.0, 0.0), vertex(0.0, 1.0), vertex(1.0, 0.0)];
+
+        compute_tangents(&mut vertices, &[0, 1, 2]);
+
+        for vertex in vertices.iter() {
+            assert_eq!(vertex.in_tangent.w, -1.0);
+        }
+    }
+
+    #[test]
+    fn flat_normals_unweld_triangles() {
+        let vertex = |x: f32, z: f32| Vertex {
+            in_position: Vec3::new(x, 0.0, z),
+            in_normal: Vec3::ONE.normalize(),
+            ..Default::default()
+        };
+        // Quad of the XZ plane facing up, shared vertices
+        let vertices = vec![
+            vertex(0.0, 0.0),
+            vertex(0.0, -1.0),
+            vertex(1.0, -1.0),
+            vertex(1.0, 0.0),
+        ];
+
+        let (flat_vertices, flat_indices) = compute_flat_normals(&vertices, &[0, 1, 3, 1, 2, 3]);
+
+        assert_eq!(flat_vertices.len(), 6);
+        assert_eq!(flat_indices, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(flat_vertices[3].in_position, vertices[1].in_position);
+        for vertex in flat_vertices.iter() {
+            assert!(vertex.in_normal.abs_diff_eq(Vec3::Y, 1e-5));
+        }
+    }
+
+    #[test]
+    fn merge_bakes_transforms_and_offsets_indices() {
+        let vertex = |x: f32, z: f32| Vertex {
+            in_position: Vec3::new(x, 0.0, z),
+            in_normal: Vec3::Y,
+            ..Default::default()
+        };
+        let triangle = MeshData {
+            vertices: vec![vertex(0.0, 0.0), vertex(0.0, -1.0), vertex(1.0, 0.0)],
+            indices: vec![0, 1, 2],
+            material_groups: Vec::new(),
+        };
+
+        let mut moved = Transform::new();
+        moved.translate(Vec3::new(5.0, 0.0, 0.0));
+        let mut upside_down = Transform::new();
+        upside_down.scale(Vec3::new(1.0, -1.0, 1.0));
+
+        let merged = merge_data(&[
+            (&triangle, Transform::new()),
+            (&triangle, moved),
+            (&triangle, upside_down),
+        ]);
+
+        assert_eq!(merged.vertices.len(), 9);
+        assert_eq!(merged.indices, vec![0, 1, 2, 3, 4, 5, 6, 8, 7]);
+        assert_eq!(merged.vertices[3].in_position, Vec3::new(5.0, 0.0, 0.0));
+        assert!(merged.vertices[6].in_normal.abs_diff_eq(Vec3::NEG_Y, 1e-5));
+    }
+
+    #[test]
+    fn subdivide_shares_edge_midpoints() {
+        let vertex = |x: f32, z: f32| Vertex {
+            in_position: Vec3::new(x, 0.0, z),
+            in_normal: Vec3::Y,
+            in_texture_coord: Vec2::new(x, -z),
+            ..Default::default()
+        };
+        let vertices = vec![
+            vertex(0.0, 0.0),
+            vertex(0.0, -1.0),
+            vertex(1.0, -1.0),
+            vertex(1.0, 0.0),
+        ];
+        let indices = [0, 1, 3, 1, 2, 3];
+
+        let (new_vertices, new_indices) = subdivide(&vertices, &indices);
+
+        // 5 edges, the diagonal being shared
+        assert_eq!(new_vertices.len(), 4 + 5);
+        assert_eq!(new_indices.len(), indices.len() * 4);
+
+        let diagonal_middle = new_vertices
+            .iter()
+            .filter(|vertex| vertex.in_position.abs_diff_eq(Vec3::new(0.5, 0.0, -0.5), 1e-6))
+            .collect::<Vec<_>>();
+        assert_eq!(diagonal_middle.len(), 1);
+        assert!(diagonal_middle[0].in_texture_coord.abs_diff_eq(Vec2::splat(0.5), 1e-6));
+
+        // The faces still point up with the clockwise winding
+        for triangle in new_indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| new_vertices[triangle[i] as usize].in_position);
+            assert!((c - a).cross(b - a).normalize().abs_diff_eq(Vec3::Y, 1e-5));
+        }
+    }
+
+    #[test]
+    fn bone_weights_sum_to_one() {
+        let bone = |weights: Vec4| BoneVertex {
+            in_bone_ids: UVec4::new(0, 1, 2, 3),
+            in_bone_weights: weights,
+        };
+        let mut bones = vec![
+            bone(Vec4::new(2.0, 1.0, 1.0, 0.0)),
+            bone(Vec4::new(0.5, -1.0, 0.5, 0.0)),
+            bone(Vec4::ZERO),
+        ];
+
+        normalize_bone_weights(&mut bones);
+
+        assert!(bones[0]
+            .in_bone_weights
+            .abs_diff_eq(Vec4::new(0.5, 0.25, 0.25, 0.0), 1e-6));
+        // Negative weights are dropped
+        assert!(bones[1]
+            .in_bone_weights
+            .abs_diff_eq(Vec4::new(0.5, 0.0, 0.5, 0.0), 1e-6));
+        // Unweighted vertices follow their first bone
+        assert_eq!(bones[2].in_bone_weights, Vec4::X);
+    }
+
+    #[test]
+    fn bones_within_the_limit() {
+        let bone = |id: u32| BoneVertex {
+            in_bone_ids: UVec4::new(0, id, 0, 0),
+            in_bone_weights: Vec4::X,
+        };
+
+        assert_eq!(check_bones(2, &[bone(3), bone(1)]).unwrap(), 4);
+        assert_eq!(check_bones(1, &[bone(MAX_BONES - 1)]).unwrap(), MAX_BONES);
+        assert!(check_bones(1, &[bone(MAX_BONES)]).is_err());
+        // One bone vertex per vertex
+        assert!(check_bones(3, &[bone(0), bone(0)]).is_err());
+    }
+
+    #[test]
+    fn bounds_of_positions() {
+        let positions = [
+            Vec3::new(1.0, -2.0, 0.5),
+            Vec3::new(-1.0, 3.0, 0.0),
+            Vec3::new(0.0, 0.0, 2.0),
+        ];
+
+        assert_eq!(
+            bounds(positions.into_iter()),
+            (Vec3::new(-1.0, -2.0, 0.0), Vec3::new(1.0, 3.0, 2.0))
+        );
+        assert_eq!(bounds(std::iter::empty()), (Vec3::ZERO, Vec3::ZERO));
+    }
+}
@@ -0,0 +1,72 @@
+use std::{
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::engine::Engine;
+
+use super::{
+    obj::{self, MeshData},
+    Mesh,
+};
+
+/// Mesh being loaded on a worker thread. The file is read and parsed in the background, the GPU
+/// buffers are then created on the thread calling `poll` so the allocator is never shared
+pub struct MeshHandle {
+    receiver: Receiver<Result<MeshData>>,
+    mesh_data: Option<Result<MeshData>>,
+}
+
+impl MeshHandle {
+    pub(crate) fn load_obj(path: PathBuf) -> Self {
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mesh_data = std::fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|source| obj::parse_obj(&source));
+
+            // The handle may have been dropped in the meantime
+            let _ = sender.send(mesh_data);
+        });
+
+        Self {
+            receiver,
+            mesh_data: None,
+        }
+    }
+
+    /// Whether the file has been parsed, `poll` then returns the mesh or the loading error
+    pub fn is_ready(&mut self) -> bool {
+        if self.mesh_data.is_none() {
+            self.mesh_data = match self.receiver.try_recv() {
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => {
+                    Some(Err(anyhow!("The mesh loading thread stopped unexpectedly")))
+                }
+                Ok(mesh_data) => Some(mesh_data),
+            };
+        }
+
+        self.mesh_data.is_some()
+    }
+
+    /// Uploads the mesh once it has been parsed, returns `None` while it is still loading. The
+    /// mesh is returned only once, later calls return an error
+    pub fn poll(&mut self, engine: &Engine) -> Result<Option<Mesh>> {
+        if !self.is_ready() {
+            return Ok(None);
+        }
+
+        let mesh_data = std::mem::replace(
+            &mut self.mesh_data,
+            Some(Err(anyhow!("The mesh has already been returned"))),
+        )
+        .unwrap()?;
+
+        Ok(Some(Mesh::from_data(engine, mesh_data)?))
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context, Result};
+use glam::{Vec2, Vec3};
+
+use super::{compute_tangents, MaterialGroup, Vertex};
+
+/// Vertex data parsed from a Wavefront OBJ file, ready to be given to `Mesh::from_data`
+pub struct MeshData {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    /// Consecutive faces sharing a material, empty when the file uses no material
+    pub material_groups: Vec<MaterialGroup>,
+}
+
+/// Parses the positions, texture coordinates, normals, faces and material names (`usemtl`) of an
+/// OBJ file, other statements are ignored. Polygons are triangulated as fans and smooth normals
+/// are computed when the file has none
+pub fn parse_obj(source: &str) -> Result<MeshData> {
+    let mut positions = Vec::new();
+    let mut texture_coords = Vec::new();
+    let mut normals = Vec::new();
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    let mut material_groups: Vec<MaterialGroup> = Vec::new();
+    // Corners sharing the same position, texture coordinate and normal are the same vertex
+    let mut vertex_ids: HashMap<(usize, Option<usize>, Option<usize>), u32> = HashMap::new();
+    let mut has_normals = true;
+
+    for (line_number, line) in source.lines().enumerate() {
+        let mut tokens = line.split_whitespace();
+        let context = || format!("Invalid OBJ statement at line {}", line_number + 1);
+
+        match tokens.next() {
+            Some("v") => {
+                let position = parse_floats::<3>(tokens).with_context(context)?;
+                positions.push(Vec3::from(position));
+            }
+            Some("vt") => {
+                let texture_coord = parse_floats::<2>(tokens).with_context(context)?;
+                texture_coords.push(Vec2::from(texture_coord));
+            }
+            Some("vn") => {
+                let normal = parse_floats::<3>(tokens).with_context(context)?;
+                normals.push(Vec3::from(normal));
+            }
+            Some("f") => {
+                let mut corners = Vec::new();
+                for token in tokens {
+                    let corner = parse_corner(
+                        token,
+                        positions.len(),
+                        texture_coords.len(),
+                        normals.len(),
+                    )
+                    .with_context(context)?;
+
+                    let id = match vertex_ids.get(&corner) {
+                        Some(id) => *id,
+                        None => {
+                            let (position, texture_coord, normal) = corner;
+                            has_normals &= normal.is_some();
+
+                            vertices.push(Vertex {
+                                in_position: positions[position],
+                                in_normal: normal.map_or(Vec3::ZERO, |normal| normals[normal]),
+                                in_texture_coord: texture_coord
+                                    .map_or(Vec2::ZERO, |texture_coord| {
+                                        texture_coords[texture_coord]
+                                    }),
+                                in_color: Vec3::ONE,
+                                ..Default::default()
+                            });
+
+                            let id = vertices.len() as u32 - 1;
+                            vertex_ids.insert(corner, id);
+                            id
+                        }
+                    };
+                    corners.push(id);
+                }
+
+                anyhow::ensure!(corners.len() >= 3, "{}: faces need 3 vertices", context());
+                for i in 1..corners.len() - 1 {
+                    indices.extend([corners[0], corners[i], corners[i + 1]]);
+                }
+
+                if let Some(group) = material_groups.last_mut() {
+                    group.index_count = indices.len() as u32 - group.index_offset;
+                }
+            }
+            Some("usemtl") => {
+                let material_name = tokens.collect::<Vec<_>>().join(" ");
+
+                // Faces declared before the first material still have to be drawn
+                if material_groups.is_empty() && !indices.is_empty() {
+                    material_groups.push(MaterialGroup {
+                        material_name: String::new(),
+                        index_offset: 0,
+                        index_count: indices.len() as u32,
+                    });
+                }
+
+                material_groups.push(MaterialGroup {
+                    material_name,
+                    index_offset: indices.len() as u32,
+                    index_count: 0,
+                });
+            }
+            _ => (),
+        }
+    }
+    material_groups.retain(|group| group.index_count > 0);
+
+    if !has_normals {
+        compute_normals(&mut vertices, &indices);
+    }
+    compute_tangents(&mut vertices, &indices);
+
+    Ok(MeshData {
+        vertices,
+        indices,
+        material_groups,
+    })
+}
+
+fn parse_floats<'a, const N: usize>(
+    mut tokens: impl Iterator<Item = &'a str>,
+) -> Result<[f32; N]> {
+    let mut values = [0.0; N];
+    for value in values.iter_mut() {
+        *value = tokens
+            .next()
+            .ok_or_else(|| anyhow!("Expected {N} values"))?
+            .parse()?;
+    }
+
+    Ok(values)
+}
+
+/// Parses a `v`, `v/vt`, `v//vn` or `v/vt/vn` face corner into zero based indices. Negative
+/// indices are relative to the end of the lists read so far
+fn parse_corner(
+    token: &str,
+    position_count: usize,
+    texture_coord_count: usize,
+    normal_count: usize,
+) -> Result<(usize, Option<usize>, Option<usize>)> {
+    let resolve = |index: &str, count: usize| -> Result<usize> {
+        let index: i64 = index.parse()?;
+        let resolved = if index < 0 {
+            count as i64 + index
+        } else {
+            index - 1
+        };
+
+        anyhow::ensure!(
+            (0..count as i64).contains(&resolved),
+            "Index {index} out of range"
+        );
+        Ok(resolved as usize)
+    };
+
+    let mut parts = token.split('/');
+    let position = resolve(parts.next().unwrap_or(""), position_count)?;
+    let texture_coord = match parts.next() {
+        Some("") | None => None,
+        Some(index) => Some(resolve(index, texture_coord_count)?),
+    };
+    let normal = match parts.next() {
+        Some("") | None => None,
+        Some(index) => Some(resolve(index, normal_count)?),
+    };
+
+    Ok((position, texture_coord, normal))
+}
+
+/// Area weighted vertex normals
+fn compute_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut normals = vec![Vec3::ZERO; vertices.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let [i0, i1, i2] = [0, 1, 2].map(|i| triangle[i] as usize);
+
+        let edge1 = vertices[i1].in_position - vertices[i0].in_position;
+        let edge2 = vertices[i2].in_position - vertices[i0].in_position;
+        let normal = edge1.cross(edge2);
+
+        for i in [i0, i1, i2] {
+            normals[i] += normal;
+        }
+    }
+
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        vertex.in_normal = normal.normalize_or_zero();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quad_is_triangulated_and_shares_vertices() {
+        let source = "
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            vn 0 0 1
+            f 1//1 2//1 3//1 4//1
+        ";
+
+        let mesh_data = parse_obj(source).unwrap();
+
+        assert_eq!(mesh_data.vertices.len(), 4);
+        assert_eq!(mesh_data.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(mesh_data.vertices[2].in_position, Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(mesh_data.vertices[0].in_normal, Vec3::Z);
+    }
+
+    #[test]
+    fn negative_indices_and_missing_normals() {
+        let source = "
+            v 0 0 0
+            v 1 0 0
+            v 0 1 0
+            vt 0 0
+            vt 1 0
+            vt 0 1
+            f -3/-3 -2/-2 -1/-1
+        ";
+
+        let mesh_data = parse_obj(source).unwrap();
+
+        assert_eq!(mesh_data.indices, vec![0, 1, 2]);
+        assert_eq!(mesh_data.vertices[1].in_texture_coord, Vec2::new(1.0, 0.0));
+        for vertex in mesh_data.vertices.iter() {
+            assert!(vertex.in_normal.abs_diff_eq(Vec3::Z, 1e-6));
+        }
+    }
+
+    #[test]
+    fn faces_are_grouped_by_material() {
+        let source = "
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            f 1 2 3
+            usemtl red
+            f 1 2 3 4
+            usemtl unused
+            usemtl blue
+            f 1 3 4
+        ";
+
+        let mesh_data = parse_obj(source).unwrap();
+
+        let groups = mesh_data
+            .material_groups
+            .iter()
+            .map(|group| (group.material_name.as_str(), group.index_offset, group.index_count))
+            .collect::<Vec<_>>();
+        assert_eq!(groups, vec![("", 0, 3), ("red", 3, 6), ("blue", 9, 3)]);
+    }
+
+    #[test]
+    fn out_of_range_index_is_an_error() {
+        assert!(parse_obj("v 0 0 0\nf 1 2 3").is_err());
+    }
+}
@@ -1,47 +1,103 @@
 use std::f32::consts::PI;
 
+use anyhow::Result;
 use glam::{Vec2, Vec3};
 
 use crate::engine::Engine;
 
-use super::{Mesh, Vertex};
+use super::{compute_flat_normals, compute_tangents, obj::MeshData, Mesh, MeshTopology, Vertex};
 
-pub fn make_plane_xz(engine: &Engine, num_cols: u32, num_rows: u32) -> Mesh {
+/// Plane of the XZ axes facing up. A double sided plane also faces down, to be visible from both
+/// sides despite back face culling, like a wall or a floor seen from below
+pub fn make_plane_xz(
+    engine: &Engine,
+    num_cols: u32,
+    num_rows: u32,
+    double_sided: bool,
+) -> Result<Mesh> {
     let vertex_func = |u, v| Vertex {
         in_position: Vec3::new(u - 0.5, 0.0, 0.5 - v),
         in_normal: Vec3::Y,
         in_texture_coord: Vec2::new(u, v),
+        in_color: Vec3::new(u, v, 0.0),
         ..Default::default()
     };
 
-    make_plane(engine, num_cols, num_rows, vertex_func)
+    make_plane(engine, num_cols, num_rows, double_sided, vertex_func)
 }
 
-pub fn make_plane_xy(engine: &Engine, num_cols: u32, num_rows: u32) -> Mesh {
+/// Same plane as `make_plane_xz` drawn as one triangle strip per row of quads, the rows being
+/// separated by primitive restarts
+pub fn make_plane_xz_strip(engine: &Engine, num_cols: u32, num_rows: u32) -> Result<Mesh> {
+    let vertex_func = |u, v| Vertex {
+        in_position: Vec3::new(u - 0.5, 0.0, 0.5 - v),
+        in_normal: Vec3::Y,
+        in_texture_coord: Vec2::new(u, v),
+        in_color: Vec3::new(u, v, 0.0),
+        ..Default::default()
+    };
+
+    let MeshData { vertices, indices, .. } = make_plane_strip_data(num_cols, num_rows, vertex_func);
+
+    Mesh::with_topology(engine, vertices, indices, MeshTopology::TRIANGLE_STRIP)
+}
+
+/// Plane of the XY axes facing +Z, see `make_plane_xz` for `double_sided`
+pub fn make_plane_xy(
+    engine: &Engine,
+    num_cols: u32,
+    num_rows: u32,
+    double_sided: bool,
+) -> Result<Mesh> {
+    let mut mesh_data = plane_xy_data(num_cols, num_rows);
+    if double_sided {
+        add_back_faces(&mut mesh_data);
+    }
+
+    let MeshData { vertices, indices, .. } = mesh_data;
+
+    Mesh::new(engine, vertices, indices)
+}
+
+/// Vertices of a single quad of `make_plane_xy`, kept on the CPU for geometry built every frame
+/// like sprites
+pub(crate) fn quad_xy_data() -> MeshData {
+    plane_xy_data(2, 2)
+}
+
+fn plane_xy_data(num_cols: u32, num_rows: u32) -> MeshData {
     let vertex_func = |u, v| Vertex {
         in_position: Vec3::new(u - 0.5, v - 0.5, 0.0),
         in_normal: Vec3::Z,
         in_texture_coord: Vec2::new(u, v),
+        in_color: Vec3::new(u, v, 0.0),
         ..Default::default()
     };
 
-    make_plane(engine, num_cols, num_rows, vertex_func)
+    make_plane_data(num_cols, num_rows, vertex_func)
 }
 
-pub fn make_plane_yz(engine: &Engine, num_cols: u32, num_rows: u32) -> Mesh {
+/// Plane of the YZ axes facing +X, see `make_plane_xz` for `double_sided`
+pub fn make_plane_yz(
+    engine: &Engine,
+    num_cols: u32,
+    num_rows: u32,
+    double_sided: bool,
+) -> Result<Mesh> {
     let vertex_func = |u, v| Vertex {
         in_position: Vec3::new(0.0, v - 0.5, 0.5 - u),
         in_normal: Vec3::X,
         in_texture_coord: Vec2::new(u, v),
+        in_color: Vec3::new(u, v, 0.0),
         ..Default::default()
     };
 
-    make_plane(engine, num_cols, num_rows, vertex_func)
+    make_plane(engine, num_cols, num_rows, double_sided, vertex_func)
 }
 
-pub fn make_sharp_cube(engine: &Engine) -> Mesh {
+pub fn make_sharp_cube(engine: &Engine) -> Result<Mesh> {
     #[rustfmt::skip]
-    let vertices = vec![
+    let mut vertices = vec![
         // Front
         Vertex { in_position: Vec3::new(-0.5, -0.5,  0.5), in_normal: Vec3::Z, in_texture_coord: Vec2::new(0.0, 0.0), ..Default::default() },
         Vertex { in_position: Vec3::new(-0.5,  0.5,  0.5), in_normal: Vec3::Z, in_texture_coord: Vec2::new(0.0, 1.0), ..Default::default() },
@@ -89,57 +145,156 @@ pub fn make_sharp_cube(engine: &Engine) -> Mesh {
         20, 21, 23,   21, 22, 23, // Bottom
     ];
 
+    // Each corner gets the color of its position in the RGB cube
+    for vertex in vertices.iter_mut() {
+        vertex.in_color = vertex.in_position + 0.5;
+    }
+
+    compute_tangents(&mut vertices, &indices);
+
+    Mesh::new(engine, vertices, indices)
+}
+
+pub fn make_sphere_uv(engine: &Engine, nb_slices: u32, nb_stacks: u32) -> Result<Mesh> {
+    let MeshData { mut vertices, indices, .. } = sphere_uv_data(nb_slices, nb_stacks);
+
+    compute_tangents(&mut vertices, &indices);
+
+    Mesh::new(engine, vertices, indices)
+}
+
+/// Same sphere as `make_sphere_uv` with one normal per triangle instead of smooth normals, for a
+/// faceted low-poly look
+pub fn make_sphere_uv_flat(engine: &Engine, nb_slices: u32, nb_stacks: u32) -> Result<Mesh> {
+    let MeshData { vertices, indices, .. } = sphere_uv_data(nb_slices, nb_stacks);
+
+    let (mut vertices, indices) = compute_flat_normals(&vertices, &indices);
+    compute_tangents(&mut vertices, &indices);
+
     Mesh::new(engine, vertices, indices)
 }
 
-pub fn make_sphere_uv(engine: &Engine, nb_slices: u32, nb_stacks: u32) -> Mesh {
+fn sphere_uv_data(nb_slices: u32, nb_stacks: u32) -> MeshData {
     assert!(nb_slices >= 4, "A sphere needs at least 4 slices");
     assert!(nb_stacks >= 3, "A sphere needs at least 3 stacks");
 
+    let vertex = |u: f32, v: f32| {
+        let theta = 2.0 * PI * u;
+        let phi = PI * v;
+
+        let position = Vec3 {
+            x: theta.cos() * phi.sin(),
+            y: f32::cos(phi),
+            z: theta.sin() * phi.sin(),
+        };
+
+        Vertex {
+            in_position: position * 0.5,
+            in_normal: position,
+            in_texture_coord: Vec2::new(u, v),
+            in_color: position * 0.5 + 0.5,
+            ..Default::default()
+        }
+    };
+
     let mut vertices = Vec::new();
     let mut indices = Vec::new();
 
-    for j in 0..nb_stacks {
-        let v = j as f32 / (nb_stacks as f32 - 1.0);
-        let phi = PI * v;
-        for i in 0..nb_slices {
-            let u = i as f32 / (nb_slices as f32 - 1.0);
-            let theta = 2.0 * PI * u;
-
-            let position = Vec3 {
-                x: theta.cos() * phi.sin(),
-                y: f32::cos(phi),
-                z: theta.sin() * phi.sin(),
-            };
-
-            vertices.push(Vertex {
-                in_position: position * 0.5,
-                in_normal: position,
-                in_texture_coord: Vec2::new(u, v),
-                ..Default::default()
-            });
-        }
+    // The poles get one vertex per slice, in the middle of the slice, so that each triangle
+    // touching a pole maps to its own part of the texture instead of a collapsed row
+    let pole_u = |i: u32| (i as f32 + 0.5) / nb_slices as f32;
+
+    let top_pole = vertices.len() as u32;
+    vertices.extend((0..nb_slices).map(|i| vertex(pole_u(i), 0.0)));
+
+    // Each ring repeats its first vertex with u = 1, the last slice would otherwise wrap back to
+    // u = 0 and squeeze the whole texture into it
+    let first_ring = vertices.len() as u32;
+    for j in 1..nb_stacks {
+        let v = j as f32 / nb_stacks as f32;
+        vertices.extend((0..=nb_slices).map(|i| vertex(i as f32 / nb_slices as f32, v)));
+    }
+    let ring = |j: u32, i: u32| first_ring + (j - 1) * (nb_slices + 1) + i;
+
+    let bottom_pole = vertices.len() as u32;
+    vertices.extend((0..nb_slices).map(|i| vertex(pole_u(i), 1.0)));
+
+    for i in 0..nb_slices {
+        indices.extend([ring(1, i), ring(1, i + 1), top_pole + i]);
     }
 
-    for j in 0..(nb_stacks - 1) {
-        for i in 0..(nb_slices - 1) {
+    for j in 1..(nb_stacks - 1) {
+        for i in 0..nb_slices {
             indices.extend([
                 // First triangle
-                j * nb_slices + i,
-                (j + 1) * nb_slices + i,
-                j * nb_slices + (i + 1),
+                ring(j, i),
+                ring(j + 1, i),
+                ring(j, i + 1),
                 // Second triangle
-                (j + 1) * nb_slices + i,
-                (j + 1) * nb_slices + (i + 1),
-                j * nb_slices + (i + 1),
+                ring(j + 1, i),
+                ring(j + 1, i + 1),
+                ring(j, i + 1),
             ])
         }
     }
 
+    let last_ring = nb_stacks - 1;
+    for i in 0..nb_slices {
+        indices.extend([ring(last_ring, i), bottom_pole + i, ring(last_ring, i + 1)]);
+    }
+
+    MeshData {
+        vertices,
+        indices,
+        material_groups: Vec::new(),
+    }
+}
+
+fn make_plane<F>(
+    engine: &Engine,
+    num_cols: u32,
+    num_rows: u32,
+    double_sided: bool,
+    vertex_func: F,
+) -> Result<Mesh>
+where
+    F: Fn(f32, f32) -> Vertex,
+{
+    let mut mesh_data = make_plane_data(num_cols, num_rows, vertex_func);
+    if double_sided {
+        add_back_faces(&mut mesh_data);
+    }
+
+    let MeshData { vertices, indices, .. } = mesh_data;
+
     Mesh::new(engine, vertices, indices)
 }
 
-fn make_plane<F>(engine: &Engine, num_cols: u32, num_rows: u32, vertex_func: F) -> Mesh
+/// Appends a copy of the triangles facing the other way, with their own vertices so that the
+/// back gets the opposite normals
+fn add_back_faces(mesh_data: &mut MeshData) {
+    let MeshData { vertices, indices, .. } = mesh_data;
+
+    let back_start = vertices.len() as u32;
+    let back_vertices = vertices
+        .iter()
+        .map(|vertex| Vertex {
+            in_normal: -vertex.in_normal,
+            ..*vertex
+        })
+        .collect::<Vec<_>>();
+    let back_indices = indices
+        .chunks_exact(3)
+        .flat_map(|triangle| [triangle[0], triangle[2], triangle[1]].map(|i| back_start + i))
+        .collect::<Vec<_>>();
+
+    vertices.extend(back_vertices);
+    indices.extend(back_indices);
+
+    compute_tangents(vertices, indices);
+}
+
+fn make_plane_data<F>(num_cols: u32, num_rows: u32, vertex_func: F) -> MeshData
 where
     F: Fn(f32, f32) -> Vertex,
 {
@@ -171,5 +326,141 @@ where
         }
     }
 
-    Mesh::new(engine, vertices, indices)
+    compute_tangents(&mut vertices, &indices);
+
+    MeshData {
+        vertices,
+        indices,
+        material_groups: Vec::new(),
+    }
+}
+
+fn make_plane_strip_data<F>(num_cols: u32, num_rows: u32, vertex_func: F) -> MeshData
+where
+    F: Fn(f32, f32) -> Vertex,
+{
+    // Same vertices, tangents included, as the triangle list, only the indices differ
+    let MeshData { vertices, .. } = make_plane_data(num_cols, num_rows, vertex_func);
+
+    let num_cols = num_cols.max(2);
+    let num_rows = num_rows.max(2);
+
+    // Alternating between two rows of vertices gives the same triangles, with the same winding,
+    // as the list
+    let mut indices = Vec::new();
+    for j in 0..(num_rows - 1) {
+        if j > 0 {
+            indices.push(MeshTopology::RESTART_INDEX);
+        }
+
+        for i in 0..num_cols {
+            indices.push(i + j * num_cols);
+            indices.push(i + (j + 1) * num_cols);
+        }
+    }
+
+    MeshData {
+        vertices,
+        indices,
+        material_groups: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Triangles of a strip with primitive restart, in the order and winding Vulkan assembles them
+    fn strip_triangles(indices: &[u32]) -> Vec<[u32; 3]> {
+        indices
+            .split(|index| *index == MeshTopology::RESTART_INDEX)
+            .flat_map(|strip| {
+                strip.windows(3).enumerate().map(|(k, window)| {
+                    if k % 2 == 0 {
+                        [window[0], window[1], window[2]]
+                    } else {
+                        [window[1], window[0], window[2]]
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// Rotates a triangle to start with its smallest index, keeping its winding
+    fn normalized(triangle: [u32; 3]) -> [u32; 3] {
+        let start = (0..3).min_by_key(|i| triangle[*i]).unwrap();
+        [0, 1, 2].map(|i| triangle[(start + i) % 3])
+    }
+
+    #[test]
+    fn plane_strip_matches_plane_list() {
+        let vertex_func = |u, v| Vertex {
+            in_position: Vec3::new(u, v, 0.0),
+            ..Default::default()
+        };
+
+        let list = make_plane_data(4, 3, vertex_func);
+        let strip = make_plane_strip_data(4, 3, vertex_func);
+
+        // Two rows of quads, each a strip of 8 indices, separated by one restart
+        assert_eq!(strip.indices.len(), 2 * 8 + 1);
+        assert_eq!(strip.indices[8], MeshTopology::RESTART_INDEX);
+
+        let mut list_triangles = list
+            .indices
+            .chunks(3)
+            .map(|triangle| normalized([triangle[0], triangle[1], triangle[2]]))
+            .collect::<Vec<_>>();
+        let mut strip_triangles = strip_triangles(&strip.indices)
+            .into_iter()
+            .map(normalized)
+            .collect::<Vec<_>>();
+        list_triangles.sort();
+        strip_triangles.sort();
+
+        assert_eq!(strip_triangles, list_triangles);
+    }
+
+    #[test]
+    fn double_sided_plane_faces_both_ways() {
+        let mut plane = plane_xy_data(3, 3);
+        let front_vertices = plane.vertices.len();
+        let front_indices = plane.indices.len();
+
+        add_back_faces(&mut plane);
+
+        assert_eq!(plane.vertices.len(), 2 * front_vertices);
+        assert_eq!(plane.indices.len(), 2 * front_indices);
+
+        for triangle in plane.indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| plane.vertices[triangle[i] as usize]);
+
+            // Clockwise front faces, the face normal has to match the vertex normals
+            let normal = (c.in_position - a.in_position).cross(b.in_position - a.in_position);
+            assert!(normal.normalize().abs_diff_eq(a.in_normal, 1e-5));
+        }
+    }
+
+    #[test]
+    fn sphere_triangles_stay_within_their_slice() {
+        let nb_slices = 8;
+        let sphere = sphere_uv_data(nb_slices, 6);
+
+        for triangle in sphere.indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| sphere.vertices[triangle[i] as usize]);
+
+            let u = [a, b, c].map(|vertex| vertex.in_texture_coord.x);
+            let u_span = u.iter().copied().fold(f32::MIN, f32::max)
+                - u.iter().copied().fold(f32::MAX, f32::min);
+            assert!(u_span <= 1.0 / nb_slices as f32 + 1e-5);
+
+            // No degenerate triangles at the poles
+            let area = (b.in_position - a.in_position).cross(c.in_position - a.in_position);
+            assert!(area.length() > 1e-6);
+
+            // Clockwise front faces point away from the center
+            let normal = (c.in_position - a.in_position).cross(b.in_position - a.in_position);
+            assert!(normal.dot(a.in_position + b.in_position + c.in_position) > 0.0);
+        }
+    }
 }
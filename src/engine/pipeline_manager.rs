@@ -2,66 +2,500 @@ use std::sync::Arc;
 
 use vulkano::{
     descriptor_set::layout::DescriptorSetLayout,
-    pipeline::{GraphicsPipeline, PipelineLayout},
+    device::Device,
+    image::SampleCount,
+    pipeline::{
+        cache::PipelineCache,
+        graphics::rasterization::{CullMode, FrontFace},
+        GraphicsPipeline, PipelineLayout,
+    },
     render_pass::RenderPass,
+    shader::EntryPoint,
 };
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 
-use crate::vulkan_context::VulkanContext;
+use crate::{
+    engine::mesh::{MeshTopology, VertexAttributes},
+    vulkan_context::VulkanContext,
+};
 
 mod shader_loader;
 
+pub(crate) use shader_loader::{
+    load_compute, load_material_compute, load_post_process, load_user_shader,
+    validate_material_fragment_shader,
+};
+
 pub struct VulkanPipeline {
     pub pipeline: Arc<GraphicsPipeline>,
     pub layout: Arc<PipelineLayout>,
+    /// Vertex stream bound for the meshes drawn with the pipeline, see `Mesh::bind_vertex_stream`
+    pub vertex_attributes: VertexAttributes,
+}
+
+/// Fixed function states shared by every pipeline, changing them requires rebuilding the
+/// pipelines
+#[derive(Debug, Clone, Copy)]
+pub struct PipelineSettings {
+    pub front_face: FrontFace,
+    /// Faces culled by the scene pipelines, overlays like text are never culled
+    pub cull_mode: CullMode,
+    /// Must match the sample count of the render pass attachments
+    pub samples: SampleCount,
+    /// Maps the near plane to a depth of 1 and the far plane to 0, reversing every depth test.
+    /// Floats are most precise close to 0, where the perspective divide also packs the distant
+    /// depths, so both errors mostly cancel out and far away geometry stops z-fighting
+    pub reversed_z: bool,
+    /// Draws the depth of the meshes before shading them, the material pipelines then only shade
+    /// the fragments left visible. Saves time in scenes where many meshes hide each other
+    pub depth_prepass: bool,
+}
+
+impl Default for PipelineSettings {
+    fn default() -> Self {
+        Self {
+            front_face: FrontFace::Clockwise,
+            cull_mode: CullMode::Back,
+            samples: SampleCount::Sample1,
+            reversed_z: false,
+            depth_prepass: false,
+        }
+    }
+}
+
+/// Pipelines drawing the meshes of one topology, one per render mode
+pub struct MeshPipelines {
+    pub normal: VulkanPipeline,
+    pub depth: VulkanPipeline,
+    /// The wireframe pipelines are only created when the device enabled `fill_mode_non_solid`
+    pub wireframe: Option<VulkanPipeline>,
+    /// Drawn over the other render modes when the wireframe overlay is enabled
+    pub wireframe_overlay: Option<VulkanPipeline>,
+    pub vertex_color: VulkanPipeline,
+    pub material: VulkanPipeline,
+    /// Only created when the depth prepass is enabled
+    pub depth_prepass: Option<VulkanPipeline>,
+}
+
+impl MeshPipelines {
+    fn new(
+        device: &Arc<Device>,
+        pipeline_cache: &Arc<PipelineCache>,
+        render_pass: &Arc<RenderPass>,
+        material_set_layout: &Arc<DescriptorSetLayout>,
+        frame_set_layout: &Arc<DescriptorSetLayout>,
+        entity_set_layout: &Arc<DescriptorSetLayout>,
+        settings: &PipelineSettings,
+        topology: MeshTopology,
+    ) -> Result<Self> {
+        let depth_prepass = if settings.depth_prepass {
+            Some(shader_loader::load_depth_prepass(
+                device,
+                pipeline_cache,
+                render_pass,
+                settings,
+                topology,
+            )?)
+        } else {
+            None
+        };
+
+        let (wireframe, wireframe_overlay) = if device.enabled_features().fill_mode_non_solid {
+            (
+                Some(shader_loader::load_wireframe(
+                    device,
+                    pipeline_cache,
+                    render_pass,
+                    settings,
+                    topology,
+                )?),
+                Some(shader_loader::load_wireframe_overlay(
+                    device,
+                    pipeline_cache,
+                    render_pass,
+                    settings,
+                    topology,
+                )?),
+            )
+        } else {
+            (None, None)
+        };
+
+        Ok(Self {
+            normal: shader_loader::load_normal(
+                device,
+                pipeline_cache,
+                render_pass,
+                settings,
+                topology,
+            )?,
+            depth: shader_loader::load_depth(
+                device,
+                pipeline_cache,
+                render_pass,
+                settings,
+                topology,
+            )?,
+            wireframe,
+            wireframe_overlay,
+            vertex_color: shader_loader::load_vertex_color(
+                device,
+                pipeline_cache,
+                render_pass,
+                settings,
+                topology,
+            )?,
+            material: shader_loader::load_material_simple(
+                device,
+                pipeline_cache,
+                render_pass,
+                Arc::clone(material_set_layout),
+                Arc::clone(frame_set_layout),
+                Arc::clone(entity_set_layout),
+                None,
+                settings,
+                topology,
+            )?,
+            depth_prepass,
+        })
+    }
 }
 
 pub struct PipelineManager {
-    normal_pipeline: VulkanPipeline,
-    depth_pipeline: VulkanPipeline,
+    vulkan_context: Arc<VulkanContext>,
+    render_pass: Arc<RenderPass>,
+    material_set_layout: Arc<DescriptorSetLayout>,
+    text_set_layout: Arc<DescriptorSetLayout>,
+    object_set_layout: Arc<DescriptorSetLayout>,
+    frame_set_layout: Arc<DescriptorSetLayout>,
+    entity_set_layout: Arc<DescriptorSetLayout>,
+    settings: PipelineSettings,
+
+    /// Triangle list pipelines first, the other topologies are added once a mesh uses them
+    mesh_pipelines: Vec<(MeshTopology, MeshPipelines)>,
+    /// Material pipelines of the materials with their own fragment shader, by material id and
+    /// topology
+    custom_material_pipelines: Vec<(u64, MeshTopology, VulkanPipeline)>,
+    debug_line_pipeline: VulkanPipeline,
+    text_pipeline: VulkanPipeline,
+    sprite_pipeline: VulkanPipeline,
+    screen_sprite_pipeline: VulkanPipeline,
+    background_gradient_pipeline: VulkanPipeline,
+    background_image_pipeline: VulkanPipeline,
     _mesh_view_pipeine: VulkanPipeline,
-    material_pipeline: VulkanPipeline,
+    material_indirect_pipeline: VulkanPipeline,
+    material_skinned_pipeline: VulkanPipeline,
+    material_instanced_pipeline: VulkanPipeline,
+    occlusion_proxy_pipeline: VulkanPipeline,
 }
 
 impl PipelineManager {
     pub const MATERIAL_BINDING: u32 = 0;
+    /// First of the `MAX_MATERIAL_STORAGE_BUFFERS` consecutive storage buffer bindings of the
+    /// material set
+    pub const MATERIAL_STORAGE_BINDING: u32 = 1;
+    pub const MAX_MATERIAL_STORAGE_BUFFERS: u32 = 4;
+    /// Sets of the material pipelines after the material set at 0, the uniforms of the frame then
+    /// the storage buffer of the objects or bones for the pipelines using one
+    pub const FRAME_SET: u32 = 1;
+    pub const OBJECT_SET: u32 = 2;
+    /// Set of the uniform component of the entity, in place of the object set for the material
+    /// pipelines drawing meshes one by one
+    pub const ENTITY_SET: u32 = 2;
+    /// Push constants of the fragment stage follow those of the vertex stage, the matrices, the
+    /// tint and the bone offset, up to the 256 bytes most devices support
+    pub const FRAGMENT_PUSH_CONSTANT_OFFSET: u32 = 208;
+    pub const MAX_FRAGMENT_PUSH_CONSTANT_SIZE: u32 = 48;
 
     pub fn new(
         vulkan_context: &Arc<VulkanContext>,
         render_pass: &Arc<RenderPass>,
         material_set_layout: Arc<DescriptorSetLayout>,
+        text_set_layout: Arc<DescriptorSetLayout>,
+        object_set_layout: Arc<DescriptorSetLayout>,
+        frame_set_layout: Arc<DescriptorSetLayout>,
+        entity_set_layout: Arc<DescriptorSetLayout>,
+        settings: PipelineSettings,
     ) -> Result<Self> {
         let device = vulkan_context.device();
+        let pipeline_cache = vulkan_context.pipeline_cache();
 
-        let normal_pipeline = shader_loader::load_normal(device, render_pass)?;
-        let depth_pipeline = shader_loader::load_depth(device, render_pass)?;
-        let mesh_view_pipeine = shader_loader::load_mesh_view(device, render_pass)?;
+        let triangle_list_pipelines = MeshPipelines::new(
+            device,
+            pipeline_cache,
+            render_pass,
+            &material_set_layout,
+            &frame_set_layout,
+            &entity_set_layout,
+            &settings,
+            MeshTopology::TRIANGLE_LIST,
+        )?;
+        let debug_line_pipeline =
+            shader_loader::load_debug_line(device, pipeline_cache, render_pass, &settings)?;
+        let text_pipeline = shader_loader::load_text(
+            device,
+            pipeline_cache,
+            render_pass,
+            Arc::clone(&text_set_layout),
+            &settings,
+        )?;
+        // Sprites bind their texture with the same single sampler layout as the glyph atlas
+        let sprite_pipeline = shader_loader::load_sprite(
+            device,
+            pipeline_cache,
+            render_pass,
+            Arc::clone(&text_set_layout),
+            &settings,
+            false,
+        )?;
+        let screen_sprite_pipeline = shader_loader::load_sprite(
+            device,
+            pipeline_cache,
+            render_pass,
+            Arc::clone(&text_set_layout),
+            &settings,
+            true,
+        )?;
+        let background_gradient_pipeline = shader_loader::load_background_gradient(
+            device,
+            pipeline_cache,
+            render_pass,
+            &settings,
+        )?;
+        // Background images are sprite textures
+        let background_image_pipeline = shader_loader::load_background_image(
+            device,
+            pipeline_cache,
+            render_pass,
+            Arc::clone(&text_set_layout),
+            &settings,
+        )?;
+        let mesh_view_pipeine =
+            shader_loader::load_mesh_view(device, pipeline_cache, render_pass, &settings)?;
 
-        let material_pipeline =
-            shader_loader::load_material_simple(device, render_pass, material_set_layout)?;
+        let material_indirect_pipeline = shader_loader::load_material_simple_indirect(
+            device,
+            pipeline_cache,
+            render_pass,
+            Arc::clone(&material_set_layout),
+            Arc::clone(&frame_set_layout),
+            Arc::clone(&object_set_layout),
+            &settings,
+        )?;
+        // Skinned meshes bind the poses of the frame with the same single storage buffer layout as
+        // the objects of indirect draws
+        let material_skinned_pipeline = shader_loader::load_material_skinned(
+            device,
+            pipeline_cache,
+            render_pass,
+            Arc::clone(&material_set_layout),
+            Arc::clone(&frame_set_layout),
+            Arc::clone(&object_set_layout),
+            &settings,
+        )?;
+        let material_instanced_pipeline = shader_loader::load_material_instanced(
+            device,
+            pipeline_cache,
+            render_pass,
+            Arc::clone(&material_set_layout),
+            Arc::clone(&frame_set_layout),
+            &settings,
+        )?;
+        let occlusion_proxy_pipeline =
+            shader_loader::load_occlusion_proxy(device, pipeline_cache, render_pass, &settings)?;
 
         Ok(Self {
-            normal_pipeline,
-            depth_pipeline,
+            vulkan_context: Arc::clone(vulkan_context),
+            render_pass: Arc::clone(render_pass),
+            material_set_layout,
+            text_set_layout,
+            object_set_layout,
+            frame_set_layout,
+            entity_set_layout,
+            settings,
+
+            mesh_pipelines: vec![(MeshTopology::TRIANGLE_LIST, triangle_list_pipelines)],
+            custom_material_pipelines: Vec::new(),
+            debug_line_pipeline,
+            text_pipeline,
+            sprite_pipeline,
+            screen_sprite_pipeline,
+            background_gradient_pipeline,
+            background_image_pipeline,
             _mesh_view_pipeine: mesh_view_pipeine,
-            material_pipeline,
+            material_indirect_pipeline,
+            material_skinned_pipeline,
+            material_instanced_pipeline,
+            occlusion_proxy_pipeline,
         })
     }
 
-    pub fn normal_pipeline(&self) -> &VulkanPipeline {
-        &self.normal_pipeline
+    pub fn settings(&self) -> &PipelineSettings {
+        &self.settings
+    }
+
+    /// Rebuilds every pipeline with the new settings, the pipelines of the materials with their
+    /// own shader are prepared again before the next frame
+    pub fn set_settings(&mut self, settings: PipelineSettings) -> Result<()> {
+        let topologies = self
+            .mesh_pipelines
+            .iter()
+            .map(|(topology, _)| *topology)
+            .collect::<Vec<_>>();
+
+        *self = Self::new(
+            &self.vulkan_context,
+            &self.render_pass,
+            Arc::clone(&self.material_set_layout),
+            Arc::clone(&self.text_set_layout),
+            Arc::clone(&self.object_set_layout),
+            Arc::clone(&self.frame_set_layout),
+            Arc::clone(&self.entity_set_layout),
+            settings,
+        )?;
+
+        for topology in topologies {
+            self.prepare_mesh_topology(topology)?;
+        }
+
+        Ok(())
+    }
+
+    /// Creates the pipelines drawing meshes of `topology` if they do not exist yet
+    pub fn prepare_mesh_topology(&mut self, topology: MeshTopology) -> Result<()> {
+        if self.mesh_pipelines.iter().any(|(existing, _)| *existing == topology) {
+            return Ok(());
+        }
+
+        let pipelines = MeshPipelines::new(
+            self.vulkan_context.device(),
+            self.vulkan_context.pipeline_cache(),
+            &self.render_pass,
+            &self.material_set_layout,
+            &self.frame_set_layout,
+            &self.entity_set_layout,
+            &self.settings,
+            topology,
+        )?;
+        self.mesh_pipelines.push((topology, pipelines));
+
+        Ok(())
+    }
+
+    /// Creates the material pipeline drawing meshes of `topology` with the fragment shader of
+    /// `material` if it does not exist yet. The shader must have been validated with
+    /// `validate_material_fragment_shader`
+    pub fn prepare_custom_material(
+        &mut self,
+        material: u64,
+        fragment_shader: &EntryPoint,
+        topology: MeshTopology,
+    ) -> Result<()> {
+        if self.custom_material_pipeline(material, topology).is_some() {
+            return Ok(());
+        }
+
+        let pipeline = shader_loader::load_material_simple(
+            self.vulkan_context.device(),
+            self.vulkan_context.pipeline_cache(),
+            &self.render_pass,
+            Arc::clone(&self.material_set_layout),
+            Arc::clone(&self.frame_set_layout),
+            Arc::clone(&self.entity_set_layout),
+            Some(fragment_shader.clone()),
+            &self.settings,
+            topology,
+        )
+        .with_context(|| format!("Failed to create the pipeline of material {material}"))?;
+        self.custom_material_pipelines
+            .push((material, topology, pipeline));
+
+        Ok(())
+    }
+
+    /// Drops the pipelines of the materials `keep` rejects, once they have been removed
+    pub fn retain_custom_materials(&mut self, mut keep: impl FnMut(u64) -> bool) {
+        self.custom_material_pipelines
+            .retain(|(material, _, _)| keep(*material));
+    }
+
+    /// Pipeline prepared with `prepare_custom_material`, `None` for the materials shaded by the
+    /// engine
+    pub fn custom_material_pipeline(
+        &self,
+        material: u64,
+        topology: MeshTopology,
+    ) -> Option<&VulkanPipeline> {
+        self.custom_material_pipelines
+            .iter()
+            .find(|(existing_material, existing_topology, _)| {
+                *existing_material == material && *existing_topology == topology
+            })
+            .map(|(_, _, pipeline)| pipeline)
+    }
+
+    /// Pipelines of a topology prepared with `prepare_mesh_topology`
+    pub fn mesh_pipelines(&self, topology: MeshTopology) -> &MeshPipelines {
+        match self.mesh_pipelines.iter().find(|(existing, _)| *existing == topology) {
+            Some((_, pipelines)) => pipelines,
+            None => panic!("Pipelines of {topology:?} have not been prepared"),
+        }
+    }
+
+    pub fn object_set_layout(&self) -> &Arc<DescriptorSetLayout> {
+        &self.object_set_layout
+    }
+
+    pub fn frame_set_layout(&self) -> &Arc<DescriptorSetLayout> {
+        &self.frame_set_layout
+    }
+
+    pub fn entity_set_layout(&self) -> &Arc<DescriptorSetLayout> {
+        &self.entity_set_layout
     }
 
-    pub fn depth_pipeline(&self) -> &VulkanPipeline {
-        &self.depth_pipeline
+    pub fn debug_line_pipeline(&self) -> &VulkanPipeline {
+        &self.debug_line_pipeline
+    }
+
+    pub fn text_pipeline(&self) -> &VulkanPipeline {
+        &self.text_pipeline
+    }
+
+    pub fn sprite_pipeline(&self) -> &VulkanPipeline {
+        &self.sprite_pipeline
+    }
+
+    pub fn screen_sprite_pipeline(&self) -> &VulkanPipeline {
+        &self.screen_sprite_pipeline
+    }
+
+    pub fn background_gradient_pipeline(&self) -> &VulkanPipeline {
+        &self.background_gradient_pipeline
+    }
+
+    pub fn background_image_pipeline(&self) -> &VulkanPipeline {
+        &self.background_image_pipeline
     }
 
     pub fn _mesh_view_pipeine(&self) -> &VulkanPipeline {
         &self._mesh_view_pipeine
     }
 
-    pub fn material_pipeline(&self) -> &VulkanPipeline {
-        &self.material_pipeline
+    pub fn material_indirect_pipeline(&self) -> &VulkanPipeline {
+        &self.material_indirect_pipeline
+    }
+
+    pub fn material_skinned_pipeline(&self) -> &VulkanPipeline {
+        &self.material_skinned_pipeline
+    }
+
+    pub fn material_instanced_pipeline(&self) -> &VulkanPipeline {
+        &self.material_instanced_pipeline
+    }
+
+    pub fn occlusion_proxy_pipeline(&self) -> &VulkanPipeline {
+        &self.occlusion_proxy_pipeline
     }
 }
@@ -1,39 +1,152 @@
-use std::{mem::size_of, sync::Arc};
+use std::sync::Arc;
 
-use glam::Mat4;
 use vulkano::{
-    descriptor_set::layout::DescriptorSetLayout,
+    descriptor_set::layout::{
+        DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorSetLayoutCreateInfo,
+        DescriptorType,
+    },
     device::Device,
     pipeline::{
         graphics::{
             color_blend::{
-                ColorBlendAttachmentState, ColorBlendState, ColorBlendStateFlags, ColorComponents,
+                AttachmentBlend, ColorBlendAttachmentState, ColorBlendState, ColorBlendStateFlags,
+                ColorComponents,
             },
             depth_stencil::{CompareOp, DepthState, DepthStencilState},
             input_assembly::{InputAssemblyState, PrimitiveTopology},
             multisample::MultisampleState,
             rasterization::{
-                CullMode, FrontFace, LineRasterizationMode, PolygonMode, RasterizationState,
+                CullMode, DepthBiasState, LineRasterizationMode, PolygonMode, RasterizationState,
+            },
+            vertex_input::{
+                Vertex, VertexBufferDescription, VertexDefinition, VertexInputState,
             },
-            vertex_input::{Vertex, VertexDefinition},
             viewport::{Scissor, Viewport, ViewportState},
             GraphicsPipelineCreateInfo,
         },
-        layout::{PipelineLayoutCreateFlags, PipelineLayoutCreateInfo, PushConstantRange},
-        DynamicState, GraphicsPipeline, PipelineCreateFlags, PipelineLayout,
+        cache::PipelineCache,
+        compute::ComputePipelineCreateInfo,
+        layout::{
+            PipelineDescriptorSetLayoutCreateInfo, PipelineLayoutCreateFlags,
+            PipelineLayoutCreateInfo, PushConstantRange,
+        },
+        ComputePipeline, DynamicState, GraphicsPipeline, PipelineCreateFlags, PipelineLayout,
         PipelineShaderStageCreateInfo,
     },
     render_pass::{RenderPass, Subpass},
-    shader::ShaderStages,
+    shader::{
+        spirv::ExecutionModel, EntryPoint, ShaderModule, ShaderModuleCreateInfo, ShaderStages,
+    },
 };
 use vulkano_shaders;
 
 use anyhow::Result;
 
-use super::VulkanPipeline;
-use crate::engine::mesh::Vertex as MyVertex;
+use super::{PipelineManager, PipelineSettings, VulkanPipeline};
+use crate::engine::{
+    debug_draw::LineVertex,
+    mesh::{BoneVertex, MeshTopology, PositionVertex, Vertex as MyVertex, VertexAttributes},
+    renderer::instancing::InstanceVertex,
+    sprite_renderer::SpriteVertex,
+    text_renderer::TextVertex,
+};
+
+/// Everything that differs between the engine's graphics pipelines, the remaining states are
+/// shared by all of them
+struct PipelineDescription {
+    vertex_shader: EntryPoint,
+    fragment_shader: EntryPoint,
+    vertex_input_state: VertexInputState,
+    /// Stream of the meshes read by the vertex shader, must match `vertex_input_state`
+    vertex_attributes: VertexAttributes,
+    set_layouts: Vec<Arc<DescriptorSetLayout>>,
+    topology: PrimitiveTopology,
+    /// Only allowed with strip topologies
+    primitive_restart: bool,
+    polygon_mode: PolygonMode,
+    /// Overrides the cull mode of the settings
+    cull_mode: Option<CullMode>,
+    depth: Option<DepthState>,
+    /// Enables the dynamic depth bias, which must then be set before drawing
+    depth_bias: bool,
+    /// Adds the range of the fragment push constants of the materials, which the renderer then
+    /// pushes whenever it binds a material
+    material_push_constants: bool,
+    /// Enables the dynamic line width, which must then be set before drawing
+    line_width: bool,
+    blend: Option<AttachmentBlend>,
+    /// Disabled for pipelines only writing the depth
+    color_write: bool,
+}
+
+impl PipelineDescription {
+    fn new(vertex_shader: EntryPoint, fragment_shader: EntryPoint) -> Result<Self> {
+        Self::with_vertex_type::<MyVertex>(vertex_shader, fragment_shader)
+    }
+
+    /// Reads the meshes' positions stream, for vertex shaders only reading `in_position`
+    fn with_positions(vertex_shader: EntryPoint, fragment_shader: EntryPoint) -> Result<Self> {
+        Ok(Self {
+            vertex_attributes: VertexAttributes::Position,
+            ..Self::with_vertex_type::<PositionVertex>(vertex_shader, fragment_shader)?
+        })
+    }
+
+    fn with_vertex_type<V: Vertex>(
+        vertex_shader: EntryPoint,
+        fragment_shader: EntryPoint,
+    ) -> Result<Self> {
+        Self::with_vertex_buffers(vertex_shader, fragment_shader, &[V::per_vertex()])
+    }
+
+    /// Reads the vertex attributes from several vertex buffers, the buffer of each description
+    /// being bound at its index
+    fn with_vertex_buffers(
+        vertex_shader: EntryPoint,
+        fragment_shader: EntryPoint,
+        vertex_buffers: &[VertexBufferDescription],
+    ) -> Result<Self> {
+        let vertex_input_state = vertex_buffers.definition(&vertex_shader.info().input_interface)?;
 
-pub fn load_depth(device: &Arc<Device>, render_pass: &Arc<RenderPass>) -> Result<VulkanPipeline> {
+        Ok(Self {
+            vertex_shader,
+            fragment_shader,
+            vertex_input_state,
+            vertex_attributes: VertexAttributes::All,
+            set_layouts: Vec::new(),
+            topology: PrimitiveTopology::TriangleList,
+            primitive_restart: false,
+            polygon_mode: PolygonMode::Fill,
+            cull_mode: None,
+            depth: Some(DepthState {
+                write_enable: true,
+                compare_op: CompareOp::Less,
+            }),
+            depth_bias: false,
+            material_push_constants: false,
+            line_width: false,
+            blend: None,
+            color_write: true,
+        })
+    }
+
+    /// Assembles the vertices like meshes of `topology`
+    fn with_mesh_topology(self, topology: MeshTopology) -> Self {
+        Self {
+            topology: topology.topology,
+            primitive_restart: topology.primitive_restart,
+            ..self
+        }
+    }
+}
+
+pub fn load_depth(
+    device: &Arc<Device>,
+    pipeline_cache: &Arc<PipelineCache>,
+    render_pass: &Arc<RenderPass>,
+    settings: &PipelineSettings,
+    topology: MeshTopology,
+) -> Result<VulkanPipeline> {
     vulkano_shaders::shader! {
         shaders: {
             vertex: {
@@ -50,117 +163,579 @@ pub fn load_depth(device: &Arc<Device>, render_pass: &Arc<RenderPass>) -> Result
     let vertex_shader = load_vertex(Arc::clone(device))?
         .entry_point("main")
         .unwrap();
+    // The shader linearizes the depth, which it first has to bring back to the standard range
     let fragment_shader = load_fragment(Arc::clone(device))?
+        .specialize([(0, settings.reversed_z.into())].into_iter().collect())?
         .entry_point("main")
         .unwrap();
 
-    let vertex_input_state =
-        MyVertex::per_vertex().definition(&vertex_shader.info().input_interface)?;
+    let description = PipelineDescription::with_positions(vertex_shader, fragment_shader)?
+        .with_mesh_topology(topology);
 
-    let pipeline_layout = {
-        let layout_info = PipelineLayoutCreateInfo {
-            flags: PipelineLayoutCreateFlags::empty(),
-            push_constant_ranges: vec![PushConstantRange {
-                stages: ShaderStages::VERTEX,
-                offset: 0,
-                size: 3 * size_of::<Mat4>() as u32,
-            }],
-            ..Default::default()
-        };
+    create_pipeline(device, pipeline_cache, render_pass, settings, description)
+}
 
-        PipelineLayout::new(Arc::clone(device), layout_info)?
-    };
+pub fn load_normal(
+    device: &Arc<Device>,
+    pipeline_cache: &Arc<PipelineCache>,
+    render_pass: &Arc<RenderPass>,
+    settings: &PipelineSettings,
+    topology: MeshTopology,
+) -> Result<VulkanPipeline> {
+    vulkano_shaders::shader! {
+        shaders: {
+            vertex: {
+                ty: "vertex",
+                path: "shaders/debug/normal.vert"
+            },
+            fragment: {
+                ty: "fragment",
+                path: "shaders/debug/normal.frag"
+            }
+        }
+    }
 
-    let pipeline_info = GraphicsPipelineCreateInfo {
-        flags: PipelineCreateFlags::empty(),
-        stages: [
-            PipelineShaderStageCreateInfo::new(vertex_shader),
-            PipelineShaderStageCreateInfo::new(fragment_shader),
-        ]
-        .into_iter()
-        .collect(),
-        vertex_input_state: Some(vertex_input_state),
-        input_assembly_state: Some(InputAssemblyState {
-            topology: PrimitiveTopology::TriangleList,
-            primitive_restart_enable: false,
-            ..Default::default()
-        }),
-        tessellation_state: None,
-        viewport_state: Some(ViewportState {
-            viewports: [Viewport {
-                offset: [0.0, 0.0],
-                extent: [800.0, 600.0],
-                ..Default::default()
-            }]
-            .into_iter()
-            .collect(),
-            scissors: [Scissor {
-                offset: [0, 0],
-                extent: [800, 600],
-            }]
-            .into_iter()
-            .collect(),
-            ..Default::default()
-        }),
-        rasterization_state: Some(RasterizationState {
-            depth_clamp_enable: false,
-            rasterizer_discard_enable: false,
-            polygon_mode: PolygonMode::Fill,
-            cull_mode: CullMode::Back,
-            front_face: FrontFace::Clockwise,
-            depth_bias: None,
-            line_width: 1.0,
-            line_rasterization_mode: LineRasterizationMode::Default,
-            line_stipple: None,
-            ..Default::default()
-        }),
-        multisample_state: Some(MultisampleState::default()),
-        depth_stencil_state: Some(DepthStencilState {
-            depth: Some(DepthState {
-                write_enable: true,
-                compare_op: CompareOp::Less,
-            }),
-            ..Default::default()
-        }),
-        color_blend_state: Some(ColorBlendState {
-            flags: ColorBlendStateFlags::empty(),
-            logic_op: None,
-            attachments: vec![ColorBlendAttachmentState {
-                blend: None,
-                color_write_mask: ColorComponents::all(),
-                color_write_enable: true,
-            }],
-            blend_constants: [0.0; 4],
-            ..Default::default()
+    let vertex_shader = load_vertex(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+    let fragment_shader = load_fragment(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+
+    let description =
+        PipelineDescription::new(vertex_shader, fragment_shader)?.with_mesh_topology(topology);
+
+    create_pipeline(device, pipeline_cache, render_pass, settings, description)
+}
+
+pub fn load_mesh_view(
+    device: &Arc<Device>,
+    pipeline_cache: &Arc<PipelineCache>,
+    render_pass: &Arc<RenderPass>,
+    settings: &PipelineSettings,
+) -> Result<VulkanPipeline> {
+    vulkano_shaders::shader! {
+        shaders: {
+            vertex: {
+                ty: "vertex",
+                path: "shaders/debug/mesh_view.vert"
+            },
+            fragment: {
+                ty: "fragment",
+                path: "shaders/debug/mesh_view.frag"
+            }
+        }
+    }
+
+    let vertex_shader = load_vertex(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+    let fragment_shader = load_fragment(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+
+    let description = PipelineDescription::new(vertex_shader, fragment_shader)?;
+
+    create_pipeline(device, pipeline_cache, render_pass, settings, description)
+}
+
+pub fn load_wireframe(
+    device: &Arc<Device>,
+    pipeline_cache: &Arc<PipelineCache>,
+    render_pass: &Arc<RenderPass>,
+    settings: &PipelineSettings,
+    topology: MeshTopology,
+) -> Result<VulkanPipeline> {
+    let description = wireframe_description(device, topology)?;
+
+    create_pipeline(device, pipeline_cache, render_pass, settings, description)
+}
+
+/// Wireframe drawn over the shaded meshes, pulled towards the camera by a dynamic depth bias so
+/// that the lines win the depth test against the faces they lie on
+pub fn load_wireframe_overlay(
+    device: &Arc<Device>,
+    pipeline_cache: &Arc<PipelineCache>,
+    render_pass: &Arc<RenderPass>,
+    settings: &PipelineSettings,
+    topology: MeshTopology,
+) -> Result<VulkanPipeline> {
+    let description = PipelineDescription {
+        depth: Some(DepthState {
+            write_enable: false,
+            compare_op: CompareOp::LessOrEqual,
         }),
-        subpass: Some(Subpass::from(render_pass.clone(), 0).unwrap().into()),
-        discard_rectangle_state: None,
+        depth_bias: true,
+        ..wireframe_description(device, topology)?
+    };
 
-        dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
-            .into_iter()
-            .collect(),
+    create_pipeline(device, pipeline_cache, render_pass, settings, description)
+}
 
-        ..GraphicsPipelineCreateInfo::layout(pipeline_layout.clone())
+fn wireframe_description(
+    device: &Arc<Device>,
+    topology: MeshTopology,
+) -> Result<PipelineDescription> {
+    vulkano_shaders::shader! {
+        shaders: {
+            vertex: {
+                ty: "vertex",
+                path: "shaders/debug/wireframe.vert"
+            },
+            fragment: {
+                ty: "fragment",
+                path: "shaders/debug/wireframe.frag"
+            }
+        }
+    }
+
+    let vertex_shader = load_vertex(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+    let fragment_shader = load_fragment(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+
+    Ok(PipelineDescription {
+        polygon_mode: PolygonMode::Line,
+        line_width: true,
+        ..PipelineDescription::with_positions(vertex_shader, fragment_shader)?
+            .with_mesh_topology(topology)
+    })
+}
+
+pub fn load_vertex_color(
+    device: &Arc<Device>,
+    pipeline_cache: &Arc<PipelineCache>,
+    render_pass: &Arc<RenderPass>,
+    settings: &PipelineSettings,
+    topology: MeshTopology,
+) -> Result<VulkanPipeline> {
+    vulkano_shaders::shader! {
+        shaders: {
+            vertex: {
+                ty: "vertex",
+                path: "shaders/debug/vertex_color.vert"
+            },
+            fragment: {
+                ty: "fragment",
+                path: "shaders/debug/vertex_color.frag"
+            }
+        }
+    }
+
+    let vertex_shader = load_vertex(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+    let fragment_shader = load_fragment(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+
+    let description =
+        PipelineDescription::new(vertex_shader, fragment_shader)?.with_mesh_topology(topology);
+
+    create_pipeline(device, pipeline_cache, render_pass, settings, description)
+}
+
+pub fn load_debug_line(
+    device: &Arc<Device>,
+    pipeline_cache: &Arc<PipelineCache>,
+    render_pass: &Arc<RenderPass>,
+    settings: &PipelineSettings,
+) -> Result<VulkanPipeline> {
+    vulkano_shaders::shader! {
+        shaders: {
+            vertex: {
+                ty: "vertex",
+                path: "shaders/debug/line.vert"
+            },
+            fragment: {
+                ty: "fragment",
+                path: "shaders/debug/line.frag"
+            }
+        }
+    }
+
+    let vertex_shader = load_vertex(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+    let fragment_shader = load_fragment(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+
+    let description = PipelineDescription {
+        topology: PrimitiveTopology::LineList,
+        line_width: true,
+        ..PipelineDescription::with_vertex_type::<LineVertex>(vertex_shader, fragment_shader)?
+    };
+
+    create_pipeline(device, pipeline_cache, render_pass, settings, description)
+}
+
+pub fn load_text(
+    device: &Arc<Device>,
+    pipeline_cache: &Arc<PipelineCache>,
+    render_pass: &Arc<RenderPass>,
+    text_set_layout: Arc<DescriptorSetLayout>,
+    settings: &PipelineSettings,
+) -> Result<VulkanPipeline> {
+    vulkano_shaders::shader! {
+        shaders: {
+            vertex: {
+                ty: "vertex",
+                path: "shaders/text/text.vert"
+            },
+            fragment: {
+                ty: "fragment",
+                path: "shaders/text/text.frag"
+            }
+        }
+    }
+
+    let vertex_shader = load_vertex(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+    let fragment_shader = load_fragment(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+
+    let description = PipelineDescription {
+        set_layouts: vec![text_set_layout],
+        cull_mode: Some(CullMode::None),
+        depth: None,
+        blend: Some(AttachmentBlend::alpha()),
+        ..PipelineDescription::with_vertex_type::<TextVertex>(vertex_shader, fragment_shader)?
+    };
+
+    create_pipeline(device, pipeline_cache, render_pass, settings, description)
+}
+
+/// World space sprites are hidden by the meshes in front of them without hiding each other,
+/// screen space ones are drawn over everything
+pub fn load_sprite(
+    device: &Arc<Device>,
+    pipeline_cache: &Arc<PipelineCache>,
+    render_pass: &Arc<RenderPass>,
+    texture_set_layout: Arc<DescriptorSetLayout>,
+    settings: &PipelineSettings,
+    screen_space: bool,
+) -> Result<VulkanPipeline> {
+    vulkano_shaders::shader! {
+        shaders: {
+            vertex: {
+                ty: "vertex",
+                path: "shaders/sprite/sprite.vert"
+            },
+            fragment: {
+                ty: "fragment",
+                path: "shaders/sprite/sprite.frag"
+            }
+        }
+    }
+
+    let vertex_shader = load_vertex(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+    let fragment_shader = load_fragment(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+
+    let depth = (!screen_space).then_some(DepthState {
+        write_enable: false,
+        compare_op: CompareOp::Less,
+    });
+
+    let description = PipelineDescription {
+        set_layouts: vec![texture_set_layout],
+        cull_mode: Some(CullMode::None),
+        depth,
+        blend: Some(AttachmentBlend::alpha()),
+        ..PipelineDescription::with_vertex_type::<SpriteVertex>(vertex_shader, fragment_shader)?
+    };
+
+    create_pipeline(device, pipeline_cache, render_pass, settings, description)
+}
+
+pub fn load_background_gradient(
+    device: &Arc<Device>,
+    pipeline_cache: &Arc<PipelineCache>,
+    render_pass: &Arc<RenderPass>,
+    settings: &PipelineSettings,
+) -> Result<VulkanPipeline> {
+    vulkano_shaders::shader! {
+        shaders: {
+            vertex: {
+                ty: "vertex",
+                path: "shaders/background/background.vert"
+            },
+            fragment: {
+                ty: "fragment",
+                path: "shaders/background/gradient.frag"
+            }
+        }
+    }
+
+    let vertex_shader = load_vertex(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+    let fragment_shader = load_fragment(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+
+    let description = background_description(vertex_shader, fragment_shader, Vec::new());
+
+    create_pipeline(device, pipeline_cache, render_pass, settings, description)
+}
+
+pub fn load_background_image(
+    device: &Arc<Device>,
+    pipeline_cache: &Arc<PipelineCache>,
+    render_pass: &Arc<RenderPass>,
+    texture_set_layout: Arc<DescriptorSetLayout>,
+    settings: &PipelineSettings,
+) -> Result<VulkanPipeline> {
+    vulkano_shaders::shader! {
+        shaders: {
+            vertex: {
+                ty: "vertex",
+                path: "shaders/background/background.vert"
+            },
+            fragment: {
+                ty: "fragment",
+                path: "shaders/background/image.frag"
+            }
+        }
+    }
+
+    let vertex_shader = load_vertex(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+    let fragment_shader = load_fragment(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+
+    let description =
+        background_description(vertex_shader, fragment_shader, vec![texture_set_layout]);
+
+    create_pipeline(device, pipeline_cache, render_pass, settings, description)
+}
+
+/// Full screen triangle drawn before the scene, without depth so that everything covers it
+fn background_description(
+    vertex_shader: EntryPoint,
+    fragment_shader: EntryPoint,
+    set_layouts: Vec<Arc<DescriptorSetLayout>>,
+) -> PipelineDescription {
+    PipelineDescription {
+        vertex_shader,
+        fragment_shader,
+        vertex_input_state: VertexInputState::new(),
+        set_layouts,
+        topology: PrimitiveTopology::TriangleList,
+        primitive_restart: false,
+        polygon_mode: PolygonMode::Fill,
+        cull_mode: Some(CullMode::None),
+        depth: None,
+        depth_bias: false,
+        material_push_constants: false,
+        line_width: false,
+        blend: None,
+        color_write: true,
+    }
+}
+
+/// Shades the meshes with the fragment shader of their material when it has one, see
+/// `Scene::new_material_with_shader`, or else with the engine's flat shading
+pub fn load_material_simple(
+    device: &Arc<Device>,
+    pipeline_cache: &Arc<PipelineCache>,
+    render_pass: &Arc<RenderPass>,
+    material_set_layout: Arc<DescriptorSetLayout>,
+    frame_set_layout: Arc<DescriptorSetLayout>,
+    entity_set_layout: Arc<DescriptorSetLayout>,
+    material_fragment_shader: Option<EntryPoint>,
+    settings: &PipelineSettings,
+    topology: MeshTopology,
+) -> Result<VulkanPipeline> {
+    vulkano_shaders::shader! {
+        shaders: {
+            vertex: {
+                ty: "vertex",
+                path: "shaders/material/simple.vert"
+            },
+            fragment: {
+                ty: "fragment",
+                path: "shaders/material/simple.frag"
+            }
+        }
+    }
+
+    let vertex_shader = load_vertex(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+    let fragment_shader = match material_fragment_shader {
+        Some(fragment_shader) => fragment_shader,
+        None => load_fragment(Arc::clone(device))?
+            .entry_point("main")
+            .unwrap(),
+    };
+
+    let mut description = PipelineDescription {
+        set_layouts: vec![material_set_layout, frame_set_layout, entity_set_layout],
+        depth_bias: true,
+        material_push_constants: true,
+        ..PipelineDescription::new(vertex_shader, fragment_shader)?.with_mesh_topology(topology)
+    };
+
+    // After a depth prepass only the closest fragments are shaded
+    if settings.depth_prepass {
+        description.depth = Some(DepthState {
+            write_enable: false,
+            compare_op: CompareOp::Equal,
+        });
+    }
+
+    create_pipeline(device, pipeline_cache, render_pass, settings, description)
+}
+
+/// Writes the depth of the meshes drawn by `load_material_simple`, with the same vertex shader so
+/// that the depths of the color pass are exactly equal
+pub fn load_depth_prepass(
+    device: &Arc<Device>,
+    pipeline_cache: &Arc<PipelineCache>,
+    render_pass: &Arc<RenderPass>,
+    settings: &PipelineSettings,
+    topology: MeshTopology,
+) -> Result<VulkanPipeline> {
+    vulkano_shaders::shader! {
+        shaders: {
+            vertex: {
+                ty: "vertex",
+                path: "shaders/material/simple.vert"
+            },
+            fragment: {
+                ty: "fragment",
+                path: "shaders/material/depth_only.frag"
+            }
+        }
+    }
+
+    let vertex_shader = load_vertex(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+    let fragment_shader = load_fragment(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+
+    let description = PipelineDescription {
+        depth_bias: true,
+        color_write: false,
+        ..PipelineDescription::new(vertex_shader, fragment_shader)?.with_mesh_topology(topology)
+    };
+
+    create_pipeline(device, pipeline_cache, render_pass, settings, description)
+}
+
+/// Draws the boxes counted by the occlusion queries, testing the depth of the scene without
+/// writing anything
+pub fn load_occlusion_proxy(
+    device: &Arc<Device>,
+    pipeline_cache: &Arc<PipelineCache>,
+    render_pass: &Arc<RenderPass>,
+    settings: &PipelineSettings,
+) -> Result<VulkanPipeline> {
+    vulkano_shaders::shader! {
+        shaders: {
+            vertex: {
+                ty: "vertex",
+                path: "shaders/debug/depth.vert"
+            },
+            fragment: {
+                ty: "fragment",
+                path: "shaders/material/depth_only.frag"
+            }
+        }
+    }
+
+    let vertex_shader = load_vertex(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+    let fragment_shader = load_fragment(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+
+    // The faces of a box around the camera are seen from the inside
+    let description = PipelineDescription {
+        cull_mode: Some(CullMode::None),
+        depth: Some(DepthState {
+            write_enable: false,
+            compare_op: CompareOp::LessOrEqual,
+        }),
+        color_write: false,
+        ..PipelineDescription::with_positions(vertex_shader, fragment_shader)?
     };
 
-    let pipeline = GraphicsPipeline::new(device.clone(), None, pipeline_info)?;
+    create_pipeline(device, pipeline_cache, render_pass, settings, description)
+}
+
+pub fn load_material_simple_indirect(
+    device: &Arc<Device>,
+    pipeline_cache: &Arc<PipelineCache>,
+    render_pass: &Arc<RenderPass>,
+    material_set_layout: Arc<DescriptorSetLayout>,
+    frame_set_layout: Arc<DescriptorSetLayout>,
+    object_set_layout: Arc<DescriptorSetLayout>,
+    settings: &PipelineSettings,
+) -> Result<VulkanPipeline> {
+    vulkano_shaders::shader! {
+        shaders: {
+            vertex: {
+                ty: "vertex",
+                path: "shaders/material/simple_indirect.vert"
+            },
+            fragment: {
+                ty: "fragment",
+                path: "shaders/material/simple.frag"
+            }
+        }
+    }
+
+    let vertex_shader = load_vertex(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+    let fragment_shader = load_fragment(Arc::clone(device))?
+        .entry_point("main")
+        .unwrap();
+
+    let description = PipelineDescription {
+        set_layouts: vec![material_set_layout, frame_set_layout, object_set_layout],
+        depth_bias: true,
+        material_push_constants: true,
+        ..PipelineDescription::new(vertex_shader, fragment_shader)?
+    };
 
-    Ok(VulkanPipeline {
-        pipeline,
-        layout: pipeline_layout,
-    })
+    create_pipeline(device, pipeline_cache, render_pass, settings, description)
 }
 
-pub fn load_normal(device: &Arc<Device>, render_pass: &Arc<RenderPass>) -> Result<VulkanPipeline> {
+/// The mesh vertices are bound at binding 0 and their bones at `BONE_VERTEX_BINDING`
+pub fn load_material_skinned(
+    device: &Arc<Device>,
+    pipeline_cache: &Arc<PipelineCache>,
+    render_pass: &Arc<RenderPass>,
+    material_set_layout: Arc<DescriptorSetLayout>,
+    frame_set_layout: Arc<DescriptorSetLayout>,
+    bone_set_layout: Arc<DescriptorSetLayout>,
+    settings: &PipelineSettings,
+) -> Result<VulkanPipeline> {
     vulkano_shaders::shader! {
         shaders: {
             vertex: {
                 ty: "vertex",
-                path: "shaders/debug/normal.vert"
+                path: "shaders/material/skinned.vert"
             },
             fragment: {
                 ty: "fragment",
-                path: "shaders/debug/normal.frag"
+                path: "shaders/material/simple.frag"
             }
         }
     }
@@ -172,116 +747,37 @@ pub fn load_normal(device: &Arc<Device>, render_pass: &Arc<RenderPass>) -> Resul
         .entry_point("main")
         .unwrap();
 
-    let vertex_input_state =
-        MyVertex::per_vertex().definition(&vertex_shader.info().input_interface)?;
-
-    let pipeline_layout = {
-        let layout_info = PipelineLayoutCreateInfo {
-            flags: PipelineLayoutCreateFlags::empty(),
-            push_constant_ranges: vec![PushConstantRange {
-                stages: ShaderStages::VERTEX,
-                offset: 0,
-                size: 3 * size_of::<Mat4>() as u32,
-            }],
-            ..Default::default()
-        };
-
-        PipelineLayout::new(Arc::clone(device), layout_info)?
-    };
-
-    let pipeline_info = GraphicsPipelineCreateInfo {
-        flags: PipelineCreateFlags::empty(),
-        stages: [
-            PipelineShaderStageCreateInfo::new(vertex_shader),
-            PipelineShaderStageCreateInfo::new(fragment_shader),
-        ]
-        .into_iter()
-        .collect(),
-        vertex_input_state: Some(vertex_input_state),
-        input_assembly_state: Some(InputAssemblyState {
-            topology: PrimitiveTopology::TriangleList,
-            primitive_restart_enable: false,
-            ..Default::default()
-        }),
-        tessellation_state: None,
-        viewport_state: Some(ViewportState {
-            viewports: [Viewport {
-                offset: [0.0, 0.0],
-                extent: [800.0, 600.0],
-                ..Default::default()
-            }]
-            .into_iter()
-            .collect(),
-            scissors: [Scissor {
-                offset: [0, 0],
-                extent: [800, 600],
-            }]
-            .into_iter()
-            .collect(),
-            ..Default::default()
-        }),
-        rasterization_state: Some(RasterizationState {
-            depth_clamp_enable: false,
-            rasterizer_discard_enable: false,
-            polygon_mode: PolygonMode::Fill,
-            cull_mode: CullMode::Back,
-            front_face: FrontFace::Clockwise,
-            depth_bias: None,
-            line_width: 1.0,
-            line_rasterization_mode: LineRasterizationMode::Default,
-            line_stipple: None,
-            ..Default::default()
-        }),
-        multisample_state: Some(MultisampleState::default()),
-        depth_stencil_state: Some(DepthStencilState {
-            depth: Some(DepthState {
-                write_enable: true,
-                compare_op: CompareOp::Less,
-            }),
-            ..Default::default()
-        }),
-        color_blend_state: Some(ColorBlendState {
-            flags: ColorBlendStateFlags::empty(),
-            logic_op: None,
-            attachments: vec![ColorBlendAttachmentState {
-                blend: None,
-                color_write_mask: ColorComponents::all(),
-                color_write_enable: true,
-            }],
-            blend_constants: [0.0; 4],
-            ..Default::default()
-        }),
-        subpass: Some(Subpass::from(render_pass.clone(), 0).unwrap().into()),
-        discard_rectangle_state: None,
-
-        dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
-            .into_iter()
-            .collect(),
+    let vertex_buffers = [MyVertex::per_vertex(), BoneVertex::per_vertex()];
 
-        ..GraphicsPipelineCreateInfo::layout(pipeline_layout.clone())
+    let description = PipelineDescription {
+        set_layouts: vec![material_set_layout, frame_set_layout, bone_set_layout],
+        vertex_attributes: VertexAttributes::Skinned,
+        depth_bias: true,
+        material_push_constants: true,
+        ..PipelineDescription::with_vertex_buffers(vertex_shader, fragment_shader, &vertex_buffers)?
     };
 
-    let pipeline = GraphicsPipeline::new(device.clone(), None, pipeline_info)?;
-
-    Ok(VulkanPipeline {
-        pipeline,
-        layout: pipeline_layout,
-    })
+    create_pipeline(device, pipeline_cache, render_pass, settings, description)
 }
 
-pub fn load_mesh_view(
+/// The mesh vertices are bound at binding 0 and the instances at `INSTANCE_BINDING`
+pub fn load_material_instanced(
     device: &Arc<Device>,
+    pipeline_cache: &Arc<PipelineCache>,
     render_pass: &Arc<RenderPass>,
+    material_set_layout: Arc<DescriptorSetLayout>,
+    frame_set_layout: Arc<DescriptorSetLayout>,
+    settings: &PipelineSettings,
 ) -> Result<VulkanPipeline> {
     vulkano_shaders::shader! {
         shaders: {
             vertex: {
                 ty: "vertex",
-                path: "shaders/debug/mesh_view.vert"
+                path: "shaders/material/instanced.vert"
             },
             fragment: {
                 ty: "fragment",
-                path: "shaders/debug/mesh_view.frag"
+                path: "shaders/material/simple.frag"
             }
         }
     }
@@ -293,140 +789,342 @@ pub fn load_mesh_view(
         .entry_point("main")
         .unwrap();
 
-    let vertex_input_state =
-        MyVertex::per_vertex().definition(&vertex_shader.info().input_interface)?;
-
-    let pipeline_layout = {
-        let layout_info = PipelineLayoutCreateInfo {
-            flags: PipelineLayoutCreateFlags::empty(),
-            push_constant_ranges: vec![PushConstantRange {
-                stages: ShaderStages::VERTEX,
-                offset: 0,
-                size: 3 * size_of::<Mat4>() as u32,
-            }],
-            ..Default::default()
-        };
-
-        PipelineLayout::new(Arc::clone(device), layout_info)?
-    };
-
-    let pipeline_info = GraphicsPipelineCreateInfo {
-        flags: PipelineCreateFlags::empty(),
-        stages: [
-            PipelineShaderStageCreateInfo::new(vertex_shader),
-            PipelineShaderStageCreateInfo::new(fragment_shader),
-        ]
-        .into_iter()
-        .collect(),
-        vertex_input_state: Some(vertex_input_state),
-        input_assembly_state: Some(InputAssemblyState {
-            topology: PrimitiveTopology::TriangleList,
-            primitive_restart_enable: false,
-            ..Default::default()
-        }),
-        tessellation_state: None,
-        viewport_state: Some(ViewportState {
-            viewports: [Viewport {
-                offset: [0.0, 0.0],
-                extent: [800.0, 600.0],
-                ..Default::default()
-            }]
-            .into_iter()
-            .collect(),
-            scissors: [Scissor {
-                offset: [0, 0],
-                extent: [800, 600],
-            }]
-            .into_iter()
-            .collect(),
-            ..Default::default()
-        }),
-        rasterization_state: Some(RasterizationState {
-            depth_clamp_enable: false,
-            rasterizer_discard_enable: false,
-            polygon_mode: PolygonMode::Fill,
-            cull_mode: CullMode::Back,
-            front_face: FrontFace::Clockwise,
-            depth_bias: None,
-            line_width: 1.0,
-            line_rasterization_mode: LineRasterizationMode::Default,
-            line_stipple: None,
-            ..Default::default()
-        }),
-        multisample_state: Some(MultisampleState::default()),
-        depth_stencil_state: Some(DepthStencilState {
-            depth: Some(DepthState {
-                write_enable: true,
-                compare_op: CompareOp::Less,
-            }),
-            ..Default::default()
-        }),
-        color_blend_state: Some(ColorBlendState {
-            flags: ColorBlendStateFlags::empty(),
-            logic_op: None,
-            attachments: vec![ColorBlendAttachmentState {
-                blend: None,
-                color_write_mask: ColorComponents::all(),
-                color_write_enable: true,
-            }],
-            blend_constants: [0.0; 4],
-            ..Default::default()
-        }),
-        subpass: Some(Subpass::from(render_pass.clone(), 0).unwrap().into()),
-        discard_rectangle_state: None,
-
-        dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
-            .into_iter()
-            .collect(),
+    let vertex_buffers = [MyVertex::per_vertex(), InstanceVertex::per_instance()];
 
-        ..GraphicsPipelineCreateInfo::layout(pipeline_layout.clone())
+    let description = PipelineDescription {
+        set_layouts: vec![material_set_layout, frame_set_layout],
+        depth_bias: true,
+        material_push_constants: true,
+        ..PipelineDescription::with_vertex_buffers(vertex_shader, fragment_shader, &vertex_buffers)?
     };
 
-    let pipeline = GraphicsPipeline::new(device.clone(), None, pipeline_info)?;
-
-    Ok(VulkanPipeline {
-        pipeline,
-        layout: pipeline_layout,
-    })
+    create_pipeline(device, pipeline_cache, render_pass, settings, description)
 }
 
-pub fn load_material_simple(
+/// The post process fragment shader is provided by the user as SPIR-V, it samples the scene color
+/// at set 0 binding 0 with the `uv` received at location 0
+pub fn load_post_process(
     device: &Arc<Device>,
+    pipeline_cache: &Arc<PipelineCache>,
     render_pass: &Arc<RenderPass>,
-    material_set_layout: Arc<DescriptorSetLayout>,
+    fragment_spirv: &[u32],
 ) -> Result<VulkanPipeline> {
     vulkano_shaders::shader! {
         shaders: {
             vertex: {
                 ty: "vertex",
-                path: "shaders/material/simple.vert"
+                path: "shaders/post/fullscreen.vert"
             },
-            fragment: {
-                ty: "fragment",
-                path: "shaders/material/simple.frag"
-            }
         }
     }
 
     let vertex_shader = load_vertex(Arc::clone(device))?
         .entry_point("main")
         .unwrap();
-    let fragment_shader = load_fragment(Arc::clone(device))?
+    let fragment_module = unsafe {
+        ShaderModule::new(Arc::clone(device), ShaderModuleCreateInfo::new(fragment_spirv))?
+    };
+    let fragment_shader = fragment_module
         .entry_point("main")
-        .unwrap();
+        .ok_or_else(|| anyhow::anyhow!("Post process shader has no main entry point"))?;
+
+    let scene_color_set_layout = {
+        let set_info = DescriptorSetLayoutCreateInfo {
+            bindings: [(
+                0,
+                DescriptorSetLayoutBinding {
+                    descriptor_count: 1,
+                    stages: ShaderStages::FRAGMENT,
+                    ..DescriptorSetLayoutBinding::descriptor_type(
+                        DescriptorType::CombinedImageSampler,
+                    )
+                },
+            )]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+
+        DescriptorSetLayout::new(Arc::clone(device), set_info)?
+    };
+
+    // The full screen triangle is generated in the vertex shader, no vertex buffer is bound
+    let description = PipelineDescription {
+        vertex_shader,
+        fragment_shader,
+        vertex_input_state: VertexInputState::new(),
+        set_layouts: vec![scene_color_set_layout],
+        topology: PrimitiveTopology::TriangleList,
+        primitive_restart: false,
+        polygon_mode: PolygonMode::Fill,
+        cull_mode: Some(CullMode::None),
+        depth: None,
+        depth_bias: false,
+        material_push_constants: false,
+        line_width: false,
+        blend: None,
+        color_write: true,
+    };
+
+    create_pipeline(
+        device,
+        pipeline_cache,
+        render_pass,
+        &PipelineSettings::default(),
+        description,
+    )
+}
+
+/// Entry point of a shader provided by the user as SPIR-V, named `main` like in GLSL
+pub fn load_user_shader(device: &Arc<Device>, spirv: &[u32]) -> Result<EntryPoint> {
+    let module =
+        unsafe { ShaderModule::new(Arc::clone(device), ShaderModuleCreateInfo::new(spirv))? };
+
+    module
+        .entry_point("main")
+        .ok_or_else(|| anyhow::anyhow!("The shader has no main entry point"))
+}
+
+/// Compute shaders are provided by the user as SPIR-V, the pipeline layout is reflected from the
+/// shader instead of being written by hand
+pub fn load_compute(
+    device: &Arc<Device>,
+    pipeline_cache: &Arc<PipelineCache>,
+    spirv: &[u32],
+) -> Result<Arc<ComputePipeline>> {
+    let module =
+        unsafe { ShaderModule::new(Arc::clone(device), ShaderModuleCreateInfo::new(spirv))? };
+    let compute_shader = module
+        .entry_point("main")
+        .ok_or_else(|| anyhow::anyhow!("Compute shader has no main entry point"))?;
+
+    let stage = PipelineShaderStageCreateInfo::new(compute_shader);
+    let layout = PipelineLayout::new(
+        Arc::clone(device),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+            .into_pipeline_layout_create_info(Arc::clone(device))?,
+    )?;
+
+    let pipeline = ComputePipeline::new(
+        Arc::clone(device),
+        Some(Arc::clone(pipeline_cache)),
+        ComputePipelineCreateInfo::stage_layout(stage, layout),
+    )?;
+
+    Ok(pipeline)
+}
+
+/// Compute pipeline binding the descriptor set of a material at set 0, for compute passes
+/// reading and writing the storage buffers of the material. Fails if the shader reads anything
+/// else than the material set or if its storage buffers are not visible to compute shaders
+pub fn load_material_compute(
+    device: &Arc<Device>,
+    pipeline_cache: &Arc<PipelineCache>,
+    material_set_layout: Arc<DescriptorSetLayout>,
+    compute_shader: EntryPoint,
+) -> Result<Arc<ComputePipeline>> {
+    anyhow::ensure!(
+        compute_shader.info().execution_model == ExecutionModel::GLCompute,
+        "The material compute shader is a {:?} shader instead of a compute shader",
+        compute_shader.info().execution_model
+    );
+
+    let set_layouts = vec![material_set_layout];
+    validate_shader_layout("compute", &compute_shader, &set_layouts, &[])?;
+
+    let layout = PipelineLayout::new(
+        Arc::clone(device),
+        PipelineLayoutCreateInfo {
+            set_layouts,
+            ..Default::default()
+        },
+    )?;
+
+    let pipeline = ComputePipeline::new(
+        Arc::clone(device),
+        Some(Arc::clone(pipeline_cache)),
+        ComputePipelineCreateInfo::stage_layout(
+            PipelineShaderStageCreateInfo::new(compute_shader),
+            layout,
+        ),
+    )?;
+
+    Ok(pipeline)
+}
+
+/// Checks that the descriptors and push constants the shaders read are provided by the pipeline
+/// layout, with the right type and visible to their stage. A mismatch would otherwise only show
+/// up as a validation error or a black screen, user provided shaders are the most likely culprit
+fn validate_layout(
+    description: &PipelineDescription,
+    push_constant_ranges: &[PushConstantRange],
+) -> Result<()> {
+    let shaders = [
+        ("vertex", &description.vertex_shader),
+        ("fragment", &description.fragment_shader),
+    ];
+
+    for (stage_name, shader) in shaders {
+        validate_shader_layout(
+            stage_name,
+            shader,
+            &description.set_layouts,
+            push_constant_ranges,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Checks a fragment shader given by a material against the layout of the material pipelines
+/// before any pipeline is created with it, the sets being the material, frame and entity sets
+pub fn validate_material_fragment_shader(
+    fragment_shader: &EntryPoint,
+    set_layouts: &[Arc<DescriptorSetLayout>],
+) -> Result<()> {
+    anyhow::ensure!(
+        fragment_shader.info().execution_model == ExecutionModel::Fragment,
+        "The material shader is a {:?} shader instead of a fragment shader",
+        fragment_shader.info().execution_model
+    );
+
+    validate_shader_layout(
+        "fragment",
+        fragment_shader,
+        set_layouts,
+        &[material_push_constant_range()],
+    )
+}
+
+fn validate_shader_layout(
+    stage_name: &str,
+    shader: &EntryPoint,
+    set_layouts: &[Arc<DescriptorSetLayout>],
+    push_constant_ranges: &[PushConstantRange],
+) -> Result<()> {
+    let info = shader.info();
+
+    for ((set, binding), requirements) in info.descriptor_binding_requirements.iter() {
+        let set_layout = set_layouts.get(*set as usize).ok_or_else(|| {
+            anyhow::anyhow!(
+                "The {stage_name} shader reads set {set} binding {binding} but the pipeline only \
+                 has {} descriptor sets",
+                set_layouts.len()
+            )
+        })?;
+
+        let layout_binding = set_layout.bindings().get(binding).ok_or_else(|| {
+            anyhow::anyhow!(
+                "The {stage_name} shader reads set {set} binding {binding} which is not in the \
+                 set layout"
+            )
+        })?;
+
+        anyhow::ensure!(
+            requirements
+                .descriptor_types
+                .contains(&layout_binding.descriptor_type),
+            "The {stage_name} shader expects set {set} binding {binding} to be one of {:?} but \
+             the set layout declares a {:?}",
+            requirements.descriptor_types,
+            layout_binding.descriptor_type
+        );
+
+        anyhow::ensure!(
+            layout_binding.stages.contains(requirements.stages),
+            "Set {set} binding {binding} is not visible to the {stage_name} shader, the set \
+             layout only declares it for {:?}",
+            layout_binding.stages
+        );
+
+        if let Some(descriptor_count) = requirements.descriptor_count {
+            anyhow::ensure!(
+                layout_binding.descriptor_count >= descriptor_count,
+                "The {stage_name} shader reads {descriptor_count} descriptors at set {set} \
+                 binding {binding} but the set layout only declares {}",
+                layout_binding.descriptor_count
+            );
+        }
+    }
+
+    if let Some(requirements) = &info.push_constant_requirements {
+        let end = requirements.offset + requirements.size;
+        let covered = push_constant_ranges.iter().any(|range| {
+            range.stages.contains(requirements.stages)
+                && range.offset <= requirements.offset
+                && end <= range.offset + range.size
+        });
 
-    let vertex_input_state =
-        MyVertex::per_vertex().definition(&vertex_shader.info().input_interface)?;
+        anyhow::ensure!(
+            covered,
+            "The {stage_name} shader reads push constants from byte {} to {end} which no push \
+             constant range of the pipeline layout covers for its stage",
+            requirements.offset
+        );
+    }
+
+    Ok(())
+}
+
+/// Range of the fragment push constants the renderer pushes for every material, see
+/// `Material::fragment_push_constants`
+fn material_push_constant_range() -> PushConstantRange {
+    PushConstantRange {
+        stages: ShaderStages::FRAGMENT,
+        offset: PipelineManager::FRAGMENT_PUSH_CONSTANT_OFFSET,
+        size: PipelineManager::MAX_FRAGMENT_PUSH_CONSTANT_SIZE,
+    }
+}
+
+/// Depth tests are written for the standard depth range, closer fragments having a smaller depth
+fn depth_compare_op(compare_op: CompareOp, reversed_z: bool) -> CompareOp {
+    if !reversed_z {
+        return compare_op;
+    }
+
+    match compare_op {
+        CompareOp::Less => CompareOp::Greater,
+        CompareOp::LessOrEqual => CompareOp::GreaterOrEqual,
+        CompareOp::Greater => CompareOp::Less,
+        CompareOp::GreaterOrEqual => CompareOp::LessOrEqual,
+        compare_op => compare_op,
+    }
+}
+
+fn create_pipeline(
+    device: &Arc<Device>,
+    pipeline_cache: &Arc<PipelineCache>,
+    render_pass: &Arc<RenderPass>,
+    settings: &PipelineSettings,
+    description: PipelineDescription,
+) -> Result<VulkanPipeline> {
+    // The vertex range is the block of the vertex shader, the model, view and projection matrices
+    // followed by the tint of the material shaders and the bone offset of the skinned one. Every
+    // byte of the ranges has to be pushed before drawing, a range shared by all the pipelines
+    // would leave the bytes their shaders do not declare unset
+    let mut push_constant_ranges = Vec::new();
+    push_constant_ranges.extend(description.vertex_shader.info().push_constant_requirements);
+    if description.material_push_constants {
+        push_constant_ranges.push(material_push_constant_range());
+    }
+
+    validate_layout(&description, &push_constant_ranges)?;
+
+    let vertex_attributes = description.vertex_attributes;
+
+    let mut dynamic_state = vec![DynamicState::Viewport, DynamicState::Scissor];
+    if description.depth_bias {
+        dynamic_state.push(DynamicState::DepthBias);
+    }
+    if description.line_width {
+        dynamic_state.push(DynamicState::LineWidth);
+    }
 
     let pipeline_layout = {
         let layout_info = PipelineLayoutCreateInfo {
             flags: PipelineLayoutCreateFlags::empty(),
-            set_layouts: vec![material_set_layout],
-            push_constant_ranges: vec![PushConstantRange {
-                stages: ShaderStages::VERTEX,
-                offset: 0,
-                size: 3 * size_of::<Mat4>() as u32,
-            }],
+            set_layouts: description.set_layouts,
+            push_constant_ranges,
             ..Default::default()
         };
 
@@ -436,15 +1134,15 @@ pub fn load_material_simple(
     let pipeline_info = GraphicsPipelineCreateInfo {
         flags: PipelineCreateFlags::empty(),
         stages: [
-            PipelineShaderStageCreateInfo::new(vertex_shader),
-            PipelineShaderStageCreateInfo::new(fragment_shader),
+            PipelineShaderStageCreateInfo::new(description.vertex_shader),
+            PipelineShaderStageCreateInfo::new(description.fragment_shader),
         ]
         .into_iter()
         .collect(),
-        vertex_input_state: Some(vertex_input_state),
+        vertex_input_state: Some(description.vertex_input_state),
         input_assembly_state: Some(InputAssemblyState {
-            topology: PrimitiveTopology::TriangleList,
-            primitive_restart_enable: false,
+            topology: description.topology,
+            primitive_restart_enable: description.primitive_restart,
             ..Default::default()
         }),
         tessellation_state: None,
@@ -467,20 +1165,27 @@ pub fn load_material_simple(
         rasterization_state: Some(RasterizationState {
             depth_clamp_enable: false,
             rasterizer_discard_enable: false,
-            polygon_mode: PolygonMode::Fill,
-            cull_mode: CullMode::Back,
-            front_face: FrontFace::Clockwise,
-            depth_bias: None,
+            polygon_mode: description.polygon_mode,
+            cull_mode: description.cull_mode.unwrap_or(settings.cull_mode),
+            front_face: settings.front_face,
+            depth_bias: description.depth_bias.then_some(DepthBiasState {
+                constant_factor: 0.0,
+                clamp: 0.0,
+                slope_factor: 0.0,
+            }),
             line_width: 1.0,
             line_rasterization_mode: LineRasterizationMode::Default,
             line_stipple: None,
             ..Default::default()
         }),
-        multisample_state: Some(MultisampleState::default()),
+        multisample_state: Some(MultisampleState {
+            rasterization_samples: settings.samples,
+            ..Default::default()
+        }),
         depth_stencil_state: Some(DepthStencilState {
-            depth: Some(DepthState {
-                write_enable: true,
-                compare_op: CompareOp::Less,
+            depth: description.depth.map(|depth| DepthState {
+                compare_op: depth_compare_op(depth.compare_op, settings.reversed_z),
+                ..depth
             }),
             ..Default::default()
         }),
@@ -488,8 +1193,12 @@ pub fn load_material_simple(
             flags: ColorBlendStateFlags::empty(),
             logic_op: None,
             attachments: vec![ColorBlendAttachmentState {
-                blend: None,
-                color_write_mask: ColorComponents::all(),
+                blend: description.blend,
+                color_write_mask: if description.color_write {
+                    ColorComponents::all()
+                } else {
+                    ColorComponents::empty()
+                },
                 color_write_enable: true,
             }],
             blend_constants: [0.0; 4],
@@ -498,17 +1207,20 @@ pub fn load_material_simple(
         subpass: Some(Subpass::from(render_pass.clone(), 0).unwrap().into()),
         discard_rectangle_state: None,
 
-        dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
-            .into_iter()
-            .collect(),
+        dynamic_state: dynamic_state.into_iter().collect(),
 
         ..GraphicsPipelineCreateInfo::layout(pipeline_layout.clone())
     };
 
-    let pipeline = GraphicsPipeline::new(device.clone(), None, pipeline_info)?;
+    let pipeline = GraphicsPipeline::new(
+        device.clone(),
+        Some(Arc::clone(pipeline_cache)),
+        pipeline_info,
+    )?;
 
     Ok(VulkanPipeline {
         pipeline,
         layout: pipeline_layout,
+        vertex_attributes,
     })
 }
@@ -1,34 +1,42 @@
 use std::mem::size_of;
+use std::ptr;
 use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use glam::{Mat4, Vec2, Vec3, Vec4};
 
 use vulkano::{
+    buffer::{allocator::SubbufferAllocator, Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
     command_buffer::{
-        AutoCommandBufferBuilder, CommandBufferUsage, PrimaryAutoCommandBuffer,
-        RenderPassBeginInfo, SubpassBeginInfo, SubpassContents, SubpassEndInfo,
+        AutoCommandBufferBuilder, ClearAttachment, ClearRect, CommandBufferUsage,
+        PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassBeginInfo, SubpassEndInfo,
     },
-    descriptor_set::DescriptorSetWithOffsets,
+    descriptor_set::{DescriptorSetWithOffsets, PersistentDescriptorSet},
     device::Device,
-    format::{ClearValue, Format},
+    format::{ClearColorValue, ClearValue, Format},
     image::{
         sampler::ComponentMapping,
         view::{ImageView, ImageViewCreateInfo, ImageViewType},
-        Image, ImageAspects, ImageCreateInfo, ImageLayout, ImageSubresourceRange, ImageType,
-        ImageUsage, SampleCount,
+        Image, ImageAspects, ImageLayout, ImageSubresourceRange, ImageUsage, SampleCount,
     },
-    memory::allocator::{AllocationCreateInfo, MemoryAllocatePreference, MemoryTypeFilter},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
     pipeline::{
-        graphics::viewport::{Scissor, Viewport},
-        Pipeline, PipelineBindPoint,
+        graphics::{
+            rasterization::{CullMode, FrontFace},
+            viewport::{Scissor, Viewport},
+        },
+        PipelineBindPoint,
     },
     render_pass::{
         AttachmentDescription, AttachmentLoadOp, AttachmentReference, AttachmentStoreOp,
-        Framebuffer, FramebufferCreateInfo, RenderPass, RenderPassCreateInfo, SubpassDescription,
+        Framebuffer, FramebufferCreateInfo, RenderPass, RenderPassCreateInfo, ResolveMode, Subpass,
+        SubpassDescription,
     },
     swapchain::{
-        self, ColorSpace, CompositeAlpha, FullScreenExclusive, PresentMode, Surface,
-        SurfaceCapabilities, SurfaceInfo, Swapchain, SwapchainCreateInfo, SwapchainPresentInfo,
+        self, ColorSpace, CompositeAlpha, CompositeAlphas, FullScreenExclusive, PresentMode,
+        Surface, SurfaceCapabilities, SurfaceInfo, SurfaceTransform, Swapchain,
+        SwapchainCreateInfo, SwapchainPresentInfo,
     },
     sync::{GpuFuture, Sharing},
     Validated, VulkanError,
@@ -36,22 +44,247 @@ use vulkano::{
 
 use winit::{dpi::PhysicalSize, window::Window};
 
+#[cfg(feature = "egui")]
+use egui_winit_vulkano::{egui, Gui, GuiConfig};
+#[cfg(feature = "egui")]
+use winit::{event::WindowEvent, event_loop::EventLoopWindowTarget};
+
 use crate::{
+    camera::Camera3D,
     engine::{
-        ecs::Scene,
-        material::material_manager::MaterialManager,
-        pipeline_manager::{PipelineManager, VulkanPipeline},
+        color,
+        debug_draw::DebugDraw,
+        ecs::{
+            components::{
+                InstancedMeshComponent, MeshComponent, SkinnedMeshComponent, Sprite, SpriteSpace,
+            },
+            Entity, Scene,
+        },
+        material::{material_manager::MaterialManager, DepthBias},
+        mesh::MeshTopology,
+        pipeline_manager::{PipelineManager, PipelineSettings, VulkanPipeline},
+        sprite_renderer::{SpriteBatches, SpriteRenderer},
+        text_renderer::TextRenderer,
     },
     vulkan_context::VulkanContext,
 };
 
-use super::ecs::components::MeshComponent;
+#[cfg(feature = "profiling")]
+use self::gpu_timer::GpuTimer;
+use self::{
+    entity_uniform::EntityUniforms,
+    frame_graph::{FrameContext, FrameGraph, RenderPassNode},
+    frame_uniform::FrameData,
+    indirect_draw::{IndirectBatch, IndirectMeshes},
+    instancing::InstanceVertex,
+    occlusion::{OcclusionProxy, OcclusionQueries},
+    post_process::PostProcess,
+    render_targets::{RenderTargets, DEPTH_FORMAT, SCENE_COLOR_FORMAT},
+    skinning::FrameBones,
+};
 
-#[derive(Debug, Clone, Copy)]
+#[cfg(feature = "profiling")]
+mod gpu_timer;
+pub(crate) mod entity_uniform;
+pub mod frame_graph;
+pub(crate) mod frame_uniform;
+mod indirect_draw;
+pub(crate) mod instancing;
+mod occlusion;
+mod parallel_recording;
+mod post_process;
+mod recording_threads;
+mod render_targets;
+mod skinning;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RenderMode {
     Default,
     NormalView,
     DepthView,
+    Wireframe,
+    /// Shades the meshes with their per vertex colors
+    VertexColor,
+}
+
+/// What the scene is drawn over, colors are in linear space
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Background {
+    /// Cleared to a single color
+    Solid(Vec3),
+    /// Vertical gradient from the top to the bottom of the window
+    Gradient { top: Vec3, bottom: Vec3 },
+    /// Texture loaded with `Engine::load_sprite_texture`, stretched over the whole window
+    Image(u64),
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Self::Solid(Vec4::from(clear_color()).truncate())
+    }
+}
+
+/// Settings used to create the renderer
+#[derive(Debug, Clone)]
+pub struct RendererInfo {
+    /// Swapchain format and color space pairs by order of preference, the first pair supported
+    /// by the surface is used. Falls back to the first format of the surface when none is
+    pub surface_formats: Vec<(Format, ColorSpace)>,
+    /// Number of samples per pixel used for multisample anti-aliasing
+    pub samples: SampleCount,
+    /// Keeps a single sampled copy of the depth buffer after the main pass, see
+    /// `Renderer::resolved_depth_view`
+    pub resolve_depth: bool,
+    /// SPIR-V of a fragment shader applied to the whole frame before presenting, for effects such
+    /// as tone mapping or vignetting. It receives the screen coordinates as `vec2 uv` at location
+    /// 0 and samples the linear scene color from a `sampler2D` at set 0 binding 0
+    pub post_process_shader: Option<Vec<u32>>,
+    /// Present mode used when the surface supports it, otherwise `PresentMode::Fifo` which every
+    /// surface supports. See `Renderer::current_present_mode` for the one actually used
+    pub present_mode: PresentMode,
+    /// Longest wait for a swapchain image before the swapchain is recreated and the frame
+    /// skipped, so that a lost device doesn't hang the application. `None` waits forever
+    pub acquire_timeout: Option<Duration>,
+    /// What the main pass does with the previous content of the color target: `Clear` fills it
+    /// with the solid background, `Load` draws over it and `DontCare` leaves it undefined when
+    /// every pixel is drawn anyway. Without MSAA or post processing the swapchain images are
+    /// loaded, each holding the frame rendered a few frames ago. See `Engine::clear_next_frame`
+    pub color_load_op: AttachmentLoadOp,
+    /// Same as `color_load_op` for the depth buffer
+    pub depth_load_op: AttachmentLoadOp,
+    /// `DontCare` lets tiled GPUs skip writing the depth back to memory, the depth can then
+    /// neither be loaded by the next frame nor read through `Renderer::depth_view`
+    pub depth_store_op: AttachmentStoreOp,
+}
+
+impl Default for RendererInfo {
+    fn default() -> Self {
+        Self {
+            surface_formats: vec![(Format::R8G8B8A8_SRGB, ColorSpace::SrgbNonLinear)],
+            samples: SampleCount::Sample1,
+            resolve_depth: false,
+            post_process_shader: None,
+            present_mode: PresentMode::Mailbox,
+            acquire_timeout: Some(Duration::from_secs(5)),
+            color_load_op: AttachmentLoadOp::Clear,
+            depth_load_op: AttachmentLoadOp::Clear,
+            depth_store_op: AttachmentStoreOp::Store,
+        }
+    }
+}
+
+/// Background of the frames, a mid gray given in sRGB and converted to linear space like every
+/// color the swapchain encodes on write
+pub(crate) fn clear_color() -> [f32; 4] {
+    color::srgb(0.5, 0.5, 0.5).extend(1.0).to_array()
+}
+
+/// Pulls the wireframe overlay towards the camera, enough for the lines to win the depth test
+/// against the faces they are drawn on without showing through the meshes in front
+const WIREFRAME_OVERLAY_DEPTH_BIAS: DepthBias = DepthBias {
+    constant_factor: -1.0,
+    slope_factor: -1.0,
+};
+
+/// Camera matrices and target rectangle of one view of the scene
+pub struct RenderView {
+    view: Mat4,
+    projection: Mat4,
+    viewport: Viewport,
+    scissor: Scissor,
+}
+
+impl RenderView {
+    fn new(
+        camera: &Camera3D,
+        viewport: Viewport,
+        scissor: Scissor,
+        reversed_z: bool,
+        pre_transform: SurfaceTransform,
+    ) -> Self {
+        let [width, height] = pre_transformed_extent(viewport.extent, pre_transform);
+        let projection = camera.projection_with_depth_range(width / height, reversed_z);
+
+        Self {
+            view: camera.view(),
+            projection: pre_transform_rotation(pre_transform) * projection,
+            viewport,
+            scissor,
+        }
+    }
+
+    pub fn view(&self) -> Mat4 {
+        self.view
+    }
+
+    /// Projection of the camera, already rotated for the pre-transform of the swapchain and
+    /// matching the depth range of the renderer
+    pub fn projection(&self) -> Mat4 {
+        self.projection
+    }
+
+    pub fn viewport(&self) -> &Viewport {
+        &self.viewport
+    }
+
+    pub fn scissor(&self) -> Scissor {
+        self.scissor
+    }
+}
+
+/// Rotation applied after the projection so that the image ends up upright once the presentation
+/// engine applies `pre_transform`. Mirrored transforms are left as they are
+fn pre_transform_rotation(pre_transform: SurfaceTransform) -> Mat4 {
+    let angle = match pre_transform {
+        SurfaceTransform::Rotate90 => 90.0,
+        SurfaceTransform::Rotate180 => 180.0,
+        SurfaceTransform::Rotate270 => 270.0,
+        _ => 0.0,
+    };
+
+    Mat4::from_rotation_z(f32::to_radians(angle))
+}
+
+/// Extent of the image as seen by the user, the swapchain images keep the orientation of the
+/// display so their sides are swapped by a quarter turn
+fn pre_transformed_extent(extent: [f32; 2], pre_transform: SurfaceTransform) -> [f32; 2] {
+    match pre_transform {
+        SurfaceTransform::Rotate90 | SurfaceTransform::Rotate270 => [extent[1], extent[0]],
+        _ => extent,
+    }
+}
+
+/// Meshes of the scene with their entity and draw order. Meshes are drawn by entity within a draw
+/// order and a material so that the same scene always gives the same frame, the draws are
+/// grouped by material afterwards
+fn sorted_meshes(scene: &Scene) -> Vec<(i32, (Entity, &MeshComponent))> {
+    let mut meshes = scene
+        .components_by_entity::<MeshComponent>()
+        .into_iter()
+        .map(|(entity, mesh_component)| (scene.draw_order(entity), (entity, mesh_component)))
+        .collect::<Vec<_>>();
+    meshes.sort_by_key(|(draw_order, _)| *draw_order);
+
+    meshes
+}
+
+/// Everything the scene draws in a frame, gathered once before the render pass and shared by
+/// every view
+struct SceneDraws<'a> {
+    frame_descriptor_set: Arc<PersistentDescriptorSet>,
+    /// Sorted by draw order, then by entity
+    mesh_components: Vec<&'a MeshComponent>,
+    /// Draw order of each of `mesh_components`
+    mesh_draw_orders: Vec<i32>,
+    /// Entity set of each of `mesh_components`, see `EntityUniforms::descriptor_set`
+    mesh_entity_sets: Vec<Arc<PersistentDescriptorSet>>,
+    indirect_batch: Option<IndirectBatch<'a>>,
+    skinned_meshes: Vec<&'a SkinnedMeshComponent>,
+    /// Poses of `skinned_meshes`
+    bones: Option<FrameBones>,
+    instanced_meshes: Vec<(&'a InstancedMeshComponent, Subbuffer<[InstanceVertex]>)>,
+    world_sprites: SpriteBatches,
+    screen_sprites: SpriteBatches,
 }
 
 pub struct Renderer {
@@ -59,18 +292,60 @@ pub struct Renderer {
     window: Arc<Window>,
 
     swapchain: Arc<Swapchain>,
-    _swapchain_images: Vec<Arc<Image>>,
-    _swapchain_image_views: Vec<Arc<ImageView>>,
+    swapchain_images: Vec<Arc<Image>>,
+    swapchain_image_views: Vec<Arc<ImageView>>,
 
-    depth_image: Arc<Image>,
-    depth_image_view: Arc<ImageView>,
+    render_targets: RenderTargets,
 
     render_pass: Arc<RenderPass>,
     framebuffers: Vec<Arc<Framebuffer>>,
 
     pipeline_manager: PipelineManager,
+    post_process: Option<PostProcess>,
+    frame_graph: FrameGraph,
 
     render_mode: RenderMode,
+    background: Background,
+    indirect_drawing: bool,
+    /// Meshes of the scene packed for indirect drawing, kept while they stay the same
+    indirect_meshes: Option<IndirectMeshes>,
+    /// Draws the edges of the meshes over the current render mode
+    wireframe_overlay: bool,
+    /// Set by `clear_next_frame`, the next frame clears its attachments whatever their load op
+    clear_requested: bool,
+    line_width: f32,
+    /// Number of threads recording the meshes of the scene, 1 records everything inline
+    parallel_recording: usize,
+    /// Counts the samples of a box around every mesh to estimate which entities are visible
+    occlusion_queries: bool,
+    /// Skips the meshes of the entities found occluded by the queries
+    occlusion_culling: bool,
+    occlusion: OcclusionQueries,
+
+    debug_draw: DebugDraw,
+    text_renderer: TextRenderer,
+    sprite_renderer: SpriteRenderer,
+    /// Poses of the skinned meshes, uploaded every frame
+    bone_allocator: SubbufferAllocator,
+    /// Uniform components of the entities, written every frame into the buffers of the previous
+    /// frame
+    entity_uniforms: EntityUniforms,
+
+    renderer_info: RendererInfo,
+    /// Seconds elapsed and index of the frame, uploaded for the material shaders
+    time: f32,
+    frame_index: u64,
+    /// Latest window size received since the last frame, resizing many times per frame while the
+    /// window is dragged would recreate the swapchain for sizes never drawn
+    pending_size: Option<PhysicalSize<u32>>,
+    /// Extent of the swapchain when it changed size since the last `take_applied_size`
+    applied_size: Option<PhysicalSize<u32>>,
+
+    #[cfg(feature = "profiling")]
+    gpu_timer: GpuTimer,
+
+    #[cfg(feature = "egui")]
+    gui: Option<Gui>,
 }
 
 impl Renderer {
@@ -78,29 +353,79 @@ impl Renderer {
         vulkan_context: Arc<VulkanContext>,
         window: Arc<Window>,
         material_manager: &MaterialManager,
+        renderer_info: &RendererInfo,
     ) -> Result<Self> {
         let device = vulkan_context.device();
 
-        let (swapchain, swapchain_images) = Self::create_swapchain(&vulkan_context, &window)?;
+        Self::check_renderer_info(&vulkan_context, renderer_info)?;
+
+        let (swapchain, swapchain_images) =
+            Self::create_swapchain(&vulkan_context, &window, renderer_info)?;
         let swapchain_image_views =
             Self::create_swapchain_image_views(&swapchain, &swapchain_images)?;
 
-        let image_extent = swapchain.image_extent();
-        let (depth_image, depth_image_view) =
-            Self::create_depth_image(&vulkan_context, image_extent)?;
+        let post_process_enabled = renderer_info.post_process_shader.is_some();
+
+        let render_targets = RenderTargets::new(
+            &vulkan_context,
+            swapchain.image_extent(),
+            swapchain.image_format(),
+            renderer_info.samples,
+            renderer_info.resolve_depth,
+            post_process_enabled,
+        )?;
 
-        let render_pass = Self::create_render_pass(&device, &swapchain, &depth_image);
+        let render_pass = Self::create_render_pass(
+            device,
+            swapchain.image_format(),
+            renderer_info,
+            post_process_enabled,
+        );
         let framebuffers = Self::create_framebuffers(
             &render_pass,
             &swapchain,
             &swapchain_image_views,
-            &depth_image_view,
+            &render_targets,
+        )?;
+
+        let post_process = match &renderer_info.post_process_shader {
+            Some(fragment_spirv) => Some(PostProcess::new(
+                &vulkan_context,
+                swapchain.image_format(),
+                &swapchain_image_views,
+                render_targets.scene_color_image_view().unwrap(),
+                fragment_spirv,
+            )?),
+            None => None,
+        };
+
+        let text_renderer = TextRenderer::new(Arc::clone(device));
+        let sprite_renderer = SpriteRenderer::new(
+            &vulkan_context,
+            Arc::clone(text_renderer.text_set_layout()),
+        )?;
+
+        #[cfg(feature = "profiling")]
+        let gpu_timer = GpuTimer::new(&vulkan_context, swapchain_images.len())?;
+        let occlusion = OcclusionQueries::new(&vulkan_context, swapchain_images.len())?;
+
+        let entity_uniforms = EntityUniforms::new(
+            &vulkan_context,
+            Arc::clone(material_manager.entity_set_layout()),
         )?;
 
         let pipeline_manager = PipelineManager::new(
             &vulkan_context,
             &render_pass,
             Arc::clone(material_manager.material_set_layout()),
+            Arc::clone(text_renderer.text_set_layout()),
+            indirect_draw::create_object_set_layout(device)?,
+            Arc::clone(material_manager.frame_set_layout()),
+            Arc::clone(material_manager.entity_set_layout()),
+            PipelineSettings {
+                samples: renderer_info.samples,
+                ..Default::default()
+            },
         )?;
 
         Ok(Self {
@@ -108,63 +433,532 @@ impl Renderer {
             window,
 
             swapchain,
-            _swapchain_images: swapchain_images,
-            _swapchain_image_views: swapchain_image_views,
+            swapchain_images,
+            swapchain_image_views,
 
-            depth_image,
-            depth_image_view,
+            render_targets,
 
             render_pass,
             framebuffers,
             pipeline_manager,
+            post_process,
+            frame_graph: FrameGraph::new(),
 
             render_mode: RenderMode::Default,
+            background: Background::default(),
+            indirect_drawing: false,
+            indirect_meshes: None,
+            wireframe_overlay: false,
+            clear_requested: false,
+            line_width: 1.0,
+            parallel_recording: 1,
+            occlusion_queries: false,
+            occlusion_culling: false,
+            occlusion,
+
+            debug_draw: DebugDraw::new(),
+            text_renderer,
+            sprite_renderer,
+            bone_allocator: skinning::create_bone_allocator(&vulkan_context),
+            entity_uniforms,
+
+            renderer_info: renderer_info.clone(),
+            time: 0.0,
+            frame_index: 0,
+            pending_size: None,
+            applied_size: None,
+
+            #[cfg(feature = "profiling")]
+            gpu_timer,
+
+            #[cfg(feature = "egui")]
+            gui: None,
         })
     }
 
-    pub(crate) fn _set_render_mode(&mut self, render_mode: RenderMode) {
+    /// Format of the swapchain images the scene is rendered to
+    pub fn swapchain_format(&self) -> Format {
+        self.swapchain.image_format()
+    }
+
+    pub fn swapchain_color_space(&self) -> ColorSpace {
+        self.swapchain.image_color_space()
+    }
+
+    pub fn swapchain_image_count(&self) -> u32 {
+        self.swapchain_images.len() as u32
+    }
+
+    pub fn swapchain_extent(&self) -> [u32; 2] {
+        self.swapchain.image_extent()
+    }
+
+    /// Capabilities of the window surface queried from the driver, they follow the window so they
+    /// are queried again on every call. `min_image_extent` and `max_image_extent` bound the sizes
+    /// the swapchain can be resized to, `current_extent` is the size of the window when the
+    /// surface imposes it
+    pub fn surface_capabilities(&self) -> Result<SurfaceCapabilities> {
+        let physical_device = self.vulkan_context.device().physical_device();
+
+        Ok(physical_device
+            .surface_capabilities(self.swapchain.surface().as_ref(), Self::surface_info())?)
+    }
+
+    /// Present mode of the swapchain, which differs from `RendererInfo::present_mode` when the
+    /// surface does not support the requested one
+    pub fn current_present_mode(&self) -> PresentMode {
+        self.swapchain.present_mode()
+    }
+
+    /// Depth buffer of the last rendered frame, multisampled when MSAA is enabled. The image is
+    /// recreated when the window is resized, the view must be fetched again afterwards. The near
+    /// plane is at a depth of 1 when reversed-Z is enabled
+    pub fn depth_view(&self) -> &Arc<ImageView> {
+        self.render_targets.depth_image_view()
+    }
+
+    /// Single sampled depth of the last rendered frame, available when
+    /// `RendererInfo::resolve_depth` is set. The view changes when the window is resized
+    pub fn resolved_depth_view(&self) -> Option<&Arc<ImageView>> {
+        self.render_targets.resolved_depth_image_view()
+    }
+
+    /// GPU time spent in the main render pass of a recent frame, the result of a frame is only
+    /// known once its swapchain image comes back, a few frames later. `None` until then or if
+    /// the device can't write timestamps
+    #[cfg(feature = "profiling")]
+    pub fn last_gpu_time_ms(&self) -> Option<f32> {
+        self.gpu_timer.last_gpu_time_ms()
+    }
+
+    pub fn samples(&self) -> SampleCount {
+        self.renderer_info.samples
+    }
+
+    /// Render pass the scene is drawn in, pipelines built against `subpass` can be bound while
+    /// recording the scene. Its only subpass writes a color attachment of `color_format` and a
+    /// depth attachment of `depth_format`, both with `samples` samples
+    pub fn render_pass(&self) -> &Arc<RenderPass> {
+        &self.render_pass
+    }
+
+    /// Subpass of `render_pass` the meshes are drawn in
+    pub fn subpass(&self) -> Subpass {
+        Subpass::from(Arc::clone(&self.render_pass), 0).unwrap()
+    }
+
+    /// Format of the color attachment written by the scene, the swapchain format unless a post
+    /// process shader is set, in which case the scene is drawn into a linear HDR image
+    pub fn color_format(&self) -> Format {
+        match self.post_process {
+            Some(_) => SCENE_COLOR_FORMAT,
+            None => self.swapchain.image_format(),
+        }
+    }
+
+    pub fn depth_format(&self) -> Format {
+        DEPTH_FORMAT
+    }
+
+    pub(crate) fn set_render_mode(&mut self, render_mode: RenderMode) -> Result<()> {
+        anyhow::ensure!(
+            render_mode != RenderMode::Wireframe || self.wireframe_supported(),
+            "The wireframe render mode needs the fill_mode_non_solid feature, which the device \
+             does not support"
+        );
+
         self.render_mode = render_mode;
+        Ok(())
+    }
+
+    pub(crate) fn render_mode(&self) -> RenderMode {
+        self.render_mode
+    }
+
+    pub(crate) fn set_frame_time(&mut self, time: f32, frame_index: u64) {
+        self.time = time;
+        self.frame_index = frame_index;
+    }
+
+    pub(crate) fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
+
+    pub(crate) fn background(&self) -> Background {
+        self.background
     }
 
-    pub fn clear_screen(&self) -> Result<()> {
-        todo!("Rendering currently clears automaticaly => TODO: Handle rendering without clearing");
+    pub(crate) fn set_indirect_drawing(&mut self, indirect_drawing: bool) -> Result<()> {
+        let features = self.vulkan_context.device().enabled_features();
+        anyhow::ensure!(
+            !indirect_drawing
+                || (features.multi_draw_indirect && features.draw_indirect_first_instance),
+            "Indirect drawing is not supported by the device"
+        );
+
+        self.indirect_drawing = indirect_drawing;
+        Ok(())
+    }
+
+    pub(crate) fn indirect_drawing(&self) -> bool {
+        self.indirect_drawing
+    }
+
+    pub(crate) fn set_wireframe_overlay(&mut self, wireframe_overlay: bool) -> Result<()> {
+        anyhow::ensure!(
+            !wireframe_overlay || self.wireframe_supported(),
+            "The wireframe overlay needs the fill_mode_non_solid feature, which the device does \
+             not support"
+        );
+
+        self.wireframe_overlay = wireframe_overlay;
+        Ok(())
+    }
+
+    pub(crate) fn wireframe_overlay(&self) -> bool {
+        self.wireframe_overlay
+    }
+
+    /// Whether the device enabled `fill_mode_non_solid`, without it the meshes can't be drawn as
+    /// lines
+    pub(crate) fn wireframe_supported(&self) -> bool {
+        self.vulkan_context
+            .device()
+            .enabled_features()
+            .fill_mode_non_solid
+    }
+
+    pub(crate) fn set_front_face(&mut self, front_face: FrontFace) -> Result<()> {
+        let mut settings = *self.pipeline_manager.settings();
+        settings.front_face = front_face;
+
+        self.pipeline_manager.set_settings(settings)
+    }
+
+    pub(crate) fn front_face(&self) -> FrontFace {
+        self.pipeline_manager.settings().front_face
+    }
+
+    pub(crate) fn set_cull_mode(&mut self, cull_mode: CullMode) -> Result<()> {
+        let mut settings = *self.pipeline_manager.settings();
+        settings.cull_mode = cull_mode;
+
+        self.pipeline_manager.set_settings(settings)
+    }
+
+    pub(crate) fn cull_mode(&self) -> CullMode {
+        self.pipeline_manager.settings().cull_mode
+    }
+
+    pub(crate) fn set_depth_prepass(&mut self, depth_prepass: bool) -> Result<()> {
+        let mut settings = *self.pipeline_manager.settings();
+        settings.depth_prepass = depth_prepass;
+
+        self.pipeline_manager.set_settings(settings)
+    }
+
+    pub(crate) fn depth_prepass(&self) -> bool {
+        self.pipeline_manager.settings().depth_prepass
+    }
+
+    pub(crate) fn render_node_names(&self) -> Vec<&'static str> {
+        self.frame_graph.node_names()
+    }
+
+    pub(crate) fn insert_render_node(
+        &mut self,
+        index: usize,
+        node: Box<dyn RenderPassNode>,
+    ) -> Result<()> {
+        self.frame_graph.insert(index, node)
+    }
+
+    pub(crate) fn remove_render_node(&mut self, name: &str) -> Result<Box<dyn RenderPassNode>> {
+        self.frame_graph.remove(name)
+    }
+
+    pub(crate) fn move_render_node(&mut self, name: &str, index: usize) -> Result<()> {
+        self.frame_graph.move_node(name, index)
+    }
+
+    pub(crate) fn set_reversed_z(&mut self, reversed_z: bool) -> Result<()> {
+        let mut settings = *self.pipeline_manager.settings();
+        settings.reversed_z = reversed_z;
+
+        self.pipeline_manager.set_settings(settings)
+    }
+
+    pub(crate) fn reversed_z(&self) -> bool {
+        self.pipeline_manager.settings().reversed_z
+    }
+
+    /// Width in pixels of the debug lines and wireframe edges. Devices without the `wide_lines`
+    /// feature only draw 1 pixel wide lines, other widths are clamped to the supported range
+    pub(crate) fn set_line_width(&mut self, line_width: f32) {
+        let device = self.vulkan_context.device();
+        if !device.enabled_features().wide_lines {
+            if line_width != 1.0 {
+                eprintln!("Wide lines are not supported by the device, using a width of 1.0");
+            }
+            self.line_width = 1.0;
+            return;
+        }
+
+        let [min, max] = device.physical_device().properties().line_width_range;
+        self.line_width = line_width.clamp(min, max);
+    }
+
+    pub(crate) fn line_width(&self) -> f32 {
+        self.line_width
+    }
+
+    /// Records the meshes drawn with their material on `threads` worker threads, each into its
+    /// own secondary command buffer. Only worth it for scenes with many meshes, the threads are
+    /// started for each frame. 0 or 1 records everything on the calling thread
+    pub(crate) fn set_parallel_recording(&mut self, threads: usize) {
+        self.parallel_recording = threads.max(1);
     }
 
+    pub(crate) fn parallel_recording(&self) -> usize {
+        self.parallel_recording
+    }
+
+    /// Disabling the queries disables the occlusion culling and forgets the visibility estimate
+    pub(crate) fn set_occlusion_queries(&mut self, occlusion_queries: bool) {
+        self.occlusion_queries = occlusion_queries;
+        if !occlusion_queries {
+            self.occlusion_culling = false;
+            self.occlusion.clear();
+        }
+    }
+
+    pub(crate) fn occlusion_queries(&self) -> bool {
+        self.occlusion_queries
+    }
+
+    /// Enabling the occlusion culling enables the queries it relies on
+    pub(crate) fn set_occlusion_culling(&mut self, occlusion_culling: bool) {
+        self.occlusion_culling = occlusion_culling;
+        if occlusion_culling {
+            self.occlusion_queries = true;
+        }
+    }
+
+    pub(crate) fn occlusion_culling(&self) -> bool {
+        self.occlusion_culling
+    }
+
+    pub(crate) fn entity_visible(&self, entity: Entity) -> Option<bool> {
+        self.occlusion.entity_visible(entity)
+    }
+
+    pub(crate) fn debug_draw_mut(&mut self) -> &mut DebugDraw {
+        &mut self.debug_draw
+    }
+
+    pub(crate) fn text_renderer_mut(&mut self) -> &mut TextRenderer {
+        &mut self.text_renderer
+    }
+
+    pub(crate) fn sprite_renderer_mut(&mut self) -> &mut SpriteRenderer {
+        &mut self.sprite_renderer
+    }
+
+    #[cfg(feature = "egui")]
+    pub(crate) fn init_gui<T>(&mut self, event_loop: &EventLoopWindowTarget<T>) {
+        // The gui is drawn on top of the scene, directly into the sRGB swapchain images
+        self.gui = Some(Gui::new(
+            event_loop,
+            Arc::clone(self.swapchain.surface()),
+            Arc::clone(self.vulkan_context.graphics_queue()),
+            self.swapchain.image_format(),
+            GuiConfig {
+                allow_srgb_render_target: true,
+                is_overlay: true,
+                ..Default::default()
+            },
+        ));
+    }
+
+    #[cfg(feature = "egui")]
+    pub(crate) fn gui_update(&mut self, window_event: &WindowEvent) {
+        if let Some(gui) = &mut self.gui {
+            gui.update(window_event);
+        }
+    }
+
+    #[cfg(feature = "egui")]
+    pub(crate) fn gui_frame(&mut self, build_ui: impl FnOnce(&egui::Context)) {
+        if let Some(gui) = &mut self.gui {
+            gui.immediate_ui(|gui| build_ui(&gui.context()));
+        }
+    }
+
+    pub(crate) fn clear_next_frame(&mut self) {
+        self.clear_requested = true;
+    }
+
+    /// Presents a frame only cleared to the background color, without drawing the scene
+    pub(crate) fn clear_screen(&mut self) -> Result<()> {
+        if let Some(new_size) = self.pending_size.take() {
+            self.resize(new_size)?;
+        }
+
+        self.present_frame(|renderer, image_index| renderer.record_clear_frame(image_index))
+    }
+
+    /// Without a camera only the background and the overlays are drawn, so that a scene can be
+    /// set up over several frames
     pub(crate) fn render_scene(&mut self, scene: &Scene) -> Result<()> {
-        debug_assert!(scene.camera().is_some());
-
-        let (image_index, _suboptimal, swapchain_future) =
-            match swapchain::acquire_next_image(self.swapchain.clone(), None)
-                .map_err(Validated::unwrap)
-            {
-                Ok(x) => x,
-                Err(vulkano::VulkanError::OutOfDate) => panic!(),
-                Err(e) => panic!("{e}"),
-            };
+        let Some(camera) = scene.camera() else {
+            return self.render_views(scene, &[]);
+        };
 
-        let command_buffer = match self.render_mode {
-            RenderMode::Default => self.record_draw_command_buffer(
-                image_index as usize,
-                scene,
-                self.pipeline_manager.material_pipeline(),
-            )?,
-            RenderMode::NormalView => self.record_debug_draw_command_buffer(
-                image_index as usize,
-                scene,
-                self.pipeline_manager.normal_pipeline(),
-            )?,
-            RenderMode::DepthView => self.record_debug_draw_command_buffer(
-                image_index as usize,
-                scene,
-                self.pipeline_manager.depth_pipeline(),
-            )?,
+        let (viewport, scissor) = self.full_viewport();
+        let views = [RenderView::new(
+            camera,
+            viewport,
+            scissor,
+            self.reversed_z(),
+            self.swapchain.pre_transform(),
+        )];
+
+        self.render_views(scene, &views)
+    }
+
+    /// Renders the scene once per camera in a single render pass, each into its own part of the
+    /// window. Used for split-screen or picture-in-picture
+    pub(crate) fn render_scene_multi(
+        &mut self,
+        scene: &Scene,
+        views: &[(Camera3D, Viewport, Scissor)],
+    ) -> Result<()> {
+        let views = views
+            .iter()
+            .map(|(camera, viewport, scissor)| {
+                RenderView::new(
+                    camera,
+                    viewport.clone(),
+                    *scissor,
+                    self.reversed_z(),
+                    self.swapchain.pre_transform(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        self.render_views(scene, &views)
+    }
+
+    fn render_views(&mut self, scene: &Scene, views: &[RenderView]) -> Result<()> {
+        if let Some(new_size) = self.pending_size.take() {
+            self.resize(new_size)?;
+        }
+
+        // Pipelines can't be created while recording, which only borrows the renderer
+        let material_manager = scene.material_manager();
+        self.pipeline_manager
+            .retain_custom_materials(|material| material_manager.contains_material(material));
+        for (_, mesh_component) in scene.meshes() {
+            let topology = mesh_component.mesh.topology();
+            self.pipeline_manager.prepare_mesh_topology(topology)?;
+
+            for submesh in mesh_component.draw_ranges() {
+                if let Some(fragment_shader) = material_manager.fragment_shader(submesh.material) {
+                    self.pipeline_manager.prepare_custom_material(
+                        submesh.material,
+                        fragment_shader,
+                        topology,
+                    )?;
+                }
+            }
+        }
+
+        // Like the pipelines, the packed meshes are kept by the renderer and updated beforehand
+        let (draw_orders, meshes): (Vec<_>, Vec<_>) = sorted_meshes(scene).into_iter().unzip();
+        let (mesh_entities, mesh_components): (Vec<_>, Vec<_>) = meshes.into_iter().unzip();
+        if self.draws_indirect(&mesh_components) {
+            IndirectMeshes::update(
+                &mut self.indirect_meshes,
+                &self.vulkan_context,
+                &mesh_components,
+                &draw_orders,
+            )?;
+        } else {
+            self.indirect_meshes = None;
+        }
+
+        // Written once and shared by every view, the previous frame is done reading the buffers
+        self.entity_uniforms
+            .update(&self.vulkan_context, scene, &mesh_entities)?;
+
+        let proxies = self.occlusion_proxies(scene, views);
+        self.occlusion
+            .set_frame_proxies(&self.vulkan_context, proxies)?;
+
+        self.present_frame(|renderer, image_index| {
+            renderer.record_command_buffer(image_index, scene, views)
+        })?;
+        self.clear_requested = false;
+
+        self.debug_draw.clear();
+        self.text_renderer.clear();
+
+        Ok(())
+    }
+
+    /// Acquires a swapchain image, submits the command buffer recorded for it then presents it.
+    /// The swapchain is recreated and the frame dropped when it went out of date
+    fn present_frame(
+        &mut self,
+        record: impl FnOnce(&Self, usize) -> Result<Arc<PrimaryAutoCommandBuffer>>,
+    ) -> Result<()> {
+        let acquired = swapchain::acquire_next_image(
+            self.swapchain.clone(),
+            self.renderer_info.acquire_timeout,
+        )
+        .map_err(Validated::unwrap);
+        let (image_index, _suboptimal, swapchain_future) = match acquired {
+            Ok(x) => x,
+            Err(VulkanError::OutOfDate) => return self.recover_swapchain(),
+            Err(VulkanError::Timeout) => {
+                eprintln!(
+                    "No swapchain image was available after {:?}, skipping the frame",
+                    self.renderer_info.acquire_timeout.unwrap_or_default()
+                );
+                return self.recover_swapchain();
+            }
+            Err(e) => return Err(e).context("Failed to acquire a swapchain image"),
         };
 
-        let future = swapchain_future
-            .then_execute(
-                Arc::clone(self.vulkan_context.graphics_queue()),
-                command_buffer,
-            )?
+        #[cfg(feature = "profiling")]
+        self.gpu_timer.prepare_frame(image_index as usize)?;
+        self.occlusion.prepare_frame(image_index as usize)?;
+
+        let command_buffer = record(self, image_index as usize)?;
+
+        let future = swapchain_future.then_execute(
+            Arc::clone(self.vulkan_context.graphics_queue()),
+            command_buffer,
+        )?;
+
+        #[cfg(feature = "egui")]
+        let future = match &mut self.gui {
+            Some(gui) => gui.draw_on_image(
+                future,
+                Arc::clone(&self.swapchain_image_views[image_index as usize]),
+            ),
+            None => future.boxed(),
+        };
+
+        // Presenting from another queue has to wait for the rendering to finish on the graphics
+        // queue, on a single queue the submissions are already executed in order
+        let future = if self.vulkan_context.separate_present_queue() {
+            future.then_signal_semaphore_and_flush()?.boxed()
+        } else {
+            future.boxed()
+        };
+
+        let future = future
             .then_swapchain_present(
                 Arc::clone(self.vulkan_context.present_queue()),
                 SwapchainPresentInfo::swapchain_image_index(self.swapchain.clone(), image_index),
@@ -174,213 +968,992 @@ impl Renderer {
         match future.map_err(Validated::unwrap) {
             Ok(_) => (),
 
-            Err(VulkanError::OutOfDate) => {
-                self.resize(self.window.inner_size())?;
+            Err(VulkanError::OutOfDate) => return self.recover_swapchain(),
+
+            Err(e) => return Err(e).context("Failed to present the frame"),
+        }
+
+        Ok(())
+    }
+
+    /// Command buffer clearing the main render pass attachments, followed by the post process
+    /// which then writes the cleared color to the swapchain image
+    fn record_clear_frame(&self, image_index: usize) -> Result<Arc<PrimaryAutoCommandBuffer>> {
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.vulkan_context
+                .standard_command_buffer_allocator()
+                .as_ref(),
+            self.vulkan_context.graphics_queue().queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+
+        let render_pass_begin_info = RenderPassBeginInfo {
+            render_pass: self.render_pass.clone(),
+            render_area_offset: [0, 0],
+            render_area_extent: self.swapchain.image_extent(),
+            clear_values: self.clear_values(),
+            ..RenderPassBeginInfo::framebuffer(self.framebuffers[image_index].clone())
+        };
+
+        builder.begin_render_pass(render_pass_begin_info, SubpassBeginInfo::default())?;
+        self.record_clear(&mut builder)?;
+        builder.end_render_pass(SubpassEndInfo::default())?;
+
+        if let Some(post_process) = &self.post_process {
+            post_process.record(&mut builder, image_index)?;
+        }
+
+        Ok(builder.build()?)
+    }
+
+    /// Recreates the swapchain at the current size of the window after it went out of date or no
+    /// image could be acquired in time. The current frame is dropped along with its debug lines
+    /// and text, which are submitted again with the next one
+    fn recover_swapchain(&mut self) -> Result<()> {
+        self.debug_draw.clear();
+        self.text_renderer.clear();
+        self.pending_size = None;
+
+        self.resize(self.window.inner_size())
+    }
+
+    /// Viewport and scissor covering the whole swapchain image
+    fn full_viewport(&self) -> (Viewport, Scissor) {
+        let viewport = Viewport {
+            offset: [0.0, 0.0],
+            extent: self.swapchain.image_extent().map(|x| x as f32),
+            depth_range: 0.0..=1.0,
+        };
+
+        let scissor = Scissor {
+            offset: [0, 0],
+            extent: self.swapchain.image_extent(),
+        };
+
+        (viewport, scissor)
+    }
+
+    /// Color the frame is cleared to, gradients and images are drawn over the whole frame so
+    /// their clear color is never seen
+    fn background_clear_color(&self) -> [f32; 4] {
+        match self.background {
+            Background::Solid(color) => color.extend(1.0).to_array(),
+            _ => clear_color(),
+        }
+    }
+
+    /// Depth of the far plane
+    fn clear_depth(&self) -> f32 {
+        if self.reversed_z() {
+            0.0
+        } else {
+            1.0
+        }
+    }
+
+    /// Clear values of the main render pass attachments, in the order of `create_render_pass`.
+    /// Only the attachments cleared by their load op have one
+    fn clear_values(&self) -> Vec<Option<ClearValue>> {
+        let renderer_info = &self.renderer_info;
+        let color = (renderer_info.color_load_op == AttachmentLoadOp::Clear)
+            .then_some(ClearValue::Float(self.background_clear_color()));
+        let depth = (renderer_info.depth_load_op == AttachmentLoadOp::Clear)
+            .then_some(ClearValue::Depth(self.clear_depth()));
+
+        if renderer_info.samples == SampleCount::Sample1 {
+            vec![color, depth]
+        } else {
+            let mut clear_values = vec![None, depth, color];
+            if self.renderer_info.resolve_depth {
+                clear_values.push(None);
             }
 
-            Err(e) => panic!("{:#?}", e),
+            clear_values
+        }
+    }
+
+    /// Whether the meshes are packed and drawn indirectly, the packed meshes being drawn as a
+    /// single triangle list
+    fn draws_indirect(&self, mesh_components: &[&MeshComponent]) -> bool {
+        self.indirect_drawing
+            && self.render_mode == RenderMode::Default
+            && mesh_components
+                .iter()
+                .all(|mesh_component| mesh_component.mesh.topology() == MeshTopology::TRIANGLE_LIST)
+    }
+
+    /// Proxy of every mesh in every view counted by an occlusion query in the next frame, empty
+    /// when the queries are disabled. The meshes crossing the near plane of a view have no proxy
+    /// in it, see `OcclusionProxy::new`
+    fn occlusion_proxies(&self, scene: &Scene, views: &[RenderView]) -> Vec<OcclusionProxy> {
+        if !self.occlusion_queries {
+            return Vec::new();
+        }
+
+        let reversed_z = self.reversed_z();
+        let meshes = scene.components_by_entity::<MeshComponent>();
+        views
+            .iter()
+            .enumerate()
+            .flat_map(|(view_index, view)| {
+                let view_projection = view.projection * view.view;
+                meshes.iter().filter_map(move |(entity, mesh_component)| {
+                    OcclusionProxy::new(
+                        *entity,
+                        view_index,
+                        view_projection,
+                        reversed_z,
+                        mesh_component.model.transform(),
+                        mesh_component.mesh.bounds(),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Whether the occlusion culling skips the mesh of `entity`, found occluded by the queries
+    fn occlusion_culled(&self, entity: Entity) -> bool {
+        self.occlusion_culling && self.occlusion.entity_visible(entity) == Some(false)
+    }
+
+    fn record_command_buffer(
+        &self,
+        image_index: usize,
+        scene: &Scene,
+        views: &[RenderView],
+    ) -> Result<Arc<PrimaryAutoCommandBuffer>> {
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.vulkan_context
+                .standard_command_buffer_allocator()
+                .as_ref(),
+            self.vulkan_context.graphics_queue().queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+
+        #[cfg(feature = "profiling")]
+        self.gpu_timer.begin(&mut builder, image_index)?;
+        self.occlusion.begin(&mut builder, image_index)?;
+
+        let [width, height] = self.swapchain.image_extent();
+        let frame_descriptor_set = frame_uniform::create_frame_descriptor_set(
+            &self.vulkan_context,
+            self.pipeline_manager.frame_set_layout(),
+            FrameData {
+                resolution: Vec2::new(width as f32, height as f32),
+                time: self.time,
+                frame_index: self.frame_index as u32,
+            },
+        )?;
+
+        let meshes = sorted_meshes(scene);
+
+        // Only the objects and draw commands change from one frame to the next, the culled meshes
+        // stay packed
+        let indirect_batch = self
+            .indirect_meshes
+            .as_ref()
+            .map(|indirect_meshes| {
+                let (culled, mesh_components): (Vec<_>, Vec<_>) = meshes
+                    .iter()
+                    .map(|(_, (entity, mesh_component))| {
+                        (self.occlusion_culled(*entity), *mesh_component)
+                    })
+                    .unzip();
+
+                IndirectBatch::build(
+                    &self.vulkan_context,
+                    self.pipeline_manager.object_set_layout(),
+                    indirect_meshes,
+                    &mesh_components,
+                    &culled,
+                )
+            })
+            .transpose()?;
+
+        // The occlusion queries keep testing the culled meshes, see `occlusion_proxies`
+        let (mesh_draw_orders, meshes): (Vec<_>, Vec<_>) = meshes
+            .into_iter()
+            .filter(|(_, (entity, _))| !self.occlusion_culled(*entity))
+            .unzip();
+        let (mesh_entities, mesh_components): (Vec<_>, Vec<_>) = meshes.into_iter().unzip();
+
+        let mesh_entity_sets = mesh_entities
+            .iter()
+            .map(|&entity| Arc::clone(self.entity_uniforms.descriptor_set(entity)))
+            .collect::<Vec<_>>();
+
+        // The poses are uploaded once and shared by every view
+        let skinned_meshes = if self.render_mode == RenderMode::Default {
+            scene
+                .components_by_entity::<SkinnedMeshComponent>()
+                .into_iter()
+                .map(|(_, skinned_mesh)| skinned_mesh)
+                .collect::<Vec<_>>()
+        } else {
+            Vec::new()
+        };
+        let bones = skinning::upload_bones(
+            &self.vulkan_context,
+            &self.bone_allocator,
+            self.pipeline_manager.object_set_layout(),
+            &skinned_meshes,
+        )?;
+
+        // Like the poses, the instances are uploaded once for every view. A mesh without
+        // instances has nothing to draw
+        let instanced_meshes = if self.render_mode == RenderMode::Default {
+            scene
+                .components_by_entity::<InstancedMeshComponent>()
+                .into_iter()
+                .filter(|(_, instanced_mesh)| !instanced_mesh.instances.is_empty())
+                .map(|(_, instanced_mesh)| {
+                    let instance_buffer = instancing::create_instance_buffer(
+                        &self.vulkan_context,
+                        &instanced_mesh.instances,
+                    )?;
+
+                    Ok((instanced_mesh, instance_buffer))
+                })
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            Vec::new()
+        };
+
+        let sprites = scene
+            .components_by_entity::<Sprite>()
+            .into_iter()
+            .map(|(_, sprite)| sprite)
+            .collect::<Vec<_>>();
+        let world_sprites = self.sprite_renderer.build_batches(
+            &self.vulkan_context,
+            &sprites,
+            SpriteSpace::World,
+        )?;
+        let screen_sprites = self.sprite_renderer.build_batches(
+            &self.vulkan_context,
+            &sprites,
+            SpriteSpace::Screen,
+        )?;
+
+        let draws = SceneDraws {
+            frame_descriptor_set,
+            mesh_components,
+            mesh_draw_orders,
+            mesh_entity_sets,
+            indirect_batch,
+            skinned_meshes,
+            bones,
+            instanced_meshes,
+            world_sprites,
+            screen_sprites,
+        };
+
+        let frame_ctx = FrameContext::new(self, image_index, views, &draws);
+        self.frame_graph.record(&mut builder, scene, &frame_ctx)?;
+
+        #[cfg(feature = "profiling")]
+        self.gpu_timer.end(&mut builder, image_index)?;
+
+        let command_buffer = builder.build()?;
+
+        Ok(command_buffer)
+    }
+
+    /// Records everything drawn within the viewport of `view`, the meshes drawn being
+    /// `mesh_components` rather than all those of `draws`
+    fn record_view<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        scene: &Scene,
+        view: &RenderView,
+        mesh_components: &[&MeshComponent],
+        draws: &SceneDraws,
+    ) -> Result<()> {
+        builder
+            .set_viewport(0, [view.viewport.clone()].into_iter().collect())?
+            .set_scissor(0, [view.scissor].into_iter().collect())?;
+
+        match self.render_mode {
+            RenderMode::Default => {
+                self.record_material_draws(builder, scene, view, mesh_components, draws)?;
+                self.record_skinned_draws(builder, scene, view, draws)?;
+                self.record_instanced_draws(builder, scene, view, draws)?;
+            }
+            _ => self.record_debug_draws(builder, view, mesh_components)?,
+        }
+
+        // The wireframe render mode already draws the edges. The overlay covers every mesh, even
+        // those recorded on other threads
+        if self.wireframe_overlay && self.render_mode != RenderMode::Wireframe {
+            self.record_wireframe_overlay(builder, view, &draws.mesh_components)?;
+        }
+
+        self.record_sprites(
+            builder,
+            &draws.world_sprites,
+            self.pipeline_manager.sprite_pipeline(),
+            view.view,
+            view.projection,
+        )?;
+
+        self.record_debug_lines(builder, view.view, view.projection)
+    }
+
+    /// Clears the color and depth of the current subpass to the background over the whole
+    /// window, whatever the load ops of the attachments
+    fn record_clear<L>(&self, builder: &mut AutoCommandBufferBuilder<L>) -> Result<()> {
+        let clear_rect = ClearRect {
+            offset: [0, 0],
+            extent: self.swapchain.image_extent(),
+            array_layers: 0..1,
+        };
+
+        builder.clear_attachments(
+            [
+                ClearAttachment::Color {
+                    color_attachment: 0,
+                    clear_value: ClearColorValue::Float(self.background_clear_color()),
+                },
+                ClearAttachment::Depth(self.clear_depth()),
+            ]
+            .into_iter()
+            .collect(),
+            [clear_rect].into_iter().collect(),
+        )?;
+
+        Ok(())
+    }
+
+    /// Records the background over the whole window, before every view. Solid backgrounds are
+    /// only a clear color
+    fn record_background<L>(&self, builder: &mut AutoCommandBufferBuilder<L>) -> Result<()> {
+        let vulkan_pipeline = match self.background {
+            Background::Solid(_) => return Ok(()),
+            Background::Gradient { .. } => self.pipeline_manager.background_gradient_pipeline(),
+            Background::Image(_) => self.pipeline_manager.background_image_pipeline(),
+        };
+        let layout = &vulkan_pipeline.layout;
+
+        let (viewport, scissor) = self.full_viewport();
+        builder
+            .set_viewport(0, [viewport].into_iter().collect())?
+            .set_scissor(0, [scissor].into_iter().collect())?
+            .bind_pipeline_graphics(Arc::clone(&vulkan_pipeline.pipeline))?;
+
+        match self.background {
+            Background::Gradient { top, bottom } => {
+                builder.push_constants(
+                    Arc::clone(layout),
+                    0,
+                    [top.extend(1.0), bottom.extend(1.0)],
+                )?;
+            }
+            Background::Image(texture) => {
+                let texture_descriptor_set = self.sprite_renderer.descriptor_set(texture);
+
+                // The vertex shader is shared with the gradient, its colors are unused but have
+                // to be set like every push constant of the layout
+                builder
+                    .push_constants(Arc::clone(layout), 0, [Vec4::ZERO; 2])?
+                    .bind_descriptor_sets(
+                        PipelineBindPoint::Graphics,
+                        Arc::clone(layout),
+                        0,
+                        vec![DescriptorSetWithOffsets::new(
+                            Arc::clone(texture_descriptor_set),
+                            [],
+                        )],
+                    )?;
+            }
+            Background::Solid(_) => (),
+        }
+
+        // The full screen triangle is generated in the vertex shader
+        builder.draw(3, 1, 0, 0)?;
+
+        Ok(())
+    }
+
+    /// Records what is drawn over every view, the screen space sprites then the text
+    fn record_overlay<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        draws: &SceneDraws,
+    ) -> Result<()> {
+        // Overlays are drawn over the whole window whatever the viewports of the scene views
+        let (viewport, scissor) = self.full_viewport();
+        builder
+            .set_viewport(0, [viewport].into_iter().collect())?
+            .set_scissor(0, [scissor].into_iter().collect())?;
+
+        self.record_sprites(
+            builder,
+            &draws.screen_sprites,
+            self.pipeline_manager.screen_sprite_pipeline(),
+            Mat4::IDENTITY,
+            self.screen_projection(),
+        )?;
+
+        self.record_text(builder)
+    }
+
+    /// Records the render pass from secondary command buffers. The meshes drawn with their
+    /// material are split between the recording threads, the rest of each view and the text are
+    /// recorded on the calling thread
+    fn record_secondary_command_buffers(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        image_index: usize,
+        scene: &Scene,
+        views: &[RenderView],
+        draws: &SceneDraws,
+    ) -> Result<()> {
+        let framebuffer = &self.framebuffers[image_index];
+
+        let mut background_builder =
+            parallel_recording::secondary_builder(&self.vulkan_context, framebuffer)?;
+        self.record_background(&mut background_builder)?;
+        builder.execute_commands(background_builder.build()?)?;
+
+        // Indirect drawing already records a single draw per material, nothing to split
+        let split_meshes =
+            self.render_mode == RenderMode::Default && draws.indirect_batch.is_none();
+        let mesh_draws = if split_meshes {
+            parallel_recording::mesh_draws(
+                scene,
+                &self.pipeline_manager,
+                &draws.mesh_components,
+                &draws.mesh_draw_orders,
+                &draws.mesh_entity_sets,
+            )
+        } else {
+            Vec::new()
+        };
+
+        for view in views.iter() {
+            let view_meshes: &[&MeshComponent] = if split_meshes {
+                let command_buffers = parallel_recording::record_parallel(
+                    &self.vulkan_context,
+                    framebuffer,
+                    view,
+                    &draws.frame_descriptor_set,
+                    &mesh_draws,
+                    self.parallel_recording,
+                )?;
+
+                for command_buffer in command_buffers {
+                    builder.execute_commands(command_buffer)?;
+                }
+
+                &[]
+            } else {
+                &draws.mesh_components
+            };
+
+            let mut view_builder =
+                parallel_recording::secondary_builder(&self.vulkan_context, framebuffer)?;
+            self.record_view(&mut view_builder, scene, view, view_meshes, draws)?;
+            builder.execute_commands(view_builder.build()?)?;
+        }
+
+        let mut overlay_builder =
+            parallel_recording::secondary_builder(&self.vulkan_context, framebuffer)?;
+        self.record_overlay(&mut overlay_builder, draws)?;
+        builder.execute_commands(overlay_builder.build()?)?;
+
+        Ok(())
+    }
+
+    fn record_material_draws<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        scene: &Scene,
+        view: &RenderView,
+        mesh_components: &[&MeshComponent],
+        draws: &SceneDraws,
+    ) -> Result<()> {
+        if let Some(indirect_batch) = &draws.indirect_batch {
+            let vulkan_pipeline = self.pipeline_manager.material_indirect_pipeline();
+            let layout = &vulkan_pipeline.layout;
+
+            // The models are read from the objects, the model matrix is only pushed so that the
+            // whole block is set
+            builder
+                .bind_pipeline_graphics(Arc::clone(&vulkan_pipeline.pipeline))?
+                .push_constants(
+                    Arc::clone(layout),
+                    0,
+                    [Mat4::IDENTITY, view.view, view.projection],
+                )?
+                .bind_vertex_buffers(0, indirect_batch.meshes.vertex_buffer.clone())?
+                .bind_index_buffer(indirect_batch.meshes.index_buffer.clone())?
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    Arc::clone(layout),
+                    PipelineManager::FRAME_SET,
+                    vec![
+                        DescriptorSetWithOffsets::new(Arc::clone(&draws.frame_descriptor_set), []),
+                        DescriptorSetWithOffsets::new(
+                            Arc::clone(&indirect_batch.object_descriptor_set),
+                            [],
+                        ),
+                    ],
+                )?;
+
+            // One multi draw per material instead of one draw per mesh
+            for (material, range) in indirect_batch.meshes.material_ranges.iter() {
+                let material_descriptor_set =
+                    Arc::clone(scene.material_manager().descriptor_set(*material));
+                let depth_bias = scene
+                    .material_manager()
+                    .depth_bias(*material)
+                    .for_depth_range(self.reversed_z());
+
+                builder
+                    .bind_descriptor_sets(
+                        PipelineBindPoint::Graphics,
+                        Arc::clone(layout),
+                        0,
+                        vec![DescriptorSetWithOffsets::new(material_descriptor_set, [])],
+                    )?
+                    .push_constants(
+                        Arc::clone(layout),
+                        PipelineManager::FRAGMENT_PUSH_CONSTANT_OFFSET,
+                        *scene.material_manager().fragment_push_constants(*material),
+                    )?
+                    .set_depth_bias(depth_bias.constant_factor, 0.0, depth_bias.slope_factor)?
+                    .draw_indexed_indirect(
+                        indirect_batch.draw_commands.clone().slice(range.clone()),
+                    )?;
+            }
+
+            return Ok(());
+        }
+
+        // `mesh_components` is either empty or all the meshes of `draws`
+        let mesh_draws = parallel_recording::mesh_draws(
+            scene,
+            &self.pipeline_manager,
+            mesh_components,
+            &draws.mesh_draw_orders,
+            &draws.mesh_entity_sets,
+        );
+        parallel_recording::record_mesh_draws(
+            builder,
+            view,
+            &draws.frame_descriptor_set,
+            &mesh_draws,
+        )
+    }
+
+    fn record_skinned_draws<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        scene: &Scene,
+        view: &RenderView,
+        draws: &SceneDraws,
+    ) -> Result<()> {
+        let Some(bones) = &draws.bones else {
+            return Ok(());
+        };
+
+        let vulkan_pipeline = self.pipeline_manager.material_skinned_pipeline();
+        let layout = &vulkan_pipeline.layout;
+
+        builder
+            .bind_pipeline_graphics(Arc::clone(&vulkan_pipeline.pipeline))?
+            .push_constants(Arc::clone(layout), 16 * size_of::<f32>() as u32, view.view)?
+            .push_constants(
+                Arc::clone(layout),
+                2 * 16 * size_of::<f32>() as u32,
+                view.projection,
+            )?;
+
+        let skinned_meshes = draws.skinned_meshes.iter().zip(bones.bone_offsets.iter());
+        for (skinned_mesh, bone_offset) in skinned_meshes {
+            let index_buffer = skinned_mesh.mesh.index_buffer();
+
+            let material_descriptor_set = Arc::clone(
+                scene
+                    .material_manager()
+                    .descriptor_set(skinned_mesh.material),
+            );
+            let depth_bias = scene
+                .material_manager()
+                .depth_bias(skinned_mesh.material)
+                .for_depth_range(self.reversed_z());
+
+            builder
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    Arc::clone(layout),
+                    0,
+                    vec![
+                        DescriptorSetWithOffsets::new(material_descriptor_set, []),
+                        DescriptorSetWithOffsets::new(Arc::clone(&draws.frame_descriptor_set), []),
+                        DescriptorSetWithOffsets::new(Arc::clone(&bones.descriptor_set), []),
+                    ],
+                )?
+                .push_constants(
+                    Arc::clone(layout),
+                    PipelineManager::FRAGMENT_PUSH_CONSTANT_OFFSET,
+                    *scene
+                        .material_manager()
+                        .fragment_push_constants(skinned_mesh.material),
+                )?
+                .set_depth_bias(depth_bias.constant_factor, 0.0, depth_bias.slope_factor)?;
+            skinned_mesh
+                .mesh
+                .bind_vertex_stream(builder, vulkan_pipeline.vertex_attributes)?;
+            builder
+                .bind_index_buffer(index_buffer.clone())?
+                .push_constants(Arc::clone(layout), 0, skinned_mesh.model.transform())?
+                .push_constants(
+                    Arc::clone(layout),
+                    3 * 16 * size_of::<f32>() as u32,
+                    skinned_mesh.tint,
+                )?
+                .push_constants(
+                    Arc::clone(layout),
+                    (3 * 16 + 3) * size_of::<f32>() as u32,
+                    *bone_offset,
+                )?
+                .draw_indexed(index_buffer.len() as u32, 1, 0, 0, 0)?;
         }
 
         Ok(())
     }
 
-    fn record_draw_command_buffer(
+    fn record_instanced_draws<L>(
         &self,
-        image_index: usize,
+        builder: &mut AutoCommandBufferBuilder<L>,
         scene: &Scene,
-        vulkan_pipeline: &VulkanPipeline,
-    ) -> Result<Arc<PrimaryAutoCommandBuffer>> {
-        let pipeline = &vulkan_pipeline.pipeline;
-        let layout = &vulkan_pipeline.layout;
-        let camera = scene.camera().as_ref().unwrap();
-
-        let render_pass_begin_info = RenderPassBeginInfo {
-            render_pass: self.render_pass.clone(),
-            render_area_offset: [0, 0],
-            render_area_extent: self.swapchain.image_extent(),
-            clear_values: vec![
-                Some(ClearValue::Float([0.5, 0.5, 0.5, 1.0])),
-                Some(ClearValue::Depth(1.0)),
-            ],
-            ..RenderPassBeginInfo::framebuffer(self.framebuffers[image_index].clone())
-        };
-
-        let subpass_begin_info = SubpassBeginInfo {
-            contents: SubpassContents::Inline,
-            ..Default::default()
-        };
-
-        let subpass_end_info = SubpassEndInfo {
-            ..Default::default()
-        };
-
-        let mut builder = AutoCommandBufferBuilder::primary(
-            self.vulkan_context
-                .standard_command_buffer_allocator()
-                .as_ref(),
-            self.vulkan_context.graphics_queue().queue_family_index(),
-            CommandBufferUsage::OneTimeSubmit,
-        )?;
+        view: &RenderView,
+        draws: &SceneDraws,
+    ) -> Result<()> {
+        let instanced_meshes = &draws.instanced_meshes;
+        if instanced_meshes.is_empty() {
+            return Ok(());
+        }
 
-        let [width, height] = self.swapchain.image_extent().map(|x| x as f32);
-        let mut projection =
-            glam::Mat4::perspective_rh(f32::to_radians(45.0), width / height, 0.1, 100.0);
-        projection.as_mut()[1 * 4 + 1] *= -1.0;
+        let vulkan_pipeline = self.pipeline_manager.material_instanced_pipeline();
+        let layout = &vulkan_pipeline.layout;
 
+        // The models are per instance vertex attributes
         builder
-            .begin_render_pass(render_pass_begin_info, subpass_begin_info)?
-            .bind_pipeline_graphics(Arc::clone(pipeline))?
-            .push_constants(
-                Arc::clone(layout),
-                16 * size_of::<f32>() as u32,
-                camera.get_view(),
-            )?
+            .bind_pipeline_graphics(Arc::clone(&vulkan_pipeline.pipeline))?
             .push_constants(
                 Arc::clone(layout),
-                2 * 16 * size_of::<f32>() as u32,
-                projection,
-            )?
-            .set_viewport(
-                0,
-                [Viewport {
-                    offset: [0.0, 0.0],
-                    extent: self.swapchain.image_extent().map(|x| x as f32),
-                    depth_range: 0.0..=1.0,
-                }]
-                .into_iter()
-                .collect(),
-            )?
-            .set_scissor(
                 0,
-                [Scissor {
-                    offset: [0, 0],
-                    extent: self.swapchain.image_extent(),
-                }]
-                .into_iter()
-                .collect(),
+                [Mat4::IDENTITY, view.view, view.projection],
             )?;
 
-        for (_, mesh_component) in scene.components::<MeshComponent>().unwrap() {
-            let vertex_buffer = mesh_component.mesh.vectex_buffer();
-            let index_buffer = mesh_component.mesh.index_buffer();
+        for (instanced_mesh, instance_buffer) in instanced_meshes.iter() {
+            let vertex_buffer = instanced_mesh.mesh.vectex_buffer();
+            let index_buffer = instanced_mesh.mesh.index_buffer();
+
             let material_descriptor_set = Arc::clone(
                 scene
                     .material_manager()
-                    .descriptor_set(mesh_component.material),
+                    .descriptor_set(instanced_mesh.material),
             );
+            let depth_bias = scene
+                .material_manager()
+                .depth_bias(instanced_mesh.material)
+                .for_depth_range(self.reversed_z());
 
             builder
-                .bind_vertex_buffers(0, vertex_buffer.clone())?
-                .bind_index_buffer(index_buffer.clone())?
                 .bind_descriptor_sets(
                     PipelineBindPoint::Graphics,
-                    Arc::clone(pipeline.layout()),
+                    Arc::clone(layout),
                     0,
-                    vec![DescriptorSetWithOffsets::new(material_descriptor_set, [])],
+                    vec![
+                        DescriptorSetWithOffsets::new(material_descriptor_set, []),
+                        DescriptorSetWithOffsets::new(Arc::clone(&draws.frame_descriptor_set), []),
+                    ],
                 )?
+                .push_constants(
+                    Arc::clone(layout),
+                    PipelineManager::FRAGMENT_PUSH_CONSTANT_OFFSET,
+                    *scene
+                        .material_manager()
+                        .fragment_push_constants(instanced_mesh.material),
+                )?
+                .set_depth_bias(depth_bias.constant_factor, 0.0, depth_bias.slope_factor)?
+                .bind_vertex_buffers(0, vertex_buffer.clone())?
+                .bind_vertex_buffers(instancing::INSTANCE_BINDING, instance_buffer.clone())?
+                .bind_index_buffer(index_buffer.clone())?
+                .draw_indexed(index_buffer.len() as u32, instance_buffer.len() as u32, 0, 0, 0)?;
+        }
+
+        Ok(())
+    }
+
+    fn record_debug_draws<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        view: &RenderView,
+        mesh_components: &[&MeshComponent],
+    ) -> Result<()> {
+        if self.render_mode == RenderMode::Wireframe {
+            builder.set_line_width(self.line_width)?;
+        }
+
+        let mut bound_pipeline: Option<&VulkanPipeline> = None;
+        for mesh_component in mesh_components.iter() {
+            let index_buffer = mesh_component.mesh.index_buffer();
+
+            let mesh_pipelines = self
+                .pipeline_manager
+                .mesh_pipelines(mesh_component.mesh.topology());
+            let vulkan_pipeline = match self.render_mode {
+                RenderMode::NormalView => &mesh_pipelines.normal,
+                RenderMode::DepthView => &mesh_pipelines.depth,
+                RenderMode::Wireframe => mesh_pipelines
+                    .wireframe
+                    .as_ref()
+                    .expect("The wireframe render mode is only set when it is supported"),
+                RenderMode::VertexColor => &mesh_pipelines.vertex_color,
+                RenderMode::Default => &mesh_pipelines.material,
+            };
+            let layout = &vulkan_pipeline.layout;
+
+            if !bound_pipeline.is_some_and(|pipeline| ptr::eq(pipeline, vulkan_pipeline)) {
+                builder
+                    .bind_pipeline_graphics(Arc::clone(&vulkan_pipeline.pipeline))?
+                    .push_constants(Arc::clone(layout), 16 * size_of::<f32>() as u32, view.view)?
+                    .push_constants(
+                        Arc::clone(layout),
+                        2 * 16 * size_of::<f32>() as u32,
+                        view.projection,
+                    )?;
+
+                bound_pipeline = Some(vulkan_pipeline);
+            }
+
+            mesh_component
+                .mesh
+                .bind_vertex_stream(builder, vulkan_pipeline.vertex_attributes)?;
+            builder
+                .bind_index_buffer(index_buffer.clone())?
                 .push_constants(Arc::clone(layout), 0, mesh_component.model.transform())?
                 .draw_indexed(index_buffer.len() as u32, 1, 0, 0, 0)?;
         }
 
-        builder.end_render_pass(subpass_end_info)?;
+        Ok(())
+    }
 
-        let command_buffer = builder.build()?;
+    fn record_wireframe_overlay<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        view: &RenderView,
+        mesh_components: &[&MeshComponent],
+    ) -> Result<()> {
+        let depth_bias = WIREFRAME_OVERLAY_DEPTH_BIAS.for_depth_range(self.reversed_z());
+        builder
+            .set_line_width(self.line_width)?
+            .set_depth_bias(depth_bias.constant_factor, 0.0, depth_bias.slope_factor)?;
 
-        Ok(command_buffer)
+        let mut bound_pipeline: Option<&VulkanPipeline> = None;
+        for mesh_component in mesh_components.iter() {
+            let index_buffer = mesh_component.mesh.index_buffer();
+
+            let vulkan_pipeline = self
+                .pipeline_manager
+                .mesh_pipelines(mesh_component.mesh.topology())
+                .wireframe_overlay
+                .as_ref()
+                .expect("The wireframe overlay is only enabled when it is supported");
+            let layout = &vulkan_pipeline.layout;
+
+            if !bound_pipeline.is_some_and(|pipeline| ptr::eq(pipeline, vulkan_pipeline)) {
+                builder
+                    .bind_pipeline_graphics(Arc::clone(&vulkan_pipeline.pipeline))?
+                    .push_constants(Arc::clone(layout), 16 * size_of::<f32>() as u32, view.view)?
+                    .push_constants(
+                        Arc::clone(layout),
+                        2 * 16 * size_of::<f32>() as u32,
+                        view.projection,
+                    )?;
+
+                bound_pipeline = Some(vulkan_pipeline);
+            }
+
+            mesh_component
+                .mesh
+                .bind_vertex_stream(builder, vulkan_pipeline.vertex_attributes)?;
+            builder
+                .bind_index_buffer(index_buffer.clone())?
+                .push_constants(Arc::clone(layout), 0, mesh_component.model.transform())?
+                .draw_indexed(index_buffer.len() as u32, 1, 0, 0, 0)?;
+        }
+
+        Ok(())
     }
 
-    fn record_debug_draw_command_buffer(
+    fn record_debug_lines<L>(
         &self,
-        image_index: usize,
-        scene: &Scene,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        view: Mat4,
+        projection: Mat4,
+    ) -> Result<()> {
+        let line_vertices = self.debug_draw.line_vertices();
+        if line_vertices.is_empty() {
+            return Ok(());
+        }
+
+        let vertex_buffer = Buffer::from_iter(
+            Arc::clone(self.vulkan_context.standard_memory_allocator()),
+            BufferCreateInfo {
+                sharing: Sharing::Exclusive,
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            line_vertices.iter().copied(),
+        )?;
+
+        let line_pipeline = self.pipeline_manager.debug_line_pipeline();
+        let layout = &line_pipeline.layout;
+
+        builder
+            .bind_pipeline_graphics(Arc::clone(&line_pipeline.pipeline))?
+            .set_line_width(self.line_width)?
+            .push_constants(Arc::clone(layout), 0, [Mat4::IDENTITY, view, projection])?
+            .bind_vertex_buffers(0, vertex_buffer)?
+            .draw(line_vertices.len() as u32, 1, 0, 0)?;
+
+        Ok(())
+    }
+
+    fn record_sprites<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        sprites: &SpriteBatches,
         vulkan_pipeline: &VulkanPipeline,
-    ) -> Result<Arc<PrimaryAutoCommandBuffer>> {
-        let pipeline = &vulkan_pipeline.pipeline;
+        view: Mat4,
+        projection: Mat4,
+    ) -> Result<()> {
+        let Some(vertex_buffer) = &sprites.vertex_buffer else {
+            return Ok(());
+        };
+
         let layout = &vulkan_pipeline.layout;
-        let camera = scene.camera().as_ref().unwrap();
 
-        let render_pass_begin_info = RenderPassBeginInfo {
-            render_pass: self.render_pass.clone(),
-            render_area_offset: [0, 0],
-            render_area_extent: self.swapchain.image_extent(),
-            clear_values: vec![
-                Some(ClearValue::Float([0.5, 0.5, 0.5, 1.0])),
-                Some(ClearValue::Depth(1.0)),
-            ],
-            ..RenderPassBeginInfo::framebuffer(self.framebuffers[image_index].clone())
-        };
+        builder
+            .bind_pipeline_graphics(Arc::clone(&vulkan_pipeline.pipeline))?
+            .push_constants(Arc::clone(layout), 0, [Mat4::IDENTITY, view, projection])?
+            .bind_vertex_buffers(0, vertex_buffer.clone())?;
 
-        let subpass_begin_info = SubpassBeginInfo {
-            contents: SubpassContents::Inline,
-            ..Default::default()
-        };
+        for batch in sprites.batches.iter() {
+            let texture_descriptor_set = self.sprite_renderer.descriptor_set(batch.texture);
 
-        let subpass_end_info = SubpassEndInfo {
-            ..Default::default()
+            builder
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    Arc::clone(layout),
+                    0,
+                    vec![DescriptorSetWithOffsets::new(
+                        Arc::clone(texture_descriptor_set),
+                        [],
+                    )],
+                )?
+                .draw(batch.vertices.len() as u32, 1, batch.vertices.start, 0)?;
+        }
+
+        Ok(())
+    }
+
+    /// Projection of the overlays, positioned in pixels from the top left corner of the window
+    fn screen_projection(&self) -> Mat4 {
+        let pre_transform = self.swapchain.pre_transform();
+        let [width, height] =
+            pre_transformed_extent(self.swapchain.image_extent().map(|x| x as f32), pre_transform);
+        pre_transform_rotation(pre_transform)
+            * Mat4::orthographic_rh(0.0, width, 0.0, height, -1.0, 1.0)
+    }
+
+    fn record_text<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+    ) -> Result<()> {
+        let text_vertices = self.text_renderer.vertices();
+        let Some(descriptor_set) = self.text_renderer.descriptor_set() else {
+            return Ok(());
         };
+        if text_vertices.is_empty() {
+            return Ok(());
+        }
 
-        let mut builder = AutoCommandBufferBuilder::primary(
-            self.vulkan_context
-                .standard_command_buffer_allocator()
-                .as_ref(),
-            self.vulkan_context.graphics_queue().queue_family_index(),
-            CommandBufferUsage::OneTimeSubmit,
+        let vertex_buffer = Buffer::from_iter(
+            Arc::clone(self.vulkan_context.standard_memory_allocator()),
+            BufferCreateInfo {
+                sharing: Sharing::Exclusive,
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            text_vertices.iter().copied(),
         )?;
 
-        let [width, height] = self.swapchain.image_extent().map(|x| x as f32);
-        let mut projection =
-            glam::Mat4::perspective_rh(f32::to_radians(45.0), width / height, 0.1, 100.0);
-        projection.as_mut()[1 * 4 + 1] *= -1.0;
+        let text_pipeline = self.pipeline_manager.text_pipeline();
+        let layout = &text_pipeline.layout;
 
         builder
-            .begin_render_pass(render_pass_begin_info, subpass_begin_info)?
-            .bind_pipeline_graphics(Arc::clone(pipeline))?
+            .bind_pipeline_graphics(Arc::clone(&text_pipeline.pipeline))?
             .push_constants(
                 Arc::clone(layout),
-                16 * size_of::<f32>() as u32,
-                camera.get_view(),
+                0,
+                [Mat4::IDENTITY, Mat4::IDENTITY, self.screen_projection()],
             )?
-            .push_constants(
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
                 Arc::clone(layout),
-                2 * 16 * size_of::<f32>() as u32,
-                projection,
-            )?
-            .set_viewport(
                 0,
-                [Viewport {
-                    offset: [0.0, 0.0],
-                    extent: self.swapchain.image_extent().map(|x| x as f32),
-                    depth_range: 0.0..=1.0,
-                }]
-                .into_iter()
-                .collect(),
+                vec![DescriptorSetWithOffsets::new(
+                    Arc::clone(descriptor_set),
+                    [],
+                )],
             )?
-            .set_scissor(
-                0,
-                [Scissor {
-                    offset: [0, 0],
-                    extent: self.swapchain.image_extent(),
-                }]
-                .into_iter()
-                .collect(),
-            )?;
+            .bind_vertex_buffers(0, vertex_buffer)?
+            .draw(text_vertices.len() as u32, 1, 0, 0)?;
 
-        for (_, mesh_component) in scene.components::<MeshComponent>().unwrap() {
-            let vertex_buffer = mesh_component.mesh.vectex_buffer();
-            let index_buffer = mesh_component.mesh.index_buffer();
+        Ok(())
+    }
 
-            builder
-                .bind_vertex_buffers(0, vertex_buffer.clone())?
-                .bind_index_buffer(index_buffer.clone())?
-                .push_constants(Arc::clone(layout), 0, mesh_component.model.transform())?
-                .draw_indexed(index_buffer.len() as u32, 1, 0, 0, 0)?;
+    fn check_renderer_info(
+        vulkan_context: &VulkanContext,
+        renderer_info: &RendererInfo,
+    ) -> Result<()> {
+        let properties = vulkan_context.device().physical_device().properties();
+
+        let supported_samples =
+            properties.framebuffer_color_sample_counts & properties.framebuffer_depth_sample_counts;
+        anyhow::ensure!(
+            supported_samples.contains_enum(renderer_info.samples),
+            "{:?} is not supported by the device",
+            renderer_info.samples
+        );
+
+        if renderer_info.resolve_depth && renderer_info.samples != SampleCount::Sample1 {
+            let depth_resolve_supported = properties
+                .supported_depth_resolve_modes
+                .is_some_and(|modes| modes.contains_enum(ResolveMode::SampleZero));
+            anyhow::ensure!(
+                depth_resolve_supported,
+                "Resolving a multisampled depth buffer is not supported by the device"
+            );
         }
 
-        builder.end_render_pass(subpass_end_info)?;
-
-        let command_buffer = builder.build()?;
-
-        Ok(command_buffer)
+        Ok(())
     }
 
     fn get_minimum_image_count(capabilities: &SurfaceCapabilities) -> u32 {
@@ -395,10 +1968,11 @@ impl Renderer {
 
     fn choose_swapchain_format(
         available_formats: Vec<(Format, ColorSpace)>,
+        preferred_formats: &[(Format, ColorSpace)],
     ) -> (Format, ColorSpace) {
-        for (format, color_space) in available_formats.iter() {
-            if *format == Format::R8G8B8A8_SRGB && *color_space == ColorSpace::SrgbNonLinear {
-                return (*format, *color_space);
+        for preferred_format in preferred_formats.iter() {
+            if available_formats.contains(preferred_format) {
+                return *preferred_format;
             }
         }
 
@@ -429,46 +2003,118 @@ impl Renderer {
         extent
     }
 
-    fn choose_present_mode(available_present_modes: Vec<PresentMode>) -> PresentMode {
-        for present_mode in available_present_modes.iter() {
-            if *present_mode == PresentMode::Mailbox {
-                return *present_mode;
+    fn choose_present_mode(
+        available_present_modes: Vec<PresentMode>,
+        requested_present_mode: PresentMode,
+    ) -> PresentMode {
+        let present_mode = if available_present_modes.contains(&requested_present_mode) {
+            requested_present_mode
+        } else {
+            PresentMode::Fifo
+        };
+
+        // Logged once per window, the present mode is kept when the swapchain is recreated
+        eprintln!(
+            "Available present modes: {:?}, requested {:?}, using {:?}{}",
+            available_present_modes,
+            requested_present_mode,
+            present_mode,
+            if present_mode == requested_present_mode {
+                ""
+            } else {
+                " as the requested mode is not supported by the surface"
             }
+        );
+
+        present_mode
+    }
+
+    /// Identity when the surface supports it, the presentation engine then rotates the images
+    /// itself. Otherwise the current transform of the surface, which the projections undo with
+    /// `pre_transform_rotation`
+    fn choose_pre_transform(capabilities: &SurfaceCapabilities) -> SurfaceTransform {
+        if capabilities
+            .supported_transforms
+            .contains_enum(SurfaceTransform::Identity)
+        {
+            SurfaceTransform::Identity
+        } else {
+            capabilities.current_transform
         }
+    }
+
+    /// Opaque when the surface supports it so that the compositor ignores the alpha of the images,
+    /// otherwise the first supported fallback. A surface always supports at least one mode
+    fn choose_composite_alpha(supported_composite_alpha: CompositeAlphas) -> CompositeAlpha {
+        [
+            CompositeAlpha::Opaque,
+            CompositeAlpha::Inherit,
+            CompositeAlpha::PreMultiplied,
+            CompositeAlpha::PostMultiplied,
+        ]
+        .into_iter()
+        .find(|&composite_alpha| supported_composite_alpha.contains_enum(composite_alpha))
+        .expect("The surface supports no composite alpha mode")
+    }
 
-        PresentMode::Fifo
+    fn surface_info() -> SurfaceInfo {
+        SurfaceInfo {
+            full_screen_exclusive: FullScreenExclusive::Default,
+            ..Default::default()
+        }
     }
 
     fn create_swapchain(
         vulkan_context: &Arc<VulkanContext>,
         window: &Arc<Window>,
+        renderer_info: &RendererInfo,
     ) -> Result<(Arc<Swapchain>, Vec<Arc<Image>>)> {
         let device = vulkan_context.device();
         let physical_device = device.physical_device();
 
-        let surface_info = SurfaceInfo {
-            full_screen_exclusive: FullScreenExclusive::Default,
-            ..Default::default()
-        };
+        let surface_info = Self::surface_info();
 
         let surface =
             Surface::from_window(Arc::clone(vulkan_context.instance()), Arc::clone(window))?;
 
+        // The device was chosen for the window the context was created with, another window may
+        // live on a surface its present queue can't reach
+        let present_family = vulkan_context.present_queue().queue_family_index();
+        anyhow::ensure!(
+            physical_device.surface_support(present_family, surface.as_ref())?,
+            "The present queue can't present to the surface of this window"
+        );
+
         let surface_capabilities =
             physical_device.surface_capabilities(surface.as_ref(), surface_info.clone())?;
 
         let available_formats =
             physical_device.surface_formats(surface.as_ref(), surface_info.clone())?;
 
-        let (format, color_space) = Self::choose_swapchain_format(available_formats);
+        let (format, color_space) =
+            Self::choose_swapchain_format(available_formats, &renderer_info.surface_formats);
         let extent = Self::choose_swapchain_extent(window, &surface_capabilities);
 
-        let sharing = Sharing::Exclusive;
+        // Concurrent sharing lets the present queue read the images rendered by the graphics
+        // queue without transferring their ownership between the families every frame
+        let sharing = if vulkan_context.separate_present_queue() {
+            Sharing::Concurrent(
+                [
+                    vulkan_context.graphics_queue_family_index(),
+                    vulkan_context.present_queue_family_index(),
+                ]
+                .into_iter()
+                .collect(),
+            )
+        } else {
+            Sharing::Exclusive
+        };
 
         let available_present_modes = physical_device
             .surface_present_modes(surface.as_ref(), surface_info)?
             .collect();
-        let present_mode = Self::choose_present_mode(available_present_modes);
+        let present_mode =
+            Self::choose_present_mode(available_present_modes, renderer_info.present_mode);
 
         let swapchain_info = SwapchainCreateInfo {
             min_image_count: Self::get_minimum_image_count(&surface_capabilities),
@@ -478,8 +2124,10 @@ impl Renderer {
             image_array_layers: 1,
             image_usage: ImageUsage::COLOR_ATTACHMENT,
             image_sharing: sharing,
-            pre_transform: surface_capabilities.current_transform,
-            composite_alpha: CompositeAlpha::Opaque,
+            pre_transform: Self::choose_pre_transform(&surface_capabilities),
+            composite_alpha: Self::choose_composite_alpha(
+                surface_capabilities.supported_composite_alpha,
+            ),
             present_mode,
             clipped: true,
             ..Default::default()
@@ -521,13 +2169,21 @@ impl Renderer {
         render_pass: &Arc<RenderPass>,
         swapchain: &Arc<Swapchain>,
         image_views: &Vec<Arc<ImageView>>,
-        depth_image_view: &Arc<ImageView>,
+        render_targets: &RenderTargets,
     ) -> Result<Vec<Arc<Framebuffer>>> {
         let mut framebuffers = Vec::new();
 
         for image_view in image_views.iter() {
+            // With post processing the main pass renders into the scene color instead
+            let color_view = render_targets
+                .scene_color_image_view()
+                .unwrap_or(image_view);
+
+            let mut attachments = vec![Arc::clone(color_view)];
+            attachments.extend(render_targets.attachments());
+
             let framebuffer_info = FramebufferCreateInfo {
-                attachments: vec![Arc::clone(image_view), Arc::clone(depth_image_view)],
+                attachments,
                 extent: swapchain.image_extent(),
                 layers: 1,
                 ..Default::default()
@@ -539,78 +2195,66 @@ impl Renderer {
         Ok(framebuffers)
     }
 
-    fn create_depth_image(
-        vulkan_context: &Arc<VulkanContext>,
-        image_extent: [u32; 2],
-    ) -> Result<(Arc<Image>, Arc<ImageView>)> {
-        let allocator = Arc::clone(vulkan_context.standard_memory_allocator());
-
-        let depth_image = Image::new(
-            allocator,
-            ImageCreateInfo {
-                image_type: ImageType::Dim2d,
-                format: Format::D32_SFLOAT,
-                view_formats: vec![Format::D32_SFLOAT],
-                extent: [image_extent[0], image_extent[1], 1],
-                usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT,
-                sharing: Sharing::Exclusive,
-                initial_layout: ImageLayout::Undefined,
-                ..Default::default()
-            },
-            AllocationCreateInfo {
-                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
-                allocate_preference: MemoryAllocatePreference::AlwaysAllocate,
-                ..Default::default()
-            },
-        )?;
-
-        let depth_image_view = ImageView::new(
-            Arc::clone(&depth_image),
-            ImageViewCreateInfo {
-                view_type: ImageViewType::Dim2d,
-                format: depth_image.format(),
-                component_mapping: ComponentMapping::identity(),
-                subresource_range: ImageSubresourceRange {
-                    aspects: ImageAspects::DEPTH,
-                    mip_levels: 0..1,
-                    array_layers: 0..1,
-                },
-                usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT,
-                ..Default::default()
-            },
-        )?;
-
-        Ok((depth_image, depth_image_view))
-    }
-
     fn create_render_pass(
         device: &Arc<Device>,
-        swapchain: &Arc<Swapchain>,
-        depth_stencil_image: &Arc<Image>,
+        color_format: Format,
+        renderer_info: &RendererInfo,
+        post_process: bool,
     ) -> Arc<RenderPass> {
-        let color_attachment = AttachmentDescription {
-            format: swapchain.image_format(),
+        let samples = renderer_info.samples;
+        let resolve_depth = renderer_info.resolve_depth;
+        let multisampled = samples != SampleCount::Sample1;
+
+        // Loaded attachments start in the layout the previous frame left them in
+        let load_color = renderer_info.color_load_op == AttachmentLoadOp::Load;
+        let load_depth = renderer_info.depth_load_op == AttachmentLoadOp::Load;
+
+        // With post processing the scene is rendered into the scene color, which the post
+        // process pass then samples
+        let (color_format, color_final_layout) = if post_process {
+            (SCENE_COLOR_FORMAT, ImageLayout::ShaderReadOnlyOptimal)
+        } else {
+            (color_format, ImageLayout::PresentSrc)
+        };
+
+        // With multisampling the swapchain image only receives the resolved colors
+        let swapchain_attachment = AttachmentDescription {
+            format: color_format,
             samples: SampleCount::Sample1,
-            load_op: AttachmentLoadOp::Clear,
+            load_op: if multisampled {
+                AttachmentLoadOp::DontCare
+            } else {
+                renderer_info.color_load_op
+            },
             store_op: AttachmentStoreOp::Store,
-            initial_layout: ImageLayout::Undefined,
-            final_layout: ImageLayout::PresentSrc,
+            initial_layout: if load_color && !multisampled {
+                color_final_layout
+            } else {
+                ImageLayout::Undefined
+            },
+            final_layout: color_final_layout,
             ..Default::default()
         };
 
-        let color_attachment_ref = AttachmentReference {
+        let swapchain_attachment_ref = AttachmentReference {
             attachment: 0,
             layout: ImageLayout::ColorAttachmentOptimal,
             ..Default::default()
         };
 
+        // The depth is kept after the pass by default so that effects can sample it through
+        // `depth_view`
         let depth_attachment = AttachmentDescription {
-            format: depth_stencil_image.format(),
-            samples: SampleCount::Sample1,
-            load_op: AttachmentLoadOp::Clear,
-            store_op: AttachmentStoreOp::DontCare,
-            initial_layout: ImageLayout::Undefined,
-            final_layout: ImageLayout::DepthStencilAttachmentOptimal,
+            format: DEPTH_FORMAT,
+            samples,
+            load_op: renderer_info.depth_load_op,
+            store_op: renderer_info.depth_store_op,
+            initial_layout: if load_depth {
+                ImageLayout::DepthStencilReadOnlyOptimal
+            } else {
+                ImageLayout::Undefined
+            },
+            final_layout: ImageLayout::DepthStencilReadOnlyOptimal,
             ..Default::default()
         };
 
@@ -620,14 +2264,72 @@ impl Renderer {
             ..Default::default()
         };
 
-        let subpass = SubpassDescription {
-            view_mask: 0,
-            color_attachments: vec![Some(color_attachment_ref)],
-            depth_stencil_attachment: Some(depth_attachment_ref),
-            ..Default::default()
+        let mut attachments = vec![swapchain_attachment, depth_attachment];
+
+        let subpass = if multisampled {
+            // Only kept after the pass when the next frame loads it
+            attachments.push(AttachmentDescription {
+                format: color_format,
+                samples,
+                load_op: renderer_info.color_load_op,
+                store_op: if load_color {
+                    AttachmentStoreOp::Store
+                } else {
+                    AttachmentStoreOp::DontCare
+                },
+                initial_layout: if load_color {
+                    ImageLayout::ColorAttachmentOptimal
+                } else {
+                    ImageLayout::Undefined
+                },
+                final_layout: ImageLayout::ColorAttachmentOptimal,
+                ..Default::default()
+            });
+
+            let color_attachment_ref = AttachmentReference {
+                attachment: 2,
+                layout: ImageLayout::ColorAttachmentOptimal,
+                ..Default::default()
+            };
+
+            let depth_resolve_attachment_ref = if resolve_depth {
+                attachments.push(AttachmentDescription {
+                    format: DEPTH_FORMAT,
+                    samples: SampleCount::Sample1,
+                    load_op: AttachmentLoadOp::DontCare,
+                    store_op: AttachmentStoreOp::Store,
+                    initial_layout: ImageLayout::Undefined,
+                    final_layout: ImageLayout::DepthStencilReadOnlyOptimal,
+                    ..Default::default()
+                });
+
+                Some(AttachmentReference {
+                    attachment: 3,
+                    layout: ImageLayout::DepthStencilAttachmentOptimal,
+                    ..Default::default()
+                })
+            } else {
+                None
+            };
+
+            SubpassDescription {
+                view_mask: 0,
+                color_attachments: vec![Some(color_attachment_ref)],
+                color_resolve_attachments: vec![Some(swapchain_attachment_ref)],
+                depth_stencil_attachment: Some(depth_attachment_ref),
+                depth_stencil_resolve_attachment: depth_resolve_attachment_ref,
+                depth_resolve_mode: resolve_depth.then_some(ResolveMode::SampleZero),
+                ..Default::default()
+            }
+        } else {
+            SubpassDescription {
+                view_mask: 0,
+                color_attachments: vec![Some(swapchain_attachment_ref)],
+                depth_stencil_attachment: Some(depth_attachment_ref),
+                ..Default::default()
+            }
         };
 
-        let attachments = vec![color_attachment, depth_attachment];
         let subpasses = vec![subpass];
         let dependencies = vec![];
 
@@ -641,6 +2343,26 @@ impl Renderer {
         RenderPass::new(device.clone(), render_pass_info).expect("Failed to create render pass")
     }
 
+    /// Blocks until the GPU has finished every submitted frame, after which the resources they
+    /// use can be released
+    pub(crate) fn wait_idle(&self) -> Result<()> {
+        // The renderer is the only one submitting to the queues and it is borrowed here
+        unsafe { self.vulkan_context.device().wait_idle()? };
+
+        Ok(())
+    }
+
+    /// Resizes the swapchain before drawing the next frame, only the last size requested in a
+    /// frame is used
+    pub(crate) fn request_resize(&mut self, new_size: PhysicalSize<u32>) {
+        self.pending_size = Some(new_size);
+    }
+
+    /// New extent of the swapchain if a frame recreated it at another size since the last call
+    pub(crate) fn take_applied_size(&mut self) -> Option<PhysicalSize<u32>> {
+        self.applied_size.take()
+    }
+
     pub(crate) fn resize(&mut self, new_size: PhysicalSize<u32>) -> Result<()> {
         let (new_swapchain, new_swapchain_images) =
             self.swapchain.recreate(SwapchainCreateInfo {
@@ -652,25 +2374,61 @@ impl Renderer {
         let new_swapchain_image_views =
             Self::create_swapchain_image_views(&new_swapchain, &new_swapchain_images)?;
 
-        let (new_depth_image, new_depth_image_view) =
-            Self::create_depth_image(&self.vulkan_context, new_swapchain.image_extent())?;
+        let [width, height] = new_swapchain.image_extent();
+        let extent_changed = new_swapchain.image_extent() != self.swapchain.image_extent();
+
+        let new_render_targets = RenderTargets::new(
+            &self.vulkan_context,
+            new_swapchain.image_extent(),
+            new_swapchain.image_format(),
+            self.renderer_info.samples,
+            self.renderer_info.resolve_depth,
+            self.post_process.is_some(),
+        )?;
 
         let new_framebuffers = Self::create_framebuffers(
             &self.render_pass,
             &new_swapchain,
             &new_swapchain_image_views,
-            &new_depth_image_view,
+            &new_render_targets,
         )?;
 
+        if let Some(post_process) = &mut self.post_process {
+            post_process.resize(
+                &self.vulkan_context,
+                &new_swapchain_image_views,
+                new_render_targets.scene_color_image_view().unwrap(),
+            )?;
+        }
+
         self.swapchain = new_swapchain;
-        self._swapchain_images = new_swapchain_images;
-        self._swapchain_image_views = new_swapchain_image_views;
+        self.swapchain_images = new_swapchain_images;
+        self.swapchain_image_views = new_swapchain_image_views;
+
+        self.render_targets = new_render_targets;
 
-        self.depth_image = new_depth_image;
-        self.depth_image_view = new_depth_image_view;
+        #[cfg(feature = "profiling")]
+        self.gpu_timer
+            .resize(&self.vulkan_context, self.swapchain_images.len())?;
+        self.occlusion
+            .resize(&self.vulkan_context, self.swapchain_images.len())?;
 
         self.framebuffers = new_framebuffers;
 
+        if extent_changed {
+            self.applied_size = Some(PhysicalSize::new(width, height));
+        }
+
         Ok(())
     }
 }
+
+impl Drop for Renderer {
+    /// The fields are only dropped once this returns, the swapchain images and buffers of the
+    /// last frames must not be destroyed while the GPU still reads them
+    fn drop(&mut self) {
+        if let Err(error) = self.wait_idle() {
+            eprintln!("Failed to wait for the device before releasing the renderer: {error}");
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use std::{collections::HashMap, iter, mem::size_of, sync::Arc};
+
+use anyhow::Result;
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    descriptor_set::{
+        layout::{
+            DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorSetLayoutCreateInfo,
+            DescriptorType,
+        },
+        PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::{Device, DeviceSize},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    shader::ShaderStages,
+    sync::Sharing,
+};
+
+use crate::{
+    engine::ecs::{components::UniformComponent, Entity, Scene},
+    vulkan_context::VulkanContext,
+};
+
+pub(crate) const ENTITY_BINDING: u32 = 0;
+
+/// Size of the zeroed uniform bound for the entities without a uniform component, their shaders
+/// read zeros from any block that fits in it
+pub(crate) const DEFAULT_ENTITY_UNIFORM_SIZE: DeviceSize = 256;
+
+/// Writes the `UniformComponent` at an index of its component vec into the given buffer when it
+/// has the size of the data, otherwise uploads it into a new buffer which is returned. Registered
+/// for each data type by `Scene::entity_set_uniform` so that the renderer does not need to know
+/// the type
+pub(crate) type UniformUpload =
+    fn(&Scene, usize, Option<&Subbuffer<[u8]>>, &VulkanContext) -> Result<Option<Subbuffer<[u8]>>>;
+
+pub(crate) fn upload_uniform<T: BufferContents + Copy>(
+    scene: &Scene,
+    index: usize,
+    buffer: Option<&Subbuffer<[u8]>>,
+    vulkan_context: &VulkanContext,
+) -> Result<Option<Subbuffer<[u8]>>> {
+    let data = scene.components::<UniformComponent<T>>().unwrap()[index]
+        .1
+        .data;
+
+    if let Some(buffer) = buffer.filter(|buffer| buffer.size() == size_of::<T>() as DeviceSize) {
+        *buffer.clone().reinterpret::<T>().write()? = data;
+        return Ok(None);
+    }
+
+    let uniform_buffer = Buffer::from_data(
+        Arc::clone(vulkan_context.standard_memory_allocator()),
+        BufferCreateInfo {
+            sharing: Sharing::Exclusive,
+            usage: BufferUsage::UNIFORM_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        data,
+    )?;
+
+    Ok(Some(uniform_buffer.into_bytes()))
+}
+
+/// Uniform block of an entity bound to the material pipelines drawing meshes one by one at set
+/// `PipelineManager::ENTITY_SET` binding 0, read by the fragment shaders given to
+/// `Scene::new_material_with_shader`. Declared in GLSL like the data given to
+/// `Scene::entity_set_uniform`, with the std140 layout, for instance
+///
+/// ```glsl
+/// layout(set = 2, binding = 0) uniform Entity
+/// {
+///     float animation_phase;
+/// } entity;
+/// ```
+///
+/// Every mesh drawn one by one binds a set, the entities without a uniform component read zeros
+pub(crate) fn create_entity_set_layout(device: &Arc<Device>) -> Result<Arc<DescriptorSetLayout>> {
+    let set_info = DescriptorSetLayoutCreateInfo {
+        bindings: [(
+            ENTITY_BINDING,
+            DescriptorSetLayoutBinding {
+                descriptor_count: 1,
+                stages: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                ..DescriptorSetLayoutBinding::descriptor_type(DescriptorType::UniformBuffer)
+            },
+        )]
+        .into_iter()
+        .collect(),
+        ..Default::default()
+    };
+
+    Ok(DescriptorSetLayout::new(Arc::clone(device), set_info)?)
+}
+
+/// Uniform buffers and descriptor sets of the entities, kept between frames so that the uniform
+/// components are written into the same buffers every frame. The entities without a uniform
+/// component share a zeroed uniform
+pub(crate) struct EntityUniforms {
+    entity_set_layout: Arc<DescriptorSetLayout>,
+    default_descriptor_set: Arc<PersistentDescriptorSet>,
+    entities: HashMap<Entity, EntityUniform>,
+}
+
+struct EntityUniform {
+    buffer: Subbuffer<[u8]>,
+    descriptor_set: Arc<PersistentDescriptorSet>,
+}
+
+impl EntityUniform {
+    fn new(
+        vulkan_context: &VulkanContext,
+        entity_set_layout: &Arc<DescriptorSetLayout>,
+        buffer: Subbuffer<[u8]>,
+    ) -> Result<Self> {
+        let descriptor_set = PersistentDescriptorSet::new(
+            vulkan_context.standard_descripor_set_allocator().as_ref(),
+            Arc::clone(entity_set_layout),
+            [WriteDescriptorSet::buffer(ENTITY_BINDING, buffer.clone())],
+            [],
+        )?;
+
+        Ok(Self {
+            buffer,
+            descriptor_set,
+        })
+    }
+}
+
+impl EntityUniforms {
+    pub fn new(
+        vulkan_context: &VulkanContext,
+        entity_set_layout: Arc<DescriptorSetLayout>,
+    ) -> Result<Self> {
+        let default_buffer = Buffer::from_iter(
+            Arc::clone(vulkan_context.standard_memory_allocator()),
+            BufferCreateInfo {
+                sharing: Sharing::Exclusive,
+                usage: BufferUsage::UNIFORM_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            iter::repeat(0).take(DEFAULT_ENTITY_UNIFORM_SIZE as usize),
+        )?;
+        let default_uniform =
+            EntityUniform::new(vulkan_context, &entity_set_layout, default_buffer)?;
+
+        Ok(Self {
+            entity_set_layout,
+            default_descriptor_set: default_uniform.descriptor_set,
+            entities: HashMap::new(),
+        })
+    }
+
+    /// Writes the uniform components of `entities` for the current frame. The buffers are only
+    /// created again when the size of the data changes, those of the entities that are not given
+    /// anymore are released
+    pub fn update(
+        &mut self,
+        vulkan_context: &VulkanContext,
+        scene: &Scene,
+        entities: &[Entity],
+    ) -> Result<()> {
+        let mut entity_uniforms = HashMap::new();
+        for &entity in entities {
+            let Some((upload, index)) = scene.entity_uniform_upload(entity) else {
+                continue;
+            };
+
+            let previous = self.entities.remove(&entity);
+            let previous_buffer = previous.as_ref().map(|previous| &previous.buffer);
+            let entity_uniform = match upload(scene, index, previous_buffer, vulkan_context)? {
+                Some(buffer) => {
+                    EntityUniform::new(vulkan_context, &self.entity_set_layout, buffer)?
+                }
+                None => previous.expect("Uniforms are only written into an existing buffer"),
+            };
+            entity_uniforms.insert(entity, entity_uniform);
+        }
+        self.entities = entity_uniforms;
+
+        Ok(())
+    }
+
+    /// Set bound for the meshes of `entity`, the zeroed default uniform when it had no uniform
+    /// component at the last `update`
+    pub fn descriptor_set(&self, entity: Entity) -> &Arc<PersistentDescriptorSet> {
+        self.entities
+            .get(&entity)
+            .map_or(&self.default_descriptor_set, |entity_uniform| {
+                &entity_uniform.descriptor_set
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::{event_loop::EventLoop, window::WindowBuilder};
+
+    use super::*;
+    use crate::vulkan_context::VulkanContextInfo;
+
+    fn create_scene() -> (Scene, EntityUniforms, Arc<VulkanContext>) {
+        let dummy_window = WindowBuilder::new()
+            .build(&EventLoop::new().unwrap())
+            .unwrap();
+        let vulkan_context = Arc::new(
+            VulkanContext::new(&Arc::new(dummy_window), VulkanContextInfo::default()).unwrap(),
+        );
+
+        let scene = Scene::with_capacity(Arc::clone(&vulkan_context), 0);
+        let entity_uniforms = EntityUniforms::new(
+            &vulkan_context,
+            Arc::clone(scene.material_manager().entity_set_layout()),
+        )
+        .unwrap();
+
+        (scene, entity_uniforms, vulkan_context)
+    }
+
+    #[test]
+    fn uniform_buffers_are_reused_between_frames() {
+        let (mut scene, mut entity_uniforms, vulkan_context) = create_scene();
+        let entity = scene.spawn_entity();
+
+        scene.entity_set_uniform(entity, 1.0f32);
+        entity_uniforms
+            .update(&vulkan_context, &scene, &[entity])
+            .unwrap();
+        let descriptor_set = Arc::clone(entity_uniforms.descriptor_set(entity));
+
+        scene.entity_set_uniform(entity, 2.0f32);
+        entity_uniforms
+            .update(&vulkan_context, &scene, &[entity])
+            .unwrap();
+
+        assert!(Arc::ptr_eq(
+            entity_uniforms.descriptor_set(entity),
+            &descriptor_set
+        ));
+        let buffer = &entity_uniforms.entities[&entity].buffer;
+        assert_eq!(&*buffer.read().unwrap(), &2.0f32.to_ne_bytes());
+    }
+
+    #[test]
+    fn entities_without_uniform_bind_the_default_set() {
+        let (mut scene, mut entity_uniforms, vulkan_context) = create_scene();
+        let with_uniform = scene.spawn_entity();
+        let without_uniform = scene.spawn_entity();
+        scene.entity_set_uniform(with_uniform, 1.0f32);
+
+        entity_uniforms
+            .update(&vulkan_context, &scene, &[with_uniform, without_uniform])
+            .unwrap();
+        let default_descriptor_set = &entity_uniforms.default_descriptor_set;
+        assert!(Arc::ptr_eq(
+            entity_uniforms.descriptor_set(without_uniform),
+            default_descriptor_set
+        ));
+        assert!(!Arc::ptr_eq(
+            entity_uniforms.descriptor_set(with_uniform),
+            default_descriptor_set
+        ));
+
+        // The uniform of an entity that is not drawn anymore is released
+        entity_uniforms
+            .update(&vulkan_context, &scene, &[without_uniform])
+            .unwrap();
+        assert!(entity_uniforms.entities.is_empty());
+        assert!(Arc::ptr_eq(
+            entity_uniforms.descriptor_set(with_uniform),
+            &entity_uniforms.default_descriptor_set
+        ));
+    }
+}
@@ -0,0 +1,552 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use vulkano::{
+    command_buffer::{
+        AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, RenderPassBeginInfo,
+        SecondaryAutoCommandBuffer, SubpassBeginInfo, SubpassContents, SubpassEndInfo,
+    },
+    descriptor_set::PersistentDescriptorSet,
+};
+
+use crate::engine::ecs::Scene;
+
+use super::{parallel_recording, RenderMode, RenderView, Renderer, SceneDraws};
+
+/// What the nodes of a frame share, gathered before the first node is recorded
+pub struct FrameContext<'a> {
+    renderer: &'a Renderer,
+    image_index: usize,
+    views: &'a [RenderView],
+    draws: &'a SceneDraws<'a>,
+}
+
+impl<'a> FrameContext<'a> {
+    pub(super) fn new(
+        renderer: &'a Renderer,
+        image_index: usize,
+        views: &'a [RenderView],
+        draws: &'a SceneDraws<'a>,
+    ) -> Self {
+        Self {
+            renderer,
+            image_index,
+            views,
+            draws,
+        }
+    }
+
+    /// Index of the swapchain image drawn by the frame
+    pub fn image_index(&self) -> usize {
+        self.image_index
+    }
+
+    /// Views of the scene drawn by the frame, empty until the scene has a camera
+    pub fn views(&self) -> &[RenderView] {
+        self.views
+    }
+
+    /// Uniforms of the frame bound at set `PipelineManager::FRAME_SET` of the material pipelines,
+    /// see `Engine::set_frame_time`
+    pub fn frame_descriptor_set(&self) -> &Arc<PersistentDescriptorSet> {
+        &self.draws.frame_descriptor_set
+    }
+
+    /// Whether the scene render pass is recorded from secondary command buffers, with parallel
+    /// recording. The scene nodes then execute their commands from a `secondary_builder`
+    pub fn secondary_command_buffers(&self) -> bool {
+        self.renderer.parallel_recording > 1
+    }
+
+    /// Secondary command buffer recording within the scene render pass, inheriting nothing
+    pub fn secondary_builder(
+        &self,
+    ) -> Result<AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>> {
+        parallel_recording::secondary_builder(
+            &self.renderer.vulkan_context,
+            &self.renderer.framebuffers[self.image_index],
+        )
+    }
+}
+
+/// One step of the frame, the renderer records its nodes in order into the frame's command buffer
+pub trait RenderPassNode {
+    /// Name of the pass, for debugging and to find the node in the frame graph
+    fn name(&self) -> &'static str;
+
+    /// Whether the node records within the scene render pass rather than outside of any render
+    /// pass. The scene nodes are consecutive and share a single instance of the render pass
+    fn in_scene_pass(&self) -> bool {
+        false
+    }
+
+    fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        scene: &Scene,
+        frame_ctx: &FrameContext,
+    ) -> Result<()>;
+}
+
+/// Nodes recorded in order every frame, a new renderer runs the depth prepass, the scene, the
+/// occlusion queries then the post process
+pub(crate) struct FrameGraph {
+    nodes: Vec<Box<dyn RenderPassNode>>,
+}
+
+impl FrameGraph {
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![
+                Box::new(DepthPrepassPass),
+                Box::new(ScenePass),
+                Box::new(OcclusionQueryPass),
+                Box::new(PostProcessPass),
+            ],
+        }
+    }
+
+    pub fn node_names(&self) -> Vec<&'static str> {
+        self.nodes.iter().map(|node| node.name()).collect()
+    }
+
+    /// Fails when a node already has the same name, or when the scene nodes would no longer be
+    /// consecutive
+    pub fn insert(&mut self, index: usize, node: Box<dyn RenderPassNode>) -> Result<()> {
+        anyhow::ensure!(
+            index <= self.nodes.len(),
+            "Node index {index} is past the {} nodes of the frame graph",
+            self.nodes.len()
+        );
+        anyhow::ensure!(
+            self.position(node.name()).is_none(),
+            "The frame graph already has a {} node",
+            node.name()
+        );
+
+        self.nodes.insert(index, node);
+        if let Err(e) = self.check_scene_nodes() {
+            self.nodes.remove(index);
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Removes the node named `name`, reinserting it restores it. Fails when the graph has no
+    /// such node or when it is the last scene node, the frame would then silently draw nothing
+    pub fn remove(&mut self, name: &str) -> Result<Box<dyn RenderPassNode>> {
+        let index = self
+            .position(name)
+            .with_context(|| format!("The frame graph has no {name} node"))?;
+
+        let node = self.nodes.remove(index);
+        if let Err(e) = self.check_scene_nodes() {
+            self.nodes.insert(index, node);
+            return Err(e);
+        }
+
+        Ok(node)
+    }
+
+    /// Moves the node named `name` to `index` in the order of the other nodes. Fails like
+    /// `insert`, leaving the node where it was
+    pub fn move_node(&mut self, name: &str, index: usize) -> Result<()> {
+        let previous_index = self
+            .position(name)
+            .with_context(|| format!("The frame graph has no {name} node"))?;
+        anyhow::ensure!(
+            index < self.nodes.len(),
+            "Node index {index} is past the {} other nodes of the frame graph",
+            self.nodes.len() - 1
+        );
+
+        let node = self.nodes.remove(previous_index);
+        self.nodes.insert(index, node);
+        if let Err(e) = self.check_scene_nodes() {
+            let node = self.nodes.remove(index);
+            self.nodes.insert(previous_index, node);
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.nodes.iter().position(|node| node.name() == name)
+    }
+
+    fn check_scene_nodes(&self) -> Result<()> {
+        let first = self.nodes.iter().position(|node| node.in_scene_pass());
+        let last = self.nodes.iter().rposition(|node| node.in_scene_pass());
+
+        let (Some(first), Some(last)) = (first, last) else {
+            anyhow::bail!("The frame graph needs at least one scene node to draw the scene");
+        };
+        anyhow::ensure!(
+            self.nodes[first..=last]
+                .iter()
+                .all(|node| node.in_scene_pass()),
+            "The scene nodes have to be consecutive, they share a single render pass"
+        );
+
+        Ok(())
+    }
+
+    /// Records every node, the scene render pass beginning before the first scene node and ending
+    /// after the last one
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        scene: &Scene,
+        frame_ctx: &FrameContext,
+    ) -> Result<()> {
+        let mut in_scene_pass = false;
+        for node in self.nodes.iter() {
+            if node.in_scene_pass() && !in_scene_pass {
+                begin_scene_pass(builder, frame_ctx)?;
+            } else if !node.in_scene_pass() && in_scene_pass {
+                builder.end_render_pass(SubpassEndInfo::default())?;
+            }
+            in_scene_pass = node.in_scene_pass();
+
+            node.record(builder, scene, frame_ctx)
+                .with_context(|| format!("Failed to record the {} pass", node.name()))?;
+        }
+
+        if in_scene_pass {
+            builder.end_render_pass(SubpassEndInfo::default())?;
+        }
+
+        Ok(())
+    }
+}
+
+fn begin_scene_pass(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    frame_ctx: &FrameContext,
+) -> Result<()> {
+    let renderer = frame_ctx.renderer;
+
+    let render_pass_begin_info = RenderPassBeginInfo {
+        render_pass: renderer.render_pass.clone(),
+        render_area_offset: [0, 0],
+        render_area_extent: renderer.swapchain.image_extent(),
+        clear_values: renderer.clear_values(),
+        ..RenderPassBeginInfo::framebuffer(renderer.framebuffers[frame_ctx.image_index].clone())
+    };
+
+    // A subpass is either recorded inline or entirely from secondary command buffers
+    let subpass_begin_info = SubpassBeginInfo {
+        contents: if frame_ctx.secondary_command_buffers() {
+            SubpassContents::SecondaryCommandBuffers
+        } else {
+            SubpassContents::Inline
+        },
+        ..Default::default()
+    };
+
+    builder.begin_render_pass(render_pass_begin_info, subpass_begin_info)?;
+
+    // The requested clear comes before anything is drawn, the depth prepass included
+    if renderer.clear_requested {
+        if frame_ctx.secondary_command_buffers() {
+            let mut clear_builder = frame_ctx.secondary_builder()?;
+            renderer.record_clear(&mut clear_builder)?;
+            builder.execute_commands(clear_builder.build()?)?;
+        } else {
+            renderer.record_clear(builder)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the depth of the meshes drawn one by one with their material before `ScenePass` shades
+/// them, does nothing unless the depth prepass is enabled
+pub(crate) struct DepthPrepassPass;
+
+impl RenderPassNode for DepthPrepassPass {
+    fn name(&self) -> &'static str {
+        "depth prepass"
+    }
+
+    fn in_scene_pass(&self) -> bool {
+        true
+    }
+
+    fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        scene: &Scene,
+        frame_ctx: &FrameContext,
+    ) -> Result<()> {
+        let renderer = frame_ctx.renderer;
+        let draws = frame_ctx.draws;
+
+        // Indirect draws and the debug render modes are shaded without a prepass
+        if !renderer.depth_prepass()
+            || renderer.render_mode != RenderMode::Default
+            || draws.indirect_batch.is_some()
+        {
+            return Ok(());
+        }
+
+        let mesh_draws = parallel_recording::mesh_draws(
+            scene,
+            &renderer.pipeline_manager,
+            &draws.mesh_components,
+            &draws.mesh_draw_orders,
+            &draws.mesh_entity_sets,
+        );
+
+        for view in frame_ctx.views.iter() {
+            if frame_ctx.secondary_command_buffers() {
+                let mut view_builder = frame_ctx.secondary_builder()?;
+                record_view_depth_prepass(&mut view_builder, view, &mesh_draws)?;
+                builder.execute_commands(view_builder.build()?)?;
+            } else {
+                record_view_depth_prepass(builder, view, &mesh_draws)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn record_view_depth_prepass<L>(
+    builder: &mut AutoCommandBufferBuilder<L>,
+    view: &RenderView,
+    mesh_draws: &[parallel_recording::MeshDraw],
+) -> Result<()> {
+    builder
+        .set_viewport(0, [view.viewport.clone()].into_iter().collect())?
+        .set_scissor(0, [view.scissor].into_iter().collect())?;
+
+    parallel_recording::record_depth_prepass(builder, view, mesh_draws)
+}
+
+/// Draws every view of the scene followed by the overlays, in the render mode of the renderer
+pub(crate) struct ScenePass;
+
+impl RenderPassNode for ScenePass {
+    fn name(&self) -> &'static str {
+        "scene"
+    }
+
+    fn in_scene_pass(&self) -> bool {
+        true
+    }
+
+    fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        scene: &Scene,
+        frame_ctx: &FrameContext,
+    ) -> Result<()> {
+        let renderer = frame_ctx.renderer;
+        let draws = frame_ctx.draws;
+
+        if frame_ctx.secondary_command_buffers() {
+            return renderer.record_secondary_command_buffers(
+                builder,
+                frame_ctx.image_index,
+                scene,
+                frame_ctx.views,
+                draws,
+            );
+        }
+
+        renderer.record_background(builder)?;
+
+        for view in frame_ctx.views.iter() {
+            renderer.record_view(builder, scene, view, &draws.mesh_components, draws)?;
+        }
+
+        renderer.record_overlay(builder, draws)
+    }
+}
+
+/// Draws the proxies of the occlusion queries against the depth of the scene, does nothing when
+/// the queries are disabled
+pub(crate) struct OcclusionQueryPass;
+
+impl RenderPassNode for OcclusionQueryPass {
+    fn name(&self) -> &'static str {
+        "occlusion queries"
+    }
+
+    fn in_scene_pass(&self) -> bool {
+        true
+    }
+
+    fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        _scene: &Scene,
+        frame_ctx: &FrameContext,
+    ) -> Result<()> {
+        let renderer = frame_ctx.renderer;
+        let occlusion = &renderer.occlusion;
+        if !occlusion.has_proxies(frame_ctx.image_index) {
+            return Ok(());
+        }
+
+        let pipeline = renderer.pipeline_manager.occlusion_proxy_pipeline();
+        if frame_ctx.secondary_command_buffers() {
+            // The queries begin and end within the secondary command buffer
+            let mut query_builder = frame_ctx.secondary_builder()?;
+            occlusion.record(
+                &mut query_builder,
+                frame_ctx.image_index,
+                pipeline,
+                frame_ctx.views,
+            )?;
+            builder.execute_commands(query_builder.build()?)?;
+        } else {
+            occlusion.record(builder, frame_ctx.image_index, pipeline, frame_ctx.views)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Full screen pass writing the swapchain image from the scene color, does nothing when the
+/// renderer was created without a post process shader
+pub(crate) struct PostProcessPass;
+
+impl RenderPassNode for PostProcessPass {
+    fn name(&self) -> &'static str {
+        "post process"
+    }
+
+    fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        _scene: &Scene,
+        frame_ctx: &FrameContext,
+    ) -> Result<()> {
+        match &frame_ctx.renderer.post_process {
+            Some(post_process) => post_process.record(builder, frame_ctx.image_index),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestNode {
+        name: &'static str,
+        in_scene_pass: bool,
+    }
+
+    impl RenderPassNode for TestNode {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn in_scene_pass(&self) -> bool {
+            self.in_scene_pass
+        }
+
+        fn record(
+            &self,
+            _builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+            _scene: &Scene,
+            _frame_ctx: &FrameContext,
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn test_node(name: &'static str, in_scene_pass: bool) -> Box<dyn RenderPassNode> {
+        Box::new(TestNode {
+            name,
+            in_scene_pass,
+        })
+    }
+
+    #[test]
+    fn nodes_are_inserted_and_moved() {
+        let mut frame_graph = FrameGraph::new();
+        assert_eq!(
+            frame_graph.node_names(),
+            vec![
+                "depth prepass",
+                "scene",
+                "occlusion queries",
+                "post process"
+            ]
+        );
+
+        frame_graph.insert(4, test_node("blur", false)).unwrap();
+        frame_graph.insert(2, test_node("outline", true)).unwrap();
+        assert_eq!(
+            frame_graph.node_names(),
+            vec![
+                "depth prepass",
+                "scene",
+                "outline",
+                "occlusion queries",
+                "post process",
+                "blur"
+            ]
+        );
+
+        frame_graph.move_node("blur", 4).unwrap();
+        assert_eq!(
+            frame_graph.node_names(),
+            vec![
+                "depth prepass",
+                "scene",
+                "outline",
+                "occlusion queries",
+                "blur",
+                "post process"
+            ]
+        );
+
+        assert!(frame_graph.remove("depth prepass").is_ok());
+        assert!(frame_graph.remove("depth prepass").is_err());
+        assert!(frame_graph.move_node("depth prepass", 0).is_err());
+    }
+
+    #[test]
+    fn last_scene_node_is_not_removed() {
+        let mut frame_graph = FrameGraph::new();
+
+        frame_graph.remove("depth prepass").unwrap();
+        frame_graph.remove("occlusion queries").unwrap();
+        assert!(frame_graph.remove("scene").is_err());
+        assert_eq!(frame_graph.node_names(), vec!["scene", "post process"]);
+
+        // Another scene node takes over
+        frame_graph.insert(0, test_node("outline", true)).unwrap();
+        assert!(frame_graph.remove("scene").is_ok());
+        assert_eq!(frame_graph.node_names(), vec!["outline", "post process"]);
+    }
+
+    #[test]
+    fn invalid_insertions_are_rejected() {
+        let mut frame_graph = FrameGraph::new();
+
+        assert!(frame_graph.insert(0, test_node("scene", true)).is_err());
+        assert!(frame_graph.insert(5, test_node("blur", false)).is_err());
+        // Between the depth prepass and the scene, which share the render pass
+        assert!(frame_graph.insert(1, test_node("blur", false)).is_err());
+        // The node moved in between stays where it was
+        assert!(frame_graph.move_node("post process", 2).is_err());
+
+        assert_eq!(
+            frame_graph.node_names(),
+            vec![
+                "depth prepass",
+                "scene",
+                "occlusion queries",
+                "post process"
+            ]
+        );
+    }
+}
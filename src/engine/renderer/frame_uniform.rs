@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use glam::Vec2;
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage},
+    descriptor_set::{
+        layout::{
+            DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorSetLayoutCreateInfo,
+            DescriptorType,
+        },
+        PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::Device,
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    shader::ShaderStages,
+    sync::Sharing,
+};
+
+use crate::vulkan_context::VulkanContext;
+
+pub(crate) const FRAME_BINDING: u32 = 0;
+
+/// Uniform block of the frame bound to every material pipeline at set 1 binding 0, for shaders
+/// animating without the CPU. Declared in GLSL, with the std140 layout, as
+///
+/// ```glsl
+/// layout(set = 1, binding = 0) uniform Frame
+/// {
+///     vec2 resolution; // Size of the window in pixels
+///     float time;      // Seconds elapsed, see `FrameInfo::total_time`
+///     uint frame_index;
+/// } frame;
+/// ```
+#[derive(BufferContents, Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub(crate) struct FrameData {
+    pub resolution: Vec2,
+    pub time: f32,
+    pub frame_index: u32,
+}
+
+pub(crate) fn create_frame_set_layout(device: &Arc<Device>) -> Result<Arc<DescriptorSetLayout>> {
+    let set_info = DescriptorSetLayoutCreateInfo {
+        bindings: [(
+            FRAME_BINDING,
+            DescriptorSetLayoutBinding {
+                descriptor_count: 1,
+                stages: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                ..DescriptorSetLayoutBinding::descriptor_type(DescriptorType::UniformBuffer)
+            },
+        )]
+        .into_iter()
+        .collect(),
+        ..Default::default()
+    };
+
+    Ok(DescriptorSetLayout::new(Arc::clone(device), set_info)?)
+}
+
+/// Uploads the uniform block of the current frame
+pub(crate) fn create_frame_descriptor_set(
+    vulkan_context: &VulkanContext,
+    frame_set_layout: &Arc<DescriptorSetLayout>,
+    frame_data: FrameData,
+) -> Result<Arc<PersistentDescriptorSet>> {
+    let frame_buffer = Buffer::from_data(
+        Arc::clone(vulkan_context.standard_memory_allocator()),
+        BufferCreateInfo {
+            sharing: Sharing::Exclusive,
+            usage: BufferUsage::UNIFORM_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        frame_data,
+    )?;
+
+    let descriptor_set = PersistentDescriptorSet::new(
+        vulkan_context.standard_descripor_set_allocator().as_ref(),
+        Arc::clone(frame_set_layout),
+        [WriteDescriptorSet::buffer(FRAME_BINDING, frame_buffer)],
+        [],
+    )?;
+
+    Ok(descriptor_set)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_data_matches_std140_layout() {
+        assert_eq!(std::mem::size_of::<FrameData>(), 16);
+        assert_eq!(std::mem::offset_of!(FrameData, time), 8);
+        assert_eq!(std::mem::offset_of!(FrameData, frame_index), 12);
+    }
+}
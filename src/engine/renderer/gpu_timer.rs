@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    query::{QueryPool, QueryPoolCreateInfo, QueryResultFlags, QueryType},
+    sync::PipelineStage,
+};
+
+use crate::vulkan_context::VulkanContext;
+
+/// Measures the GPU time of the main render pass with timestamp queries. Every swapchain image
+/// owns a pair of queries, the results of a frame are read back the next time its image is
+/// rendered to so the CPU never waits on the GPU
+pub(crate) struct GpuTimer {
+    query_pool: Option<Arc<QueryPool>>,
+    written: Vec<bool>,
+    timestamp_period: f32,
+    last_gpu_time_ms: Option<f32>,
+}
+
+impl GpuTimer {
+    pub fn new(vulkan_context: &VulkanContext, image_count: usize) -> Result<Self> {
+        let device = vulkan_context.device();
+        let queue_family_index = vulkan_context.graphics_queue().queue_family_index();
+
+        // Queues without valid timestamp bits can't write timestamps, the timer stays disabled
+        let timestamps_supported = device.physical_device().queue_family_properties()
+            [queue_family_index as usize]
+            .timestamp_valid_bits
+            .is_some();
+
+        let query_pool = if timestamps_supported {
+            Some(QueryPool::new(
+                Arc::clone(device),
+                QueryPoolCreateInfo {
+                    query_count: 2 * image_count as u32,
+                    ..QueryPoolCreateInfo::query_type(QueryType::Timestamp)
+                },
+            )?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            query_pool,
+            written: vec![false; image_count],
+            timestamp_period: device.physical_device().properties().timestamp_period,
+            last_gpu_time_ms: None,
+        })
+    }
+
+    pub fn last_gpu_time_ms(&self) -> Option<f32> {
+        self.last_gpu_time_ms
+    }
+
+    /// Reads the timestamps written the last time `image_index` was rendered to if the GPU is
+    /// done with them, the queries of the image are then recorded again by `begin` and `end`
+    pub fn prepare_frame(&mut self, image_index: usize) -> Result<()> {
+        let Some(query_pool) = &self.query_pool else {
+            return Ok(());
+        };
+        if !self.written[image_index] {
+            self.written[image_index] = true;
+            return Ok(());
+        }
+
+        let mut timestamps = [0u64; 2];
+        let first_query = 2 * image_index as u32;
+        let available = query_pool.get_results(
+            first_query..first_query + 2,
+            &mut timestamps,
+            QueryResultFlags::empty(),
+        )?;
+
+        if available {
+            let ticks = timestamps[1].saturating_sub(timestamps[0]);
+            self.last_gpu_time_ms = Some(ticks as f32 * self.timestamp_period / 1_000_000.0);
+        }
+
+        Ok(())
+    }
+
+    /// Must be recorded outside of a render pass
+    pub fn begin(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        image_index: usize,
+    ) -> Result<()> {
+        let Some(query_pool) = &self.query_pool else {
+            return Ok(());
+        };
+
+        let first_query = 2 * image_index as u32;
+        unsafe {
+            builder
+                .reset_query_pool(Arc::clone(query_pool), first_query..first_query + 2)?
+                .write_timestamp(Arc::clone(query_pool), first_query, PipelineStage::TopOfPipe)?;
+        }
+
+        Ok(())
+    }
+
+    /// Must be recorded outside of a render pass
+    pub fn end(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        image_index: usize,
+    ) -> Result<()> {
+        let Some(query_pool) = &self.query_pool else {
+            return Ok(());
+        };
+
+        let first_query = 2 * image_index as u32;
+        unsafe {
+            builder.write_timestamp(
+                Arc::clone(query_pool),
+                first_query + 1,
+                PipelineStage::BottomOfPipe,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// The swapchain image count may change when the swapchain is recreated
+    pub fn resize(&mut self, vulkan_context: &VulkanContext, image_count: usize) -> Result<()> {
+        if image_count != self.written.len() {
+            let last_gpu_time_ms = self.last_gpu_time_ms;
+            *self = Self::new(vulkan_context, image_count)?;
+            self.last_gpu_time_ms = last_gpu_time_ms;
+        }
+
+        Ok(())
+    }
+}
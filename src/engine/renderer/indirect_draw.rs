@@ -0,0 +1,361 @@
+use std::{ops::Range, sync::Arc};
+
+use anyhow::Result;
+use glam::{Mat4, Vec4};
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        AutoCommandBufferBuilder, CommandBufferUsage, CopyBufferInfo, DrawIndexedIndirectCommand,
+    },
+    descriptor_set::{
+        layout::{
+            DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorSetLayoutCreateInfo,
+            DescriptorType,
+        },
+        PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::Device,
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    shader::ShaderStages,
+    sync::{GpuFuture, Sharing},
+};
+
+use crate::{
+    engine::{
+        ecs::components::MeshComponent,
+        mesh::{Submesh, Vertex},
+    },
+    vulkan_context::VulkanContext,
+};
+
+pub(crate) const OBJECT_BINDING: u32 = 0;
+
+/// Per object data of the meshes drawn indirectly, matches the `Object` struct of the indirect
+/// vertex shader
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct ObjectData {
+    model: Mat4,
+    tint: Vec4,
+}
+
+/// Layout of the storage buffer holding the `ObjectData` of every object drawn indirectly,
+/// indexed by the instance index of the draw
+pub(crate) fn create_object_set_layout(device: &Arc<Device>) -> Result<Arc<DescriptorSetLayout>> {
+    let set_info = DescriptorSetLayoutCreateInfo {
+        bindings: [(
+            OBJECT_BINDING,
+            DescriptorSetLayoutBinding {
+                descriptor_count: 1,
+                stages: ShaderStages::VERTEX,
+                ..DescriptorSetLayoutBinding::descriptor_type(DescriptorType::StorageBuffer)
+            },
+        )]
+        .into_iter()
+        .collect(),
+        ..Default::default()
+    };
+
+    Ok(DescriptorSetLayout::new(Arc::clone(device), set_info)?)
+}
+
+/// Vertex and index buffers of a mesh, identifying the meshes packed by `IndirectMeshes`
+type MeshBuffers = (Subbuffer<[Vertex]>, Subbuffer<[u32]>);
+
+/// Every mesh drawn indirectly packed into shared vertex and index buffers, with one indirect draw
+/// command per submesh. Kept between frames, the meshes are only packed again when they change
+pub(crate) struct IndirectMeshes {
+    /// Buffers of each packed mesh, in the order of the meshes of the scene
+    meshes: Vec<MeshBuffers>,
+    /// First vertex and first index of each packed mesh
+    mesh_offsets: Vec<(u32, u32)>,
+    /// Draw order and submeshes of each mesh, the draw commands are built again when they change
+    draw_layout: Vec<(i32, Vec<Submesh>)>,
+    pub vertex_buffer: Subbuffer<[Vertex]>,
+    pub index_buffer: Subbuffer<[u32]>,
+    draw_commands: Vec<DrawIndexedIndirectCommand>,
+    /// Consecutive draw commands sharing the same material
+    pub material_ranges: Vec<(u64, Range<u64>)>,
+}
+
+impl IndirectMeshes {
+    /// Packs `mesh_components` unless `packed` already holds them, waiting for the copies.
+    /// `draw_orders` holds the draw order of each mesh. Leaves `None` when there is nothing to
+    /// draw
+    pub fn update(
+        packed: &mut Option<Self>,
+        vulkan_context: &VulkanContext,
+        mesh_components: &[&MeshComponent],
+        draw_orders: &[i32],
+    ) -> Result<()> {
+        if mesh_components.is_empty() {
+            *packed = None;
+            return Ok(());
+        }
+
+        let meshes = mesh_components
+            .iter()
+            .map(|mesh_component| {
+                (
+                    mesh_component.mesh.vectex_buffer().clone(),
+                    mesh_component.mesh.index_buffer().clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let draw_layout = mesh_components
+            .iter()
+            .zip(draw_orders.iter())
+            .map(|(mesh_component, draw_order)| (*draw_order, mesh_component.draw_ranges()))
+            .collect::<Vec<_>>();
+
+        match packed {
+            Some(packed) if packed.meshes == meshes => {
+                if packed.draw_layout != draw_layout {
+                    (packed.draw_commands, packed.material_ranges) =
+                        build_draw_commands(&packed.mesh_offsets, &draw_layout);
+                    packed.draw_layout = draw_layout;
+                }
+            }
+            _ => *packed = Some(Self::pack(vulkan_context, meshes, draw_layout)?),
+        }
+
+        Ok(())
+    }
+
+    fn pack(
+        vulkan_context: &VulkanContext,
+        meshes: Vec<MeshBuffers>,
+        draw_layout: Vec<(i32, Vec<Submesh>)>,
+    ) -> Result<Self> {
+        let allocator = vulkan_context.standard_memory_allocator();
+
+        let vertex_count: u64 = meshes.iter().map(|(vertices, _)| vertices.len()).sum();
+        let index_count: u64 = meshes.iter().map(|(_, indices)| indices.len()).sum();
+
+        let vertex_buffer = Buffer::new_slice::<Vertex>(
+            Arc::clone(allocator),
+            BufferCreateInfo {
+                sharing: Sharing::Exclusive,
+                usage: BufferUsage::VERTEX_BUFFER | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                ..Default::default()
+            },
+            vertex_count,
+        )?;
+
+        let index_buffer = Buffer::new_slice::<u32>(
+            Arc::clone(allocator),
+            BufferCreateInfo {
+                sharing: Sharing::Exclusive,
+                usage: BufferUsage::INDEX_BUFFER | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                ..Default::default()
+            },
+            index_count,
+        )?;
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            vulkan_context.standard_command_buffer_allocator().as_ref(),
+            vulkan_context.graphics_queue().queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+
+        let mut mesh_offsets = Vec::with_capacity(meshes.len());
+        let mut vertex_offset = 0;
+        let mut index_offset = 0;
+        for (mesh_vertices, mesh_indices) in meshes.iter() {
+            builder
+                .copy_buffer(CopyBufferInfo::buffers(
+                    mesh_vertices.clone(),
+                    vertex_buffer
+                        .clone()
+                        .slice(vertex_offset..vertex_offset + mesh_vertices.len()),
+                ))?
+                .copy_buffer(CopyBufferInfo::buffers(
+                    mesh_indices.clone(),
+                    index_buffer
+                        .clone()
+                        .slice(index_offset..index_offset + mesh_indices.len()),
+                ))?;
+
+            mesh_offsets.push((vertex_offset as u32, index_offset as u32));
+            vertex_offset += mesh_vertices.len();
+            index_offset += mesh_indices.len();
+        }
+
+        // The frames drawing the packed meshes are recorded right after, the copies have to be
+        // done by then
+        builder
+            .build()?
+            .execute(Arc::clone(vulkan_context.graphics_queue()))?
+            .then_signal_fence_and_flush()?
+            .wait(None)?;
+
+        let (draw_commands, material_ranges) = build_draw_commands(&mesh_offsets, &draw_layout);
+
+        Ok(Self {
+            meshes,
+            mesh_offsets,
+            draw_layout,
+            vertex_buffer,
+            index_buffer,
+            draw_commands,
+            material_ranges,
+        })
+    }
+}
+
+/// Draw commands of every submesh, sorted by draw order then material so that each material of
+/// a draw order is drawn by a single multi draw. Returns them with their material ranges
+fn build_draw_commands(
+    mesh_offsets: &[(u32, u32)],
+    draw_layout: &[(i32, Vec<Submesh>)],
+) -> (Vec<DrawIndexedIndirectCommand>, Vec<(u64, Range<u64>)>) {
+    let mut draws = Vec::new();
+    for (object_index, ((vertex_offset, index_offset), (draw_order, submeshes))) in
+        mesh_offsets.iter().zip(draw_layout.iter()).enumerate()
+    {
+        for submesh in submeshes.iter() {
+            let draw_command = DrawIndexedIndirectCommand {
+                index_count: submesh.index_count,
+                instance_count: 1,
+                first_index: index_offset + submesh.index_offset,
+                vertex_offset: *vertex_offset,
+                first_instance: object_index as u32,
+            };
+            draws.push((*draw_order, submesh.material, draw_command));
+        }
+    }
+
+    // Each material of a draw order is drawn by a single multi draw, its commands have to be
+    // consecutive
+    draws.sort_by_key(|(draw_order, material, _)| (*draw_order, *material));
+
+    let mut draw_commands = Vec::with_capacity(draws.len());
+    let mut material_ranges: Vec<(u64, Range<u64>)> = Vec::new();
+    for (draw_index, (_, draw_material, draw_command)) in draws.into_iter().enumerate() {
+        draw_commands.push(draw_command);
+
+        let draw_index = draw_index as u64;
+        match material_ranges.last_mut() {
+            Some((material, range)) if *material == draw_material => {
+                range.end = draw_index + 1;
+            }
+            _ => material_ranges.push((draw_material, draw_index..draw_index + 1)),
+        }
+    }
+
+    (draw_commands, material_ranges)
+}
+
+/// Per frame data of the meshes packed by `IndirectMeshes`
+pub(crate) struct IndirectBatch<'a> {
+    pub meshes: &'a IndirectMeshes,
+    pub draw_commands: Subbuffer<[DrawIndexedIndirectCommand]>,
+    pub object_descriptor_set: Arc<PersistentDescriptorSet>,
+}
+
+impl<'a> IndirectBatch<'a> {
+    /// Uploads the objects and draw commands of the frame, `mesh_components` being the meshes
+    /// packed by `meshes`. The meshes `culled` by the occlusion culling stay packed and are drawn
+    /// without any instance
+    pub fn build(
+        vulkan_context: &VulkanContext,
+        object_set_layout: &Arc<DescriptorSetLayout>,
+        meshes: &'a IndirectMeshes,
+        mesh_components: &[&MeshComponent],
+        culled: &[bool],
+    ) -> Result<Self> {
+        let allocator = vulkan_context.standard_memory_allocator();
+
+        let host_allocation_info = AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        };
+
+        let draw_commands = Buffer::from_iter(
+            Arc::clone(allocator),
+            BufferCreateInfo {
+                sharing: Sharing::Exclusive,
+                usage: BufferUsage::INDIRECT_BUFFER,
+                ..Default::default()
+            },
+            host_allocation_info.clone(),
+            meshes
+                .draw_commands
+                .iter()
+                .map(|draw_command| DrawIndexedIndirectCommand {
+                    instance_count: if culled[draw_command.first_instance as usize] {
+                        0
+                    } else {
+                        draw_command.instance_count
+                    },
+                    ..*draw_command
+                }),
+        )?;
+
+        let object_buffer = Buffer::from_iter(
+            Arc::clone(allocator),
+            BufferCreateInfo {
+                sharing: Sharing::Exclusive,
+                usage: BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            host_allocation_info,
+            mesh_components.iter().map(|mesh_component| ObjectData {
+                model: mesh_component.model.transform(),
+                tint: mesh_component.tint.extend(1.0),
+            }),
+        )?;
+
+        let object_descriptor_set = PersistentDescriptorSet::new(
+            vulkan_context.standard_descripor_set_allocator().as_ref(),
+            Arc::clone(object_set_layout),
+            [WriteDescriptorSet::buffer(OBJECT_BINDING, object_buffer)],
+            [],
+        )?;
+
+        Ok(Self {
+            meshes,
+            draw_commands,
+            object_descriptor_set,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draw_commands_grouped_by_draw_order_then_material() {
+        let submesh = |index_offset, material| Submesh {
+            index_offset,
+            index_count: 3,
+            material,
+        };
+        let mesh_offsets = [(0, 0), (4, 6), (8, 12)];
+        let draw_layout = [
+            (0, vec![submesh(0, 2), submesh(3, 1)]),
+            (1, vec![submesh(0, 1)]),
+            (0, vec![submesh(0, 2)]),
+        ];
+
+        let (draw_commands, material_ranges) = build_draw_commands(&mesh_offsets, &draw_layout);
+
+        assert_eq!(material_ranges, vec![(1, 0..1), (2, 1..3), (1, 3..4)]);
+        let objects = draw_commands
+            .iter()
+            .map(|draw_command| (draw_command.first_instance, draw_command.first_index))
+            .collect::<Vec<_>>();
+        assert_eq!(objects, vec![(0, 3), (0, 0), (2, 12), (1, 6)]);
+        assert_eq!(draw_commands[2].vertex_offset, 8);
+    }
+}
@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use glam::{Mat4, Vec3};
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    pipeline::graphics::vertex_input,
+    sync::Sharing,
+};
+
+use crate::{engine::ecs::components::MeshInstance, vulkan_context::VulkanContext};
+
+/// Vertex buffer binding of the instance stream, the mesh vertices being at binding 0
+pub(crate) const INSTANCE_BINDING: u32 = 1;
+
+/// Per instance attributes of the instanced vertex shader, read once per instance from the buffer
+/// bound at `INSTANCE_BINDING`
+#[derive(BufferContents, vertex_input::Vertex, Clone, Copy)]
+#[repr(C)]
+pub(crate) struct InstanceVertex {
+    /// Spans 4 locations, one per column
+    #[format(R32G32B32A32_SFLOAT)]
+    pub in_instance_model: Mat4,
+
+    #[format(R32G32B32_SFLOAT)]
+    pub in_instance_tint: Vec3,
+}
+
+/// Uploads the instances of an instanced mesh for the current frame, `instances` can't be empty
+pub(crate) fn create_instance_buffer(
+    vulkan_context: &VulkanContext,
+    instances: &[MeshInstance],
+) -> Result<Subbuffer<[InstanceVertex]>> {
+    let instance_buffer = Buffer::from_iter(
+        Arc::clone(vulkan_context.standard_memory_allocator()),
+        BufferCreateInfo {
+            sharing: Sharing::Exclusive,
+            usage: BufferUsage::VERTEX_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        instances.iter().map(|instance| InstanceVertex {
+            in_instance_model: instance.model.transform(),
+            in_instance_tint: instance.tint,
+        }),
+    )?;
+
+    Ok(instance_buffer)
+}
@@ -0,0 +1,413 @@
+use std::{collections::HashMap, mem::size_of, sync::Arc};
+
+use anyhow::Result;
+use glam::{Mat4, Vec3, Vec4};
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    query::{QueryControlFlags, QueryPool, QueryPoolCreateInfo, QueryResultFlags, QueryType},
+    sync::Sharing,
+};
+
+use crate::{
+    engine::{ecs::Entity, mesh::PositionVertex, pipeline_manager::VulkanPipeline},
+    vulkan_context::VulkanContext,
+};
+
+use super::RenderView;
+
+/// Queries owned by every swapchain image before the pool has to grow
+const INITIAL_CAPACITY: u32 = 64;
+
+/// Padding of the proxies around their mesh, relative to the largest side of its bounds, so
+/// that the faces of a mesh lying on its bounds don't hide its proxy
+const PROXY_MARGIN: f32 = 0.01;
+
+/// Box drawn in place of the mesh of an entity to count its samples in one of the views
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct OcclusionProxy {
+    pub entity: Entity,
+    /// Index of the view the proxy is drawn in
+    pub view_index: usize,
+    /// Transform of the unit cube to the box bounding the mesh in the world
+    pub model: Mat4,
+}
+
+impl OcclusionProxy {
+    /// Proxy of a mesh with the `bounds` of `Mesh::bounds` placed by `model`. `None` when part of
+    /// the box is closer than the near plane of the view, the mesh could hide its own proxy
+    /// behind it and is never considered occluded
+    pub fn new(
+        entity: Entity,
+        view_index: usize,
+        view_projection: Mat4,
+        reversed_z: bool,
+        model: Mat4,
+        bounds: (Vec3, Vec3),
+    ) -> Option<Self> {
+        let model = model * bounds_transform(bounds);
+        beyond_near_plane(view_projection * model, reversed_z).then_some(Self {
+            entity,
+            view_index,
+            model,
+        })
+    }
+}
+
+/// Transform of the unit cube to `bounds` padded by `PROXY_MARGIN`
+fn bounds_transform((min, max): (Vec3, Vec3)) -> Mat4 {
+    let half_extent = (max - min) / 2.0;
+    let half_extent = half_extent + Vec3::splat(half_extent.max_element() * PROXY_MARGIN);
+
+    Mat4::from_translation((min + max) / 2.0) * Mat4::from_scale(half_extent)
+}
+
+/// Whether every corner of the unit cube transformed by `clip_from_cube` is past the near plane
+fn beyond_near_plane(clip_from_cube: Mat4, reversed_z: bool) -> bool {
+    (0..8).all(|corner| {
+        let corner = Vec4::new(
+            if corner & 1 == 0 { -1.0 } else { 1.0 },
+            if corner & 2 == 0 { -1.0 } else { 1.0 },
+            if corner & 4 == 0 { -1.0 } else { 1.0 },
+            1.0,
+        );
+        let clip = clip_from_cube * corner;
+
+        // The near plane is at a depth of 1 with a reversed depth range
+        clip.w > 0.0
+            && if reversed_z {
+                clip.z < clip.w
+            } else {
+                clip.z > 0.0
+            }
+    })
+}
+
+/// Counts the samples of a box drawn around the mesh of every entity after the scene, giving an
+/// estimate of which entities are visible. The boxes are drawn whether their entity was drawn or
+/// not, so that the entities skipped by the occlusion culling are found again once they come
+/// into view. Like the timestamps of the GPU timer, every swapchain image owns a range of
+/// queries read back the next time its image is rendered to, so the estimate lags a few frames
+/// behind and the CPU never waits on the GPU
+pub(crate) struct OcclusionQueries {
+    query_pool: Arc<QueryPool>,
+    /// Queries of each swapchain image
+    capacity: u32,
+    /// Unit cube drawn for every proxy
+    cube_vertices: Subbuffer<[PositionVertex]>,
+    cube_indices: Subbuffer<[u32]>,
+    /// Proxy counted by each query the last time each image was rendered to
+    image_proxies: Vec<Vec<OcclusionProxy>>,
+    /// Proxies of the next frame
+    pending_proxies: Vec<OcclusionProxy>,
+    visibility: HashMap<Entity, bool>,
+}
+
+impl OcclusionQueries {
+    pub fn new(vulkan_context: &VulkanContext, image_count: usize) -> Result<Self> {
+        Self::with_capacity(vulkan_context, image_count, INITIAL_CAPACITY)
+    }
+
+    fn with_capacity(
+        vulkan_context: &VulkanContext,
+        image_count: usize,
+        capacity: u32,
+    ) -> Result<Self> {
+        let query_pool = QueryPool::new(
+            Arc::clone(vulkan_context.device()),
+            QueryPoolCreateInfo {
+                query_count: capacity * image_count as u32,
+                ..QueryPoolCreateInfo::query_type(QueryType::Occlusion)
+            },
+        )?;
+
+        let (cube_vertices, cube_indices) = create_cube(vulkan_context)?;
+
+        Ok(Self {
+            query_pool,
+            capacity,
+            cube_vertices,
+            cube_indices,
+            image_proxies: vec![Vec::new(); image_count],
+            pending_proxies: Vec::new(),
+            visibility: HashMap::new(),
+        })
+    }
+
+    /// Whether any sample of the entity's proxies passed the depth test in the last frame read
+    /// back, `None` when the entity had no proxy
+    pub fn entity_visible(&self, entity: Entity) -> Option<bool> {
+        self.visibility.get(&entity).copied()
+    }
+
+    /// Proxies queried in the next frame, empty to record the frame without queries. Growing the
+    /// pool drops the results still pending
+    pub fn set_frame_proxies(
+        &mut self,
+        vulkan_context: &VulkanContext,
+        proxies: Vec<OcclusionProxy>,
+    ) -> Result<()> {
+        if proxies.len() as u32 > self.capacity {
+            let capacity = (proxies.len() as u32).next_power_of_two();
+            let visibility = std::mem::take(&mut self.visibility);
+
+            *self = Self::with_capacity(vulkan_context, self.image_proxies.len(), capacity)?;
+            self.visibility = visibility;
+        }
+
+        self.pending_proxies = proxies;
+        Ok(())
+    }
+
+    /// Forgets the visibility of every entity and the results still pending
+    pub fn clear(&mut self) {
+        self.image_proxies.iter_mut().for_each(Vec::clear);
+        self.pending_proxies.clear();
+        self.visibility.clear();
+    }
+
+    /// Reads the samples counted the last time `image_index` was rendered to if the GPU is done
+    /// with them, the queries of the image then count the proxies given to `set_frame_proxies`
+    pub fn prepare_frame(&mut self, image_index: usize) -> Result<()> {
+        let pending_proxies = std::mem::take(&mut self.pending_proxies);
+        let proxies = std::mem::replace(&mut self.image_proxies[image_index], pending_proxies);
+        if proxies.is_empty() {
+            return Ok(());
+        }
+
+        let mut samples = vec![0u64; proxies.len()];
+        let first_query = self.first_query(image_index);
+        let available = self.query_pool.get_results(
+            first_query..first_query + proxies.len() as u32,
+            &mut samples,
+            QueryResultFlags::empty(),
+        )?;
+
+        if available {
+            self.visibility = fold_visibility(&proxies, &samples);
+        }
+
+        Ok(())
+    }
+
+    /// Resets the queries used by the frame, must be recorded outside of a render pass
+    pub fn begin(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        image_index: usize,
+    ) -> Result<()> {
+        let query_count = self.image_proxies[image_index].len() as u32;
+        if query_count == 0 {
+            return Ok(());
+        }
+
+        let first_query = self.first_query(image_index);
+        unsafe {
+            builder.reset_query_pool(
+                Arc::clone(&self.query_pool),
+                first_query..first_query + query_count,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether the frame drawn to `image_index` has proxies to query
+    pub fn has_proxies(&self, image_index: usize) -> bool {
+        !self.image_proxies[image_index].is_empty()
+    }
+
+    /// Draws the proxies of the frame within the scene render pass, each counted by its own
+    /// query. `pipeline` tests the depth of the scene without writing it
+    pub fn record<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        image_index: usize,
+        pipeline: &VulkanPipeline,
+        views: &[RenderView],
+    ) -> Result<()> {
+        let layout = &pipeline.layout;
+        builder
+            .bind_pipeline_graphics(Arc::clone(&pipeline.pipeline))?
+            .bind_vertex_buffers(0, self.cube_vertices.clone())?
+            .bind_index_buffer(self.cube_indices.clone())?;
+
+        let first_query = self.first_query(image_index);
+        let mut bound_view = None;
+        for (query, proxy) in self.image_proxies[image_index].iter().enumerate() {
+            if bound_view != Some(proxy.view_index) {
+                let view = &views[proxy.view_index];
+                builder
+                    .set_viewport(0, [view.viewport.clone()].into_iter().collect())?
+                    .set_scissor(0, [view.scissor].into_iter().collect())?
+                    .push_constants(Arc::clone(layout), 16 * size_of::<f32>() as u32, view.view)?
+                    .push_constants(
+                        Arc::clone(layout),
+                        2 * 16 * size_of::<f32>() as u32,
+                        view.projection,
+                    )?;
+
+                bound_view = Some(proxy.view_index);
+            }
+
+            let query = first_query + query as u32;
+            unsafe {
+                builder.begin_query(
+                    Arc::clone(&self.query_pool),
+                    query,
+                    QueryControlFlags::empty(),
+                )?;
+            }
+            builder
+                .push_constants(Arc::clone(layout), 0, proxy.model)?
+                .draw_indexed(self.cube_indices.len() as u32, 1, 0, 0, 0)?;
+            unsafe {
+                builder.end_query(Arc::clone(&self.query_pool), query)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The swapchain image count may change when the swapchain is recreated
+    pub fn resize(&mut self, vulkan_context: &VulkanContext, image_count: usize) -> Result<()> {
+        if image_count != self.image_proxies.len() {
+            let visibility = std::mem::take(&mut self.visibility);
+            *self = Self::with_capacity(vulkan_context, image_count, self.capacity)?;
+            self.visibility = visibility;
+        }
+
+        Ok(())
+    }
+
+    fn first_query(&self, image_index: usize) -> u32 {
+        image_index as u32 * self.capacity
+    }
+}
+
+/// Visibility of the entities of `proxies` from the samples counted for each of them. An entity
+/// drawn in several views is visible as soon as one of its proxies is
+fn fold_visibility(proxies: &[OcclusionProxy], samples: &[u64]) -> HashMap<Entity, bool> {
+    let mut visibility = HashMap::with_capacity(proxies.len());
+    for (proxy, samples) in proxies.iter().zip(samples) {
+        *visibility.entry(proxy.entity).or_insert(false) |= *samples > 0;
+    }
+
+    visibility
+}
+
+/// Cube from -1 to 1 on every axis, its faces wound either way as the proxies are not culled
+fn create_cube(
+    vulkan_context: &VulkanContext,
+) -> Result<(Subbuffer<[PositionVertex]>, Subbuffer<[u32]>)> {
+    let allocator = vulkan_context.standard_memory_allocator();
+
+    let allocation_info = AllocationCreateInfo {
+        memory_type_filter: MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+        ..Default::default()
+    };
+
+    let vertices = (0..8).map(|corner| PositionVertex {
+        in_position: Vec3::new(
+            if corner & 1 == 0 { -1.0 } else { 1.0 },
+            if corner & 2 == 0 { -1.0 } else { 1.0 },
+            if corner & 4 == 0 { -1.0 } else { 1.0 },
+        ),
+    });
+    let vertex_buffer = Buffer::from_iter(
+        Arc::clone(allocator),
+        BufferCreateInfo {
+            sharing: Sharing::Exclusive,
+            usage: BufferUsage::VERTEX_BUFFER,
+            ..Default::default()
+        },
+        allocation_info.clone(),
+        vertices,
+    )?;
+
+    #[rustfmt::skip]
+    let indices = [
+        0, 2, 1, 1, 2, 3, // -z
+        4, 5, 6, 5, 7, 6, // +z
+        0, 1, 4, 1, 5, 4, // -y
+        2, 6, 3, 3, 6, 7, // +y
+        0, 4, 2, 2, 4, 6, // -x
+        1, 3, 5, 3, 7, 5, // +x
+    ];
+    let index_buffer = Buffer::from_iter(
+        Arc::clone(allocator),
+        BufferCreateInfo {
+            sharing: Sharing::Exclusive,
+            usage: BufferUsage::INDEX_BUFFER,
+            ..Default::default()
+        },
+        allocation_info,
+        indices,
+    )?;
+
+    Ok((vertex_buffer, index_buffer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxy(entity: Entity, view_index: usize) -> OcclusionProxy {
+        OcclusionProxy {
+            entity,
+            view_index,
+            model: Mat4::IDENTITY,
+        }
+    }
+
+    #[test]
+    fn entities_visible_when_any_proxy_passes() {
+        let proxies = [
+            proxy(3, 0),
+            proxy(5, 0),
+            proxy(3, 1),
+            proxy(5, 1),
+            proxy(8, 1),
+        ];
+        let samples = [0, 0, 12, 0, 1];
+
+        let visibility = fold_visibility(&proxies, &samples);
+
+        assert_eq!(visibility.len(), 3);
+        assert_eq!(visibility.get(&3), Some(&true));
+        assert_eq!(visibility.get(&5), Some(&false));
+        assert_eq!(visibility.get(&8), Some(&true));
+        // Entities without a proxy are not guessed
+        assert_eq!(visibility.get(&4), None);
+    }
+
+    #[test]
+    fn no_proxies_no_visibility() {
+        assert!(fold_visibility(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn proxies_crossing_the_near_plane_are_skipped() {
+        let view = Mat4::look_at_rh(Vec3::new(0.0, 0.0, 5.0), Vec3::ZERO, Vec3::Y);
+        let bounds = (Vec3::splat(-1.0), Vec3::splat(1.0));
+
+        for reversed_z in [false, true] {
+            let projection = if reversed_z {
+                Mat4::perspective_rh(1.0, 1.0, 100.0, 0.1)
+            } else {
+                Mat4::perspective_rh(1.0, 1.0, 0.1, 100.0)
+            };
+            let view_projection = projection * view;
+            let proxy = |translation: Vec3| {
+                let model = Mat4::from_translation(translation);
+                OcclusionProxy::new(7, 0, view_projection, reversed_z, model, bounds)
+            };
+
+            assert!(proxy(Vec3::ZERO).is_some());
+            // Around the camera, then behind it
+            assert!(proxy(Vec3::new(0.0, 0.0, 4.5)).is_none());
+            assert!(proxy(Vec3::new(0.0, 0.0, 10.0)).is_none());
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use std::{mem::size_of, ptr, sync::Arc};
+
+use anyhow::Result;
+use vulkano::{
+    command_buffer::{
+        AutoCommandBufferBuilder, CommandBufferInheritanceInfo,
+        CommandBufferInheritanceRenderPassInfo, CommandBufferInheritanceRenderPassType,
+        CommandBufferUsage, SecondaryAutoCommandBuffer,
+    },
+    descriptor_set::{DescriptorSetWithOffsets, PersistentDescriptorSet},
+    pipeline::PipelineBindPoint,
+    render_pass::{Framebuffer, Subpass},
+};
+
+use crate::{
+    engine::{
+        ecs::{components::MeshComponent, Scene},
+        material::{material_manager::FragmentPushConstants, DepthBias},
+        mesh::Submesh,
+        pipeline_manager::{PipelineManager, VulkanPipeline},
+    },
+    vulkan_context::VulkanContext,
+};
+
+use super::{recording_threads, RenderView};
+
+/// Submesh with its pipeline and material resolved beforehand, so that it can be recorded from
+/// any thread
+pub(crate) struct MeshDraw<'a> {
+    pub mesh_component: &'a MeshComponent,
+    /// Index of the mesh in the meshes given to `mesh_draws`
+    pub mesh_index: usize,
+    pub submesh: Submesh,
+    pub pipeline: &'a VulkanPipeline,
+    /// Set when the depth prepass is enabled
+    pub depth_prepass_pipeline: Option<&'a VulkanPipeline>,
+    pub material_descriptor_set: &'a Arc<PersistentDescriptorSet>,
+    /// Uniform component of the mesh's entity, zeroed when it has none
+    pub entity_descriptor_set: &'a Arc<PersistentDescriptorSet>,
+    pub fragment_push_constants: &'a FragmentPushConstants,
+    pub depth_bias: DepthBias,
+}
+
+/// Submeshes drawn with their material, the pipelines of their topologies and of the materials
+/// with their own shader must have been prepared. `draw_orders` and `entity_sets` hold the draw
+/// order and the entity set of each mesh. The draws are sorted by draw order then grouped by
+/// material so that the material descriptor set only needs to be bound when it changes between
+/// two consecutive draws, within a material they keep the order of `mesh_components`
+pub(crate) fn mesh_draws<'a>(
+    scene: &'a Scene,
+    pipeline_manager: &'a PipelineManager,
+    mesh_components: &[&'a MeshComponent],
+    draw_orders: &[i32],
+    entity_sets: &'a [Arc<PersistentDescriptorSet>],
+) -> Vec<MeshDraw<'a>> {
+    let material_manager = scene.material_manager();
+    let reversed_z = pipeline_manager.settings().reversed_z;
+
+    let mut mesh_draws = mesh_components
+        .iter()
+        .zip(draw_orders)
+        .zip(entity_sets)
+        .enumerate()
+        .flat_map(
+            |(mesh_index, ((&mesh_component, &draw_order), entity_set))| {
+                mesh_component
+                    .draw_ranges()
+                    .into_iter()
+                    .map(move |submesh| {
+                        let topology = mesh_component.mesh.topology();
+                        let mesh_pipelines = pipeline_manager.mesh_pipelines(topology);
+                        let pipeline = pipeline_manager
+                            .custom_material_pipeline(submesh.material, topology)
+                            .unwrap_or(&mesh_pipelines.material);
+
+                        let mesh_draw = MeshDraw {
+                            mesh_component,
+                            mesh_index,
+                            submesh,
+                            pipeline,
+                            depth_prepass_pipeline: mesh_pipelines.depth_prepass.as_ref(),
+                            material_descriptor_set: material_manager
+                                .descriptor_set(submesh.material),
+                            entity_descriptor_set: entity_set,
+                            fragment_push_constants: material_manager
+                                .fragment_push_constants(submesh.material),
+                            depth_bias: material_manager
+                                .depth_bias(submesh.material)
+                                .for_depth_range(reversed_z),
+                        };
+
+                        (draw_order, mesh_draw)
+                    })
+            },
+        )
+        .collect::<Vec<_>>();
+    mesh_draws.sort_by_key(|(draw_order, mesh_draw)| (*draw_order, mesh_draw.submesh.material));
+
+    mesh_draws
+        .into_iter()
+        .map(|(_, mesh_draw)| mesh_draw)
+        .collect()
+}
+
+/// Draws the submeshes one by one, the pipeline and the material and entity descriptor sets are
+/// only bound when they change between two consecutive draws
+pub(crate) fn record_mesh_draws<L>(
+    builder: &mut AutoCommandBufferBuilder<L>,
+    view: &RenderView,
+    frame_descriptor_set: &Arc<PersistentDescriptorSet>,
+    mesh_draws: &[MeshDraw],
+) -> Result<()> {
+    let mut bound_pipeline: Option<&VulkanPipeline> = None;
+    let mut bound_material = None;
+    let mut bound_entity_set: Option<&Arc<PersistentDescriptorSet>> = None;
+    for mesh_draw in mesh_draws.iter() {
+        let mesh_component = mesh_draw.mesh_component;
+        let index_buffer = mesh_component.mesh.index_buffer();
+        let layout = &mesh_draw.pipeline.layout;
+
+        if !bound_pipeline.is_some_and(|pipeline| ptr::eq(pipeline, mesh_draw.pipeline)) {
+            builder
+                .bind_pipeline_graphics(Arc::clone(&mesh_draw.pipeline.pipeline))?
+                .push_constants(Arc::clone(layout), 16 * size_of::<f32>() as u32, view.view)?
+                .push_constants(
+                    Arc::clone(layout),
+                    2 * 16 * size_of::<f32>() as u32,
+                    view.projection,
+                )?
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    Arc::clone(layout),
+                    PipelineManager::FRAME_SET,
+                    vec![DescriptorSetWithOffsets::new(
+                        Arc::clone(frame_descriptor_set),
+                        [],
+                    )],
+                )?;
+
+            bound_pipeline = Some(mesh_draw.pipeline);
+            bound_material = None;
+            bound_entity_set = None;
+        }
+
+        let submesh = mesh_draw.submesh;
+        if bound_material != Some(submesh.material) {
+            let depth_bias = mesh_draw.depth_bias;
+
+            builder
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    Arc::clone(layout),
+                    0,
+                    vec![DescriptorSetWithOffsets::new(
+                        Arc::clone(mesh_draw.material_descriptor_set),
+                        [],
+                    )],
+                )?
+                .push_constants(
+                    Arc::clone(layout),
+                    PipelineManager::FRAGMENT_PUSH_CONSTANT_OFFSET,
+                    *mesh_draw.fragment_push_constants,
+                )?
+                .set_depth_bias(depth_bias.constant_factor, 0.0, depth_bias.slope_factor)?;
+
+            bound_material = Some(submesh.material);
+        }
+
+        let entity_descriptor_set = mesh_draw.entity_descriptor_set;
+        if !bound_entity_set.is_some_and(|bound| Arc::ptr_eq(bound, entity_descriptor_set)) {
+            builder.bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                Arc::clone(layout),
+                PipelineManager::ENTITY_SET,
+                vec![DescriptorSetWithOffsets::new(
+                    Arc::clone(entity_descriptor_set),
+                    [],
+                )],
+            )?;
+
+            bound_entity_set = Some(entity_descriptor_set);
+        }
+
+        mesh_component
+            .mesh
+            .bind_vertex_stream(builder, mesh_draw.pipeline.vertex_attributes)?;
+        builder
+            .bind_index_buffer(index_buffer.clone())?
+            .push_constants(Arc::clone(layout), 0, mesh_component.model.transform())?
+            .push_constants(
+                Arc::clone(layout),
+                3 * 16 * size_of::<f32>() as u32,
+                mesh_component.tint,
+            )?
+            .draw_indexed(submesh.index_count, 1, submesh.index_offset, 0, 0)?;
+    }
+
+    Ok(())
+}
+
+/// Writes the depth of the submeshes before `record_mesh_draws` shades them, does nothing when
+/// the depth prepass is disabled. The depth bias of the materials is applied in both passes so
+/// that the depths match
+pub(crate) fn record_depth_prepass<L>(
+    builder: &mut AutoCommandBufferBuilder<L>,
+    view: &RenderView,
+    mesh_draws: &[MeshDraw],
+) -> Result<()> {
+    let mut bound_pipeline: Option<&VulkanPipeline> = None;
+    let mut bound_depth_bias = None;
+    for mesh_draw in mesh_draws.iter() {
+        let Some(pipeline) = mesh_draw.depth_prepass_pipeline else {
+            continue;
+        };
+        let mesh_component = mesh_draw.mesh_component;
+        let submesh = mesh_draw.submesh;
+        let layout = &pipeline.layout;
+
+        if !bound_pipeline.is_some_and(|bound_pipeline| ptr::eq(bound_pipeline, pipeline)) {
+            builder
+                .bind_pipeline_graphics(Arc::clone(&pipeline.pipeline))?
+                .push_constants(Arc::clone(layout), 16 * size_of::<f32>() as u32, view.view)?
+                .push_constants(
+                    Arc::clone(layout),
+                    2 * 16 * size_of::<f32>() as u32,
+                    view.projection,
+                )?;
+
+            bound_pipeline = Some(pipeline);
+        }
+
+        let depth_bias = mesh_draw.depth_bias;
+        if bound_depth_bias != Some(depth_bias) {
+            builder.set_depth_bias(depth_bias.constant_factor, 0.0, depth_bias.slope_factor)?;
+            bound_depth_bias = Some(depth_bias);
+        }
+
+        // The tint is unused without colors but the vertex shader is the material one, whose
+        // whole push constant block has to be set
+        mesh_component
+            .mesh
+            .bind_vertex_stream(builder, pipeline.vertex_attributes)?;
+        builder
+            .bind_index_buffer(mesh_component.mesh.index_buffer().clone())?
+            .push_constants(Arc::clone(layout), 0, mesh_component.model.transform())?
+            .push_constants(
+                Arc::clone(layout),
+                3 * 16 * size_of::<f32>() as u32,
+                mesh_component.tint,
+            )?
+            .draw_indexed(submesh.index_count, 1, submesh.index_offset, 0, 0)?;
+    }
+
+    Ok(())
+}
+
+/// Secondary command buffer recording commands of the first subpass of `framebuffer`'s render
+/// pass. Nothing is inherited from the primary command buffer, the dynamic states have to be set
+/// again
+pub(crate) fn secondary_builder(
+    vulkan_context: &VulkanContext,
+    framebuffer: &Arc<Framebuffer>,
+) -> Result<AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>> {
+    let subpass = Subpass::from(Arc::clone(framebuffer.render_pass()), 0).unwrap();
+
+    let inheritance_info = CommandBufferInheritanceInfo {
+        render_pass: Some(CommandBufferInheritanceRenderPassType::BeginRenderPass(
+            CommandBufferInheritanceRenderPassInfo {
+                subpass,
+                framebuffer: Some(Arc::clone(framebuffer)),
+            },
+        )),
+        ..Default::default()
+    };
+
+    Ok(AutoCommandBufferBuilder::secondary(
+        vulkan_context.standard_command_buffer_allocator().as_ref(),
+        vulkan_context.graphics_queue().queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+        inheritance_info,
+    )?)
+}
+
+/// Splits the meshes in `threads` even parts and records each part into its own secondary
+/// command buffer on a worker thread. The command buffers are returned in the order of the
+/// meshes
+pub(crate) fn record_parallel(
+    vulkan_context: &VulkanContext,
+    framebuffer: &Arc<Framebuffer>,
+    view: &RenderView,
+    frame_descriptor_set: &Arc<PersistentDescriptorSet>,
+    mesh_draws: &[MeshDraw],
+    threads: usize,
+) -> Result<Vec<Arc<SecondaryAutoCommandBuffer>>> {
+    if mesh_draws.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let chunk_size = mesh_draws.len().div_ceil(threads.max(1));
+
+    let tasks = mesh_draws
+        .chunks(chunk_size)
+        .map(|chunk| {
+            Box::new(move || {
+                record_chunk(
+                    vulkan_context,
+                    framebuffer,
+                    view,
+                    frame_descriptor_set,
+                    chunk,
+                )
+            }) as Box<dyn FnOnce() -> _ + Send + '_>
+        })
+        .collect();
+
+    recording_threads::run(threads, tasks)?
+        .into_iter()
+        .collect()
+}
+
+fn record_chunk(
+    vulkan_context: &VulkanContext,
+    framebuffer: &Arc<Framebuffer>,
+    view: &RenderView,
+    frame_descriptor_set: &Arc<PersistentDescriptorSet>,
+    mesh_draws: &[MeshDraw],
+) -> Result<Arc<SecondaryAutoCommandBuffer>> {
+    let mut builder = secondary_builder(vulkan_context, framebuffer)?;
+    builder
+        .set_viewport(0, [view.viewport.clone()].into_iter().collect())?
+        .set_scissor(0, [view.scissor].into_iter().collect())?;
+
+    record_mesh_draws(&mut builder, view, frame_descriptor_set, mesh_draws)?;
+
+    Ok(builder.build()?)
+}
@@ -0,0 +1,211 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use vulkano::{
+    command_buffer::{
+        AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, RenderPassBeginInfo,
+        SubpassBeginInfo, SubpassContents, SubpassEndInfo,
+    },
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    format::Format,
+    image::{
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::ImageView,
+        ImageLayout, SampleCount,
+    },
+    pipeline::{
+        graphics::viewport::{Scissor, Viewport},
+        Pipeline, PipelineBindPoint,
+    },
+    render_pass::{
+        AttachmentDescription, AttachmentLoadOp, AttachmentReference, AttachmentStoreOp,
+        Framebuffer, FramebufferCreateInfo, RenderPass, RenderPassCreateInfo,
+        SubpassDescription,
+    },
+};
+
+use crate::{
+    engine::pipeline_manager::{self, VulkanPipeline},
+    vulkan_context::VulkanContext,
+};
+
+pub(crate) const SCENE_COLOR_BINDING: u32 = 0;
+
+/// Full screen pass reading the scene color rendered by the main pass and writing the swapchain
+/// image through a user provided fragment shader
+pub(crate) struct PostProcess {
+    render_pass: Arc<RenderPass>,
+    pipeline: VulkanPipeline,
+    sampler: Arc<Sampler>,
+    descriptor_set: Arc<PersistentDescriptorSet>,
+    framebuffers: Vec<Arc<Framebuffer>>,
+}
+
+impl PostProcess {
+    pub fn new(
+        vulkan_context: &VulkanContext,
+        swapchain_format: Format,
+        swapchain_image_views: &[Arc<ImageView>],
+        scene_color_view: &Arc<ImageView>,
+        fragment_spirv: &[u32],
+    ) -> Result<Self> {
+        let device = vulkan_context.device();
+
+        let render_pass = RenderPass::new(
+            Arc::clone(device),
+            RenderPassCreateInfo {
+                attachments: vec![AttachmentDescription {
+                    format: swapchain_format,
+                    samples: SampleCount::Sample1,
+                    // Every pixel is overwritten by the full screen triangle
+                    load_op: AttachmentLoadOp::DontCare,
+                    store_op: AttachmentStoreOp::Store,
+                    initial_layout: ImageLayout::Undefined,
+                    final_layout: ImageLayout::PresentSrc,
+                    ..Default::default()
+                }],
+                subpasses: vec![SubpassDescription {
+                    color_attachments: vec![Some(AttachmentReference {
+                        attachment: 0,
+                        layout: ImageLayout::ColorAttachmentOptimal,
+                        ..Default::default()
+                    })],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        )?;
+
+        let pipeline = pipeline_manager::load_post_process(
+            device,
+            vulkan_context.pipeline_cache(),
+            &render_pass,
+            fragment_spirv,
+        )?;
+
+        let sampler = Sampler::new(
+            Arc::clone(device),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )?;
+
+        let descriptor_set =
+            Self::create_descriptor_set(vulkan_context, &pipeline, &sampler, scene_color_view)?;
+        let framebuffers = Self::create_framebuffers(&render_pass, swapchain_image_views)?;
+
+        Ok(Self {
+            render_pass,
+            pipeline,
+            sampler,
+            descriptor_set,
+            framebuffers,
+        })
+    }
+
+    /// The swapchain images and the scene color are recreated with the window
+    pub fn resize(
+        &mut self,
+        vulkan_context: &VulkanContext,
+        swapchain_image_views: &[Arc<ImageView>],
+        scene_color_view: &Arc<ImageView>,
+    ) -> Result<()> {
+        self.descriptor_set = Self::create_descriptor_set(
+            vulkan_context,
+            &self.pipeline,
+            &self.sampler,
+            scene_color_view,
+        )?;
+        self.framebuffers = Self::create_framebuffers(&self.render_pass, swapchain_image_views)?;
+
+        Ok(())
+    }
+
+    /// Must be recorded after the main render pass has ended
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        image_index: usize,
+    ) -> Result<()> {
+        let framebuffer = &self.framebuffers[image_index];
+        let layout = &self.pipeline.layout;
+
+        let render_pass_begin_info = RenderPassBeginInfo {
+            clear_values: vec![None],
+            ..RenderPassBeginInfo::framebuffer(Arc::clone(framebuffer))
+        };
+
+        let subpass_begin_info = SubpassBeginInfo {
+            contents: SubpassContents::Inline,
+            ..Default::default()
+        };
+
+        let viewport = Viewport {
+            offset: [0.0, 0.0],
+            extent: framebuffer.extent().map(|x| x as f32),
+            depth_range: 0.0..=1.0,
+        };
+
+        let scissor = Scissor {
+            offset: [0, 0],
+            extent: framebuffer.extent(),
+        };
+
+        builder
+            .begin_render_pass(render_pass_begin_info, subpass_begin_info)?
+            .set_viewport(0, [viewport].into_iter().collect())?
+            .set_scissor(0, [scissor].into_iter().collect())?
+            .bind_pipeline_graphics(Arc::clone(&self.pipeline.pipeline))?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                Arc::clone(layout),
+                0,
+                Arc::clone(&self.descriptor_set),
+            )?
+            .draw(3, 1, 0, 0)?
+            .end_render_pass(SubpassEndInfo::default())?;
+
+        Ok(())
+    }
+
+    fn create_descriptor_set(
+        vulkan_context: &VulkanContext,
+        pipeline: &VulkanPipeline,
+        sampler: &Arc<Sampler>,
+        scene_color_view: &Arc<ImageView>,
+    ) -> Result<Arc<PersistentDescriptorSet>> {
+        let descriptor_set = PersistentDescriptorSet::new(
+            vulkan_context.standard_descripor_set_allocator().as_ref(),
+            Arc::clone(&pipeline.pipeline.layout().set_layouts()[0]),
+            [WriteDescriptorSet::image_view_sampler(
+                SCENE_COLOR_BINDING,
+                Arc::clone(scene_color_view),
+                Arc::clone(sampler),
+            )],
+            [],
+        )?;
+
+        Ok(descriptor_set)
+    }
+
+    fn create_framebuffers(
+        render_pass: &Arc<RenderPass>,
+        swapchain_image_views: &[Arc<ImageView>],
+    ) -> Result<Vec<Arc<Framebuffer>>> {
+        let mut framebuffers = Vec::new();
+
+        for image_view in swapchain_image_views.iter() {
+            let framebuffer_info = FramebufferCreateInfo {
+                attachments: vec![Arc::clone(image_view)],
+                ..Default::default()
+            };
+
+            framebuffers.push(Framebuffer::new(Arc::clone(render_pass), framebuffer_info)?);
+        }
+
+        Ok(framebuffers)
+    }
+}
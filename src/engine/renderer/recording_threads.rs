@@ -0,0 +1,122 @@
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    sync::Mutex,
+    thread,
+};
+
+use anyhow::{anyhow, Result};
+
+type Task<'a, T> = Box<dyn FnOnce() -> T + Send + 'a>;
+
+/// Runs every task on up to `threads` threads and returns their results in the order of `tasks`.
+/// The threads are scoped to the call and joined before this returns, so the tasks may borrow
+/// the frame data. Only a few threads are started per frame, next to the many meshes they record.
+/// A panicking task gives an error instead of its result
+pub(crate) fn run<'a, T: Send + 'a>(threads: usize, tasks: Vec<Task<'a, T>>) -> Result<Vec<T>> {
+    let task_count = tasks.len();
+    let tasks = Mutex::new(tasks.into_iter().enumerate());
+
+    let worker_results = thread::scope(|scope| {
+        let workers = (0..threads.max(1).min(task_count))
+            .map(|i| {
+                thread::Builder::new()
+                    .name(format!("Command buffer recording {i}"))
+                    .spawn_scoped(scope, || run_worker(&tasks))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        workers
+            .into_iter()
+            .map(|worker| {
+                worker.join().map_err(|_| {
+                    anyhow!("The command buffer recording threads stopped unexpectedly")
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+    })?;
+
+    let mut results = (0..task_count).map(|_| None).collect::<Vec<_>>();
+    let mut panic_message = None;
+    for (index, result) in worker_results.into_iter().flatten() {
+        match result {
+            Ok(result) => results[index] = Some(result),
+            Err(payload) => panic_message = Some(panic_payload_message(&*payload)),
+        }
+    }
+
+    if let Some(message) = panic_message {
+        return Err(anyhow!(
+            "A command buffer recording thread panicked: {message}"
+        ));
+    }
+
+    results
+        .into_iter()
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| anyhow!("The command buffer recording threads stopped unexpectedly"))
+}
+
+/// Runs the tasks left until there are none, with the index of each task
+fn run_worker<'a, T>(
+    tasks: &Mutex<impl Iterator<Item = (usize, Task<'a, T>)>>,
+) -> Vec<(usize, thread::Result<T>)> {
+    let mut results = Vec::new();
+    loop {
+        // The lock is released before running the task so that the other threads can take theirs
+        let task = tasks.lock().unwrap().next();
+        match task {
+            Some((index, task)) => {
+                results.push((index, panic::catch_unwind(AssertUnwindSafe(task))));
+            }
+            None => break,
+        }
+    }
+
+    results
+}
+
+fn panic_payload_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("unknown panic payload")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_keep_the_order_of_the_tasks() {
+        let values = (0..10).collect::<Vec<u32>>();
+
+        let tasks = values
+            .iter()
+            .map(|value| Box::new(move || value * 2) as Box<dyn FnOnce() -> u32 + Send + '_>)
+            .collect();
+
+        assert_eq!(
+            run(3, tasks).unwrap(),
+            values.iter().map(|value| value * 2).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn panicking_task_gives_an_error() {
+        let tasks: Vec<Box<dyn FnOnce() -> u32 + Send>> =
+            vec![Box::new(|| 1), Box::new(|| panic!("recording failed"))];
+        let error = run(2, tasks).unwrap_err();
+        assert!(error.to_string().contains("recording failed"));
+    }
+
+    #[test]
+    fn zero_threads_still_run_the_tasks() {
+        let tasks: Vec<Box<dyn FnOnce() -> u32 + Send>> = vec![Box::new(|| 1), Box::new(|| 2)];
+
+        assert_eq!(run(0, tasks).unwrap(), vec![1, 2]);
+    }
+}
@@ -0,0 +1,185 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use vulkano::{
+    format::Format,
+    image::{
+        sampler::ComponentMapping,
+        view::{ImageView, ImageViewCreateInfo, ImageViewType},
+        Image, ImageAspects, ImageCreateInfo, ImageLayout, ImageSubresourceRange, ImageType,
+        ImageUsage, SampleCount,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryAllocatePreference, MemoryTypeFilter},
+    sync::Sharing,
+};
+
+use crate::vulkan_context::VulkanContext;
+
+pub(crate) const DEPTH_FORMAT: Format = Format::D32_SFLOAT;
+/// Format of the scene color read by the post process pass, in linear space with enough range
+/// for tone mapping
+pub(crate) const SCENE_COLOR_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+/// Attachments of the main render pass other than the swapchain image, they are recreated with
+/// the swapchain
+pub(crate) struct RenderTargets {
+    depth_image_view: Arc<ImageView>,
+    /// Multisampled color attachment, resolved into the swapchain image at the end of the pass
+    color_image_view: Option<Arc<ImageView>>,
+    /// Single sampled copy of the depth buffer that can be read after the main pass
+    resolved_depth_image_view: Option<Arc<ImageView>>,
+    /// Replaces the swapchain image as the color target of the main pass when post processing
+    scene_color_image_view: Option<Arc<ImageView>>,
+}
+
+impl RenderTargets {
+    pub fn new(
+        vulkan_context: &VulkanContext,
+        extent: [u32; 2],
+        color_format: Format,
+        samples: SampleCount,
+        resolve_depth: bool,
+        post_process: bool,
+    ) -> Result<Self> {
+        let multisampled = samples != SampleCount::Sample1;
+
+        let scene_color_image_view = if post_process {
+            Some(create_attachment(
+                vulkan_context,
+                extent,
+                SCENE_COLOR_FORMAT,
+                SampleCount::Sample1,
+                ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED,
+                ImageAspects::COLOR,
+            )?)
+        } else {
+            None
+        };
+        // The multisampled colors are resolved into the scene color instead of the swapchain
+        let color_format = if post_process {
+            SCENE_COLOR_FORMAT
+        } else {
+            color_format
+        };
+
+        // The depth buffer can always be sampled, without multisampling it also serves as the
+        // resolved depth
+        let depth_image_view = create_attachment(
+            vulkan_context,
+            extent,
+            DEPTH_FORMAT,
+            samples,
+            ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::SAMPLED,
+            ImageAspects::DEPTH,
+        )?;
+
+        let color_image_view = if multisampled {
+            Some(create_attachment(
+                vulkan_context,
+                extent,
+                color_format,
+                samples,
+                ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
+                ImageAspects::COLOR,
+            )?)
+        } else {
+            None
+        };
+
+        let resolved_depth_image_view = match (resolve_depth, multisampled) {
+            (true, true) => Some(create_attachment(
+                vulkan_context,
+                extent,
+                DEPTH_FORMAT,
+                SampleCount::Sample1,
+                ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::SAMPLED,
+                ImageAspects::DEPTH,
+            )?),
+            (true, false) => Some(Arc::clone(&depth_image_view)),
+            (false, _) => None,
+        };
+
+        Ok(Self {
+            depth_image_view,
+            color_image_view,
+            resolved_depth_image_view,
+            scene_color_image_view,
+        })
+    }
+
+    pub fn depth_image_view(&self) -> &Arc<ImageView> {
+        &self.depth_image_view
+    }
+
+    pub fn resolved_depth_image_view(&self) -> Option<&Arc<ImageView>> {
+        self.resolved_depth_image_view.as_ref()
+    }
+
+    pub fn scene_color_image_view(&self) -> Option<&Arc<ImageView>> {
+        self.scene_color_image_view.as_ref()
+    }
+
+    /// Framebuffer attachments following the swapchain image, in render pass order
+    pub fn attachments(&self) -> Vec<Arc<ImageView>> {
+        let mut attachments = vec![Arc::clone(&self.depth_image_view)];
+
+        if let Some(color_image_view) = &self.color_image_view {
+            attachments.push(Arc::clone(color_image_view));
+
+            if let Some(resolved_depth_image_view) = &self.resolved_depth_image_view {
+                attachments.push(Arc::clone(resolved_depth_image_view));
+            }
+        }
+
+        attachments
+    }
+}
+
+fn create_attachment(
+    vulkan_context: &VulkanContext,
+    extent: [u32; 2],
+    format: Format,
+    samples: SampleCount,
+    usage: ImageUsage,
+    aspects: ImageAspects,
+) -> Result<Arc<ImageView>> {
+    let allocator = Arc::clone(vulkan_context.standard_memory_allocator());
+
+    let image = Image::new(
+        allocator,
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format,
+            view_formats: vec![format],
+            extent: [extent[0], extent[1], 1],
+            samples,
+            usage,
+            sharing: Sharing::Exclusive,
+            initial_layout: ImageLayout::Undefined,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+            allocate_preference: MemoryAllocatePreference::AlwaysAllocate,
+            ..Default::default()
+        },
+    )?;
+
+    let image_view = ImageView::new(
+        Arc::clone(&image),
+        ImageViewCreateInfo {
+            view_type: ImageViewType::Dim2d,
+            format: image.format(),
+            component_mapping: ComponentMapping::identity(),
+            subresource_range: ImageSubresourceRange {
+                aspects,
+                mip_levels: 0..1,
+                array_layers: 0..1,
+            },
+            usage,
+            ..Default::default()
+        },
+    )?;
+
+    Ok(image_view)
+}
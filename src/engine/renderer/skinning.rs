@@ -0,0 +1,122 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use glam::Mat4;
+use vulkano::{
+    buffer::{
+        allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo},
+        BufferUsage,
+    },
+    descriptor_set::{layout::DescriptorSetLayout, PersistentDescriptorSet, WriteDescriptorSet},
+    memory::allocator::MemoryTypeFilter,
+};
+
+use crate::{
+    engine::{ecs::components::SkinnedMeshComponent, mesh::MAX_BONES},
+    vulkan_context::VulkanContext,
+};
+
+/// The bone matrices use the same single storage buffer layout as the objects of indirect draws
+pub(crate) const BONE_BINDING: u32 = 0;
+
+/// Poses of every skinned mesh of a frame, packed into a single storage buffer
+pub(crate) struct FrameBones {
+    pub descriptor_set: Arc<PersistentDescriptorSet>,
+    /// Index of the first bone matrix of each mesh, pushed as its bone offset
+    pub bone_offsets: Vec<u32>,
+}
+
+/// Allocator of the poses uploaded every frame, its arenas are reused once the frames reading
+/// them are done
+pub(crate) fn create_bone_allocator(vulkan_context: &VulkanContext) -> SubbufferAllocator {
+    SubbufferAllocator::new(
+        Arc::clone(vulkan_context.standard_memory_allocator()),
+        SubbufferAllocatorCreateInfo {
+            buffer_usage: BufferUsage::STORAGE_BUFFER,
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+    )
+}
+
+/// Uploads the poses of the skinned meshes for the current frame. Returns `None` when there is
+/// nothing to draw
+pub(crate) fn upload_bones(
+    vulkan_context: &VulkanContext,
+    bone_allocator: &SubbufferAllocator,
+    bone_set_layout: &Arc<DescriptorSetLayout>,
+    skinned_meshes: &[&SkinnedMeshComponent],
+) -> Result<Option<FrameBones>> {
+    if skinned_meshes.is_empty() {
+        return Ok(None);
+    }
+
+    let mut bone_offsets = Vec::with_capacity(skinned_meshes.len());
+    let mut bone_count = 0;
+    for skinned_mesh in skinned_meshes.iter() {
+        check_bone_count(
+            skinned_mesh.bone_matrices.len(),
+            skinned_mesh.mesh.bone_count(),
+        )?;
+
+        bone_offsets.push(bone_count as u32);
+        bone_count += skinned_mesh.bone_matrices.len();
+    }
+
+    let bones = bone_allocator.allocate_slice::<Mat4>(bone_count as u64)?;
+    {
+        let mut bone_writer = bones.write()?;
+        for (skinned_mesh, bone_offset) in skinned_meshes.iter().zip(bone_offsets.iter()) {
+            let start = *bone_offset as usize;
+            bone_writer[start..start + skinned_mesh.bone_matrices.len()]
+                .copy_from_slice(&skinned_mesh.bone_matrices);
+        }
+    }
+
+    let descriptor_set = PersistentDescriptorSet::new(
+        vulkan_context.standard_descripor_set_allocator().as_ref(),
+        Arc::clone(bone_set_layout),
+        [WriteDescriptorSet::buffer(BONE_BINDING, bones)],
+        [],
+    )?;
+
+    Ok(Some(FrameBones {
+        descriptor_set,
+        bone_offsets,
+    }))
+}
+
+/// Fails when a pose has no bone, more than `MAX_BONES` or fewer than its mesh references
+fn check_bone_count(bone_count: usize, mesh_bone_count: u32) -> Result<()> {
+    anyhow::ensure!(
+        bone_count > 0,
+        "Skinned meshes need at least one bone matrix"
+    );
+    anyhow::ensure!(
+        bone_count <= MAX_BONES as usize,
+        "Skinned meshes have at most {MAX_BONES} bone matrices, got {bone_count}"
+    );
+    anyhow::ensure!(
+        bone_count >= mesh_bone_count as usize,
+        "The mesh references {mesh_bone_count} bones but the pose only has {bone_count}"
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bone_count_limits() {
+        assert!(check_bone_count(1, 1).is_ok());
+        assert!(check_bone_count(MAX_BONES as usize, 4).is_ok());
+
+        assert!(check_bone_count(0, 0).is_err());
+        assert!(check_bone_count(MAX_BONES as usize + 1, 4).is_err());
+        // The pose covers every bone of the mesh
+        assert!(check_bone_count(3, 4).is_err());
+    }
+}
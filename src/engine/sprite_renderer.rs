@@ -0,0 +1,264 @@
+use std::{ops::Range, path::Path, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use glam::{Vec2, Vec3, Vec4};
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    descriptor_set::{layout::DescriptorSetLayout, PersistentDescriptorSet, WriteDescriptorSet},
+    format::Format,
+    image::sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    pipeline::graphics::vertex_input,
+    sync::Sharing,
+};
+
+use crate::vulkan_context::VulkanContext;
+
+use super::{
+    ecs::components::{Sprite, SpriteSpace},
+    mesh::{obj::MeshData, primitives},
+    texture,
+};
+
+#[derive(BufferContents, vertex_input::Vertex, Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub(crate) struct SpriteVertex {
+    #[format(R32G32B32_SFLOAT)]
+    pub in_position: Vec3,
+
+    #[format(R32G32_SFLOAT)]
+    pub in_texture_coord: Vec2,
+
+    #[format(R32G32B32A32_SFLOAT)]
+    pub in_color: Vec4,
+}
+
+/// Consecutive sprite vertices sharing a texture, drawn with a single command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SpriteBatch {
+    pub texture: u64,
+    pub vertices: Range<u32>,
+}
+
+/// Sprites of one space uploaded for the current frame
+pub(crate) struct SpriteBatches {
+    pub vertex_buffer: Option<Subbuffer<[SpriteVertex]>>,
+    pub batches: Vec<SpriteBatch>,
+}
+
+/// Textures of the sprites and the quad they are made of
+pub(crate) struct SpriteRenderer {
+    texture_set_layout: Arc<DescriptorSetLayout>,
+    sampler: Arc<Sampler>,
+    textures: Vec<Arc<PersistentDescriptorSet>>,
+    quad: MeshData,
+}
+
+impl SpriteRenderer {
+    pub const TEXTURE_BINDING: u32 = 0;
+
+    /// `texture_set_layout` must hold a single combined image sampler at `TEXTURE_BINDING`
+    pub(crate) fn new(
+        vulkan_context: &VulkanContext,
+        texture_set_layout: Arc<DescriptorSetLayout>,
+    ) -> Result<Self> {
+        // Nearest filtering keeps pixel art sharp and does not bleed between atlas regions
+        let sampler = Sampler::new(
+            Arc::clone(vulkan_context.device()),
+            SamplerCreateInfo {
+                mag_filter: Filter::Nearest,
+                min_filter: Filter::Nearest,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )?;
+
+        Ok(Self {
+            texture_set_layout,
+            sampler,
+            textures: Vec::new(),
+            quad: primitives::quad_xy_data(),
+        })
+    }
+
+    /// Loads an image as a sprite texture and returns its id
+    pub(crate) fn load_texture(
+        &mut self,
+        vulkan_context: &VulkanContext,
+        path: &Path,
+    ) -> Result<u64> {
+        let image = image::open(path)
+            .map_err(|error| anyhow!("Failed to load sprite texture {:?}: {}", path, error))?
+            .into_rgba8();
+        let (width, height) = image.dimensions();
+
+        let texture_view = texture::create_texture(
+            vulkan_context,
+            Format::R8G8B8A8_SRGB,
+            [width, height],
+            image.into_raw(),
+        )?;
+
+        let descriptor_set = PersistentDescriptorSet::new(
+            vulkan_context.standard_descripor_set_allocator().as_ref(),
+            Arc::clone(&self.texture_set_layout),
+            vec![WriteDescriptorSet::image_view_sampler(
+                Self::TEXTURE_BINDING,
+                texture_view,
+                Arc::clone(&self.sampler),
+            )],
+            Vec::new(),
+        )?;
+
+        self.textures.push(descriptor_set);
+
+        Ok(self.textures.len() as u64 - 1)
+    }
+
+    pub(crate) fn descriptor_set(&self, texture: u64) -> &Arc<PersistentDescriptorSet> {
+        match self.textures.get(texture as usize) {
+            Some(descriptor_set) => descriptor_set,
+            None => panic!("Sprite texture {texture} does not exist"),
+        }
+    }
+
+    /// Uploads the sprites of `space` into a vertex buffer, in the order they are given
+    pub(crate) fn build_batches(
+        &self,
+        vulkan_context: &VulkanContext,
+        sprites: &[&Sprite],
+        space: SpriteSpace,
+    ) -> Result<SpriteBatches> {
+        let (vertices, batches) = batch_sprites(&self.quad, sprites, space);
+        if vertices.is_empty() {
+            return Ok(SpriteBatches {
+                vertex_buffer: None,
+                batches,
+            });
+        }
+
+        let vertex_buffer = Buffer::from_iter(
+            Arc::clone(vulkan_context.standard_memory_allocator()),
+            BufferCreateInfo {
+                sharing: Sharing::Exclusive,
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            vertices,
+        )?;
+
+        Ok(SpriteBatches {
+            vertex_buffer: Some(vertex_buffer),
+            batches,
+        })
+    }
+}
+
+/// Expands the sprites of `space` into triangles of `quad`, transformed on the CPU so that they
+/// can all be drawn from the same vertex buffer
+fn batch_sprites(
+    quad: &MeshData,
+    sprites: &[&Sprite],
+    space: SpriteSpace,
+) -> (Vec<SpriteVertex>, Vec<SpriteBatch>) {
+    let mut vertices = Vec::new();
+    let mut batches: Vec<SpriteBatch> = Vec::new();
+
+    for sprite in sprites.iter().filter(|sprite| sprite.space == space) {
+        let model = sprite.model.transform();
+        let first_vertex = vertices.len() as u32;
+
+        for index in quad.indices.iter() {
+            let corner = &quad.vertices[*index as usize];
+
+            // The quad's texture coordinates grow with y, which points up in the world but down
+            // on the screen, while the sprite's ones start at the top of the image
+            let mut uv = corner.in_texture_coord;
+            if space == SpriteSpace::World {
+                uv.y = 1.0 - uv.y;
+            }
+
+            vertices.push(SpriteVertex {
+                in_position: model.transform_point3(corner.in_position),
+                in_texture_coord: sprite.uv_min + uv * (sprite.uv_max - sprite.uv_min),
+                in_color: sprite.color,
+            });
+        }
+
+        let last_vertex = vertices.len() as u32;
+        match batches.last_mut() {
+            Some(batch) if batch.texture == sprite.texture => batch.vertices.end = last_vertex,
+            _ => batches.push(SpriteBatch {
+                texture: sprite.texture,
+                vertices: first_vertex..last_vertex,
+            }),
+        }
+    }
+
+    (vertices, batches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn consecutive_sprites_sharing_a_texture_are_batched() {
+        let quad = primitives::quad_xy_data();
+        let sprites = [
+            Sprite::new(0, SpriteSpace::World),
+            Sprite::new(0, SpriteSpace::World),
+            Sprite::new(1, SpriteSpace::World),
+            Sprite::new(0, SpriteSpace::Screen),
+            Sprite::new(0, SpriteSpace::World),
+        ];
+        let sprites = sprites.iter().collect::<Vec<_>>();
+
+        let (vertices, batches) = batch_sprites(&quad, &sprites, SpriteSpace::World);
+
+        assert_eq!(vertices.len(), 4 * 6);
+        assert_eq!(
+            batches,
+            vec![
+                SpriteBatch {
+                    texture: 0,
+                    vertices: 0..12
+                },
+                SpriteBatch {
+                    texture: 1,
+                    vertices: 12..18
+                },
+                SpriteBatch {
+                    texture: 0,
+                    vertices: 18..24
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn sprite_region_is_upright() {
+        let quad = primitives::quad_xy_data();
+        let mut sprite = Sprite::new(0, SpriteSpace::World);
+        sprite.uv_min = Vec2::new(0.5, 0.0);
+        sprite.uv_max = Vec2::new(1.0, 0.5);
+        sprite.model.scale(Vec3::new(2.0, 2.0, 1.0));
+
+        let (vertices, _) = batch_sprites(&quad, &[&sprite], SpriteSpace::World);
+
+        // The top left corner of the quad shows the top left corner of the region
+        let top_left = vertices
+            .iter()
+            .find(|vertex| vertex.in_position == Vec3::new(-1.0, 1.0, 0.0))
+            .unwrap();
+        assert_eq!(top_left.in_texture_coord, Vec2::new(0.5, 0.0));
+
+        let (vertices, _) = batch_sprites(&quad, &[&sprite], SpriteSpace::Screen);
+        assert!(vertices.is_empty());
+    }
+}
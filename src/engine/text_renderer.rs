@@ -0,0 +1,421 @@
+use std::{path::Path, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use glam::{Vec2, Vec3};
+use vulkano::{
+    buffer::BufferContents,
+    descriptor_set::{
+        layout::{
+            DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorSetLayoutCreateInfo,
+            DescriptorType,
+        },
+        PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::Device,
+    format::Format,
+    image::sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+    pipeline::graphics::vertex_input,
+    shader::ShaderStages,
+};
+
+use crate::vulkan_context::VulkanContext;
+
+use super::texture;
+
+const FIRST_GLYPH: char = ' ';
+const LAST_GLYPH: char = '~';
+const ATLAS_WIDTH: u32 = 512;
+/// Largest image dimension every Vulkan device supports
+const MAX_ATLAS_HEIGHT: u32 = 4096;
+
+#[derive(BufferContents, vertex_input::Vertex, Clone, Copy)]
+#[repr(C)]
+pub(crate) struct TextVertex {
+    #[format(R32G32_SFLOAT)]
+    pub in_position: Vec2,
+
+    #[format(R32G32_SFLOAT)]
+    pub in_texture_coord: Vec2,
+
+    #[format(R32G32B32_SFLOAT)]
+    pub in_color: Vec3,
+}
+
+struct Glyph {
+    uv_min: Vec2,
+    uv_max: Vec2,
+    size: Vec2,
+    offset: Vec2,
+    advance: f32,
+}
+
+struct FontLayout {
+    glyphs: Vec<Glyph>,
+    ascent: f32,
+    line_height: f32,
+}
+
+struct FontAtlas {
+    layout: FontLayout,
+    descriptor_set: Arc<PersistentDescriptorSet>,
+}
+
+/// Screen space text drawn with a glyph atlas of the printable ASCII characters
+pub(crate) struct TextRenderer {
+    text_set_layout: Arc<DescriptorSetLayout>,
+    font: Option<FontAtlas>,
+    vertices: Vec<TextVertex>,
+}
+
+impl FontLayout {
+    fn glyph(&self, c: char) -> Option<&Glyph> {
+        if !(FIRST_GLYPH..=LAST_GLYPH).contains(&c) {
+            return None;
+        }
+
+        self.glyphs.get(c as usize - FIRST_GLYPH as usize)
+    }
+
+    /// Appends the quads of `text` to `vertices`, `position` being the top left corner of the
+    /// first line in pixels
+    fn layout_text(
+        &self,
+        text: &str,
+        position: Vec2,
+        scale: f32,
+        color: Vec3,
+        vertices: &mut Vec<TextVertex>,
+    ) {
+        let mut pen = Vec2::new(position.x, position.y + self.ascent * scale);
+        for c in text.chars() {
+            if c == '\n' {
+                pen.x = position.x;
+                pen.y += self.line_height * scale;
+                continue;
+            }
+
+            let Some(glyph) = self.glyph(c) else {
+                continue;
+            };
+
+            if glyph.size.x > 0.0 && glyph.size.y > 0.0 {
+                // Glyph offsets are relative to the baseline with y pointing up
+                let min = Vec2::new(
+                    pen.x + glyph.offset.x * scale,
+                    pen.y - (glyph.offset.y + glyph.size.y) * scale,
+                );
+                let max = min + glyph.size * scale;
+
+                let vertex = |position: Vec2, texture_coord: Vec2| TextVertex {
+                    in_position: position,
+                    in_texture_coord: texture_coord,
+                    in_color: color,
+                };
+
+                let top_left = vertex(min, glyph.uv_min);
+                let top_right = vertex(
+                    Vec2::new(max.x, min.y),
+                    Vec2::new(glyph.uv_max.x, glyph.uv_min.y),
+                );
+                let bottom_left = vertex(
+                    Vec2::new(min.x, max.y),
+                    Vec2::new(glyph.uv_min.x, glyph.uv_max.y),
+                );
+                let bottom_right = vertex(max, glyph.uv_max);
+
+                vertices.extend([
+                    top_left,
+                    bottom_left,
+                    top_right,
+                    top_right,
+                    bottom_left,
+                    bottom_right,
+                ]);
+            }
+
+            pen.x += glyph.advance * scale;
+        }
+    }
+}
+
+impl TextRenderer {
+    pub const GLYPH_ATLAS_BINDING: u32 = 0;
+
+    pub(crate) fn new(device: Arc<Device>) -> Self {
+        let text_set_layout = {
+            let set_info = DescriptorSetLayoutCreateInfo {
+                bindings: [(
+                    Self::GLYPH_ATLAS_BINDING,
+                    DescriptorSetLayoutBinding {
+                        descriptor_count: 1,
+                        stages: ShaderStages::FRAGMENT,
+                        ..DescriptorSetLayoutBinding::descriptor_type(
+                            DescriptorType::CombinedImageSampler,
+                        )
+                    },
+                )]
+                .into_iter()
+                .collect(),
+                ..Default::default()
+            };
+
+            DescriptorSetLayout::new(device, set_info)
+                .expect("Failed to create descriptor set layout")
+        };
+
+        Self {
+            text_set_layout,
+            font: None,
+            vertices: Vec::new(),
+        }
+    }
+
+    pub(crate) fn text_set_layout(&self) -> &Arc<DescriptorSetLayout> {
+        &self.text_set_layout
+    }
+
+    pub(crate) fn load_font(
+        &mut self,
+        vulkan_context: &VulkanContext,
+        path: &Path,
+        pixel_size: f32,
+    ) -> Result<()> {
+        let font_data = std::fs::read(path)?;
+        let font = fontdue::Font::from_bytes(font_data, fontdue::FontSettings::default())
+            .map_err(|error| anyhow!("Failed to parse font {:?}: {}", path, error))?;
+        let line_metrics = font
+            .horizontal_line_metrics(pixel_size)
+            .ok_or_else(|| anyhow!("Font {:?} has no horizontal line metrics", path))?;
+
+        let rasterized_glyphs = (FIRST_GLYPH..=LAST_GLYPH)
+            .map(|c| font.rasterize(c, pixel_size))
+            .collect::<Vec<_>>();
+
+        let glyph_sizes = rasterized_glyphs
+            .iter()
+            .map(|(metrics, _)| (metrics.width as u32, metrics.height as u32))
+            .collect::<Vec<_>>();
+        let (placements, atlas_height) = pack_glyphs(&glyph_sizes)
+            .map_err(|error| anyhow!("Font {:?} at {} pixels: {}", path, pixel_size, error))?;
+
+        let mut pixels = vec![0; (ATLAS_WIDTH * atlas_height) as usize];
+        for ((metrics, bitmap), (x, y)) in rasterized_glyphs.iter().zip(placements.iter()) {
+            for row in 0..metrics.height {
+                let start = ((y + row as u32) * ATLAS_WIDTH + x) as usize;
+                pixels[start..start + metrics.width]
+                    .copy_from_slice(&bitmap[row * metrics.width..(row + 1) * metrics.width]);
+            }
+        }
+
+        let atlas_size = Vec2::new(ATLAS_WIDTH as f32, atlas_height as f32);
+        let glyphs = rasterized_glyphs
+            .iter()
+            .zip(placements.iter())
+            .map(|((metrics, _), (x, y))| {
+                let size = Vec2::new(metrics.width as f32, metrics.height as f32);
+                let uv_min = Vec2::new(*x as f32, *y as f32) / atlas_size;
+
+                Glyph {
+                    uv_min,
+                    uv_max: uv_min + size / atlas_size,
+                    size,
+                    offset: Vec2::new(metrics.xmin as f32, metrics.ymin as f32),
+                    advance: metrics.advance_width,
+                }
+            })
+            .collect();
+
+        let atlas_view = texture::create_texture(
+            vulkan_context,
+            Format::R8_UNORM,
+            [ATLAS_WIDTH, atlas_height],
+            pixels,
+        )?;
+
+        let sampler = Sampler::new(
+            Arc::clone(vulkan_context.device()),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )?;
+
+        let descriptor_set = PersistentDescriptorSet::new(
+            vulkan_context.standard_descripor_set_allocator().as_ref(),
+            Arc::clone(&self.text_set_layout),
+            vec![WriteDescriptorSet::image_view_sampler(
+                Self::GLYPH_ATLAS_BINDING,
+                atlas_view,
+                sampler,
+            )],
+            Vec::new(),
+        )?;
+
+        self.font = Some(FontAtlas {
+            layout: FontLayout {
+                glyphs,
+                ascent: line_metrics.ascent,
+                line_height: line_metrics.new_line_size,
+            },
+            descriptor_set,
+        });
+
+        Ok(())
+    }
+
+    /// Queues the quads of `text` for the current frame, `position` being the top left corner of
+    /// the first line in pixels
+    pub(crate) fn queue_text(&mut self, text: &str, position: Vec2, scale: f32, color: Vec3) {
+        let Some(font) = &self.font else {
+            return;
+        };
+
+        font.layout
+            .layout_text(text, position, scale, color, &mut self.vertices);
+    }
+
+    pub(crate) fn vertices(&self) -> &[TextVertex] {
+        &self.vertices
+    }
+
+    pub(crate) fn descriptor_set(&self) -> Option<&Arc<PersistentDescriptorSet>> {
+        self.font.as_ref().map(|font| &font.descriptor_set)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.vertices.clear();
+    }
+}
+
+/// Packs the glyphs of the given sizes row by row in the atlas, with one pixel of padding to avoid
+/// bleeding when sampling. Returns the top left corner of each glyph and the height of the atlas.
+/// Fails if a glyph is wider than the atlas or if the rows don't fit in its largest height
+fn pack_glyphs(glyph_sizes: &[(u32, u32)]) -> Result<(Vec<(u32, u32)>, u32)> {
+    let mut placements = Vec::with_capacity(glyph_sizes.len());
+    let (mut x, mut y, mut row_height) = (0, 0, 0);
+    for &(width, height) in glyph_sizes {
+        if width > ATLAS_WIDTH {
+            return Err(anyhow!(
+                "A glyph of {} pixels is wider than the {} pixels of the glyph atlas",
+                width,
+                ATLAS_WIDTH
+            ));
+        }
+
+        if x + width > ATLAS_WIDTH {
+            x = 0;
+            y += row_height + 1;
+            row_height = 0;
+        }
+
+        placements.push((x, y));
+        x += width + 1;
+        row_height = row_height.max(height);
+    }
+
+    let atlas_height = (y + row_height).max(1);
+    if atlas_height > MAX_ATLAS_HEIGHT {
+        return Err(anyhow!(
+            "The glyphs need a glyph atlas of {} pixels high, more than the {} pixels supported",
+            atlas_height,
+            MAX_ATLAS_HEIGHT
+        ));
+    }
+
+    Ok((placements, atlas_height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glyph(size: Vec2, offset: Vec2, advance: f32) -> Glyph {
+        Glyph {
+            uv_min: Vec2::ZERO,
+            uv_max: Vec2::ONE,
+            size,
+            offset,
+            advance,
+        }
+    }
+
+    /// Every printable glyph is a 10x10 square sitting on the baseline and advancing by 12
+    /// pixels, except the empty space
+    fn square_font() -> FontLayout {
+        let glyphs = (FIRST_GLYPH..=LAST_GLYPH)
+            .map(|c| match c {
+                ' ' => glyph(Vec2::ZERO, Vec2::ZERO, 12.0),
+                _ => glyph(Vec2::splat(10.0), Vec2::ZERO, 12.0),
+            })
+            .collect();
+
+        FontLayout {
+            glyphs,
+            ascent: 8.0,
+            line_height: 16.0,
+        }
+    }
+
+    #[test]
+    fn glyphs_wrap_to_a_new_row_with_padding() {
+        let (placements, atlas_height) =
+            pack_glyphs(&[(300, 10), (200, 20), (100, 5), (50, 8)]).unwrap();
+
+        assert_eq!(placements, vec![(0, 0), (301, 0), (0, 21), (101, 21)]);
+        assert_eq!(atlas_height, 29);
+    }
+
+    #[test]
+    fn empty_glyphs_give_a_one_pixel_atlas() {
+        let (placements, atlas_height) = pack_glyphs(&[(0, 0), (0, 0)]).unwrap();
+
+        assert_eq!(placements, vec![(0, 0), (1, 0)]);
+        assert_eq!(atlas_height, 1);
+    }
+
+    #[test]
+    fn glyph_wider_than_the_atlas_is_an_error() {
+        assert!(pack_glyphs(&[(ATLAS_WIDTH, 10)]).is_ok());
+        assert!(pack_glyphs(&[(10, 10), (ATLAS_WIDTH + 1, 10)]).is_err());
+    }
+
+    #[test]
+    fn rows_taller_than_the_atlas_are_an_error() {
+        assert!(pack_glyphs(&[(ATLAS_WIDTH, MAX_ATLAS_HEIGHT)]).is_ok());
+
+        let glyph_sizes = vec![(ATLAS_WIDTH, MAX_ATLAS_HEIGHT / 2); 2];
+        assert!(pack_glyphs(&glyph_sizes).is_err());
+    }
+
+    #[test]
+    fn glyph_quads_sit_on_the_baseline() {
+        let mut vertices = Vec::new();
+        square_font().layout_text("A", Vec2::new(5.0, 20.0), 2.0, Vec3::ONE, &mut vertices);
+
+        assert_eq!(vertices.len(), 6);
+        // The baseline is the ascent below the top of the line, the glyph rises above it
+        assert_eq!(vertices[0].in_position, Vec2::new(5.0, 16.0));
+        assert_eq!(vertices[5].in_position, Vec2::new(25.0, 36.0));
+    }
+
+    #[test]
+    fn spaces_advance_and_unknown_characters_are_skipped() {
+        let mut vertices = Vec::new();
+        square_font().layout_text("A é\tA", Vec2::ZERO, 1.0, Vec3::ONE, &mut vertices);
+
+        assert_eq!(vertices.len(), 12);
+        // The space advances the pen, the characters outside of the atlas are skipped
+        assert_eq!(vertices[6].in_position.x, 24.0);
+    }
+
+    #[test]
+    fn new_lines_go_back_to_the_start_of_the_text() {
+        let mut vertices = Vec::new();
+        square_font().layout_text("AA\nA", Vec2::new(3.0, 0.0), 1.0, Vec3::ONE, &mut vertices);
+
+        assert_eq!(vertices.len(), 18);
+        assert_eq!(vertices[12].in_position, Vec2::new(3.0, 14.0));
+    }
+}
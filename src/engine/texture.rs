@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage},
+    command_buffer::{
+        AutoCommandBufferBuilder, CommandBufferUsage, CopyBufferToImageInfo,
+        PrimaryCommandBufferAbstract,
+    },
+    format::Format,
+    image::{view::ImageView, Image, ImageCreateInfo, ImageType, ImageUsage},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    sync::{GpuFuture, Sharing},
+};
+
+use crate::vulkan_context::VulkanContext;
+
+/// Creates a sampled 2D image from tightly packed pixels and waits for the upload to complete
+pub(crate) fn create_texture(
+    vulkan_context: &VulkanContext,
+    format: Format,
+    extent: [u32; 2],
+    pixels: Vec<u8>,
+) -> Result<Arc<ImageView>> {
+    let allocator = Arc::clone(vulkan_context.standard_memory_allocator());
+
+    let staging_buffer = Buffer::from_iter(
+        Arc::clone(&allocator),
+        BufferCreateInfo {
+            sharing: Sharing::Exclusive,
+            usage: BufferUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        pixels,
+    )?;
+
+    let image = Image::new(
+        allocator,
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format,
+            extent: [extent[0], extent[1], 1],
+            usage: ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+            sharing: Sharing::Exclusive,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+            ..Default::default()
+        },
+    )?;
+
+    let mut builder = AutoCommandBufferBuilder::primary(
+        vulkan_context.standard_command_buffer_allocator().as_ref(),
+        vulkan_context.graphics_queue().queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )?;
+
+    builder.copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(
+        staging_buffer,
+        Arc::clone(&image),
+    ))?;
+
+    builder
+        .build()?
+        .execute(Arc::clone(vulkan_context.graphics_queue()))?
+        .then_signal_fence_and_flush()?
+        .wait(None)?;
+
+    Ok(ImageView::new_default(image)?)
+}
@@ -25,8 +25,10 @@ impl Transform {
         self
     }
 
-    pub fn rotate(&mut self, _axis: Vec3, _angle: f32) -> &mut Self {
-        todo!();
+    /// Rotates by `angle` radians around `axis`, after the current rotation
+    pub fn rotate(&mut self, axis: Vec3, angle: f32) -> &mut Self {
+        self.rotation = Quat::from_axis_angle(axis.normalize(), angle) * self.rotation;
+        self
     }
 
     pub fn scale(&mut self, scale: Vec3) -> &mut Self {
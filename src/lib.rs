@@ -2,8 +2,11 @@ pub mod application;
 
 pub mod camera;
 pub mod engine;
+pub mod vulkan_context;
 
 pub use glam;
+pub use vulkano;
 pub use winit;
 
-mod vulkan_context;
+#[cfg(feature = "egui")]
+pub use egui_winit_vulkano::egui;
@@ -1,6 +1,6 @@
-use std::sync::Arc;
+use std::{fmt::Display, fs, path::PathBuf, sync::Arc};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use vulkano::{
     command_buffer::allocator::{
         StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
@@ -19,14 +19,137 @@ use vulkano::{
         },
         Instance, InstanceCreateInfo, InstanceExtensions,
     },
-    memory::allocator::StandardMemoryAllocator,
+    memory::{allocator::StandardMemoryAllocator, MemoryHeapFlags},
+    pipeline::cache::{PipelineCache, PipelineCacheCreateInfo},
     swapchain::Surface,
-    Version, VulkanLibrary,
+    Version, VulkanLibrary, VulkanObject,
 };
 use winit::window::Window;
 
 const REQUIRED_VALIDATION_LAYERS: [&str; 1] = ["VK_LAYER_KHRONOS_validation"];
 
+/// Settings used to create the Vulkan context. The application name, version and engine name are
+/// reported to the Vulkan instance, shown by tools such as RenderDoc and used by driver profiles
+pub struct VulkanContextInfo {
+    pub application_name: String,
+    pub application_version: Version,
+    pub engine_name: Option<String>,
+    pub allocator_info: AllocatorInfo,
+    /// Optional device features to enable, such as `sampler_anisotropy` or
+    /// `sample_rate_shading`. Those the device does not support stay disabled with a warning,
+    /// check `Device::enabled_features` before relying on them
+    pub features: Features,
+    /// File the compiled pipelines are loaded from when the context is created and saved to when
+    /// it is dropped, so that later runs skip most of the pipeline compilation. Without a path
+    /// the pipelines are only cached for the lifetime of the context
+    pub pipeline_cache_path: Option<PathBuf>,
+}
+
+impl Default for VulkanContextInfo {
+    fn default() -> Self {
+        Self {
+            application_name: String::from("Vulkan engine"),
+            application_version: Version {
+                major: 0,
+                minor: 1,
+                patch: 0,
+            },
+            engine_name: None,
+            allocator_info: AllocatorInfo::default(),
+            features: default_features(),
+            pipeline_cache_path: None,
+        }
+    }
+}
+
+/// Optional features used by the engine when available: indirect drawing and wide debug lines
+pub fn default_features() -> Features {
+    Features {
+        wide_lines: true,
+        multi_draw_indirect: true,
+        draw_indirect_first_instance: true,
+        ..Features::empty()
+    }
+}
+
+/// Features the engine enables on top of the application's when the device supports them.
+/// Without `fill_mode_non_solid` the wireframe render mode and overlay are unavailable
+fn engine_features() -> Features {
+    Features {
+        fill_mode_non_solid: true,
+        ..Features::empty()
+    }
+}
+
+/// Sizing of the command buffer and descriptor set allocators. Applications allocating many
+/// command buffers or descriptor sets per frame can raise the counts to reset and reallocate the
+/// pools less often
+#[derive(Debug, Clone, Copy)]
+pub struct AllocatorInfo {
+    /// Primary command buffers allocated at once per pool
+    pub primary_command_buffer_count: usize,
+    /// Secondary command buffers allocated at once per pool, used by the parallel recording of
+    /// the renderer
+    pub secondary_command_buffer_count: usize,
+    /// Descriptor sets allocated at once per pool
+    pub descriptor_set_count: usize,
+}
+
+impl Default for AllocatorInfo {
+    fn default() -> Self {
+        Self {
+            primary_command_buffer_count: 32,
+            secondary_command_buffer_count: 16,
+            descriptor_set_count: 32,
+        }
+    }
+}
+
+/// Memory heap of the device
+#[derive(Debug, Clone)]
+pub struct HeapReport {
+    pub size: u64,
+    pub device_local: bool,
+    /// Bytes the application can use on this heap, available when the device supports the
+    /// `VK_EXT_memory_budget` extension
+    pub budget: Option<u64>,
+    /// Bytes currently allocated on this heap by the application, available with the same
+    /// extension
+    pub usage: Option<u64>,
+}
+
+/// Memory usage of the device, see `VulkanContext::memory_report`
+#[derive(Debug, Clone)]
+pub struct MemoryReport {
+    pub heaps: Vec<HeapReport>,
+    /// Number of device memory allocations, the allocator suballocates buffers from them
+    pub allocation_count: u32,
+}
+
+impl Display for MemoryReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const MIB: f64 = 1024.0 * 1024.0;
+
+        writeln!(f, "Device memory allocations: {}", self.allocation_count)?;
+        for (i, heap) in self.heaps.iter().enumerate() {
+            let kind = if heap.device_local { "device" } else { "host" };
+            write!(f, "Heap {i} ({kind}): {:.1} MiB", heap.size as f64 / MIB)?;
+
+            if let (Some(usage), Some(budget)) = (heap.usage, heap.budget) {
+                write!(
+                    f,
+                    ", {:.1} MiB used of a {:.1} MiB budget",
+                    usage as f64 / MIB,
+                    budget as f64 / MIB
+                )?;
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
 struct QueueFamilyIndices {
     graphic_family: Option<u32>,
     present_family: Option<u32>,
@@ -34,7 +157,8 @@ struct QueueFamilyIndices {
 
 pub struct VulkanContext {
     instance: Arc<Instance>,
-    _debug_messenger: DebugUtilsMessenger,
+    /// Only created with the validation layers, see `new_release`
+    _debug_messenger: Option<DebugUtilsMessenger>,
 
     device: Arc<Device>,
 
@@ -44,6 +168,10 @@ pub struct VulkanContext {
     standard_memory_allocator: Arc<StandardMemoryAllocator>,
     standard_command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
     standard_descripor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+
+    /// Shared by every pipeline created by the engine
+    pipeline_cache: Arc<PipelineCache>,
+    pipeline_cache_path: Option<PathBuf>,
 }
 
 impl QueueFamilyIndices {
@@ -53,28 +181,55 @@ impl QueueFamilyIndices {
 }
 
 impl VulkanContext {
-    pub(crate) fn new(window: &Arc<Window>) -> Result<Self> {
-        let instance = create_instance();
-        let debug_messenger = create_debug_messenger(Arc::clone(&instance));
+    /// `window` is only used to pick a device able to present to it, the context can then be
+    /// shared by engines rendering into other windows
+    pub fn new(window: &Arc<Window>, context_info: VulkanContextInfo) -> Result<Self> {
+        Self::create(window, context_info, true)
+    }
+
+    /// Context for shipping builds, created without the validation layers, the debug utils and
+    /// validation features extensions nor the debug messenger. Runs on machines without the
+    /// Vulkan SDK and skips the startup cost of the validation
+    pub fn new_release(window: &Arc<Window>, context_info: VulkanContextInfo) -> Result<Self> {
+        Self::create(window, context_info, false)
+    }
+
+    fn create(
+        window: &Arc<Window>,
+        context_info: VulkanContextInfo,
+        validation: bool,
+    ) -> Result<Self> {
+        let allocator_info = context_info.allocator_info;
+        let instance = create_instance(&context_info, validation);
+        let debug_messenger = validation.then(|| create_debug_messenger(Arc::clone(&instance)));
 
         let dummy_surface = Surface::from_window(Arc::clone(&instance), Arc::clone(window))
             .expect("Failed to create dummy surface");
         let (device, graphics_queue, present_queue) =
-            create_logical_device(Arc::clone(&instance), dummy_surface);
+            create_logical_device(Arc::clone(&instance), dummy_surface, &context_info.features)?;
 
         let standard_memory_allocator =
             Arc::new(StandardMemoryAllocator::new_default(device.clone()));
 
         let standard_command_buffer_allocator = Arc::new(StandardCommandBufferAllocator::new(
             Arc::clone(&device),
-            StandardCommandBufferAllocatorCreateInfo::default(),
+            StandardCommandBufferAllocatorCreateInfo {
+                primary_buffer_count: allocator_info.primary_command_buffer_count,
+                secondary_buffer_count: allocator_info.secondary_command_buffer_count,
+                ..Default::default()
+            },
         ));
 
         let standard_descripor_set_allocator = Arc::new(StandardDescriptorSetAllocator::new(
             Arc::clone(&device),
-            StandardDescriptorSetAllocatorCreateInfo::default(),
+            StandardDescriptorSetAllocatorCreateInfo {
+                set_count: allocator_info.descriptor_set_count,
+                ..Default::default()
+            },
         ));
 
+        let pipeline_cache = create_pipeline_cache(&device, &context_info.pipeline_cache_path)?;
+
         let vulkan_context = Self {
             instance,
             _debug_messenger: debug_messenger,
@@ -86,6 +241,9 @@ impl VulkanContext {
             standard_memory_allocator,
             standard_command_buffer_allocator,
             standard_descripor_set_allocator,
+
+            pipeline_cache,
+            pipeline_cache_path: context_info.pipeline_cache_path,
         };
 
         Ok(vulkan_context)
@@ -107,6 +265,25 @@ impl VulkanContext {
         &self.present_queue
     }
 
+    pub fn graphics_queue_family_index(&self) -> u32 {
+        self.graphics_queue.queue_family_index()
+    }
+
+    pub fn present_queue_family_index(&self) -> u32 {
+        self.present_queue.queue_family_index()
+    }
+
+    /// Whether the frames are presented from another queue family than the one rendering them,
+    /// which only happens on devices where no family can do both
+    pub fn separate_present_queue(&self) -> bool {
+        self.graphics_queue_family_index() != self.present_queue_family_index()
+    }
+
+    /// Compute work shares the graphics queue, its family is chosen to support both
+    pub fn compute_queue(&self) -> &Arc<Queue> {
+        &self.graphics_queue
+    }
+
     pub fn standard_memory_allocator(&self) -> &Arc<StandardMemoryAllocator> {
         &self.standard_memory_allocator
     }
@@ -118,44 +295,200 @@ impl VulkanContext {
     pub fn standard_descripor_set_allocator(&self) -> &Arc<StandardDescriptorSetAllocator> {
         &self.standard_descripor_set_allocator
     }
+
+    pub fn pipeline_cache(&self) -> &Arc<PipelineCache> {
+        &self.pipeline_cache
+    }
+
+    /// Writes the pipeline cache to `VulkanContextInfo::pipeline_cache_path`, does nothing
+    /// without a path. Called when the context is dropped
+    pub fn save_pipeline_cache(&self) -> Result<()> {
+        let Some(path) = &self.pipeline_cache_path else {
+            return Ok(());
+        };
+
+        let data = self.pipeline_cache.get_data()?;
+        fs::write(path, data)?;
+
+        Ok(())
+    }
+
+    /// Size of every memory heap of the device, with the memory used by the application when the
+    /// driver reports it
+    pub fn memory_report(&self) -> MemoryReport {
+        let physical_device = self.device.physical_device();
+        let memory_heaps = &physical_device.memory_properties().memory_heaps;
+        let budgets = self.query_memory_budgets();
+
+        let heaps = memory_heaps
+            .iter()
+            .enumerate()
+            .map(|(i, heap)| HeapReport {
+                size: heap.size,
+                device_local: heap.flags.intersects(MemoryHeapFlags::DEVICE_LOCAL),
+                budget: budgets.as_ref().map(|(budget, _)| budget[i]),
+                usage: budgets.as_ref().map(|(_, usage)| usage[i]),
+            })
+            .collect();
+
+        MemoryReport {
+            heaps,
+            allocation_count: self.device.allocation_count(),
+        }
+    }
+
+    /// Budget and usage of every heap. vulkano does not wrap `VK_EXT_memory_budget`, so the
+    /// properties are queried through the same `vkGetPhysicalDeviceMemoryProperties2` function
+    /// vulkano loaded for the instance, with its types from `ash`
+    fn query_memory_budgets(&self) -> Option<([u64; 16], [u64; 16])> {
+        if !self.device.enabled_extensions().ext_memory_budget {
+            return None;
+        }
+
+        let mut budget_properties = ash::vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+        let mut memory_properties =
+            ash::vk::PhysicalDeviceMemoryProperties2::builder().push_next(&mut budget_properties);
+
+        let fns = self.instance.fns();
+        let handle = self.device.physical_device().handle();
+        // Same choice of function as vulkano, the extension is only enabled on the device when
+        // one of them is loaded, see `properties2_supported`
+        unsafe {
+            if self.instance.api_version() >= Version::V1_1 {
+                (fns.v1_1.get_physical_device_memory_properties2)(handle, &mut *memory_properties);
+            } else {
+                (fns.khr_get_physical_device_properties2
+                    .get_physical_device_memory_properties2_khr)(
+                    handle, &mut *memory_properties
+                );
+            }
+        }
+
+        Some((budget_properties.heap_budget, budget_properties.heap_usage))
+    }
 }
 
-fn create_instance() -> Arc<Instance> {
+impl Drop for VulkanContext {
+    fn drop(&mut self) {
+        if let Err(error) = self.save_pipeline_cache() {
+            eprintln!("Failed to save the pipeline cache: {error}");
+        }
+    }
+}
+
+/// Size of the header written by the driver at the start of the pipeline cache data
+const PIPELINE_CACHE_HEADER_SIZE: usize = 32;
+/// `VK_PIPELINE_CACHE_HEADER_VERSION_ONE`
+const PIPELINE_CACHE_HEADER_VERSION: u32 = 1;
+
+/// Whether `data` is a pipeline cache written by the same driver and device, according to its
+/// header. Drivers should reject other data themselves but some crash on it instead
+fn pipeline_cache_matches(data: &[u8], vendor_id: u32, device_id: u32, uuid: &[u8; 16]) -> bool {
+    if data.len() < PIPELINE_CACHE_HEADER_SIZE {
+        return false;
+    }
+
+    let read_u32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+
+    read_u32(0) as usize >= PIPELINE_CACHE_HEADER_SIZE
+        && read_u32(4) == PIPELINE_CACHE_HEADER_VERSION
+        && read_u32(8) == vendor_id
+        && read_u32(12) == device_id
+        && &data[16..32] == uuid
+}
+
+/// Pipeline cache filled with the data of `path` when it was written for this device, a missing,
+/// corrupted or outdated file gives an empty cache which replaces the file when saved
+fn create_pipeline_cache(
+    device: &Arc<Device>,
+    path: &Option<PathBuf>,
+) -> Result<Arc<PipelineCache>> {
+    let properties = device.physical_device().properties();
+    let initial_data = path
+        .as_ref()
+        .and_then(|path| fs::read(path).ok())
+        .filter(|data| {
+            let matches = pipeline_cache_matches(
+                data,
+                properties.vendor_id,
+                properties.device_id,
+                &properties.pipeline_cache_uuid,
+            );
+            if !matches {
+                eprintln!("Pipeline cache file is corrupted or outdated, rebuilding it");
+            }
+
+            matches
+        })
+        .unwrap_or_default();
+
+    // The header has been checked, the driver validates the rest of the data
+    let pipeline_cache = unsafe {
+        PipelineCache::new(
+            Arc::clone(device),
+            PipelineCacheCreateInfo {
+                initial_data,
+                ..Default::default()
+            },
+        )
+    };
+
+    match pipeline_cache {
+        Ok(pipeline_cache) => Ok(pipeline_cache),
+        Err(error) => {
+            eprintln!("Failed to load the pipeline cache, rebuilding it: {error}");
+            Ok(unsafe { PipelineCache::new(Arc::clone(device), Default::default())? })
+        }
+    }
+}
+
+fn create_instance(context_info: &VulkanContextInfo, validation: bool) -> Arc<Instance> {
     let library = VulkanLibrary::new().expect("Failed to load vulkan library");
 
+    // Only needed to query the memory budgets on Vulkan 1.0 instances
+    let khr_get_physical_device_properties2 = library
+        .supported_extensions()
+        .khr_get_physical_device_properties2;
+
     let enabled_extensions = InstanceExtensions {
-        ext_validation_features: true,
-        ext_debug_utils: true,
+        ext_validation_features: validation,
+        ext_debug_utils: validation,
         khr_xcb_surface: true,
         khr_xlib_surface: true,
+        khr_get_physical_device_properties2,
         ..InstanceExtensions::empty()
     };
 
-    let layer_properties = library.layer_properties().unwrap();
+    let enabled_layers = if validation {
+        library
+            .layer_properties()
+            .unwrap()
+            .filter(|layer| REQUIRED_VALIDATION_LAYERS.contains(&layer.name()))
+            .map(|layer| layer.name().to_string())
+            .collect()
+    } else {
+        Vec::new()
+    };
 
-    let enabled_layers = layer_properties
-        .into_iter()
-        .filter(|layer| REQUIRED_VALIDATION_LAYERS.contains(&layer.name()))
-        .map(|layer| layer.name().to_string())
-        .collect();
+    let enabled_validation_features = if validation {
+        vec![ValidationFeatureEnable::DebugPrintf]
+    } else {
+        Vec::new()
+    };
 
     let instance_info = InstanceCreateInfo {
-        application_name: Some(String::from("Vulkan engine")),
-        application_version: Version {
-            major: 0,
-            minor: 1,
-            patch: 0,
-        },
+        application_name: Some(context_info.application_name.clone()),
+        application_version: context_info.application_version,
         enabled_extensions,
         enabled_layers,
-        engine_name: None,
+        engine_name: context_info.engine_name.clone(),
         engine_version: Version {
             major: 0,
             minor: 1,
             patch: 0,
         },
         max_api_version: Some(Version::HEADER_VERSION),
-        enabled_validation_features: vec![ValidationFeatureEnable::DebugPrintf],
+        enabled_validation_features,
         disabled_validation_features: vec![],
         ..Default::default()
     };
@@ -186,23 +519,32 @@ fn find_queue_family_indices(
     };
 
     for (i, queue_family) in device.queue_family_properties().iter().enumerate() {
-        if queue_family.queue_flags.contains(QueueFlags::GRAPHICS) {
-            indices.graphic_family = Some(i as u32);
+        // The graphics queue is also used for compute work
+        let graphics = queue_family
+            .queue_flags
+            .contains(QueueFlags::GRAPHICS | QueueFlags::COMPUTE);
+        let present = device
+            .surface_support(i as u32, surface.as_ref())
+            .expect("Failed to check surface support for physical device");
+
+        // A family doing both avoids synchronizing the frames between two queues
+        if graphics && present {
+            return QueueFamilyIndices {
+                graphic_family: Some(i as u32),
+                present_family: Some(i as u32),
+            };
         }
 
-        if device
-            .surface_support(i as u32, surface.as_ref())
-            .expect("Failed to check surface support for physical device")
-        {
-            indices.present_family = Some(i as u32);
+        if graphics && indices.graphic_family.is_none() {
+            indices.graphic_family = Some(i as u32);
         }
 
-        if indices.is_complete() {
-            return indices;
+        if present && indices.present_family.is_none() {
+            indices.present_family = Some(i as u32);
         }
     }
 
-    panic!("Failed to complete indices");
+    indices
 }
 
 fn is_device_suitable(device: Arc<PhysicalDevice>, surface: Arc<Surface>) -> bool {
@@ -222,21 +564,35 @@ fn choose_physical_device(instance: Arc<Instance>, surface: Arc<Surface>) -> Arc
 
     panic!("Failed to find suitable device");
 }
+
+/// Whether the physical device properties can be extended, as needed by `VK_EXT_memory_budget`:
+/// either the instance is Vulkan 1.1 or it enabled `VK_KHR_get_physical_device_properties2`
+fn properties2_supported(instance: &Instance) -> bool {
+    instance.api_version() >= Version::V1_1
+        || instance
+            .enabled_extensions()
+            .khr_get_physical_device_properties2
+}
+
 fn create_logical_device(
     instance: Arc<Instance>,
     surface: Arc<Surface>,
-) -> (Arc<Device>, Arc<Queue>, Arc<Queue>) {
+    features: &Features,
+) -> Result<(Arc<Device>, Arc<Queue>, Arc<Queue>)> {
+    let properties2_supported = properties2_supported(&instance);
     let physical_device = choose_physical_device(instance, Arc::clone(&surface));
 
+    // Depth resolve is core since Vulkan 1.2, older devices may expose it as an extension
+    let supported_extensions = physical_device.supported_extensions();
     let enabled_extensions = DeviceExtensions {
         khr_swapchain: true,
+        khr_create_renderpass2: supported_extensions.khr_create_renderpass2,
+        khr_depth_stencil_resolve: supported_extensions.khr_depth_stencil_resolve,
+        ext_memory_budget: supported_extensions.ext_memory_budget && properties2_supported,
         ..DeviceExtensions::empty()
     };
 
-    let enabled_features = Features {
-        fill_mode_non_solid: true,
-        ..Features::empty()
-    };
+    let enabled_features = negotiate_features(physical_device.supported_features(), features);
 
     let indices = find_queue_family_indices(Arc::clone(&physical_device), surface);
     let mut unique_indices = vec![
@@ -262,14 +618,101 @@ fn create_logical_device(
         ..Default::default()
     };
 
-    match Device::new(physical_device, device_info) {
-        Ok((device, queues)) => {
-            let mut queues = queues.into_iter();
-            let graphics_queue = queues.next().unwrap();
-            let present_queue = queues.next().unwrap_or(graphics_queue.clone());
+    let (device, queues) =
+        Device::new(physical_device, device_info).context("Failed to create logical device")?;
+
+    // The queues come in the order of `queue_infos`, sorted by family rather than by role
+    let queues = queues.collect::<Vec<_>>();
+    let family_queue = |family| {
+        let queue = queues
+            .iter()
+            .find(|queue| queue.queue_family_index() == family)
+            .unwrap();
+        Arc::clone(queue)
+    };
+    let graphics_queue = family_queue(indices.graphic_family.unwrap());
+    let present_queue = family_queue(indices.present_family.unwrap());
 
-            (device, graphics_queue, present_queue)
-        }
-        Err(error) => panic!("Failed to create logical device: {}", error),
+    Ok((device, graphics_queue, present_queue))
+}
+
+/// Features to enable on a device supporting `supported`: the supported part of the features of
+/// the engine and of the optional `desired` ones
+fn negotiate_features(supported: &Features, desired: &Features) -> Features {
+    let desired = desired.union(&engine_features());
+
+    let missing = desired.difference(supported);
+    if !missing.is_empty() {
+        eprintln!("Optional device features not supported, they stay disabled: {missing:?}");
+    }
+
+    desired.intersection(supported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pipeline_cache_header(vendor_id: u32, device_id: u32, uuid: [u8; 16]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(PIPELINE_CACHE_HEADER_SIZE as u32).to_le_bytes());
+        data.extend_from_slice(&PIPELINE_CACHE_HEADER_VERSION.to_le_bytes());
+        data.extend_from_slice(&vendor_id.to_le_bytes());
+        data.extend_from_slice(&device_id.to_le_bytes());
+        data.extend_from_slice(&uuid);
+        data
+    }
+
+    #[test]
+    fn pipeline_cache_of_same_device_matches() {
+        let mut data = pipeline_cache_header(0x10de, 42, [7; 16]);
+        data.extend_from_slice(&[1, 2, 3]);
+
+        assert!(pipeline_cache_matches(&data, 0x10de, 42, &[7; 16]));
+    }
+
+    #[test]
+    fn pipeline_cache_of_other_device_does_not_match() {
+        let data = pipeline_cache_header(0x10de, 42, [7; 16]);
+
+        assert!(!pipeline_cache_matches(&data, 0x1002, 42, &[7; 16]));
+        assert!(!pipeline_cache_matches(&data, 0x10de, 43, &[7; 16]));
+        assert!(!pipeline_cache_matches(&data, 0x10de, 42, &[8; 16]));
+    }
+
+    #[test]
+    fn unsupported_features_stay_disabled() {
+        let supported = Features {
+            wide_lines: true,
+            ..Features::empty()
+        };
+
+        let enabled = negotiate_features(&supported, &default_features());
+
+        assert_eq!(enabled, supported);
+        assert!(!enabled.fill_mode_non_solid);
+        assert!(!enabled.multi_draw_indirect);
+    }
+
+    #[test]
+    fn supported_engine_features_are_enabled() {
+        let supported = Features {
+            fill_mode_non_solid: true,
+            sampler_anisotropy: true,
+            ..Features::empty()
+        };
+
+        let enabled = negotiate_features(&supported, &Features::empty());
+
+        assert!(enabled.fill_mode_non_solid);
+        assert!(!enabled.sampler_anisotropy);
+    }
+
+    #[test]
+    fn truncated_pipeline_cache_does_not_match() {
+        let data = pipeline_cache_header(0x10de, 42, [7; 16]);
+
+        assert!(!pipeline_cache_matches(&data[..20], 0x10de, 42, &[7; 16]));
+        assert!(!pipeline_cache_matches(&[], 0x10de, 42, &[7; 16]));
     }
 }